@@ -1,3 +1,274 @@
-fn main() {
-    println!("Hello, world!");
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Parser;
+use cc::{Options, Target, Ir, RegAllocator, MachineModel};
+
+/// doruche's C compiler, compiles C source files into a static RISC-V executable, objects, assembly, LLVM IR or C,
+/// or into x86-64 assembly and WebAssembly text, and writes out its IRs as JSON.
+#[derive(Debug, Parser)]
+#[command(name = "drcc")]
+struct Cli {
+    /// C source files, TAC in JSON (`.json`), and objects to link with them
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Output file, `a.out` by default, or each input with a `.s` (`.wat` for wasm32), `.o`, `.ll`, `.tac.c` or `.<ir>.json` extension
+    /// with `-S`, `-c`, `--emit=llvm`, `--emit=c` or `--emit=json-<ir>`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Emit assembly instead of linking
+    #[arg(short = 'S', conflicts_with = "object")]
+    assembly: bool,
+
+    /// Assemble into ELF relocatable objects instead of linking
+    #[arg(short = 'c')]
+    object: bool,
+
+    /// Emit another representation instead of linking: llvm|c|json-ast|json-hir|json-tac|json-lir|json-asm
+    #[arg(long, value_name = "KIND", value_parser = parse_emit, conflicts_with_all = ["assembly", "object"])]
+    emit: Option<Emit>,
+
+    /// Target to generate code for: riscv64-linux|riscv32|x86_64-linux|wasm32, only assembly is emitted for targets other than riscv64-linux
+    #[arg(long, value_name = "TRIPLE", value_parser = parse_target, default_value = "riscv64-linux")]
+    target: Target,
+
+    /// Generate debug info
+    #[arg(short = 'g')]
+    debug_info: bool,
+
+    /// Optimization level
+    #[arg(short = 'O', value_name = "LEVEL", default_value_t = 0)]
+    opt_level: u8,
+
+    /// Code generation options: regalloc=linear|graph, [no-]omit-frame-pointer, [no-]schedule-insns
+    #[arg(short = 'f', value_name = "OPTION", value_parser = parse_flag)]
+    flags: Vec<Flag>,
+
+    /// Target options: tune=generic|sifive-u74
+    #[arg(short = 'm', value_name = "OPTION", value_parser = parse_machine_flag)]
+    machine_flags: Vec<MachineModel>,
+
+    /// Dump the TAC and LIR control flow graphs of every function next to each input, as `<input>.<function>.{tac,lir}.dot`: dot
+    #[arg(long, value_name = "FORMAT", value_parser = parse_dump)]
+    dump_cfg: Option<DumpFormat>,
+
+    /// Dump the register interference graphs of every function next to each input, as `<input>.<function>.rig.dot`: dot
+    #[arg(long, value_name = "FORMAT", value_parser = parse_dump)]
+    dump_rig: Option<DumpFormat>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Emit {
+    Llvm,
+    C,
+    Json(Ir),
+}
+
+fn parse_emit(kind: &str) -> Result<Emit, String> {
+    match kind {
+        "llvm" => Ok(Emit::Llvm),
+        "c" => Ok(Emit::C),
+        "json-ast" => Ok(Emit::Json(Ir::Ast)),
+        "json-hir" => Ok(Emit::Json(Ir::Hir)),
+        "json-tac" => Ok(Emit::Json(Ir::Tac)),
+        "json-lir" => Ok(Emit::Json(Ir::Lir)),
+        "json-asm" => Ok(Emit::Json(Ir::Asm)),
+        _ => Err(format!("unknown kind '{}', expected llvm, c, json-ast, json-hir, json-tac, json-lir or json-asm", kind)),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DumpFormat {
+    Dot,
+}
+
+fn parse_dump(format: &str) -> Result<DumpFormat, String> {
+    match format {
+        "dot" => Ok(DumpFormat::Dot),
+        _ => Err(format!("unknown format '{}', expected dot", format)),
+    }
+}
+
+fn parse_target(name: &str) -> Result<Target, String> {
+    Target::by_name(name)
+        .ok_or_else(|| format!("unknown target '{}', expected riscv64-linux, riscv32, x86_64-linux or wasm32", name))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Flag {
+    RegAlloc(RegAllocator),
+    OmitFramePointer(bool),
+    ScheduleInsns(bool),
+}
+
+fn parse_flag(flag: &str) -> Result<Flag, String> {
+    match flag.split_once('=') {
+        Some(("regalloc", "linear")) => Ok(Flag::RegAlloc(RegAllocator::Linear)),
+        Some(("regalloc", "graph")) => Ok(Flag::RegAlloc(RegAllocator::Graph)),
+        Some(("regalloc", other)) => Err(format!("unknown register allocator '{}', expected linear or graph", other)),
+        None if flag == "omit-frame-pointer" => Ok(Flag::OmitFramePointer(true)),
+        None if flag == "no-omit-frame-pointer" => Ok(Flag::OmitFramePointer(false)),
+        None if flag == "schedule-insns" => Ok(Flag::ScheduleInsns(true)),
+        None if flag == "no-schedule-insns" => Ok(Flag::ScheduleInsns(false)),
+        _ => Err(format!("unknown option '-f{}'", flag)),
+    }
+}
+
+fn parse_machine_flag(flag: &str) -> Result<MachineModel, String> {
+    match flag.split_once('=') {
+        Some(("tune", name)) => MachineModel::by_name(name)
+            .ok_or_else(|| format!("unknown machine model '{}', expected generic or sifive-u74", name)),
+        _ => Err(format!("unknown option '-m{}'", flag)),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let mut options = Options {
+        opt_level: cli.opt_level,
+        target: cli.target,
+        debug_info: cli.debug_info,
+        dump_cfg: matches!(cli.dump_cfg, Some(DumpFormat::Dot)),
+        dump_rig: matches!(cli.dump_rig, Some(DumpFormat::Dot)),
+        ..Options::default()
+    };
+    for flag in cli.flags {
+        match flag {
+            Flag::RegAlloc(allocator) => options.regalloc = Some(allocator),
+            Flag::OmitFramePointer(omit) => options.omit_frame_pointer = omit,
+            Flag::ScheduleInsns(schedule) => options.schedule_insns = Some(schedule),
+        }
+    }
+    if let Some(&model) = cli.machine_flags.last() {
+        options.tune = model;
+    }
+
+    let linking = !cli.assembly && !cli.object && cli.emit.is_none();
+    if !linking && cli.output.is_some() && cli.inputs.len() > 1 {
+        eprintln!("drcc: cannot specify -o with -c, -S or --emit with multiple files");
+        return ExitCode::FAILURE;
+    }
+    if cli.target != Target::Riscv64 && !cli.assembly && cli.emit.is_none() {
+        eprintln!("drcc: only assembly can be generated for {}, use -S", cli.target.name());
+        return ExitCode::FAILURE;
+    }
+
+    let mut objects = vec![];
+    for input in cli.inputs.iter() {
+        if input.extension().is_some_and(|extension| extension == "o") {
+            if !linking {
+                eprintln!("drcc: warning: {}: linker input file unused because linking not done", input.display());
+                continue;
+            }
+            match std::fs::read(input) {
+                Ok(object) => objects.push(object),
+                Err(err) => {
+                    eprintln!("drcc: cannot read {}: {}", input.display(), err);
+                    return ExitCode::FAILURE;
+                },
+            }
+            continue;
+        }
+
+        let source = match std::fs::read_to_string(input) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("drcc: cannot read {}: {}", input.display(), err);
+                return ExitCode::FAILURE;
+            },
+        };
+        let options = Options {
+            file_name: Some(input.display().to_string()),
+            tac_input: input.extension().is_some_and(|extension| extension == "json"),
+            ..options.clone()
+        };
+        if options.dump_cfg || options.dump_rig {
+            let dumps = match cc::dump(source.clone(), &options) {
+                Ok(dumps) => dumps,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return ExitCode::FAILURE;
+                },
+            };
+            for dump in dumps {
+                let path = input.with_extension(format!("{}.{}.dot", dump.function, dump.kind));
+                if !write_output(&path, dump.dot.as_bytes()) {
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        let compiled = if let Some(Emit::Llvm) = cli.emit {
+            cc::compile_llvm(source, &options).map(String::into_bytes)
+        } else if let Some(Emit::C) = cli.emit {
+            cc::compile_c(source, &options).map(String::into_bytes)
+        } else if let Some(Emit::Json(ir)) = cli.emit {
+            cc::compile_json(source, &options, ir).map(String::into_bytes)
+        } else if cli.assembly {
+            cc::compile(source, &options).map(String::into_bytes)
+        } else {
+            cc::compile_object(source, &options)
+        };
+        let bytes = match compiled {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            },
+        };
+
+        if linking {
+            objects.push(bytes);
+        } else {
+            let extension = match cli.emit {
+                Some(Emit::Llvm) => "ll",
+                // not to overwrite the input
+                Some(Emit::C) => "tac.c",
+                Some(Emit::Json(Ir::Ast)) => "ast.json",
+                Some(Emit::Json(Ir::Hir)) => "hir.json",
+                Some(Emit::Json(Ir::Tac)) => "tac.json",
+                Some(Emit::Json(Ir::Lir)) => "lir.json",
+                Some(Emit::Json(Ir::Asm)) => "asm.json",
+                None if cli.assembly && cli.target == Target::Wasm32 => "wat",
+                None if cli.assembly => "s",
+                None => "o",
+            };
+            let output = cli.output.clone().unwrap_or_else(|| input.with_extension(extension));
+            if !write_output(&output, &bytes) {
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if linking {
+        let executable = match cc::link(&objects) {
+            Ok(executable) => executable,
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            },
+        };
+        let output = cli.output.unwrap_or_else(|| PathBuf::from("a.out"));
+        if !write_output(&output, &executable) {
+            return ExitCode::FAILURE;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&output, std::fs::Permissions::from_mode(0o755));
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn write_output(path: &Path, bytes: &[u8]) -> bool {
+    if let Err(err) = std::fs::write(path, bytes) {
+        eprintln!("drcc: cannot write {}: {}", path.display(), err);
+        return false;
+    }
+    true
 }
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::marker::PhantomData;
 
 use crate::asm::riscv::TopLevel;
-use crate::asm::Register;
+use crate::asm::{Register, Xlen};
 use crate::{common::*, expect_mem, expect_register};
 use crate::lir::{
     LirTopLevel,
@@ -46,7 +46,7 @@ impl CodeGen<Parse> {
             bss_seg.add(var.into());
         }
         for (name, func) in lir.functions {
-            let function = self.parse_func(func);
+            let function = self.parse_func(func, lir.xlen);
             functions.insert(name, function);
         }
     
@@ -54,23 +54,32 @@ impl CodeGen<Parse> {
             functions,
             data_seg,
             bss_seg,
+            externs: lir.externs.into_iter()
+                .map(|(name, var)| (name, var.into()))
+                .collect(),
             strtb: lir.strtb,
+            source_file: None,
+            xlen: lir.xlen,
         }, CodeGen { 
             cur_cx: None,
             _stage: PhantomData,
         })
     }
 
-    fn parse_func(&mut self, func: LirFunction) -> Function {
+    fn parse_func(&mut self, func: LirFunction, xlen: Xlen) -> Function {
+        let callee_saved = func.callee_saved.unwrap();
         let cx = FuncContext {
             name: func.name,
-            callee_saved: func.callee_saved.unwrap(),
+            frame_pointer: callee_saved.iter().any(|&(reg, _)| reg == Register::S0),
+            callee_saved,
             frame_size: func.frame_size,
+            xlen,
         };
         self.cur_cx = Some(cx);
 
         let body = func.body.into_iter()
             .flat_map(|insn| self.parse_insn(insn))
+            .flat_map(legalize)
             .collect();
 
         self.cur_cx = None;
@@ -80,6 +89,8 @@ impl CodeGen<Parse> {
             func_type: func.func_type,
             body,
             linkage: func.linkage,
+            span: func.span,
+            vars: func.vars.into_iter().map(Into::into).collect(),
         }        
     }
 
@@ -93,12 +104,16 @@ impl CodeGen<Parse> {
             LirInsn::Subw(rd, rs1, rs2) |
             LirInsn::Mul(rd, rs1, rs2) |
             LirInsn::Mulw(rd, rs1, rs2) |
+            LirInsn::Mulh(rd, rs1, rs2) |
+            LirInsn::Mulhu(rd, rs1, rs2) |
             LirInsn::Div(rd, rs1, rs2) |
             LirInsn::Divw(rd, rs1, rs2) |
             LirInsn::Rem(rd, rs1, rs2) |
             LirInsn::Remw(rd, rs1, rs2) |
             LirInsn::Slt(rd, rs1, rs2) |
-            LirInsn::Sgt(rd, rs1, rs2) => {
+            LirInsn::Sgt(rd, rs1, rs2) |
+            LirInsn::Sltu(rd, rs1, rs2) |
+            LirInsn::Or(rd, rs1, rs2) => {
                 let rd = expect_register!(rd);
                 let rs1 = expect_register!(rs1);
                 let rs2 = expect_register!(rs2);
@@ -109,12 +124,16 @@ impl CodeGen<Parse> {
                     LirInsn::Subw(..) => Insn::Subw(rd, rs1, rs2),
                     LirInsn::Mul(..) => Insn::Mul(rd, rs1, rs2),
                     LirInsn::Mulw(..) => Insn::Mulw(rd, rs1, rs2),
+                    LirInsn::Mulh(..) => Insn::Mulh(rd, rs1, rs2),
+                    LirInsn::Mulhu(..) => Insn::Mulhu(rd, rs1, rs2),
                     LirInsn::Div(..) => Insn::Div(rd, rs1, rs2),
                     LirInsn::Divw(..) => Insn::Divw(rd, rs1, rs2),
                     LirInsn::Rem(..) => Insn::Rem(rd, rs1, rs2),
                     LirInsn::Remw(..) => Insn::Remw(rd, rs1, rs2),
                     LirInsn::Slt(..) => Insn::Slt(rd, rs1, rs2),
                     LirInsn::Sgt(..) => Insn::Sgt(rd, rs1, rs2),
+                    LirInsn::Sltu(..) => Insn::Sltu(rd, rs1, rs2),
+                    LirInsn::Or(..) => Insn::Or(rd, rs1, rs2),
                     _ => unreachable!(),
                 });
             },
@@ -164,6 +183,24 @@ impl CodeGen<Parse> {
                 let rs = expect_register!(rs);
                 insns.push(Insn::Addiw(rd, rs, imm));
             },
+            LirInsn::Slli(rd, rs, shamt) |
+            LirInsn::Slliw(rd, rs, shamt) |
+            LirInsn::Srli(rd, rs, shamt) |
+            LirInsn::Srliw(rd, rs, shamt) |
+            LirInsn::Srai(rd, rs, shamt) |
+            LirInsn::Sraiw(rd, rs, shamt) => {
+                let rd = expect_register!(rd);
+                let rs = expect_register!(rs);
+                insns.push(match insn {
+                    LirInsn::Slli(..) => Insn::Slli(rd, rs, shamt),
+                    LirInsn::Slliw(..) => Insn::Slliw(rd, rs, shamt),
+                    LirInsn::Srli(..) => Insn::Srli(rd, rs, shamt),
+                    LirInsn::Srliw(..) => Insn::Srliw(rd, rs, shamt),
+                    LirInsn::Srai(..) => Insn::Srai(rd, rs, shamt),
+                    LirInsn::Sraiw(..) => Insn::Sraiw(rd, rs, shamt),
+                    _ => unreachable!(),
+                });
+            },
             LirInsn::Mv(dst, src) |
             LirInsn::Neg(dst, src) |
             LirInsn::Negw(dst, src) |
@@ -185,19 +222,24 @@ impl CodeGen<Parse> {
                 });
             },
             LirInsn::Beq(rs1, rs2, label) |
-            LirInsn::Bne(rs1, rs2, label) => {
+            LirInsn::Bne(rs1, rs2, label) |
+            LirInsn::Blt(rs1, rs2, label) |
+            LirInsn::Bge(rs1, rs2, label) => {
                 let rs1 = expect_register!(rs1);
                 let rs2 = expect_register!(rs2);
                 insns.push(match insn {
                     LirInsn::Beq(..) => Insn::Beq(rs1, rs2, label.into()),
                     LirInsn::Bne(..) => Insn::Bne(rs1, rs2, label.into()),
+                    LirInsn::Blt(..) => Insn::Blt(rs1, rs2, label.into()),
+                    LirInsn::Bge(..) => Insn::Bge(rs1, rs2, label.into()),
                     _ => unreachable!(),
                 });
             },
             LirInsn::J(label) => insns.push(Insn::J(label.into())),
             LirInsn::Label(label) => insns.push(Insn::Label(label.into())),
-            LirInsn::LoadStatic(rd, namr) => insns.push(Insn::LoadStatic(expect_register!(rd), namr)),
-            LirInsn::StoreStatic(rs, name) => insns.push(Insn::StoreStatic(expect_register!(rs), name)),
+            LirInsn::Loc(span) => insns.push(Insn::Loc(span)),
+            LirInsn::LoadStatic(rd, name, offset) => insns.push(Insn::LoadStatic(expect_register!(rd), name, offset)),
+            LirInsn::StoreStatic(rs, addr, name, offset) => insns.push(Insn::StoreStatic(expect_register!(rs), expect_register!(addr), name, offset)),
             LirInsn::Call(name) => insns.push(Insn::Call(name)),
             LirInsn::Intermediate(insn) => insns.extend(self.parse_intermediate(insn)),
             LirInsn::Ret => unreachable!(),
@@ -211,20 +253,54 @@ impl CodeGen<Parse> {
         let mut insns = vec![];
 
         match insn {
-            IntermediateInsn::Prologue => {
+            IntermediateInsn::Prologue if cx.frame_pointer => {
                 for &(reg, offset) in cx.callee_saved.iter() {
-                    insns.push(Insn::Sd(reg, Register::Sp, offset));
+                    insns.push(cx.save(reg, Register::Sp, offset));
                 }
                 insns.push(Insn::Addi(Register::Sp, Register::Sp, -(cx.frame_size as i64)));
                 insns.push(Insn::Addi(Register::S0, Register::Sp, cx.frame_size as i64));
             },
-            IntermediateInsn::Epilogue => {
-                for &(reg, offset) in cx.callee_saved.iter().rev() {
-                    insns.push(Insn::Ld(reg, Register::S0, offset));
+            // without a frame pointer, the frame is addressed off the adjusted sp,
+            // and there's no frame at all when nothing is saved or spilled.
+            // sizes and offsets past 12 bits are left to `legalize`.
+            IntermediateInsn::Prologue => {
+                if cx.frame_size != 0 {
+                    insns.push(Insn::Addi(Register::Sp, Register::Sp, -(cx.frame_size as i64)));
                 }
-                insns.push(Insn::Addi(Register::Sp, Register::Sp, cx.frame_size as i64));
+                for &(reg, offset) in cx.callee_saved.iter() {
+                    insns.push(cx.save(reg, Register::Sp, cx.frame_size as isize + offset));
+                }
+            },
+            IntermediateInsn::Epilogue => {
+                insns.extend(self.leave_frame());
                 insns.push(Insn::Ret);
             },
+            IntermediateInsn::TailCall(name) => {
+                insns.extend(self.leave_frame());
+                insns.push(Insn::Tail(name));
+            },
+        }
+
+        insns
+    }
+
+    /// Restores the callee-saved registers and pops the frame,
+    /// `legalize` takes care of frames too large for an immediate.
+    fn leave_frame(&self) -> Vec<Insn> {
+        let cx = self.cur_cx();
+        let mut insns = vec![];
+
+        if cx.frame_pointer {
+            for &(reg, offset) in cx.callee_saved.iter().rev() {
+                insns.push(cx.restore(reg, Register::S0, offset));
+            }
+        } else {
+            for &(reg, offset) in cx.callee_saved.iter().rev() {
+                insns.push(cx.restore(reg, Register::Sp, cx.frame_size as isize + offset));
+            }
+        }
+        if cx.frame_size != 0 {
+            insns.push(Insn::Addi(Register::Sp, Register::Sp, cx.frame_size as i64));
         }
 
         insns
@@ -235,3 +311,56 @@ impl CodeGen<Parse> {
     }
 }
 
+impl FuncContext {
+    /// Saves a callee-saved register into its slot.
+    fn save(&self, reg: Register, base: Register, offset: isize) -> Insn {
+        match self.xlen {
+            Xlen::Rv64 => Insn::Sd(reg, base, offset),
+            Xlen::Rv32 => Insn::Sw(reg, base, offset),
+        }
+    }
+
+    /// Restores a callee-saved register from its slot.
+    fn restore(&self, reg: Register, base: Register, offset: isize) -> Insn {
+        match self.xlen {
+            Xlen::Rv64 => Insn::Ld(reg, base, offset),
+            Xlen::Rv32 => Insn::Lw(reg, base, offset),
+        }
+    }
+}
+
+/// Goes through `Register::SCRATCH` where a frame offset or an `sp` adjustment doesn't fit in 12 bits,
+/// as in frames over 2 KiB, e.g. `sd s1, -2336(sp)` -> `lui t6, -1; add t6, t6, sp; sd s1, 1760(t6)`
+/// and `addi sp, sp, -2416` -> `li t6, -2416; add sp, sp, t6`.
+fn legalize(insn: Insn) -> Vec<Insn> {
+    let scratch = Register::SCRATCH;
+    // the upper bits go into the scratch with a `lui`, the lowest 12 stay in the offset
+    let split = |offset: isize| {
+        let lo = (offset as i64) << 52 >> 52;
+        (offset as i64 - lo, lo as isize)
+    };
+    match insn {
+        Insn::Ld(rd, base, offset) if !fits_imm12(offset as i64) => {
+            let (hi, lo) = split(offset);
+            vec![Insn::Li(scratch, hi), Insn::Add(scratch, scratch, base), Insn::Ld(rd, scratch, lo)]
+        },
+        Insn::Lw(rd, base, offset) if !fits_imm12(offset as i64) => {
+            let (hi, lo) = split(offset);
+            vec![Insn::Li(scratch, hi), Insn::Add(scratch, scratch, base), Insn::Lw(rd, scratch, lo)]
+        },
+        Insn::Sd(rs, base, offset) if !fits_imm12(offset as i64) => {
+            let (hi, lo) = split(offset);
+            vec![Insn::Li(scratch, hi), Insn::Add(scratch, scratch, base), Insn::Sd(rs, scratch, lo)]
+        },
+        Insn::Sw(rs, base, offset) if !fits_imm12(offset as i64) => {
+            let (hi, lo) = split(offset);
+            vec![Insn::Li(scratch, hi), Insn::Add(scratch, scratch, base), Insn::Sw(rs, scratch, lo)]
+        },
+        Insn::Addi(rd, rs, imm) if !fits_imm12(imm) => vec![Insn::Li(scratch, imm), Insn::Add(rd, rs, scratch)],
+        _ => vec![insn],
+    }
+}
+
+fn fits_imm12(imm: i64) -> bool {
+    (-2048..2048).contains(&imm)
+}
@@ -0,0 +1,423 @@
+//! DWARF 4 debug info for `-g`, shared by the text output (`emit.rs`) and the assembler (`encode.rs`).
+//! - `.debug_abbrev` is fixed, one abbreviation for each kind of entry below
+//! - `.debug_info` has a single compilation unit covering `.text`, with `int` and `long` base types,
+//!   static variables defined here, and a subprogram for each function, with its parameters
+//!   and local variables located in their register or frame slot; those optimized out have no location
+//! - `.debug_line` is left to the GNU assembler for the text output, which builds it from `.loc`s,
+//!   and generated by `line_program` for objects
+//!
+//! Addresses and sizes depend on where code ends up, so they're kept as `Item`s
+//! for each side to fill in as labels or relocations.
+
+use crate::{
+    common::*,
+    elf::SectionKind,
+};
+use super::{
+    TopLevel,
+    Function,
+    StaticVar,
+    DebugVar,
+    VarLocation,
+};
+
+/// Part of the contents of a debug section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Item {
+    Bytes(Vec<u8>),
+    /// NUL-terminated
+    String(String),
+    /// 8-byte address of a symbol
+    Addr(String),
+    /// 8-byte address of the start of `.text`
+    TextStart,
+    /// 4-byte size of `.text`
+    TextSize,
+    /// 4-byte size of a function
+    FuncSize(String),
+    /// 4-byte offset of the contents of a debug section in the linked one
+    SectionStart(SectionKind),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match self {
+            Item::Bytes(bytes) => bytes.len(),
+            Item::String(string) => string.len() + 1,
+            Item::Addr(..) | Item::TextStart => 8,
+            Item::TextSize | Item::FuncSize(..) | Item::SectionStart(..) => 4,
+        }
+    }
+}
+
+const DW_TAG_FORMAL_PARAMETER: u8 = 0x05;
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_BASE_TYPE: u8 = 0x24;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_TAG_VARIABLE: u8 = 0x34;
+
+const DW_AT_LOCATION: u8 = 0x02;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_BYTE_SIZE: u8 = 0x0b;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_LANGUAGE: u8 = 0x13;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_AT_DECL_COLUMN: u8 = 0x39;
+const DW_AT_DECL_FILE: u8 = 0x3a;
+const DW_AT_DECL_LINE: u8 = 0x3b;
+const DW_AT_ENCODING: u8 = 0x3e;
+const DW_AT_EXTERNAL: u8 = 0x3f;
+const DW_AT_TYPE: u8 = 0x49;
+
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_DATA1: u8 = 0x0b;
+const DW_FORM_FLAG: u8 = 0x0c;
+const DW_FORM_UDATA: u8 = 0x0f;
+const DW_FORM_REF4: u8 = 0x13;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_EXPRLOC: u8 = 0x18;
+
+const DW_ATE_SIGNED: u8 = 0x05;
+const DW_LANG_C99: u8 = 0x0c;
+
+const DW_OP_ADDR: u8 = 0x03;
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_BREG0: u8 = 0x70;
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+/// Abbreviation codes, in the order they're declared in `.debug_abbrev`.
+#[derive(Clone, Copy)]
+enum Abbrev {
+    CompileUnit = 1,
+    BaseType,
+    StaticVar,
+    Subprogram,
+    VoidSubprogram,
+    Param,
+    OptimizedOutParam,
+    Var,
+    OptimizedOutVar,
+}
+
+/// The contents of `.debug_abbrev`.
+pub(super) fn abbrevs() -> Vec<u8> {
+    let decl = [
+        (DW_AT_NAME, DW_FORM_STRING),
+        (DW_AT_DECL_FILE, DW_FORM_DATA1),
+        (DW_AT_DECL_LINE, DW_FORM_UDATA),
+        (DW_AT_DECL_COLUMN, DW_FORM_UDATA),
+    ];
+    let var = [decl.as_slice(), &[(DW_AT_TYPE, DW_FORM_REF4)]].concat();
+    let located_var = [var.as_slice(), &[(DW_AT_LOCATION, DW_FORM_EXPRLOC)]].concat();
+    let subprogram = [decl.as_slice(), &[
+        (DW_AT_EXTERNAL, DW_FORM_FLAG),
+        (DW_AT_LOW_PC, DW_FORM_ADDR),
+        (DW_AT_HIGH_PC, DW_FORM_DATA4),
+    ]].concat();
+
+    let entries = [
+        (Abbrev::CompileUnit, DW_TAG_COMPILE_UNIT, true, vec![
+            (DW_AT_PRODUCER, DW_FORM_STRING),
+            (DW_AT_LANGUAGE, DW_FORM_DATA1),
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_HIGH_PC, DW_FORM_DATA4),
+            (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+        ]),
+        (Abbrev::BaseType, DW_TAG_BASE_TYPE, false, vec![
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_BYTE_SIZE, DW_FORM_DATA1),
+            (DW_AT_ENCODING, DW_FORM_DATA1),
+        ]),
+        (Abbrev::StaticVar, DW_TAG_VARIABLE, false,
+            [var.as_slice(), &[(DW_AT_EXTERNAL, DW_FORM_FLAG), (DW_AT_LOCATION, DW_FORM_EXPRLOC)]].concat()),
+        (Abbrev::Subprogram, DW_TAG_SUBPROGRAM, true,
+            [subprogram.as_slice(), &[(DW_AT_TYPE, DW_FORM_REF4)]].concat()),
+        (Abbrev::VoidSubprogram, DW_TAG_SUBPROGRAM, true, subprogram),
+        (Abbrev::Param, DW_TAG_FORMAL_PARAMETER, false, located_var.clone()),
+        (Abbrev::OptimizedOutParam, DW_TAG_FORMAL_PARAMETER, false, var.clone()),
+        (Abbrev::Var, DW_TAG_VARIABLE, false, located_var),
+        (Abbrev::OptimizedOutVar, DW_TAG_VARIABLE, false, var),
+    ];
+
+    let mut bytes = vec![];
+    for (code, tag, has_children, attrs) in entries {
+        bytes.extend([code as u8, tag, has_children as u8]);
+        for (attr, form) in attrs {
+            bytes.extend([attr, form]);
+        }
+        bytes.extend([0, 0]);
+    }
+    bytes.push(0);
+    bytes
+}
+
+/// Entries of `.debug_info` as they're added, keeping track of their offsets.
+struct Info {
+    items: Vec<Item>,
+    len: usize,
+}
+
+impl Info {
+    fn push(&mut self, item: Item) {
+        self.len += item.size();
+        // consecutive bytes are kept together
+        match (self.items.last_mut(), item) {
+            (Some(Item::Bytes(bytes)), Item::Bytes(more)) => bytes.extend(more),
+            (_, item) => self.items.push(item),
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.push(Item::Bytes(bytes.to_vec()));
+    }
+
+    fn string(&mut self, string: &str) {
+        self.push(Item::String(string.to_string()));
+    }
+
+    fn uleb(&mut self, value: u64) {
+        self.push(Item::Bytes(uleb128(value)));
+    }
+
+    /// Name, file and line, where an entry is declared.
+    fn decl(&mut self, name: &str, span: Span) {
+        self.string(name);
+        self.bytes(&[1]);
+        self.uleb(span.line as u64);
+        self.uleb(span.column as u64);
+    }
+}
+
+impl TopLevel {
+    /// The contents of `.debug_info` for `file`.
+    pub(super) fn debug_info(&self, file: &str) -> Vec<Item> {
+        let mut info = Info { items: vec![], len: 0 };
+        // unit_length, filled in last
+        info.bytes(&[0; 4]);
+        info.bytes(&4u16.to_le_bytes());
+        info.push(Item::SectionStart(SectionKind::DebugAbbrev));
+        info.bytes(&[8]);
+
+        info.uleb(Abbrev::CompileUnit as u64);
+        info.string("drcc");
+        info.bytes(&[DW_LANG_C99]);
+        info.string(file);
+        info.push(Item::TextStart);
+        info.push(Item::TextSize);
+        info.push(Item::SectionStart(SectionKind::DebugLine));
+
+        let int = info.len as u32;
+        info.uleb(Abbrev::BaseType as u64);
+        info.string("int");
+        info.bytes(&[4, DW_ATE_SIGNED]);
+        let long = info.len as u32;
+        info.uleb(Abbrev::BaseType as u64);
+        info.string("long int");
+        info.bytes(&[8, DW_ATE_SIGNED]);
+        let type_ref = |data_type: DataType| match data_type {
+            DataType::Int => int,
+            DataType::Long => long,
+            _ => unreachable!(),
+        };
+
+        // sorted by name, so the same source gives the same output
+        let mut vars = self.data_seg.items.values()
+            .chain(self.bss_seg.items.values())
+            .collect::<Vec<_>>();
+        vars.sort_by_key(|var| self.name(var.name));
+        for var in vars {
+            self.static_var_info(&mut info, var, type_ref(var.data_type));
+        }
+
+        let mut functions = self.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|func| self.name(func.name));
+        for func in functions {
+            let return_type = match func.func_type.return_type {
+                DataType::Void => None,
+                data_type => Some(type_ref(data_type)),
+            };
+            self.function_info(&mut info, func, return_type, &type_ref);
+        }
+        info.bytes(&[0]);
+
+        let unit_length = (info.len - 4) as u32;
+        let Some(Item::Bytes(header)) = info.items.first_mut() else { unreachable!() };
+        header[..4].copy_from_slice(&unit_length.to_le_bytes());
+        info.items
+    }
+
+    fn static_var_info(&self, info: &mut Info, var: &StaticVar, type_ref: u32) {
+        let name = self.name(var.name);
+        info.uleb(Abbrev::StaticVar as u64);
+        info.decl(name, var.span);
+        info.bytes(&type_ref.to_le_bytes());
+        info.bytes(&[(var.linkage == Linkage::External) as u8]);
+        // DW_OP_addr and the address
+        info.uleb(9);
+        info.bytes(&[DW_OP_ADDR]);
+        info.push(Item::Addr(name.to_string()));
+    }
+
+    fn function_info(&self, info: &mut Info, func: &Function, return_type: Option<u32>, type_ref: &impl Fn(DataType) -> u32) {
+        let name = self.name(func.name);
+        let abbrev = match return_type {
+            Some(..) => Abbrev::Subprogram,
+            None => Abbrev::VoidSubprogram,
+        };
+        info.uleb(abbrev as u64);
+        info.decl(name, func.span);
+        info.bytes(&[(func.linkage == Linkage::External) as u8]);
+        info.push(Item::Addr(name.to_string()));
+        info.push(Item::FuncSize(name.to_string()));
+        if let Some(return_type) = return_type {
+            info.bytes(&return_type.to_le_bytes());
+        }
+
+        for var in func.vars.iter() {
+            self.var_info(info, var, type_ref(var.data_type));
+        }
+        info.bytes(&[0]);
+    }
+
+    fn var_info(&self, info: &mut Info, var: &DebugVar, type_ref: u32) {
+        let abbrev = match (var.is_param, var.location) {
+            (true, Some(..)) => Abbrev::Param,
+            (true, None) => Abbrev::OptimizedOutParam,
+            (false, Some(..)) => Abbrev::Var,
+            (false, None) => Abbrev::OptimizedOutVar,
+        };
+        info.uleb(abbrev as u64);
+        info.decl(self.name(var.name), var.span);
+        info.bytes(&type_ref.to_le_bytes());
+
+        let expr = match var.location {
+            Some(VarLocation::Reg(reg)) => vec![DW_OP_REG0 + reg as u8],
+            Some(VarLocation::Mem(base, offset)) => {
+                let mut expr = vec![DW_OP_BREG0 + base as u8];
+                expr.extend(sleb128(offset as i64));
+                expr
+            },
+            None => return,
+        };
+        info.uleb(expr.len() as u64);
+        info.bytes(&expr);
+    }
+}
+
+/// Rows of the line table of a function: offsets into it and where they come from in the source.
+#[derive(Debug)]
+pub(super) struct LineSequence {
+    pub func: String,
+    pub size: usize,
+    pub rows: Vec<(usize, Span)>,
+}
+
+/// The contents of `.debug_line` for `file`, a sequence for each function.
+/// Rows are added with standard opcodes alone, no special ones.
+pub(super) fn line_program(file: &str, sequences: &[LineSequence]) -> Vec<Item> {
+    let mut header = vec![
+        // minimum_instruction_length, maximum_operations_per_instruction, default_is_stmt
+        1, 1, 1,
+        // line_base, line_range, opcode_base
+        -5i8 as u8, 14, 13,
+        // standard_opcode_lengths
+        0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1,
+        // no include_directories
+        0,
+    ];
+    // file_names: the file, in the compilation directory, no time or size
+    header.extend(file.as_bytes());
+    header.extend([0, 0, 0, 0, 0]);
+
+    let mut items = vec![];
+    let mut program = vec![];
+    for sequence in sequences {
+        program.extend([0, 9, DW_LNE_SET_ADDRESS]);
+        items.push(Item::Bytes(std::mem::take(&mut program)));
+        items.push(Item::Addr(sequence.func.clone()));
+
+        let (mut addr, mut line) = (0, 1);
+        for &(offset, span) in sequence.rows.iter() {
+            if offset != addr {
+                program.push(DW_LNS_ADVANCE_PC);
+                program.extend(uleb128((offset - addr) as u64));
+                addr = offset;
+            }
+            if span.line != line {
+                program.push(DW_LNS_ADVANCE_LINE);
+                program.extend(sleb128(span.line as i64 - line as i64));
+                line = span.line;
+            }
+            program.push(DW_LNS_SET_COLUMN);
+            program.extend(uleb128(span.column as u64));
+            program.push(DW_LNS_COPY);
+        }
+        if sequence.size != addr {
+            program.push(DW_LNS_ADVANCE_PC);
+            program.extend(uleb128((sequence.size - addr) as u64));
+        }
+        program.extend([0, 1, DW_LNE_END_SEQUENCE]);
+    }
+    items.push(Item::Bytes(program));
+
+    let mut bytes = vec![];
+    let unit_length = 2 + 4 + header.len() + items.iter().map(Item::size).sum::<usize>();
+    bytes.extend((unit_length as u32).to_le_bytes());
+    bytes.extend(4u16.to_le_bytes());
+    bytes.extend((header.len() as u32).to_le_bytes());
+    bytes.extend(header);
+    items.insert(0, Item::Bytes(bytes));
+    items
+}
+
+fn uleb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn sleb128(mut value: i64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128() {
+        assert_eq!(uleb128(2), [2]);
+        assert_eq!(uleb128(624485), [0xe5, 0x8e, 0x26]);
+        assert_eq!(sleb128(-24), [0x68]);
+        assert_eq!(sleb128(63), [0x3f]);
+        assert_eq!(sleb128(64), [0xc0, 0x00]);
+        assert_eq!(sleb128(-123456), [0xc0, 0xbb, 0x78]);
+    }
+}
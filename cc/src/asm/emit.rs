@@ -1,5 +1,9 @@
-use crate::common::*;
+use crate::{
+    common::*,
+    elf::SectionKind,
+};
 use super::{
+    debug::{self, Item},
     Insn,
     CodeGen,
     Register,
@@ -15,10 +19,51 @@ impl TopLevel {
     pub fn emit(&self) -> String {
         let mut output = String::new();
 
-
+        if let Some(file) = &self.source_file {
+            output.push_str(&format!("\t.file\t1 {}\n", quote(file)));
+        }
         output.push_str(&self.emit_code());
         output.push_str(&self.emit_data());
         output.push_str(&self.emit_bss());
+        if let Some(file) = &self.source_file {
+            output.push_str(&self.emit_debug(file));
+        }
+        output
+    }
+
+    /// `.debug_info` and `.debug_abbrev`, the assembler fills in `.debug_line` from the `.loc`s.
+    pub fn emit_debug(&self, file: &str) -> String {
+        let mut output = String::new();
+
+        output.push_str("\t.section\t.debug_info,\"\",@progbits\n");
+        for item in self.debug_info(file) {
+            let line = match item {
+                Item::Bytes(bytes) => {
+                    for chunk in bytes.chunks(16) {
+                        output.push_str(&emit_bytes(chunk));
+                    }
+                    continue;
+                },
+                Item::String(string) => format!(".string\t{}", quote(&string)),
+                Item::Addr(name) => format!(".8byte\t{}", name),
+                Item::TextStart => ".8byte\t.Ltext0".to_string(),
+                Item::TextSize => ".4byte\t.Letext0-.Ltext0".to_string(),
+                Item::FuncSize(name) => format!(".4byte\t.L{}_end-{}", name, name),
+                Item::SectionStart(SectionKind::DebugAbbrev) => ".4byte\t.Ldebug_abbrev0".to_string(),
+                Item::SectionStart(SectionKind::DebugLine) => ".4byte\t.Ldebug_line0".to_string(),
+                Item::SectionStart(..) => unreachable!(),
+            };
+            output.push_str(&format!("\t{}\n", line));
+        }
+
+        output.push_str("\t.section\t.debug_abbrev,\"\",@progbits\n");
+        output.push_str(".Ldebug_abbrev0:\n");
+        for chunk in debug::abbrevs().chunks(16) {
+            output.push_str(&emit_bytes(chunk));
+        }
+        output.push_str("\t.section\t.debug_line,\"\",@progbits\n");
+        output.push_str(".Ldebug_line0:\n");
+
         output
     }
 
@@ -80,9 +125,15 @@ impl TopLevel {
         let mut output = String::new();
         
         output.push_str("\t.text\n");
+        if self.source_file.is_some() {
+            output.push_str(".Ltext0:\n");
+        }
         for func in self.functions.values() {
             output.push_str(&self.emit_func(func));
         }
+        if self.source_file.is_some() {
+            output.push_str(".Letext0:\n");
+        }
 
         output
     }
@@ -97,6 +148,10 @@ impl TopLevel {
         }
         output.push_str(&format!("\t.type\t{}, @function\n", name));
         output.push_str(&format!("{}:\n", name));
+        if self.source_file.is_some() {
+            // the prologue is at the function's declaration
+            output.push_str(&format!("\t{}\n", self.emit_insn(&Insn::Loc(func.span))));
+        }
 
         for insn in &func.body {
             let prefix = if let Insn::Label(..) = insn {
@@ -107,6 +162,9 @@ impl TopLevel {
             output.push_str(&format!("{}{}\n", prefix, self.emit_insn(insn)));
         }
 
+        if self.source_file.is_some() {
+            output.push_str(&format!(".L{}_end:\n", name));
+        }
         output.push_str(&format!("\t.size\t{}, .-{}\n\n", name, name));
 
         output
@@ -125,6 +183,18 @@ impl TopLevel {
                 output.push_str(&format!("addi\t{}, {}, {}", rd, rs1, imm)),
             Addiw(rd, rs1, imm) =>
                 output.push_str(&format!("addiw\t{}, {}, {}", rd, rs1, imm)),
+            Slli(rd, rs1, shamt) =>
+                output.push_str(&format!("slli\t{}, {}, {}", rd, rs1, shamt)),
+            Slliw(rd, rs1, shamt) =>
+                output.push_str(&format!("slliw\t{}, {}, {}", rd, rs1, shamt)),
+            Srli(rd, rs1, shamt) =>
+                output.push_str(&format!("srli\t{}, {}, {}", rd, rs1, shamt)),
+            Srliw(rd, rs1, shamt) =>
+                output.push_str(&format!("srliw\t{}, {}, {}", rd, rs1, shamt)),
+            Srai(rd, rs1, shamt) =>
+                output.push_str(&format!("srai\t{}, {}, {}", rd, rs1, shamt)),
+            Sraiw(rd, rs1, shamt) =>
+                output.push_str(&format!("sraiw\t{}, {}, {}", rd, rs1, shamt)),
             Sub(rd, rs1, rs2) =>
                 output.push_str(&format!("sub\t{}, {}, {}", rd, rs1, rs2)),
             Subw(rd, rs1, rs2) =>
@@ -133,6 +203,10 @@ impl TopLevel {
                 output.push_str(&format!("mul\t{}, {}, {}", rd, rs1, rs2)),
             Mulw(rd, rs1, rs2) =>
                 output.push_str(&format!("mulw\t{}, {}, {}", rd, rs1, rs2)),
+            Mulh(rd, rs1, rs2) =>
+                output.push_str(&format!("mulh\t{}, {}, {}", rd, rs1, rs2)),
+            Mulhu(rd, rs1, rs2) =>
+                output.push_str(&format!("mulhu\t{}, {}, {}", rd, rs1, rs2)),
             Div(rd, rs1, rs2) =>
                 output.push_str(&format!("div\t{}, {}, {}", rd, rs1, rs2)),
             Divw(rd, rs1, rs2) =>
@@ -145,6 +219,10 @@ impl TopLevel {
                 output.push_str(&format!("slt\t{}, {}, {}", rd, rs1, rs2)),
             Sgt(rd, rs1, rs2) =>
                 output.push_str(&format!("sgt\t{}, {}, {}", rd, rs1, rs2)),
+            Sltu(rd, rs1, rs2) =>
+                output.push_str(&format!("sltu\t{}, {}, {}", rd, rs1, rs2)),
+            Or(rd, rs1, rs2) =>
+                output.push_str(&format!("or\t{}, {}, {}", rd, rs1, rs2)),
             Seqz(rd, rs) =>
                 output.push_str(&format!("seqz\t{}, {}", rd, rs)),
             Snez(rd, rs) =>
@@ -161,15 +239,24 @@ impl TopLevel {
                 output.push_str(&format!("not\t{}, {}", rd, rs)),
             Call(name) =>
                 output.push_str(&format!("call\t{}", self.strtb.get(*name).unwrap())),
+            Tail(name) =>
+                output.push_str(&format!("tail\t{}", self.strtb.get(*name).unwrap())),
             Beq(rs1, rs2, label) =>
                 output.push_str(&format!("beq\t{}, {}, {}", rs1, rs2, self.emit_label_operand(label))),
             Bne(rs1, rs2, label) =>
                 output.push_str(&format!("bne\t{}, {}, {}", rs1, rs2, self.emit_label_operand(label))),
+            Blt(rs1, rs2, label) =>
+                output.push_str(&format!("blt\t{}, {}, {}", rs1, rs2, self.emit_label_operand(label))),
+            Bge(rs1, rs2, label) =>
+                output.push_str(&format!("bge\t{}, {}, {}", rs1, rs2, self.emit_label_operand(label))),
             J(label) =>
                 output.push_str(&format!("j\t{}", self.emit_label_operand(label))),
             Label(label) =>
                 output.push_str(&format!("{}:", self.emit_label_operand(label))),
             Ret => output.push_str("ret"),
+            Ecall => output.push_str("ecall"),
+            Loc(span) =>
+                output.push_str(&format!(".loc\t1 {} {}", span.line, span.column)),
             Ld(rd, base, offset) =>
                 output.push_str(&format!("ld\t{}, {}({})", rd, offset, base)),
             Lw(rd, base, offset) =>
@@ -182,23 +269,24 @@ impl TopLevel {
                 output.push_str(&format!("li\t{}, {}", rd, imm)),
             La(rd, name) =>
                 output.push_str(&format!("la\t{}, {}", rd, self.strtb.get(*name).unwrap())),
-            LoadStatic(rd, name) => {
+            LoadStatic(rd, name, offset) => {
                 let static_var = self.get_static_var(name).expect("Static variable not found");
-                let name = self.strtb.get(static_var.name).unwrap();
-                output.push_str(&format!("lui\tt5, %hi({})\n", name));
-                match static_var.data_type.size() {
-                    4 => output.push_str(&format!("\tlw\t{}, %lo({})(t5)\n", rd, name)),
-                    8 => output.push_str(&format!("\tld\t{}, %lo({})(t5)\n", rd, name)),
+                let name = static_symbol(self.strtb.get(static_var.name).unwrap(), *offset);
+                // the destination holds the address until it is overwritten
+                output.push_str(&format!("lui\t{}, %hi({})\n", rd, name));
+                match self.access_size(static_var) {
+                    4 => output.push_str(&format!("\tlw\t{}, %lo({})({})\n", rd, name, rd)),
+                    8 => output.push_str(&format!("\tld\t{}, %lo({})({})\n", rd, name, rd)),
                     _ => unreachable!(),
                 }
             },
-            StoreStatic(rs, name) => {
+            StoreStatic(rs, addr, name, offset) => {
                 let static_var = self.get_static_var(name).expect("Static variable not found");
-                let name = self.strtb.get(static_var.name).unwrap();
-                output.push_str(&format!("lui\tt5, %hi({})\n", name));
-                match static_var.data_type.size() {
-                    4 => output.push_str(&format!("\tsw\t{}, %lo({})(t5)\n", rs, name)),
-                    8 => output.push_str(&format!("\tsd\t{}, %lo({})(t5)\n", rs, name)),
+                let name = static_symbol(self.strtb.get(static_var.name).unwrap(), *offset);
+                output.push_str(&format!("lui\t{}, %hi({})\n", addr, name));
+                match self.access_size(static_var) {
+                    4 => output.push_str(&format!("\tsw\t{}, %lo({})({})\n", rs, name, addr)),
+                    8 => output.push_str(&format!("\tsd\t{}, %lo({})({})\n", rs, name, addr)),
                     _ => unreachable!(),
                 }
             },
@@ -215,7 +303,35 @@ impl TopLevel {
 }
 
 impl TopLevel {
-    fn get_static_var(&self, name: &StrDescriptor) -> Option<&StaticVar> {
-        self.data_seg.items.get(name).or_else(|| self.bss_seg.items.get(name))
+    pub(super) fn get_static_var(&self, name: &StrDescriptor) -> Option<&StaticVar> {
+        self.data_seg.items.get(name)
+            .or_else(|| self.bss_seg.items.get(name))
+            .or_else(|| self.externs.get(name))
+    }
+
+    /// Bytes a load or store of `var` moves at once, 64-bit statics take two on RV32.
+    pub(super) fn access_size(&self, var: &StaticVar) -> usize {
+        var.data_type.size().min(self.xlen.bytes())
+    }
+}
+
+fn emit_bytes(bytes: &[u8]) -> String {
+    let bytes = bytes.iter()
+        .map(|byte| format!("{:#04x}", byte))
+        .collect::<Vec<_>>();
+    format!("\t.byte\t{}\n", bytes.join(", "))
+}
+
+/// `name`, or `name+offset` into it.
+fn static_symbol(name: &str, offset: isize) -> String {
+    if offset == 0 {
+        name.to_string()
+    } else {
+        format!("{}+{}", name, offset)
     }
-}
\ No newline at end of file
+}
+
+/// `string` as a string literal for the assembler.
+fn quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,480 @@
+//! Machine code for the assembly instructions, into an ELF relocatable object.
+//! Pseudo-instructions expand as the GNU assembler does without linker relaxation:
+//! - `li` into `lui` and `addi(w)`, with further 12-bit chunks shifted in for 64-bit constants
+//! - `call` and `tail` into `auipc` and `jalr` (`R_RISCV_CALL_PLT`)
+//! - `la` into `auipc` and `addi` (`R_RISCV_PCREL_HI20`, `R_RISCV_PCREL_LO12_I`)
+//! - static loads and stores into `lui` and a `%lo` access (`R_RISCV_HI20`, `R_RISCV_LO12_I/S`)
+//! - `mv`, `not`, `neg`, `seqz`, `sgt`, ... into the instructions they stand for
+//!
+//! Branches and jumps only go to labels in the same function, they're resolved right here,
+//! so branches have to be relaxed beforehand (`relax.rs`).
+//!
+//! With `-g`, `.loc`s become rows of the line table, and the debug sections are added (`debug.rs`),
+//! with `R_RISCV_64` relocations for addresses and `R_RISCV_32` ones for offsets into other debug sections.
+
+use std::collections::HashMap;
+
+use crate::{
+    common::*,
+    elf::{
+        Object,
+        SectionKind,
+        Symbol,
+        Binding,
+        SymbolKind,
+        Reloc,
+        RelocKind,
+    },
+};
+use super::{
+    debug::{self, Item, LineSequence},
+    TopLevel,
+    Function,
+    StaticVar,
+    Insn,
+    Register,
+    LabelOperand,
+};
+
+impl TopLevel {
+    pub fn assemble(&self) -> Object {
+        let mut obj = Object::default();
+
+        // sorted by name, so the same source gives the same object
+        let mut data = self.data_seg.items.values().collect::<Vec<_>>();
+        data.sort_by_key(|var| self.name(var.name));
+        for var in data {
+            let section = obj.section(SectionKind::Data);
+            let offset = obj.sections[section].align_to(var.data_type.align());
+            let bytes = match var.initializer {
+                InitVal::Const(Constant::Int(i)) => i.to_le_bytes().to_vec(),
+                InitVal::Const(Constant::Long(l)) => l.to_le_bytes().to_vec(),
+                // these should be put in .bss segment
+                InitVal::Tentative | InitVal::None => unreachable!(),
+            };
+            obj.sections[section].data.extend(bytes);
+            self.define_var(&mut obj, var, section, offset);
+        }
+
+        let mut bss = self.bss_seg.items.values().collect::<Vec<_>>();
+        bss.sort_by_key(|var| self.name(var.name));
+        for var in bss {
+            let section = obj.section(SectionKind::Bss);
+            let offset = obj.sections[section].align_to(var.data_type.align());
+            obj.sections[section].data.resize(offset + var.data_type.size(), 0);
+            self.define_var(&mut obj, var, section, offset);
+        }
+
+        let mut functions = self.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|func| self.name(func.name));
+        let mut encoder = Encoder {
+            top: self,
+            text: obj.section(SectionKind::Text),
+            obj: &mut obj,
+            labels: HashMap::new(),
+            fixups: vec![],
+            next_pcrel: 0,
+            lines: vec![],
+        };
+        encoder.obj.sections[encoder.text].align = 4;
+        for func in functions {
+            encoder.function(func);
+        }
+        let lines = encoder.lines;
+
+        if let Some(file) = &self.source_file {
+            let abbrev = obj.section(SectionKind::DebugAbbrev);
+            obj.sections[abbrev].data = debug::abbrevs();
+            let info = self.debug_info(file);
+            self.debug_section(&mut obj, SectionKind::DebugInfo, info);
+            self.debug_section(&mut obj, SectionKind::DebugLine, debug::line_program(file, &lines));
+        }
+
+        obj
+    }
+
+    fn debug_section(&self, obj: &mut Object, kind: SectionKind, items: Vec<Item>) {
+        let section = obj.section(kind);
+        let text = obj.section(SectionKind::Text);
+        for item in items {
+            let offset = obj.sections[section].data.len() as u64;
+            let (bytes, reloc) = match item {
+                Item::Bytes(bytes) => (bytes, None),
+                Item::String(string) => ([string.as_bytes(), &[0]].concat(), None),
+                Item::Addr(name) => (vec![0; 8], Some((RelocKind::Abs64, obj.symbol(&name)))),
+                Item::TextStart => (vec![0; 8], Some((RelocKind::Abs64, obj.section_symbol(text)))),
+                Item::TextSize => ((obj.sections[text].data.len() as u32).to_le_bytes().to_vec(), None),
+                Item::FuncSize(name) => {
+                    let symbol = obj.symbol(&name);
+                    ((obj.symbols[symbol].size as u32).to_le_bytes().to_vec(), None)
+                },
+                Item::SectionStart(kind) => {
+                    let start = obj.section(kind);
+                    (vec![0; 4], Some((RelocKind::Abs32, obj.section_symbol(start))))
+                },
+            };
+            if let Some((kind, symbol)) = reloc {
+                obj.sections[section].relocs.push(Reloc { offset, symbol, kind, addend: 0 });
+            }
+            obj.sections[section].data.extend(bytes);
+        }
+    }
+
+    fn define_var(&self, obj: &mut Object, var: &StaticVar, section: usize, offset: usize) {
+        define(obj, Symbol {
+            name: self.name(var.name).to_string(),
+            section: Some(section),
+            value: offset as u64,
+            size: var.data_type.size() as u64,
+            binding: binding(var.linkage),
+            kind: SymbolKind::Object,
+        });
+    }
+
+    pub(super) fn name(&self, name: StrDescriptor) -> &str {
+        self.strtb.get(name).unwrap()
+    }
+}
+
+/// Sets the definition of `symbol`, which may have been referenced already.
+fn define(obj: &mut Object, symbol: Symbol) -> usize {
+    let idx = obj.symbol(&symbol.name);
+    obj.symbols[idx] = symbol;
+    idx
+}
+
+fn binding(linkage: Linkage) -> Binding {
+    match linkage {
+        Linkage::Internal => Binding::Local,
+        Linkage::External => Binding::Global,
+    }
+}
+
+struct Encoder<'a> {
+    top: &'a TopLevel,
+    obj: &'a mut Object,
+    text: usize,
+    /// offsets of the labels in the current function
+    labels: HashMap<LabelOperand, usize>,
+    /// branches and jumps to patch once their labels are known
+    fixups: Vec<(usize, LabelOperand)>,
+    /// `.Lpcrel_hi<n>` labels of `auipc`s, which `%pcrel_lo`s refer to
+    next_pcrel: usize,
+    /// the line table of each function so far, with `-g`
+    lines: Vec<LineSequence>,
+}
+
+impl Encoder<'_> {
+    fn function(&mut self, func: &Function) {
+        let start = self.offset();
+        // the prologue is at the function's declaration
+        let mut rows = vec![(0, func.span)];
+        for insn in func.body.iter() {
+            if let Insn::Loc(span) = *insn {
+                rows.push((self.offset() - start, span));
+            }
+            self.insn(insn);
+        }
+        if self.top.source_file.is_some() {
+            self.lines.push(LineSequence {
+                func: self.top.name(func.name).to_string(),
+                size: self.offset() - start,
+                rows,
+            });
+        }
+
+        for (at, label) in std::mem::take(&mut self.fixups) {
+            let target = *self.labels.get(&label)
+                .expect("Internal error: Branch label not found in function");
+            let offset = target as i64 - at as i64;
+            let kind = match self.word_at(at) & 0x7f {
+                BRANCH => RelocKind::Branch,
+                JAL => RelocKind::Jal,
+                _ => unreachable!(),
+            };
+            let in_range = kind.apply(&mut self.obj.sections[self.text].data[at..], offset);
+            assert!(in_range, "Internal error: Branch offset {} out of range", offset);
+        }
+        self.labels.clear();
+
+        define(self.obj, Symbol {
+            name: self.top.name(func.name).to_string(),
+            section: Some(self.text),
+            value: start as u64,
+            size: (self.offset() - start) as u64,
+            binding: binding(func.linkage),
+            kind: SymbolKind::Func,
+        });
+    }
+
+    fn insn(&mut self, insn: &Insn) {
+        use Insn::*;
+        use Register::{Zero, Ra, T1};
+
+        match *insn {
+            Add(rd, rs1, rs2) => self.emit(r_type(OP, 0, 0, rd, rs1, rs2)),
+            Sub(rd, rs1, rs2) => self.emit(r_type(OP, 0, 0x20, rd, rs1, rs2)),
+            Slt(rd, rs1, rs2) => self.emit(r_type(OP, 2, 0, rd, rs1, rs2)),
+            Sgt(rd, rs1, rs2) => self.emit(r_type(OP, 2, 0, rd, rs2, rs1)),
+            Sltu(rd, rs1, rs2) => self.emit(r_type(OP, 3, 0, rd, rs1, rs2)),
+            Or(rd, rs1, rs2) => self.emit(r_type(OP, 6, 0, rd, rs1, rs2)),
+            Mul(rd, rs1, rs2) => self.emit(r_type(OP, 0, 1, rd, rs1, rs2)),
+            Mulh(rd, rs1, rs2) => self.emit(r_type(OP, 1, 1, rd, rs1, rs2)),
+            Mulhu(rd, rs1, rs2) => self.emit(r_type(OP, 3, 1, rd, rs1, rs2)),
+            Div(rd, rs1, rs2) => self.emit(r_type(OP, 4, 1, rd, rs1, rs2)),
+            Rem(rd, rs1, rs2) => self.emit(r_type(OP, 6, 1, rd, rs1, rs2)),
+            Addw(rd, rs1, rs2) => self.emit(r_type(OP_32, 0, 0, rd, rs1, rs2)),
+            Subw(rd, rs1, rs2) => self.emit(r_type(OP_32, 0, 0x20, rd, rs1, rs2)),
+            Mulw(rd, rs1, rs2) => self.emit(r_type(OP_32, 0, 1, rd, rs1, rs2)),
+            Divw(rd, rs1, rs2) => self.emit(r_type(OP_32, 4, 1, rd, rs1, rs2)),
+            Remw(rd, rs1, rs2) => self.emit(r_type(OP_32, 6, 1, rd, rs1, rs2)),
+            Neg(rd, rs) => self.emit(r_type(OP, 0, 0x20, rd, Zero, rs)),
+            Negw(rd, rs) => self.emit(r_type(OP_32, 0, 0x20, rd, Zero, rs)),
+            // sltu rd, zero, rs
+            Snez(rd, rs) => self.emit(r_type(OP, 3, 0, rd, Zero, rs)),
+
+            Addi(rd, rs, imm) => self.emit(i_type(OP_IMM, 0, rd, rs, imm)),
+            Mv(rd, rs) => self.emit(i_type(OP_IMM, 0, rd, rs, 0)),
+            // sltiu rd, rs, 1
+            Seqz(rd, rs) => self.emit(i_type(OP_IMM, 3, rd, rs, 1)),
+            // xori rd, rs, -1
+            Not(rd, rs) => self.emit(i_type(OP_IMM, 4, rd, rs, -1)),
+            Slli(rd, rs, shamt) => self.emit(i_type(OP_IMM, 1, rd, rs, shamt as i64)),
+            Srli(rd, rs, shamt) => self.emit(i_type(OP_IMM, 5, rd, rs, shamt as i64)),
+            Srai(rd, rs, shamt) => self.emit(i_type(OP_IMM, 5, rd, rs, 0x400 | shamt as i64)),
+            Addiw(rd, rs, imm) => self.emit(i_type(OP_IMM_32, 0, rd, rs, imm as i64)),
+            Sextw(rd, rs) => self.emit(i_type(OP_IMM_32, 0, rd, rs, 0)),
+            Slliw(rd, rs, shamt) => self.emit(i_type(OP_IMM_32, 1, rd, rs, shamt as i64)),
+            Srliw(rd, rs, shamt) => self.emit(i_type(OP_IMM_32, 5, rd, rs, shamt as i64)),
+            Sraiw(rd, rs, shamt) => self.emit(i_type(OP_IMM_32, 5, rd, rs, 0x400 | shamt as i64)),
+
+            Ld(rd, base, offset) => self.emit(i_type(LOAD, 3, rd, base, offset as i64)),
+            Lw(rd, base, offset) => self.emit(i_type(LOAD, 2, rd, base, offset as i64)),
+            Sd(rs, base, offset) => self.emit(s_type(3, base, rs, offset as i64)),
+            Sw(rs, base, offset) => self.emit(s_type(2, base, rs, offset as i64)),
+
+            Beq(rs1, rs2, label) => self.branch(b_type(0, rs1, rs2), label),
+            Bne(rs1, rs2, label) => self.branch(b_type(1, rs1, rs2), label),
+            Blt(rs1, rs2, label) => self.branch(b_type(4, rs1, rs2), label),
+            Bge(rs1, rs2, label) => self.branch(b_type(5, rs1, rs2), label),
+            // jal zero, label
+            J(label) => self.branch(JAL | x(Zero) << 7, label),
+            Label(label) => {
+                self.labels.insert(label, self.offset());
+            },
+            // jalr zero, 0(ra)
+            Ret => self.emit(i_type(JALR, 0, Zero, Ra, 0)),
+            Ecall => self.emit(SYSTEM),
+            Loc(..) => {},
+            Call(name) => {
+                self.reloc(RelocKind::CallPlt, self.top.name(name));
+                self.emit(u_type(AUIPC, Ra, 0));
+                self.emit(i_type(JALR, 0, Ra, Ra, 0));
+            },
+            Tail(name) => {
+                self.reloc(RelocKind::CallPlt, self.top.name(name));
+                self.emit(u_type(AUIPC, T1, 0));
+                self.emit(i_type(JALR, 0, Zero, T1, 0));
+            },
+
+            Li(rd, imm) => {
+                for word in li(rd, imm) {
+                    self.emit(word);
+                }
+            },
+            La(rd, name) => {
+                let hi = format!(".Lpcrel_hi{}", self.next_pcrel);
+                self.next_pcrel += 1;
+                define(self.obj, Symbol {
+                    name: hi.clone(),
+                    section: Some(self.text),
+                    value: self.offset() as u64,
+                    size: 0,
+                    binding: Binding::Local,
+                    kind: SymbolKind::NoType,
+                });
+                self.reloc(RelocKind::PcrelHi20, self.top.name(name));
+                self.emit(u_type(AUIPC, rd, 0));
+                self.reloc(RelocKind::PcrelLo12I, &hi);
+                self.emit(i_type(OP_IMM, 0, rd, rd, 0));
+            },
+            LoadStatic(rd, name, offset) => {
+                let var = self.top.get_static_var(&name).expect("Static variable not found");
+                let name = self.top.name(var.name);
+                let funct3 = match self.top.access_size(var) {
+                    4 => 2,
+                    8 => 3,
+                    _ => unreachable!(),
+                };
+                // the destination holds the address until it is overwritten
+                self.reloc_with(RelocKind::Hi20, name, offset as i64);
+                self.emit(u_type(LUI, rd, 0));
+                self.reloc_with(RelocKind::Lo12I, name, offset as i64);
+                self.emit(i_type(LOAD, funct3, rd, rd, 0));
+            },
+            StoreStatic(rs, addr, name, offset) => {
+                let var = self.top.get_static_var(&name).expect("Static variable not found");
+                let name = self.top.name(var.name);
+                let funct3 = match self.top.access_size(var) {
+                    4 => 2,
+                    8 => 3,
+                    _ => unreachable!(),
+                };
+                self.reloc_with(RelocKind::Hi20, name, offset as i64);
+                self.emit(u_type(LUI, addr, 0));
+                self.reloc_with(RelocKind::Lo12S, name, offset as i64);
+                self.emit(s_type(funct3, addr, rs, 0));
+            },
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.obj.sections[self.text].data.len()
+    }
+
+    fn emit(&mut self, word: u32) {
+        self.obj.sections[self.text].data.extend(word.to_le_bytes());
+    }
+
+    /// Emits `word` with the offset to `label` filled in later.
+    fn branch(&mut self, word: u32, label: LabelOperand) {
+        self.fixups.push((self.offset(), label));
+        self.emit(word);
+    }
+
+    /// Relocates the next instruction against `symbol`.
+    fn reloc(&mut self, kind: RelocKind, symbol: &str) {
+        self.reloc_with(kind, symbol, 0);
+    }
+
+    fn reloc_with(&mut self, kind: RelocKind, symbol: &str, addend: i64) {
+        let reloc = Reloc {
+            offset: self.offset() as u64,
+            symbol: self.obj.symbol(symbol),
+            kind,
+            addend,
+        };
+        self.obj.sections[self.text].relocs.push(reloc);
+    }
+
+    fn word_at(&self, at: usize) -> u32 {
+        let bytes = &self.obj.sections[self.text].data[at..at + 4];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+const LOAD: u32 = 0x03;
+const OP_IMM: u32 = 0x13;
+const AUIPC: u32 = 0x17;
+const OP_IMM_32: u32 = 0x1b;
+const STORE: u32 = 0x23;
+const OP: u32 = 0x33;
+const LUI: u32 = 0x37;
+const OP_32: u32 = 0x3b;
+const BRANCH: u32 = 0x63;
+const JALR: u32 = 0x67;
+const JAL: u32 = 0x6f;
+/// `ecall`, the whole instruction
+const SYSTEM: u32 = 0x73;
+
+/// Register number, the variants are in the order of `x0` to `x31`.
+fn x(reg: Register) -> u32 {
+    reg as u32
+}
+
+fn r_type(opcode: u32, funct3: u32, funct7: u32, rd: Register, rs1: Register, rs2: Register) -> u32 {
+    funct7 << 25 | x(rs2) << 20 | x(rs1) << 15 | funct3 << 12 | x(rd) << 7 | opcode
+}
+
+fn i_type(opcode: u32, funct3: u32, rd: Register, rs1: Register, imm: i64) -> u32 {
+    assert!((-2048..4096).contains(&imm), "Internal error: Immediate {} out of range", imm);
+    (imm as u32 & 0xfff) << 20 | x(rs1) << 15 | funct3 << 12 | x(rd) << 7 | opcode
+}
+
+fn s_type(funct3: u32, base: Register, src: Register, imm: i64) -> u32 {
+    assert!((-2048..2048).contains(&imm), "Internal error: Offset {} out of range", imm);
+    let imm = imm as u32 & 0xfff;
+    (imm >> 5) << 25 | x(src) << 20 | x(base) << 15 | funct3 << 12 | (imm & 0x1f) << 7 | STORE
+}
+
+/// A branch without its offset.
+fn b_type(funct3: u32, rs1: Register, rs2: Register) -> u32 {
+    x(rs2) << 20 | x(rs1) << 15 | funct3 << 12 | BRANCH
+}
+
+fn u_type(opcode: u32, rd: Register, imm20: u32) -> u32 {
+    imm20 << 12 | x(rd) << 7 | opcode
+}
+
+/// `li rd, imm`: `lui` and `addiw` for 32-bit constants,
+/// otherwise the upper bits first, then shifted left and the lowest 12 bits added.
+fn li(rd: Register, imm: i64) -> Vec<u32> {
+    let lo = imm << 52 >> 52;
+    if i32::try_from(imm).is_ok() {
+        let hi20 = ((imm + 0x800) >> 12) as u32 & 0xfffff;
+        return match (hi20, lo) {
+            (0, _) => vec![i_type(OP_IMM, 0, rd, Register::Zero, lo)],
+            (_, 0) => vec![u_type(LUI, rd, hi20)],
+            _ => vec![u_type(LUI, rd, hi20), i_type(OP_IMM_32, 0, rd, rd, lo)],
+        };
+    }
+
+    let hi = imm.wrapping_sub(lo) >> 12;
+    let shift = hi.trailing_zeros();
+    let mut words = li(rd, hi >> shift);
+    words.push(i_type(OP_IMM, 1, rd, rd, shift as i64 + 12));
+    if lo != 0 {
+        words.push(i_type(OP_IMM, 0, rd, rd, lo));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Register::*;
+
+    #[test]
+    fn test_encode() {
+        // from `llvm-mc -triple=riscv64 -mattr=+m -show-encoding`
+        assert_eq!(i_type(OP_IMM, 0, A0, A0, 1), 0x00150513);
+        assert_eq!(r_type(OP_32, 4, 1, A0, A1, A2), 0x02c5c53b);
+        assert_eq!(s_type(3, Sp, Ra, -8), 0xfe113c23);
+        assert_eq!(i_type(OP_IMM_32, 5, T0, T1, 0x400 | 3), 0x4033529b);
+        let mut bytes = b_type(5, A0, A1).to_le_bytes();
+        assert!(RelocKind::Branch.apply(&mut bytes, -16));
+        assert_eq!(u32::from_le_bytes(bytes), 0xfeb558e3);
+        let mut bytes = JAL.to_le_bytes();
+        assert!(RelocKind::Jal.apply(&mut bytes, 2048));
+        assert_eq!(u32::from_le_bytes(bytes), 0x0010006f);
+        assert!(!RelocKind::Branch.apply(&mut bytes, 4096));
+        assert_eq!(u_type(LUI, A5, 0x12345), 0x123457b7);
+    }
+
+    #[test]
+    fn test_li() {
+        // what the sequence leaves in the register
+        fn run(words: &[u32]) -> i64 {
+            let mut value = 0i64;
+            for &word in words {
+                let imm = (word as i32 >> 20) as i64;
+                value = match (word & 0x7f, word >> 12 & 0x7) {
+                    (LUI, _) => (word & 0xfffff000) as i32 as i64,
+                    (OP_IMM, 0) if word >> 15 & 0x1f == 0 => imm,
+                    (OP_IMM, 0) => value.wrapping_add(imm),
+                    (OP_IMM, 1) => value << (imm & 0x3f),
+                    (OP_IMM_32, 0) => value.wrapping_add(imm) as i32 as i64,
+                    _ => unreachable!(),
+                };
+            }
+            value
+        }
+
+        for imm in [0, 1, -1, 2047, -2048, 2048, 4096, 0x7fffffff, -0x80000000, 0x7ffff800,
+            0x80000000, 0xffffffff, 0x123456789abcdef0, i64::MAX, i64::MIN, -0x123456789] {
+            let words = li(A0, imm);
+            assert!(words.len() <= 8);
+            assert_eq!(run(&words), imm, "li {:#x}", imm);
+        }
+    }
+}
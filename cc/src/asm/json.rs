@@ -0,0 +1,122 @@
+//! JSON serialization of the assembly, names resolved through the string pool.
+//! Instructions are `{"op": mnemonic, "operands": [...]}` as in the LIR,
+//! registers are written by their ABI names.
+
+use std::collections::HashMap;
+
+use crate::common::*;
+use super::{
+    TopLevel,
+    Insn,
+    Function,
+    StaticVar,
+    DebugVar,
+    VarLocation,
+    LabelOperand,
+    Register,
+};
+
+impl TopLevel {
+    pub fn to_json(&self) -> Json {
+        let mut functions = self.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|func| self.strtb.get(func.name));
+        let static_vars = |items: &HashMap<StrDescriptor, StaticVar>| {
+            let mut vars = items.values().collect::<Vec<_>>();
+            vars.sort_by_key(|var| self.strtb.get(var.name));
+            Json::Array(vars.into_iter().map(|var| self.json_static_var(var)).collect())
+        };
+
+        Json::object([
+            ("xlen", self.xlen.bits().into()),
+            ("source_file", self.source_file.as_ref().into()),
+            ("data", static_vars(&self.data_seg.items)),
+            ("bss", static_vars(&self.bss_seg.items)),
+            ("externs", static_vars(&self.externs)),
+            ("functions", Json::Array(functions.into_iter().map(|func| self.json_function(func)).collect())),
+        ])
+    }
+
+    fn json_static_var(&self, var: &StaticVar) -> Json {
+        Json::object([
+            ("name", self.name(var.name).into()),
+            ("data_type", var.data_type.into()),
+            ("linkage", var.linkage.into()),
+            ("initializer", var.initializer.into()),
+            ("span", var.span.into()),
+        ])
+    }
+
+    fn json_function(&self, func: &Function) -> Json {
+        Json::object([
+            ("name", self.name(func.name).into()),
+            ("type", (&func.func_type).into()),
+            ("linkage", func.linkage.into()),
+            ("vars", Json::Array(func.vars.iter().map(|var| self.json_debug_var(var)).collect())),
+            ("body", Json::Array(func.body.iter().map(|insn| self.json_insn(insn)).collect())),
+            ("span", func.span.into()),
+        ])
+    }
+
+    fn json_debug_var(&self, var: &DebugVar) -> Json {
+        Json::object([
+            ("name", self.name(var.name).into()),
+            ("data_type", var.data_type.into()),
+            ("is_param", var.is_param.into()),
+            ("location", var.location.map_or(Json::Null, |location| match location {
+                VarLocation::Reg(register) => Json::variant("reg", [("reg", reg(register))]),
+                VarLocation::Mem(base, offset) => Json::variant("mem", [
+                    ("base", reg(base)),
+                    ("offset", offset.into()),
+                ]),
+            })),
+            ("span", var.span.into()),
+        ])
+    }
+
+    fn json_insn(&self, insn: &Insn) -> Json {
+        let operands = match *insn {
+            Insn::Add(rd, rs1, rs2) | Insn::Addw(rd, rs1, rs2) |
+            Insn::Sub(rd, rs1, rs2) | Insn::Subw(rd, rs1, rs2) |
+            Insn::Mul(rd, rs1, rs2) | Insn::Mulw(rd, rs1, rs2) |
+            Insn::Mulh(rd, rs1, rs2) | Insn::Mulhu(rd, rs1, rs2) |
+            Insn::Div(rd, rs1, rs2) | Insn::Divw(rd, rs1, rs2) |
+            Insn::Rem(rd, rs1, rs2) | Insn::Remw(rd, rs1, rs2) |
+            Insn::Slt(rd, rs1, rs2) | Insn::Sgt(rd, rs1, rs2) |
+            Insn::Sltu(rd, rs1, rs2) | Insn::Or(rd, rs1, rs2) => vec![reg(rd), reg(rs1), reg(rs2)],
+            Insn::Addi(rd, rs1, imm) => vec![reg(rd), reg(rs1), imm.into()],
+            Insn::Addiw(rd, rs1, imm) => vec![reg(rd), reg(rs1), imm.into()],
+            Insn::Slli(rd, rs1, shamt) | Insn::Slliw(rd, rs1, shamt) |
+            Insn::Srli(rd, rs1, shamt) | Insn::Srliw(rd, rs1, shamt) |
+            Insn::Srai(rd, rs1, shamt) | Insn::Sraiw(rd, rs1, shamt) => vec![reg(rd), reg(rs1), shamt.into()],
+            Insn::Neg(rd, rs) | Insn::Negw(rd, rs) | Insn::Not(rd, rs) |
+            Insn::Seqz(rd, rs) | Insn::Snez(rd, rs) | Insn::Sextw(rd, rs) | Insn::Mv(rd, rs) => vec![reg(rd), reg(rs)],
+            Insn::Call(name) | Insn::Tail(name) => vec![self.name(name).into()],
+            Insn::Beq(rs1, rs2, label) | Insn::Bne(rs1, rs2, label) |
+            Insn::Blt(rs1, rs2, label) | Insn::Bge(rs1, rs2, label) => vec![reg(rs1), reg(rs2), self.json_label(label)],
+            Insn::J(label) | Insn::Label(label) => vec![self.json_label(label)],
+            Insn::Ret | Insn::Ecall => vec![],
+            Insn::Loc(span) => vec![span.into()],
+            Insn::Ld(rd, base, offset) | Insn::Lw(rd, base, offset) |
+            Insn::Sd(rd, base, offset) | Insn::Sw(rd, base, offset) => vec![reg(rd), reg(base), offset.into()],
+            Insn::Li(rd, imm) => vec![reg(rd), imm.into()],
+            Insn::La(rd, name) => vec![reg(rd), self.name(name).into()],
+            Insn::LoadStatic(rd, name, offset) => vec![reg(rd), self.name(name).into(), offset.into()],
+            Insn::StoreStatic(rs, scratch, name, offset) => vec![reg(rs), reg(scratch), self.name(name).into(), offset.into()],
+        };
+        Json::object([
+            ("op", Json::unit(insn)),
+            ("operands", Json::Array(operands)),
+        ])
+    }
+
+    fn json_label(&self, label: LabelOperand) -> Json {
+        match label {
+            LabelOperand::AutoGen(id) => Json::variant("auto_gen", [("id", id.into())]),
+            LabelOperand::Named(name) => Json::variant("named", [("name", self.name(name).into())]),
+        }
+    }
+}
+
+fn reg(register: Register) -> Json {
+    register.to_string().into()
+}
@@ -0,0 +1,117 @@
+//! Block ordering for fall-through, ahead of the peephole pass.
+//! Blocks falling through to the next label stay glued together as chains,
+//! every chain ends with `j`, `ret` or `tail` and can go anywhere after the entry chain.
+//! A chain ending with `j .L` is followed by the chain starting at `.L` if that one's still free,
+//! so the jump goes away as a jump to the next label:
+//! e.g.    j   .L2             ->      .L2:
+//!         .L1:                ->      li  a0, 2
+//!         ret                 ->      .L1:
+//!         .L2:                ->      ret
+//!         li  a0, 2
+//!         j   .L1
+//! (jumps to the next label already dropped by the peephole pass.)
+//! Otherwise the original order is kept.
+//! `.loc`s right before a label go along with it.
+
+use std::collections::HashMap;
+
+use super::{
+    Insn,
+    LabelOperand,
+};
+
+pub(super) fn order_blocks(body: Vec<Insn>) -> Vec<Insn> {
+    let mut chains = chains(body);
+    // a chain running off the end of the function has to stay last
+    let pinned = match chains.last() {
+        Some(chain) if chains.len() > 1 && !last_insn(chain).is_some_and(ends_chain) => chains.pop(),
+        _ => None,
+    };
+
+    let heads = chains.iter()
+        .enumerate()
+        .flat_map(|(idx, chain)| chain.iter()
+            .filter(|insn| !matches!(insn, Insn::Loc(..)))
+            .map_while(move |insn| match insn {
+                Insn::Label(label) => Some((*label, idx)),
+                _ => None,
+            }))
+        .collect::<HashMap<LabelOperand, usize>>();
+
+    let mut placed = vec![false; chains.len()];
+    let mut order = vec![];
+    let mut next = (!chains.is_empty()).then_some(0);
+    while let Some(cur) = next {
+        placed[cur] = true;
+        order.push(cur);
+        next = match last_insn(&chains[cur]) {
+            Some(Insn::J(target)) => heads.get(target).copied().filter(|&idx| !placed[idx]),
+            _ => None,
+        }.or_else(|| (0..chains.len()).find(|&idx| !placed[idx]));
+    }
+
+    let mut chains = chains.into_iter().map(Some).collect::<Vec<_>>();
+    order.into_iter()
+        .flat_map(|idx| chains[idx].take().unwrap())
+        .chain(pinned.into_iter().flatten())
+        .collect()
+}
+
+/// Splits `body` at labels following `j`, `ret` or `tail`, which nothing falls through.
+fn chains(body: Vec<Insn>) -> Vec<Vec<Insn>> {
+    let mut chains = vec![];
+    let mut chain: Vec<Insn> = vec![];
+    for insn in body {
+        if matches!(insn, Insn::Label(..)) && last_insn(&chain).is_some_and(ends_chain) {
+            let locs = chain.iter().rev().take_while(|insn| matches!(insn, Insn::Loc(..))).count();
+            let next = chain.split_off(chain.len() - locs);
+            chains.push(std::mem::replace(&mut chain, next));
+        }
+        chain.push(insn);
+    }
+    if !chain.is_empty() {
+        chains.push(chain);
+    }
+    chains
+}
+
+fn last_insn(chain: &[Insn]) -> Option<&Insn> {
+    chain.iter().rev().find(|insn| !matches!(insn, Insn::Loc(..)))
+}
+
+fn ends_chain(insn: &Insn) -> bool {
+    matches!(insn, Insn::J(..) | Insn::Ret | Insn::Tail(..))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::Register::*;
+
+    #[test]
+    fn test_order_blocks() {
+        let label = LabelOperand::AutoGen;
+        let body = vec![
+            Insn::Li(A0, 0),
+            Insn::J(label(2)),
+            Insn::Label(label(1)),
+            Insn::Li(A0, 1),
+            Insn::Ret,
+            Insn::Label(label(2)),
+            Insn::Beq(A0, Zero, label(1)),
+            Insn::Li(A0, 2),
+            Insn::J(label(1)),
+        ];
+        assert_eq!(order_blocks(body), vec![
+            Insn::Li(A0, 0),
+            Insn::J(label(2)),
+            Insn::Label(label(2)),
+            Insn::Beq(A0, Zero, label(1)),
+            Insn::Li(A0, 2),
+            Insn::J(label(1)),
+            Insn::Label(label(1)),
+            Insn::Li(A0, 1),
+            Insn::Ret,
+        ]);
+    }
+}
@@ -4,6 +4,12 @@
 mod riscv;
 mod codegen;
 mod emit;
+mod peephole;
+mod layout;
+mod relax;
+mod encode;
+mod debug;
+mod json;
 
 use std::marker::PhantomData;
 use crate::common::*;
@@ -13,6 +19,8 @@ use riscv::{
     Insn,
     Function,
     StaticVar,
+    DebugVar,
+    VarLocation,
     BssSegment,
     DataSegment,
     LabelOperand,
@@ -20,6 +28,9 @@ use riscv::{
 
 pub use riscv::{
     Register,
+    Xlen,
+    DebugVar as AsmDebugVar,
+    VarLocation as AsmVarLocation,
     TopLevel as AsmTopLevel,
     Function as AsmFunction,
     StaticVar as AsmStaticVar,
@@ -40,6 +51,10 @@ pub struct FuncContext {
     pub name: StrDescriptor,
     pub callee_saved: Vec<(Register, isize)>,
     pub frame_size: usize,
+    /// whether `s0` is set up as the frame pointer, it's saved first if so
+    pub frame_pointer: bool,
+    /// callee-saved registers are saved in slots this wide
+    pub xlen: Xlen,
 }
 
 
@@ -65,10 +80,14 @@ mod tests {
     use super::*;
 
     fn tac2asm(tac: TacTopLevel) -> AsmTopLevel {
+        tac2asm_with(tac, false)
+    }
+
+    fn tac2asm_with(tac: TacTopLevel, omit_frame_pointer: bool) -> AsmTopLevel {
         let mut lir_codegen = LirCodeGen::new();
         let (lir, lir_regalloc) = lir_codegen.parse(tac);
         let (lir, lir_spill) = lir_regalloc.alloc(lir);
-        let (lir, lir_canonic) = lir_spill.spill(lir);
+        let (lir, lir_canonic) = lir_spill.spill_with(lir, omit_frame_pointer);
         let lir = lir_canonic.canonic(lir);
 
         let mut asm_codegen = CodeGen::new();
@@ -222,4 +241,190 @@ mod tests {
             2,
         );
     }
-}
\ No newline at end of file
+
+    fn gen_asm(path: &str, omit_frame_pointer: bool) -> AsmTopLevel {
+        let input = read_to_string(path).unwrap();
+        let (tokens, strtb) = Lexer::new(input).lex().unwrap();
+        let ast = AstParser::new(tokens, strtb).parse_prog().unwrap();
+        let hir = HirParser::new().parse(ast).unwrap();
+        let (tac, _) = TacCodeGen::new().parse(hir);
+        tac2asm_with(tac, omit_frame_pointer)
+    }
+
+    fn body(asm: &AsmTopLevel, name: &str) -> Vec<Insn> {
+        asm.functions.values()
+            .find(|func| asm.strtb.get(func.name).unwrap() == name)
+            .unwrap()
+            .body
+            .clone()
+    }
+
+    #[test]
+    fn test_frame_layout() {
+        let gen_asm = |omit_frame_pointer| gen_asm("../testprogs/func.c", omit_frame_pointer);
+        let uses = |body: &[Insn], reg: Register| body.iter().any(|insn| match *insn {
+            Insn::Sd(src, base, _) | Insn::Ld(src, base, _) => src == reg || base == reg,
+            Insn::Addi(rd, rs, _) => rd == reg || rs == reg,
+            _ => false,
+        });
+
+        // leaf functions don't save ra
+        let asm = gen_asm(false);
+        assert!(!uses(&body(&asm, "another"), Register::Ra));
+        assert!(uses(&body(&asm, "another"), Register::S0));
+        assert!(uses(&body(&asm, "yet_another"), Register::Ra));
+
+        // no s0 at all, and no frame for a leaf function
+        let asm = gen_asm(true);
+        for func in asm.functions.values() {
+            assert!(!uses(&func.body, Register::S0));
+        }
+        assert_eq!(body(&asm, "another").last(), Some(&Insn::Ret));
+        assert!(!uses(&body(&asm, "another"), Register::Sp));
+        assert!(uses(&body(&asm, "call_huge"), Register::Ra));
+    }
+
+    #[test]
+    fn test_large_frame() {
+        use crate::driver::{compile, compile_object, Options};
+
+        // the slots past 2 KiB below s0 are reached through the scratch register,
+        // and the encoder takes every offset left
+        let source = read_to_string("../testprogs/large_frame.c").unwrap();
+        let options = Options::default();
+        let asm = compile(source.clone(), &options).unwrap();
+        assert!(asm.contains("\tadd\tt6, t6, s0\n"));
+        assert!(asm.contains("\tadd\tsp, sp, t6\n"));
+        assert!(compile_object(source, &options).is_ok());
+    }
+
+    #[test]
+    fn test_large_frame_omit_frame_pointer() {
+        use crate::driver::{compile, compile_object, Options};
+
+        // the frame is addressed off the adjusted sp, so the scratch is based on sp instead of s0
+        let source = read_to_string("../testprogs/large_frame.c").unwrap();
+        let options = Options { omit_frame_pointer: true, ..Default::default() };
+        let asm = compile(source.clone(), &options).unwrap();
+        assert!(!asm.contains("\tadd\tt6, t6, s0\n"));
+        assert!(asm.contains("\tadd\tt6, t6, sp\n"));
+        assert!(asm.contains("\tadd\tsp, sp, t6\n"));
+        assert!(compile_object(source, &options).is_ok());
+    }
+
+    #[test]
+    fn test_tail_call() {
+        let asm = gen_asm("../testprogs/tail_call.c", false);
+        let calls = |body: &[Insn]| body.iter()
+            .filter(|insn| matches!(insn, Insn::Call(..) | Insn::Tail(..)))
+            .copied()
+            .collect::<Vec<_>>();
+
+        // self-recursion becomes a loop
+        assert!(calls(&body(&asm, "count")).is_empty());
+        assert!(body(&asm, "count").iter().any(|insn| matches!(insn, Insn::J(..))));
+        // the stack argument fits in our own incoming area
+        assert!(matches!(calls(&body(&asm, "odd"))[..], [Insn::Tail(..)]));
+        assert!(matches!(calls(&body(&asm, "forward"))[..], [Insn::Tail(..)]));
+        // there's no incoming area for the stack arguments
+        assert!(matches!(calls(&body(&asm, "widen"))[..], [Insn::Call(..)]));
+    }
+
+    #[test]
+    fn test_branch_fusion() {
+        let asm = gen_asm("../testprogs/control_flow.c", false);
+        let main = body(&asm, "main");
+        // comparisons feed the branches directly
+        assert!(main.iter().any(|insn| matches!(insn, Insn::Bge(..))));
+        assert!(!main.iter().any(|insn| matches!(insn, Insn::Slt(..) | Insn::Sgt(..) | Insn::Seqz(..))));
+    }
+
+    #[test]
+    fn test_assemble() {
+        use crate::elf::{SectionKind, Binding, SymbolKind, RelocKind};
+
+        let obj = gen_asm("../testprogs/static.c", false).relax().assemble();
+        let symbol = |name: &str| obj.symbols.iter().find(|symbol| symbol.name == name).unwrap();
+
+        // linkage decides the binding, `z` is only declared
+        assert_eq!((symbol("main").binding, symbol("main").kind), (Binding::Global, SymbolKind::Func));
+        assert_eq!(symbol("b").binding, Binding::Local);
+        assert_eq!((symbol("x").binding, symbol("x").kind), (Binding::Local, SymbolKind::Object));
+        assert!(obj.symbols.iter().all(|symbol| symbol.name != "z"));
+
+        // `w` goes first, then `x` after it
+        let data = obj.sections.iter().find(|section| section.kind == SectionKind::Data).unwrap();
+        assert_eq!(data.data, [1, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0]);
+        assert_eq!(obj.sections[symbol("y").section.unwrap()].kind, SectionKind::Bss);
+
+        let text = &obj.sections[symbol("main").section.unwrap()];
+        let kinds = text.relocs.iter().map(|reloc| reloc.kind).collect::<Vec<_>>();
+        assert!(kinds.contains(&RelocKind::CallPlt));
+        assert!(kinds.contains(&RelocKind::Hi20) && kinds.contains(&RelocKind::Lo12I));
+
+        let bytes = obj.to_bytes();
+        assert_eq!(bytes[..4], *b"\x7fELF");
+    }
+
+    #[test]
+    fn test_debug_info() {
+        use crate::driver::{compile_object, Options};
+        use crate::elf::{Object, SectionKind, RelocKind};
+
+        let source = read_to_string("../testprogs/control_flow.c").unwrap();
+        let object = |debug_info| {
+            let options = Options {
+                opt_level: 2,
+                debug_info,
+                file_name: Some("control_flow.c".to_string()),
+                ..Options::default()
+            };
+            Object::from_bytes(&compile_object(source.clone(), &options).unwrap()).unwrap()
+        };
+        let section = |obj: &Object, kind| obj.sections.iter().find(|section| section.kind == kind).map(|section| section.data.clone());
+
+        // the same code either way
+        let (plain, debug) = (object(false), object(true));
+        assert_eq!(section(&plain, SectionKind::Text), section(&debug, SectionKind::Text));
+        assert_eq!(section(&plain, SectionKind::DebugInfo), None);
+
+        let info = debug.sections.iter().find(|section| section.kind == SectionKind::DebugInfo).unwrap();
+        assert_eq!(info.data[4..6], [4, 0]);
+        assert!(info.data.windows(15).any(|bytes| bytes == b"control_flow.c\0"));
+        // the abbreviations, the line table and the address of `main`
+        let kinds = info.relocs.iter().map(|reloc| reloc.kind).collect::<Vec<_>>();
+        assert_eq!(kinds.iter().filter(|&&kind| kind == RelocKind::Abs32).count(), 2);
+        assert!(info.relocs.iter().any(|reloc| debug.symbols[reloc.symbol].name == "main"));
+        assert!(section(&debug, SectionKind::DebugLine).is_some_and(|line| line.len() > 40));
+    }
+
+    #[test]
+    fn test_riscv32() {
+        use crate::driver::{compile, Options, Target};
+
+        let source = "long long mul(long long a, long long b) { return a * b; }
+            long long div(long long a, int b) { return a / b; }
+            long f(long x, long long y) { return x + (long) y; }
+            int main(void) { long long x = 4294967295ll; return (int) div(mul(x, 2ll) + 1ll, 3) + f(1, x); }";
+        let options = Options {
+            opt_level: 2,
+            target: Target::Riscv32,
+            ..Options::default()
+        };
+        let asm = compile(source.to_string(), &options).unwrap();
+        let insns = asm.lines()
+            .filter_map(|line| line.strip_prefix('\t'))
+            .filter(|line| !line.starts_with('.'))
+            .map(|line| line.split('\t').next().unwrap())
+            .collect::<Vec<_>>();
+
+        // registers are 32-bit, there's nothing to sign-extend
+        assert!(insns.iter().all(|op| !op.ends_with('w') || *op == "lw" || *op == "sw"));
+        assert!(insns.iter().all(|op| !matches!(*op, "ld" | "sd" | "sext.w")));
+        // the carry out of the lower halves, and the upper half of their product
+        assert!(insns.contains(&"sltu") && insns.contains(&"mulhu"));
+        assert!(asm.contains("\tcall\t__divdi3\n"));
+        // callee-saved registers take 4-byte slots
+        assert!(asm.contains("\tsw\tra, -4(sp)\n"));
+    }
+}
@@ -0,0 +1,412 @@
+//! Peephole optimizations on the final instruction sequences.
+//! Each pattern looks at a few neighboring instructions and rewrites them in place,
+//! the patterns are applied until none of them matches any more:
+//! - a reload right after a store to the same slot becomes a move
+//!   e.g. `sd a0, -24(s0); ld a1, -24(s0)` -> `sd a0, -24(s0); mv a1, a0`
+//!   (`sw` and `lw` on RV32)
+//! - the second move of `mv a, b; mv b, a` is dropped
+//! - a jump to the label right after it is dropped
+//! - a branch over a jump is inverted
+//!   e.g. `beq a0, a1, .L1; j .L2; .L1:` -> `bne a0, a1, .L2; .L1:`
+//! - `addi x, x, 0` is dropped, and `addi x, y, 0` becomes `mv x, y`
+//! - a `li` only read by an add or sub is folded into its immediate form
+//!   e.g. `li t0, 5; add a0, a1, t0` -> `addi a0, a1, 5` (t0 dead afterwards)
+//!
+//! Only straight-line code is examined, whether a register is still needed
+//! is answered conservatively at labels and jumps.
+//! `.loc`s are looked through, so code is the same with and without `-g`.
+//! Blocks are ordered for fall-through first (`layout.rs`), leaving jumps to the next label behind.
+
+use std::collections::HashMap;
+
+use crate::common::*;
+use super::{
+    layout,
+    CodeGen,
+    Opt,
+    TopLevel,
+    Function,
+    Insn,
+    Register,
+    Xlen,
+};
+
+impl CodeGen<Opt> {
+    pub fn peephole(mut self, asm: TopLevel) -> TopLevel {
+        let mut functions = HashMap::new();
+        for (name, func) in asm.functions {
+            functions.insert(name, self.peephole_func(func, asm.xlen));
+        }
+
+        TopLevel {
+            functions,
+            ..asm
+        }
+    }
+
+    fn peephole_func(&mut self, func: Function, xlen: Xlen) -> Function {
+        Function {
+            body: peephole_insns(layout::order_blocks(func.body), xlen),
+            ..func
+        }
+    }
+}
+
+fn peephole_insns(mut body: Vec<Insn>, xlen: Xlen) -> Vec<Insn> {
+    loop {
+        let mut changed = false;
+        changed |= forward_stores(&mut body, xlen);
+        changed |= remove_redundant_moves(&mut body);
+        changed |= remove_jumps_to_next(&mut body);
+        changed |= invert_branches_over_jumps(&mut body);
+        changed |= remove_zero_addis(&mut body);
+        changed |= fuse_immediates(&mut body);
+        if !changed {
+            return body;
+        }
+    }
+}
+
+/// `sd r, off(b) ... ld d, off(b)` -> `sd r, off(b) ... mv d, r`,
+/// as long as nothing in between may write the slot, `r` or `b`.
+fn forward_stores(body: &mut [Insn], xlen: Xlen) -> bool {
+    let mut changed = false;
+    for i in 0..body.len() {
+        let (src, base, offset, double) = match body[i] {
+            Insn::Sd(src, base, offset) => (src, base, offset, true),
+            Insn::Sw(src, base, offset) => (src, base, offset, false),
+            _ => continue,
+        };
+        for insn in body[i + 1..].iter_mut() {
+            match *insn {
+                Insn::Ld(dst, b, off) if double && b == base && off == offset => {
+                    *insn = Insn::Mv(dst, src);
+                    changed = true;
+                },
+                // `lw` sign-extends what `sw` stored, which is all of it on RV32
+                Insn::Lw(dst, b, off) if !double && b == base && off == offset => {
+                    *insn = match xlen {
+                        Xlen::Rv64 => Insn::Sextw(dst, src),
+                        Xlen::Rv32 => Insn::Mv(dst, src),
+                    };
+                    changed = true;
+                },
+                _ => {},
+            }
+            if ends_block(insn) || is_store(insn) {
+                break;
+            }
+            let (_, writes) = accesses(insn);
+            if writes.contains(&src) || writes.contains(&base) {
+                break;
+            }
+        }
+    }
+    changed
+}
+
+/// `mv a, b; mv b, a` -> `mv a, b`, and `mv a, a` is dropped.
+fn remove_redundant_moves(body: &mut Vec<Insn>) -> bool {
+    let len = body.len();
+    let mut prev = None;
+    body.retain(|&insn| {
+        let redundant = match (prev, insn) {
+            (_, Insn::Mv(dst, src)) if dst == src => true,
+            (Some(Insn::Mv(a, b)), Insn::Mv(dst, src)) => dst == b && src == a,
+            _ => false,
+        };
+        if !redundant && !matches!(insn, Insn::Loc(..)) {
+            prev = Some(insn);
+        }
+        !redundant
+    });
+    body.len() != len
+}
+
+/// `j .L1; .L1:` -> `.L1:`, there may be other labels in between.
+fn remove_jumps_to_next(body: &mut Vec<Insn>) -> bool {
+    let len = body.len();
+    let mut i = 0;
+    while i < body.len() {
+        if let Insn::J(target) = body[i] {
+            let mut next_labels = body[i + 1..].iter()
+                .filter(|insn| !matches!(insn, Insn::Loc(..)))
+                .map_while(|insn| match insn {
+                    Insn::Label(label) => Some(*label),
+                    _ => None,
+                });
+            if next_labels.any(|label| label == target) {
+                body.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+    body.len() != len
+}
+
+/// `beq a, b, .L1; j .L2; .L1:` -> `bne a, b, .L2; .L1:`
+fn invert_branches_over_jumps(body: &mut Vec<Insn>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < body.len() {
+        let jump = next_insn(body, i);
+        let label = jump.and_then(|jump| next_insn(body, jump));
+        let inverted = match (body[i].branch_target(), jump.map(|idx| body[idx]), label.map(|idx| body[idx])) {
+            (Some(over), Some(Insn::J(target)), Some(Insn::Label(label))) if over == label =>
+                body[i].invert_branch(target),
+            _ => None,
+        };
+        if let (Some(inverted), Some(jump)) = (inverted, jump) {
+            body[i] = inverted;
+            body.remove(jump);
+            changed = true;
+        }
+        i += 1;
+    }
+    changed
+}
+
+/// `addi x, x, 0` is dropped, `addi x, y, 0` -> `mv x, y`
+fn remove_zero_addis(body: &mut Vec<Insn>) -> bool {
+    let len = body.len();
+    let mut changed = false;
+    body.retain_mut(|insn| match *insn {
+        Insn::Addi(rd, rs, 0) if rd == rs => false,
+        Insn::Addi(rd, rs, 0) => {
+            *insn = Insn::Mv(rd, rs);
+            changed = true;
+            true
+        },
+        _ => true,
+    });
+    changed || body.len() != len
+}
+
+/// `li t, c; add d, s, t` -> `addi d, s, c`, if `c` fits in 12 bits and `t` isn't needed afterwards.
+/// `addw` and `sub`/`subw` with the constant on the right are folded the same way.
+fn fuse_immediates(body: &mut Vec<Insn>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < body.len() {
+        let (Insn::Li(tmp, imm), Some(next)) = (body[i], next_insn(body, i)) else {
+            i += 1;
+            continue;
+        };
+        let fused = match body[next] {
+            Insn::Add(rd, rs, t) | Insn::Add(rd, t, rs) if t == tmp && rs != tmp && fits_imm12(imm) =>
+                Some(Insn::Addi(rd, rs, imm)),
+            Insn::Addw(rd, rs, t) | Insn::Addw(rd, t, rs) if t == tmp && rs != tmp && fits_imm12(imm) =>
+                Some(Insn::Addiw(rd, rs, imm as i32)),
+            Insn::Sub(rd, rs, t) if t == tmp && rs != tmp && fits_imm12(imm.wrapping_neg()) =>
+                Some(Insn::Addi(rd, rs, -imm)),
+            Insn::Subw(rd, rs, t) if t == tmp && rs != tmp && fits_imm12(imm.wrapping_neg()) =>
+                Some(Insn::Addiw(rd, rs, -imm as i32)),
+            _ => None,
+        };
+        match fused {
+            Some(fused) if writes_of(&fused) == Some(tmp) || !live_after(body, next, tmp) => {
+                body[next] = fused;
+                body.remove(i);
+                changed = true;
+            },
+            _ => i += 1,
+        }
+    }
+    changed
+}
+
+/// Whether `reg` may be read after `body[idx]` before being written.
+fn live_after(body: &[Insn], idx: usize, reg: Register) -> bool {
+    for insn in body[idx + 1..].iter() {
+        match insn {
+            Insn::Ret | Insn::Tail(..) =>
+                return accesses(insn).0.contains(&reg) || reg.is_callee_saved() || reg == Register::Ra,
+            Insn::Label(..) | Insn::J(..) => return true,
+            _ => {},
+        }
+        let (reads, writes) = accesses(insn);
+        if reads.contains(&reg) {
+            return true;
+        }
+        if writes.contains(&reg) {
+            return false;
+        }
+        if insn.branch_target().is_some() {
+            return true;
+        }
+    }
+    true
+}
+
+/// Index of the first instruction after `body[idx]` that isn't a `.loc`.
+fn next_insn(body: &[Insn], idx: usize) -> Option<usize> {
+    (idx + 1..body.len()).find(|&next| !matches!(body[next], Insn::Loc(..)))
+}
+
+fn writes_of(insn: &Insn) -> Option<Register> {
+    accesses(insn).1.first().copied()
+}
+
+fn ends_block(insn: &Insn) -> bool {
+    insn.branch_target().is_some() ||
+        matches!(insn, Insn::Label(..) | Insn::J(..) | Insn::Call(..) | Insn::Tail(..) | Insn::Ret)
+}
+
+fn is_store(insn: &Insn) -> bool {
+    matches!(insn, Insn::Sd(..) | Insn::Sw(..) | Insn::StoreStatic(..))
+}
+
+/// Registers read and written by `insn`, calls included.
+fn accesses(insn: &Insn) -> (Vec<Register>, Vec<Register>) {
+    use Insn::*;
+
+    match *insn {
+        Add(rd, rs1, rs2) | Addw(rd, rs1, rs2) |
+        Sub(rd, rs1, rs2) | Subw(rd, rs1, rs2) |
+        Mul(rd, rs1, rs2) | Mulw(rd, rs1, rs2) | Mulh(rd, rs1, rs2) | Mulhu(rd, rs1, rs2) |
+        Div(rd, rs1, rs2) | Divw(rd, rs1, rs2) |
+        Rem(rd, rs1, rs2) | Remw(rd, rs1, rs2) |
+        Slt(rd, rs1, rs2) | Sgt(rd, rs1, rs2) |
+        Sltu(rd, rs1, rs2) | Or(rd, rs1, rs2) => (vec![rs1, rs2], vec![rd]),
+        Addi(rd, rs, _) | Addiw(rd, rs, _) |
+        Slli(rd, rs, _) | Slliw(rd, rs, _) | Srli(rd, rs, _) |
+        Srliw(rd, rs, _) | Srai(rd, rs, _) | Sraiw(rd, rs, _) |
+        Neg(rd, rs) | Negw(rd, rs) | Not(rd, rs) |
+        Seqz(rd, rs) | Snez(rd, rs) | Sextw(rd, rs) | Mv(rd, rs) |
+        Ld(rd, rs, _) | Lw(rd, rs, _) => (vec![rs], vec![rd]),
+        Sd(rs, base, _) | Sw(rs, base, _) => (vec![rs, base], vec![]),
+        Li(rd, _) | La(rd, _) | LoadStatic(rd, ..) => (vec![], vec![rd]),
+        StoreStatic(rs, addr, ..) => (vec![rs], vec![addr]),
+        Beq(rs1, rs2, _) | Bne(rs1, rs2, _) |
+        Blt(rs1, rs2, _) | Bge(rs1, rs2, _) => (vec![rs1, rs2], vec![]),
+        Call(_) => (
+            (0..8).map(Register::a).collect(),
+            Register::iter().filter(Register::is_caller_saved).collect(),
+        ),
+        Tail(_) => ((0..8).map(Register::a).collect(), vec![]),
+        // `a1` holds the upper half of 64-bit return values on RV32
+        Ret => (vec![Register::A0, Register::A1], vec![]),
+        Ecall => ((0..8).map(Register::a).collect(), vec![Register::A0]),
+        J(_) | Label(_) | Loc(_) => (vec![], vec![]),
+    }
+}
+
+fn fits_imm12(imm: i64) -> bool {
+    (-2048..2048).contains(&imm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::LabelOperand;
+
+    use Register::*;
+
+    fn label(id: usize) -> LabelOperand {
+        LabelOperand::AutoGen(id)
+    }
+
+    #[test]
+    fn test_forward_stores() {
+        let body = peephole_insns(vec![
+            Insn::Sd(A0, S0, -24),
+            Insn::Add(A2, A3, A4),
+            Insn::Ld(A1, S0, -24),
+            Insn::Sw(A1, S0, -32),
+            Insn::Lw(A2, S0, -32),
+            Insn::Ret,
+        ], Xlen::Rv64);
+        assert_eq!(body[2], Insn::Mv(A1, A0));
+        assert_eq!(body[4], Insn::Sextw(A2, A1));
+
+        // the base register is overwritten in between
+        let body = vec![
+            Insn::Sd(A0, A5, 0),
+            Insn::Mv(A5, A6),
+            Insn::Ld(A1, A5, 0),
+            Insn::Ret,
+        ];
+        assert_eq!(peephole_insns(body.clone(), Xlen::Rv64), body);
+    }
+
+    #[test]
+    fn test_moves_and_jumps() {
+        let body = peephole_insns(vec![
+            Insn::Mv(A0, A1),
+            Insn::Mv(A1, A0),
+            Insn::Addi(A2, A2, 0),
+            Insn::Addi(A3, A2, 0),
+            Insn::Beq(A0, A1, label(1)),
+            Insn::J(label(2)),
+            Insn::Label(label(1)),
+            Insn::J(label(3)),
+            Insn::Label(label(2)),
+            Insn::Label(label(3)),
+            Insn::Ret,
+        ], Xlen::Rv64);
+        assert_eq!(body, vec![
+            Insn::Mv(A0, A1),
+            Insn::Mv(A3, A2),
+            Insn::Bne(A0, A1, label(2)),
+            Insn::Label(label(1)),
+            Insn::Label(label(2)),
+            Insn::Label(label(3)),
+            Insn::Ret,
+        ]);
+    }
+
+    #[test]
+    fn test_fuse_immediates() {
+        let body = peephole_insns(vec![
+            Insn::Li(T0, 5),
+            Insn::Add(A0, T0, A1),
+            Insn::Li(T1, 7),
+            Insn::Subw(T1, A2, T1),
+            Insn::Li(T2, 5000),
+            Insn::Add(A3, A3, T2),
+            Insn::Ret,
+        ], Xlen::Rv64);
+        assert_eq!(body, vec![
+            Insn::Addi(A0, A1, 5),
+            Insn::Addiw(T1, A2, -7),
+            Insn::Li(T2, 5000),
+            Insn::Add(A3, A3, T2),
+            Insn::Ret,
+        ]);
+
+        // the constant is still needed afterwards
+        let body = vec![
+            Insn::Li(A0, 5),
+            Insn::Add(A2, A3, A0),
+            Insn::Ret,
+        ];
+        assert_eq!(peephole_insns(body.clone(), Xlen::Rv64), body);
+    }
+
+    #[test]
+    fn test_locs() {
+        let body = vec![
+            Insn::Mv(A0, A1),
+            Insn::Mv(A1, A0),
+            Insn::Li(T0, 5),
+            Insn::Add(A0, T0, A1),
+            Insn::Beq(A0, A1, label(1)),
+            Insn::J(label(2)),
+            Insn::Label(label(1)),
+            Insn::J(label(3)),
+            Insn::Label(label(2)),
+            Insn::Label(label(3)),
+            Insn::Ret,
+        ];
+        // a `.loc` before each instruction changes nothing else
+        let with_locs = body.iter()
+            .enumerate()
+            .flat_map(|(line, &insn)| [Insn::Loc(Span::new(line + 1, 1)), insn])
+            .collect::<Vec<_>>();
+        let without_locs = peephole_insns(with_locs, Xlen::Rv64).into_iter()
+            .filter(|insn| !matches!(insn, Insn::Loc(..)))
+            .collect::<Vec<_>>();
+        assert_eq!(without_locs, peephole_insns(body, Xlen::Rv64));
+    }
+}
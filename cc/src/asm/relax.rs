@@ -0,0 +1,125 @@
+//! Long-branch relaxation.
+//! Conditional branches only reach labels within ±4KiB, those jumping further
+//! are inverted to skip over a `j`, which reaches ±1MiB:
+//! e.g.    beq a0, a1, .L1     ->      bne a0, a1, .L9
+//!                             ->      j   .L1
+//!                             ->      .L9:
+//! Each relaxed branch grows the function and may push others out of range,
+//! so branches are checked again until all of them reach their labels.
+//! Offsets are estimated from the longest expansion of each instruction.
+
+use std::collections::HashMap;
+
+use super::{
+    TopLevel,
+    Insn,
+    LabelOperand,
+};
+
+/// Branch offsets reachable by `beq` and friends, in bytes.
+const BRANCH_RANGE: std::ops::RangeInclusive<isize> = -4096..=4094;
+
+impl TopLevel {
+    pub fn relax(mut self) -> Self {
+        // labels are unique throughout the file
+        let mut next_label = self.functions.values()
+            .flat_map(|func| func.body.iter())
+            .filter_map(|insn| match insn {
+                Insn::Label(LabelOperand::AutoGen(id)) => Some(id + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        for func in self.functions.values_mut() {
+            let body = std::mem::take(&mut func.body);
+            func.body = relax_branches(body, &mut next_label);
+        }
+        self
+    }
+}
+
+fn relax_branches(mut body: Vec<Insn>, next_label: &mut usize) -> Vec<Insn> {
+    loop {
+        let mut offsets = Vec::with_capacity(body.len());
+        let mut labels = HashMap::new();
+        let mut offset = 0;
+        for insn in body.iter() {
+            offsets.push(offset);
+            if let Insn::Label(label) = insn {
+                labels.insert(*label, offset);
+            }
+            offset += insn_size(insn);
+        }
+
+        let far = body.iter()
+            .zip(offsets)
+            .position(|(insn, offset)| insn.branch_target()
+                .and_then(|target| labels.get(&target))
+                .is_some_and(|&target| !BRANCH_RANGE.contains(&(target - offset))));
+        let Some(idx) = far else { return body };
+
+        let target = body[idx].branch_target().unwrap();
+        let skip = LabelOperand::AutoGen(*next_label);
+        *next_label += 1;
+        body[idx] = body[idx].invert_branch(skip).unwrap();
+        body.splice(idx + 1..idx + 1, [Insn::J(target), Insn::Label(skip)]);
+    }
+}
+
+/// Bytes taken by `insn` once the assembler expands it, at most.
+fn insn_size(insn: &Insn) -> isize {
+    match insn {
+        Insn::Label(..) | Insn::Loc(..) => 0,
+        Insn::Li(_, imm) if (-2048..2048).contains(imm) => 4,
+        Insn::Li(_, imm) if i32::try_from(*imm).is_ok() => 8,
+        // up to `lui`, `addiw` and three pairs of `slli` and `addi`
+        Insn::Li(..) => 32,
+        Insn::La(..) |
+        Insn::LoadStatic(..) |
+        Insn::StoreStatic(..) |
+        Insn::Call(..) |
+        Insn::Tail(..) => 8,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::Register::*;
+
+    #[test]
+    fn test_relax_branches() {
+        let label = LabelOperand::AutoGen;
+        let mut body = vec![
+            Insn::Beq(A0, A1, label(1)),
+            Insn::Blt(A0, A1, label(2)),
+        ];
+        body.extend(std::iter::repeat_n(Insn::Addi(A0, A0, 1), 1100));
+        body.push(Insn::Label(label(2)));
+        body.push(Insn::Label(label(1)));
+        body.push(Insn::Ret);
+
+        let mut next_label = 3;
+        let relaxed = relax_branches(body, &mut next_label);
+        assert_eq!(relaxed[..6], [
+            Insn::Bne(A0, A1, label(3)),
+            Insn::J(label(1)),
+            Insn::Label(label(3)),
+            Insn::Bge(A0, A1, label(4)),
+            Insn::J(label(2)),
+            Insn::Label(label(4)),
+        ]);
+        assert_eq!(next_label, 5);
+
+        // short branches stay
+        let body = vec![
+            Insn::Beq(A0, A1, label(1)),
+            Insn::Addi(A0, A0, 1),
+            Insn::Label(label(1)),
+            Insn::Ret,
+        ];
+        assert_eq!(relax_branches(body.clone(), &mut next_label), body);
+    }
+}
@@ -3,13 +3,36 @@ use std::fmt::Display;
 use crate::common::*;
 use crate::lir::{
     LirFunction,
+    LirDebugVar,
+    LirOperand,
     LirBssSegment,
     LirDataSegment,
     LirStaticVar,
     LirLabelOperand,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Width of the integer registers, RV64 unless targeting `riscv32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Xlen {
+    Rv32,
+    #[default]
+    Rv64,
+}
+
+impl Xlen {
+    pub fn bytes(&self) -> usize {
+        match self {
+            Xlen::Rv32 => 4,
+            Xlen::Rv64 => 8,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bytes() as u32 * 8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Register {
     Zero, Ra, Sp, Gp, Tp,
     T0, T1, T2, S0, S1,
@@ -81,15 +104,20 @@ impl Iterator for RegIter {
 }
 
 impl Register {
-    pub const ALLOC_REGS: [&Self; 24] = [
+    /// every register but `SCRATCH` and the ones with a fixed role
+    pub const ALLOC_REGS: [&Self; 25] = [
         &Register::A0, &Register::A1, &Register::A2, &Register::A3,
         &Register::A4, &Register::A5, &Register::A6, &Register::A7,
         &Register::T0, &Register::T1, &Register::T2, &Register::T3,
-        &Register::T4, &Register::S1, &Register::S2, &Register::S3, 
-        &Register::S4, &Register::S5, &Register::S6, &Register::S7, 
-        &Register::S8, &Register::S9, &Register::S10, &Register::S11,
+        &Register::T4, &Register::T5, &Register::S1,
+        &Register::S2, &Register::S3, &Register::S4, &Register::S5,
+        &Register::S6, &Register::S7, &Register::S8, &Register::S9,
+        &Register::S10, &Register::S11,
     ];
 
+    /// Kept out of allocation, to reach frame slots and adjust `sp` by more than a 12-bit immediate.
+    pub const SCRATCH: Self = Register::T6;
+
     pub const fn iter() -> RegIter {
         RegIter {
             regs: [
@@ -186,10 +214,20 @@ pub enum Insn {
     Addi(Register, Register, i64),
     Addw(Register, Register, Register),
     Addiw(Register, Register, i32),
+    Slli(Register, Register, u32),
+    Slliw(Register, Register, u32),
+    Srli(Register, Register, u32),
+    Srliw(Register, Register, u32),
+    Srai(Register, Register, u32),
+    Sraiw(Register, Register, u32),
     Sub(Register, Register, Register),
     Subw(Register, Register, Register),
     Mul(Register, Register, Register),
     Mulw(Register, Register, Register),
+    /// upper 64 bits of the signed 128-bit product
+    Mulh(Register, Register, Register),
+    /// upper half of the unsigned product
+    Mulhu(Register, Register, Register),
     Div(Register, Register, Register),
     Divw(Register, Register, Register),
     Rem(Register, Register, Register),
@@ -204,14 +242,24 @@ pub enum Insn {
     Mv(Register, Register),
 
     Call(StrDescriptor),
+    /// jumps to a function, which returns to our caller
+    Tail(StrDescriptor),
     Beq(Register, Register, LabelOperand),
     Bne(Register, Register, LabelOperand),
+    Blt(Register, Register, LabelOperand),
+    Bge(Register, Register, LabelOperand),
     J(LabelOperand),
     Label(LabelOperand),
     Ret,
+    /// system call, its number in `a7` and arguments in `a0` to `a5`, the result in `a0`
+    Ecall,
+    /// the source location of what follows, only generated with `-g`
+    Loc(Span),
 
     Slt(Register, Register, Register),
     Sgt(Register, Register, Register),
+    Sltu(Register, Register, Register),
+    Or(Register, Register, Register),
 
 
     Ld(Register, Register, isize),
@@ -222,11 +270,35 @@ pub enum Insn {
     Li(Register, i64),
     La(Register, StrDescriptor),
 
-    LoadStatic(Register, StrDescriptor),
-    StoreStatic(Register, StrDescriptor),
+    /// (destination, name, offset), the offset is 4 for the upper half of a 64-bit static on RV32
+    LoadStatic(Register, StrDescriptor, isize),
+    /// (value, address scratch, name, offset), the scratch is clobbered
+    StoreStatic(Register, Register, StrDescriptor, isize),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Insn {
+    /// The label a conditional branch goes to.
+    pub fn branch_target(&self) -> Option<LabelOperand> {
+        match *self {
+            Insn::Beq(.., label) | Insn::Bne(.., label) |
+            Insn::Blt(.., label) | Insn::Bge(.., label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// The conditional branch taken exactly when `self` isn't, to `target`.
+    pub fn invert_branch(&self, target: LabelOperand) -> Option<Insn> {
+        match *self {
+            Insn::Beq(rs1, rs2, _) => Some(Insn::Bne(rs1, rs2, target)),
+            Insn::Bne(rs1, rs2, _) => Some(Insn::Beq(rs1, rs2, target)),
+            Insn::Blt(rs1, rs2, _) => Some(Insn::Bge(rs1, rs2, target)),
+            Insn::Bge(rs1, rs2, _) => Some(Insn::Blt(rs1, rs2, target)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LabelOperand {
     AutoGen(usize),
     Named(StrDescriptor),
@@ -247,6 +319,7 @@ pub struct StaticVar {
     pub data_type: DataType,
     pub linkage: Linkage,
     pub initializer: InitVal,
+    pub span: Span,
 }
 
 impl From<LirStaticVar> for StaticVar {
@@ -256,6 +329,7 @@ impl From<LirStaticVar> for StaticVar {
             data_type: var.data_type,
             linkage: var.linkage,
             initializer: var.initializer,
+            span: var.span,
         }
     }
 }
@@ -296,6 +370,43 @@ pub struct Function {
     pub func_type: FuncType,
     pub body: Vec<Insn>,
     pub linkage: Linkage,
+    pub span: Span,
+    /// parameters first, in order, then local variables
+    pub vars: Vec<DebugVar>,
+}
+
+/// A parameter or local variable and where it's kept, for debug info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugVar {
+    pub name: StrDescriptor,
+    pub data_type: DataType,
+    pub span: Span,
+    pub is_param: bool,
+    /// `None` if optimized out
+    pub location: Option<VarLocation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarLocation {
+    Reg(Register),
+    /// a frame slot, at an offset from `s0` or `sp`
+    Mem(Register, isize),
+}
+
+impl From<LirDebugVar> for DebugVar {
+    fn from(var: LirDebugVar) -> Self {
+        DebugVar {
+            name: var.name,
+            data_type: var.data_type,
+            span: var.span,
+            is_param: var.is_param,
+            location: var.location.and_then(|location| match location {
+                LirOperand::PhysReg(reg) => Some(VarLocation::Reg(reg)),
+                LirOperand::Mem { base, offset, .. } => Some(VarLocation::Mem(base, offset)),
+                _ => None,
+            }),
+        }
+    }
 }
 
 
@@ -304,5 +415,10 @@ pub struct TopLevel {
     pub functions: HashMap<StrDescriptor, Function>,
     pub data_seg: DataSegment,
     pub bss_seg: BssSegment,
+    /// variables only declared here, defined by another translation unit
+    pub externs: HashMap<StrDescriptor, StaticVar>,
     pub strtb: StringPool,    
+    /// name of the source file, if debug info is generated (`-g`)
+    pub source_file: Option<String>,
+    pub xlen: Xlen,
 }
\ No newline at end of file
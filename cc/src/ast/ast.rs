@@ -93,8 +93,12 @@ pub enum Stmt {
         span: Span,
         expr: Box<Expr>,
     },
-    Expr(Box<Expr>),
+    Expr {
+        span: Span,
+        expr: Box<Expr>,
+    },
     If {
+        span: Span,
         condition: Box<Expr>,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
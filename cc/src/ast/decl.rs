@@ -1,4 +1,4 @@
-use crate::{ast::parser::parse_types, common::*};
+use crate::common::*;
 use super::{
     Parser,
     Decl,
@@ -27,7 +27,7 @@ impl Parser {
         let types = types.into_iter()
             .map(|t| t.inner.as_type())
             .collect::<Vec<_>>();
-        let data_type = parse_types(types, self.cur_span())?;
+        let data_type = self.parse_types(types, self.cur_span())?;
  
         let name_token = self.eat(TokenType::Identifier, "Expected an identifier for declaration")?;
         if self.is_at_end() {
@@ -58,10 +58,14 @@ impl Parser {
                             break;
                         }
 
-                        let param_type_token = self.eat_current();
-                        if !param_type_token.is_type() {
-                            return Err(Error::parse("Expected a type for function parameter.", param_type_token.span));
+                        let mut types = vec![];
+                        while self.peek().is_ok_and(|t| t.is_type()) {
+                            types.push(self.eat_current().inner.as_type());
                         }
+                        if types.is_empty() {
+                            return Err(Error::parse("Expected a type for function parameter.", self.peek()?.span));
+                        }
+                        let data_type = self.parse_types(types, self.cur_span())?;
 
                         let id_token = self.eat(TokenType::Identifier, "Expected an identifier for function parameter")?;
                         if self.is_at_end() {
@@ -72,7 +76,7 @@ impl Parser {
                         }
                         params.push(Param {
                             name: id_token.inner.as_identifier(),
-                            data_type: param_type_token.inner.as_type(),
+                            data_type,
                             span: id_token.span,
                         });
                     }               
@@ -170,7 +174,7 @@ impl Parser {
         let types = types.into_iter()
             .map(|t| t.inner.as_type())
             .collect::<Vec<_>>();
-        let data_type = parse_types(types, self.cur_span())?;
+        let data_type = self.parse_types(types, self.cur_span())?;
 
         let id_token = self.eat(TokenType::Identifier, "Expected an identifier for variable declaration")?;
         if self.is_at_end() {
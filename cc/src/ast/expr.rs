@@ -1,4 +1,4 @@
-use crate::{ast::parser::parse_types, common::*};
+use crate::common::*;
 use super::{
     Parser,
     TopLevel,
@@ -83,8 +83,13 @@ impl Parser {
     fn primary(&mut self) -> Result<Expr> {
         let token = self.eat_current();
         match token.get_type() {
-            TokenType::IntLiteral|TokenType::LongLiteral => {
-                let constant = token.inner.as_constant();
+            TokenType::IntLiteral|TokenType::LongLiteral|TokenType::LongLongLiteral => {
+                let constant = match (token.get_type(), token.inner.as_constant()) {
+                    // a `long` literal is an `int` one if `long` is 32-bit and the value fits
+                    (TokenType::LongLiteral, Constant::Long(l)) if self.data_model.long() == DataType::Int =>
+                        i32::try_from(l).map_or(Constant::Long(l), Constant::Int),
+                    (_, constant) => constant,
+                };
                 Ok(Expr::IntegerLiteral(constant))
             },
             TokenType::LParen => {
@@ -100,7 +105,7 @@ impl Parser {
                             Err(Error::parse("Expected a type or ')'", self.cur_span()))?;
                         }
                     }
-                    let target = parse_types(types, self.cur_span())?;
+                    let target = self.parse_types(types, self.cur_span())?;
                     let expr = Box::new(self.expr_top_level()?);
                     Ok(Expr::Cast {
                         target,
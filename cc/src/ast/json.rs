@@ -0,0 +1,148 @@
+//! JSON serialization of the AST, names resolved through the string pool.
+//! Enum variants are objects with a `kind`, declarations and statements
+//! stand for themselves as block items.
+
+use crate::common::*;
+use super::{
+    TopLevel,
+    Decl,
+    Expr,
+    Stmt,
+    BlockItem,
+    ForInit,
+};
+
+impl TopLevel {
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("decls", Json::Array(self.decls.iter().map(|decl| self.json_decl(decl)).collect())),
+        ])
+    }
+
+    fn name(&self, name: StrDescriptor) -> Json {
+        self.strtb.get(name).unwrap().into()
+    }
+
+    fn json_decl(&self, decl: &Decl) -> Json {
+        match decl {
+            Decl::FuncDecl { return_type, storage_class, name, params, body } => Json::variant("func_decl", [
+                ("name", self.name(name.0)),
+                ("name_span", name.1.into()),
+                ("return_type", (*return_type).into()),
+                ("storage_class", (*storage_class).into()),
+                ("params", Json::Array(params.iter()
+                    .map(|param| Json::object([
+                        ("name", self.name(param.name)),
+                        ("data_type", param.data_type.into()),
+                        ("span", param.span.into()),
+                    ]))
+                    .collect())),
+                ("body", body.as_ref().map_or(Json::Null, |items| self.json_items(items))),
+            ]),
+            Decl::VarDecl { storage_class, name, span, data_type, initializer } => Json::variant("var_decl", [
+                ("name", self.name(name.0)),
+                ("name_span", name.1.into()),
+                ("data_type", (*data_type).into()),
+                ("storage_class", (*storage_class).into()),
+                ("initializer", initializer.as_ref().map_or(Json::Null, |expr| self.json_expr(expr))),
+                ("span", (*span).into()),
+            ]),
+        }
+    }
+
+    fn json_items(&self, items: &[BlockItem]) -> Json {
+        Json::Array(items.iter()
+            .map(|item| match item {
+                BlockItem::Declaration(decl) => self.json_decl(decl),
+                BlockItem::Statement(stmt) => self.json_stmt(stmt),
+            })
+            .collect())
+    }
+
+    fn json_stmt(&self, stmt: &Stmt) -> Json {
+        let expr = |expr: &Option<Box<Expr>>| expr.as_ref().map_or(Json::Null, |expr| self.json_expr(expr));
+        match stmt {
+            Stmt::Return { span, expr } => Json::variant("return", [
+                ("expr", self.json_expr(expr)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Expr { span, expr } => Json::variant("expr", [
+                ("expr", self.json_expr(expr)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::If { span, condition, then_branch, else_branch } => Json::variant("if", [
+                ("condition", self.json_expr(condition)),
+                ("then_branch", self.json_stmt(then_branch)),
+                ("else_branch", else_branch.as_ref().map_or(Json::Null, |stmt| self.json_stmt(stmt))),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Compound(items) => Json::variant("compound", [("items", self.json_items(items))]),
+            Stmt::Break(span) => Json::variant("break", [("span", (*span).into())]),
+            Stmt::Continue(span) => Json::variant("continue", [("span", (*span).into())]),
+            Stmt::While { span, controller, body } => Json::variant("while", [
+                ("controller", self.json_expr(controller)),
+                ("body", self.json_stmt(body)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::DoWhile { span, body, controller } => Json::variant("do_while", [
+                ("body", self.json_stmt(body)),
+                ("controller", self.json_expr(controller)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::For { span, initializer, controller, post, body } => Json::variant("for", [
+                ("initializer", initializer.as_ref().map_or(Json::Null, |init| match init.as_ref() {
+                    ForInit::Declaration(decl) => self.json_decl(decl),
+                    ForInit::Expression(expr) => self.json_expr(expr),
+                })),
+                ("controller", expr(controller)),
+                ("post", expr(post)),
+                ("body", self.json_stmt(body)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Nil => Json::variant("nil", []),
+        }
+    }
+
+    fn json_expr(&self, expr: &Expr) -> Json {
+        match expr {
+            Expr::IntegerLiteral(constant) => Json::variant("integer_literal", [("value", (*constant).into())]),
+            Expr::Variable(name, span) => Json::variant("variable", [
+                ("name", self.name(*name)),
+                ("span", (*span).into()),
+            ]),
+            Expr::Assignment { span, left, right } => Json::variant("assignment", [
+                ("left", self.json_expr(left)),
+                ("right", self.json_expr(right)),
+                ("span", (*span).into()),
+            ]),
+            Expr::FuncCall { name, span, args } => Json::variant("func_call", [
+                ("name", self.name(*name)),
+                ("args", Json::Array(args.iter().map(|arg| self.json_expr(arg)).collect())),
+                ("span", (*span).into()),
+            ]),
+            Expr::Ternary { span, condition, then_expr, else_expr } => Json::variant("ternary", [
+                ("condition", self.json_expr(condition)),
+                ("then_expr", self.json_expr(then_expr)),
+                ("else_expr", self.json_expr(else_expr)),
+                ("span", (*span).into()),
+            ]),
+            Expr::Group(expr) => Json::variant("group", [("expr", self.json_expr(expr))]),
+            Expr::Unary((op, span), expr) => Json::variant("unary", [
+                ("op", Json::unit(op)),
+                ("op_span", (*span).into()),
+                ("expr", self.json_expr(expr)),
+            ]),
+            Expr::Binary { op: (op, span), left, right } => Json::variant("binary", [
+                ("op", Json::unit(op)),
+                ("op_span", (*span).into()),
+                ("left", self.json_expr(left)),
+                ("right", self.json_expr(right)),
+            ]),
+            Expr::Cast { target, expr, span } => Json::variant("cast", [
+                ("target", (*target).into()),
+                ("expr", self.json_expr(expr)),
+                ("span", (*span).into()),
+            ]),
+        }
+    }
+}
@@ -8,6 +8,7 @@ mod expr;
 mod stmt;
 mod decl;
 mod ast;
+mod json;
 
 use parser::Parser;
 use ast::{
@@ -40,6 +41,7 @@ pub use ast::{
 mod tests {
     use std::fs::read_to_string;
 
+    use crate::common::{Constant, DataModel};
     use crate::lex::Lexer;
 
     use super::*;
@@ -121,4 +123,33 @@ mod tests {
     fn test_long() {
         test_inner("../testprogs/long.c");
     }
+
+    #[test]
+    fn test_long_long() {
+        let input = "long a = 1l; long long b = 2ll; long int c; int long long d(long long x);";
+        let (tokens, strtb) = Lexer::new(input.into()).lex().unwrap();
+        let prog = Parser::new(tokens, strtb).parse_prog_with(DataModel::Ilp32).unwrap();
+        let types = prog.decls.iter()
+            .map(|decl| match decl {
+                Decl::VarDecl { data_type, .. } => *data_type,
+                Decl::FuncDecl { return_type, .. } => *return_type,
+            })
+            .collect::<Vec<_>>();
+        // `long` is as wide as `int` on ILP32
+        assert_eq!(types, [DataType::Int, DataType::Long, DataType::Int, DataType::Long]);
+
+        let initializers = prog.decls.iter()
+            .filter_map(|decl| match decl {
+                Decl::VarDecl { initializer: Some(init), .. } => match **init {
+                    Expr::IntegerLiteral(constant) => Some(constant),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(initializers, [Constant::Int(1), Constant::Long(2)]);
+
+        let (tokens, strtb) = Lexer::new("long long long x;".into()).lex().unwrap();
+        assert!(Parser::new(tokens, strtb).parse_prog().is_err());
+    }
 }
\ No newline at end of file
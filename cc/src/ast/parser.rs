@@ -16,6 +16,7 @@ pub struct Parser {
     position: usize,
     has_error: bool,
     strtb: StringPool,
+    pub(super) data_model: DataModel,
 }
 
 impl Parser {
@@ -25,6 +26,7 @@ impl Parser {
             position: 0,
             has_error: false,
             strtb,
+            data_model: DataModel::default(),
         }
     }
 
@@ -37,6 +39,12 @@ impl Parser {
         self.strtb
     }
 
+    /// Parse with the sizes of `long` and its literals given by `data_model`.
+    pub fn parse_prog_with(mut self, data_model: DataModel) -> Result<TopLevel> {
+        self.data_model = data_model;
+        self.parse_prog()
+    }
+
     pub fn parse_prog(mut self) -> Result<TopLevel> {
         let mut decls = vec![];
         let mut errors = vec![];
@@ -140,20 +148,28 @@ impl Parser {
             }
         }
     }
-}
 
-pub(super) fn parse_types(types: Vec<DataType>, span: Span) -> Result<DataType> {
-    match types.len() {
-        0 => Err(Error::parse("Expected a type for declaration", span)),
-        1 => Ok(types[0]),
-        2 => {
-            let (first, second) = (types[0], types[1]);
-            match (first, second) {
-                (DataType::Int, DataType::Long) => Ok(DataType::Long),
-                (DataType::Long, DataType::Int) => Ok(DataType::Long),
-                _ => Err(Error::parse("Unsupported type combination for declaration", span)),
-            }
-        },
-        _ => Err(Error::parse("Too many types specified for declaration", span)),
+    /// `long` alone or with `int` is given by the data model, `long long` is always 64-bit.
+    pub(super) fn parse_types(&self, types: Vec<DataType>, span: Span) -> Result<DataType> {
+        if types.is_empty() {
+            return Err(Error::parse("Expected a type for declaration", span));
+        }
+        if types.len() > 3 {
+            return Err(Error::parse("Too many types specified for declaration", span));
+        }
+        if types == [DataType::Void] {
+            return Ok(DataType::Void);
+        }
+
+        let ints = types.iter().filter(|&&t| t == DataType::Int).count();
+        let longs = types.iter().filter(|&&t| t == DataType::Long).count();
+        match (ints, longs) {
+            _ if ints + longs < types.len() =>
+                Err(Error::parse("Unsupported type combination for declaration", span)),
+            (1, 0) => Ok(DataType::Int),
+            (0|1, 1) => Ok(self.data_model.long()),
+            (0|1, 2) => Ok(DataType::Long),
+            _ => Err(Error::parse("Unsupported type combination for declaration", span)),
+        }
     }
-}
\ No newline at end of file
+}
@@ -106,7 +106,7 @@ impl Parser {
                 Ok(Stmt::Nil)
             },
             TokenType::If => {
-                self.eat_current();
+                let span = self.eat_current().span;
                 self.eat(TokenType::LParen, "Expected '(' after 'if'.")?;
                 let condition = Box::new(self.expr_top_level()?);
                 self.eat(TokenType::RParen, "Expected ')' after 'if' condition.")?;
@@ -118,6 +118,7 @@ impl Parser {
                     None
                 };
                 Ok(Stmt::If {
+                    span,
                     condition,
                     then_branch,
                     else_branch,
@@ -134,9 +135,13 @@ impl Parser {
             },
             _ => {
                 // expression statement
+                let span = token.span;
                 let expr = self.parse_expr()?;
                 self.eat(TokenType::Semicolon, "Expected ';' after expression statement.")?;
-                Ok(Stmt::Expr(Box::new(expr)))
+                Ok(Stmt::Expr {
+                    span,
+                    expr: Box::new(expr),
+                })
             }
         }
     }
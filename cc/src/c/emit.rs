@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    common::*,
+    tac::{
+        TacTopLevel,
+        TacFunction,
+        TacStaticVar,
+        TacInsn,
+        TacOperand,
+        TacUnaryOp,
+        TacBinaryOp,
+        TacLabelOperand,
+        TacAutoGenLabel,
+    },
+};
+
+/// Wrapping arithmetic, done on the unsigned types and converted back without
+/// the implementation-defined conversion of an out-of-range value to a signed type.
+/// Dividing the minimum by -1 wraps around as well, dividing by zero is left undefined.
+const PRELUDE: &str = "\
+#include <stdint.h>
+
+static inline int32_t drcc_s32(uint32_t x) { return x <= INT32_MAX ? (int32_t) x : (int32_t) (x - (uint32_t) INT32_MIN) + INT32_MIN; }
+static inline int64_t drcc_s64(uint64_t x) { return x <= INT64_MAX ? (int64_t) x : (int64_t) (x - (uint64_t) INT64_MIN) + INT64_MIN; }
+static inline int32_t drcc_add32(int32_t a, int32_t b) { return drcc_s32((uint32_t) a + (uint32_t) b); }
+static inline int64_t drcc_add64(int64_t a, int64_t b) { return drcc_s64((uint64_t) a + (uint64_t) b); }
+static inline int32_t drcc_sub32(int32_t a, int32_t b) { return drcc_s32((uint32_t) a - (uint32_t) b); }
+static inline int64_t drcc_sub64(int64_t a, int64_t b) { return drcc_s64((uint64_t) a - (uint64_t) b); }
+static inline int32_t drcc_mul32(int32_t a, int32_t b) { return drcc_s32((uint32_t) a * (uint32_t) b); }
+static inline int64_t drcc_mul64(int64_t a, int64_t b) { return drcc_s64((uint64_t) a * (uint64_t) b); }
+static inline int32_t drcc_neg32(int32_t a) { return drcc_s32(0u - (uint32_t) a); }
+static inline int64_t drcc_neg64(int64_t a) { return drcc_s64(0u - (uint64_t) a); }
+static inline int32_t drcc_div32(int32_t a, int32_t b) { return b == -1 ? drcc_neg32(a) : a / b; }
+static inline int64_t drcc_div64(int64_t a, int64_t b) { return b == -1 ? drcc_neg64(a) : a / b; }
+static inline int32_t drcc_rem32(int32_t a, int32_t b) { return b == -1 ? 0 : a % b; }
+static inline int64_t drcc_rem64(int64_t a, int64_t b) { return b == -1 ? 0 : a % b; }
+static inline int32_t drcc_trunc(int64_t a) { return drcc_s32((uint32_t) (uint64_t) a); }
+";
+
+/// Lowers a translation unit into a C99 source file, generated from `file`.
+/// Functions and globals are emitted sorted by name, so the output is stable.
+pub fn emit(tac: &TacTopLevel, file: &str) -> String {
+    let mut output = format!("/* generated by drcc from {} */\n", file.replace("*/", "*\\/"));
+    output.push_str(PRELUDE);
+
+    let mut static_vars = tac.static_vars.values()
+        .map(|var| (tac.strtb.get(var.name).unwrap(), var))
+        .collect::<Vec<_>>();
+    static_vars.sort_by_key(|&(name, _)| name);
+    if !static_vars.is_empty() {
+        output.push('\n');
+    }
+    for (name, var) in static_vars {
+        output.push_str(&emit_static_var(name, var));
+    }
+
+    let mut functions = tac.functions.iter()
+        .map(|(&name, func)| (tac.strtb.get(name).unwrap(), func))
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|&(name, _)| name);
+
+    // every function is declared up front, so calls don't depend on the order of definitions
+    output.push('\n');
+    for &(name, func) in &functions {
+        let type_ = func.type_();
+        let params = if type_.param_types.is_empty() {
+            "void".to_string()
+        } else {
+            type_.param_types.iter()
+                .map(|&type_| c_type(type_))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        output.push_str(&format!("{}{} {}({});\n", storage_class(func), c_type(type_.return_type), name, params));
+    }
+
+    for (name, func) in functions {
+        if let TacFunction::Defined { .. } = func {
+            output.push('\n');
+            let emitter = FuncEmitter::new(tac);
+            output.push_str(&emitter.emit(name, func));
+        }
+    }
+
+    output
+}
+
+fn emit_static_var(name: &str, var: &TacStaticVar) -> String {
+    let type_ = c_type(var.data_type);
+    let static_ = match var.linkage {
+        Linkage::Internal => "static ",
+        Linkage::External => "",
+    };
+    match var.initializer {
+        InitVal::Const(val) => format!("{}{} {} = {};\n", static_, type_, name, constant(val.value(), var.data_type)),
+        InitVal::Tentative => format!("{}{} {};\n", static_, type_, name),
+        InitVal::None => format!("extern {} {};\n", type_, name),
+    }
+}
+
+fn storage_class(func: &TacFunction) -> &'static str {
+    let linkage = match func {
+        TacFunction::Defined { linkage, .. } |
+        TacFunction::Declared { linkage, .. } => linkage,
+    };
+    match linkage {
+        Linkage::Internal => "static ",
+        Linkage::External => "",
+    }
+}
+
+fn c_type(type_: DataType) -> &'static str {
+    match type_ {
+        DataType::Int => "int32_t",
+        DataType::Long => "int64_t",
+        DataType::Void => "void",
+        DataType::Indeterminate => unreachable!(),
+    }
+}
+
+/// A constant of `type_`, spelled so that it has that type whatever the host's `int` and `long` are.
+fn constant(val: i64, type_: DataType) -> String {
+    match type_ {
+        DataType::Int if val as i32 == i32::MIN => "INT32_MIN".to_string(),
+        DataType::Int => (val as i32).to_string(),
+        _ if val == i64::MIN => "INT64_MIN".to_string(),
+        _ => format!("INT64_C({})", val),
+    }
+}
+
+/// Lowers one function, one statement per instruction:
+/// every parameter, local and temporary is a local of its own, declared up front,
+/// variables named `name__id` and temporaries `_tN`,
+/// labels and jumps are kept as they are with `goto`.
+struct FuncEmitter<'a> {
+    tac: &'a TacTopLevel,
+    output: String,
+    /// labels which are jumped to, the others are left out
+    targets: HashSet<TacLabelOperand>,
+}
+
+impl<'a> FuncEmitter<'a> {
+    fn new(tac: &'a TacTopLevel) -> Self {
+        Self {
+            tac,
+            output: String::new(),
+            targets: HashSet::new(),
+        }
+    }
+
+    fn emit(mut self, name: &str, func: &TacFunction) -> String {
+        let TacFunction::Defined { return_type, params, local_vars, body, .. } = func else {
+            unreachable!()
+        };
+
+        let param_list = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.iter()
+                .map(|param| format!("{} {}__{}", c_type(param.data_type), self.tac.strtb.get(param.name).unwrap(), param.local_id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        self.output.push_str(&format!("{}{} {}({}) {{\n", storage_class(func), c_type(*return_type), name, param_list));
+
+        let vars = local_vars.values()
+            .map(|var| (var.local_id, (var.name, var.data_type)))
+            .collect::<BTreeMap<_, _>>();
+        let mut temps = BTreeMap::new();
+        for operand in body.iter().flat_map(TacInsn::operands) {
+            match *operand {
+                // where the result of a `void` call would go
+                TacOperand::Temp(_, DataType::Void) => (),
+                TacOperand::Temp(id, data_type) => {
+                    temps.entry(id).or_insert(data_type);
+                },
+                _ => (),
+            }
+        }
+        // zeroed, as reading an uninitialized local would let the host compiler assume anything
+        for (&id, &(name, data_type)) in &vars {
+            self.line(format!("{} {}__{} = 0;", c_type(data_type), self.tac.strtb.get(name).unwrap(), id));
+        }
+        for (&id, &data_type) in &temps {
+            self.line(format!("{} _t{} = 0;", c_type(data_type), id));
+        }
+
+        for insn in body {
+            if let TacInsn::Jump(label) |
+                TacInsn::BranchIfZero { label, .. } |
+                TacInsn::BranchNotZero { label, .. } = insn
+            {
+                self.targets.insert(*label);
+            }
+        }
+        for insn in body {
+            self.emit_insn(insn, *return_type);
+        }
+
+        self.output.push_str("}\n");
+        self.output
+    }
+
+    fn emit_insn(&mut self, insn: &TacInsn, return_type: DataType) {
+        match insn {
+            TacInsn::Return(val) => {
+                if let DataType::Void = return_type {
+                    self.line("return;".to_string());
+                } else {
+                    let val = self.value(val, return_type);
+                    self.line(format!("return {};", val));
+                }
+            },
+            TacInsn::Unary { op, src, dst } => {
+                // `!` produces an `int`, the others keep the type of their operand
+                let type_ = match op {
+                    TacUnaryOp::Not => operand_type(src),
+                    _ => operand_type(dst),
+                };
+                let src = self.value(src, type_);
+                let result = match op {
+                    TacUnaryOp::Pos => src,
+                    TacUnaryOp::Negate => format!("drcc_neg{}({})", bits(type_), src),
+                    TacUnaryOp::Complement => format!("~{}", src),
+                    TacUnaryOp::Not => format!("!{}", src),
+                };
+                self.assign(dst, result);
+            },
+            TacInsn::Binary { op, left, right, dst } => {
+                let cond = match op {
+                    TacBinaryOp::Ls => Some("<"),
+                    TacBinaryOp::Gt => Some(">"),
+                    TacBinaryOp::LsEq => Some("<="),
+                    TacBinaryOp::GtEq => Some(">="),
+                    TacBinaryOp::Eq => Some("=="),
+                    TacBinaryOp::NotEq => Some("!="),
+                    _ => None,
+                };
+                // comparisons produce an `int`, whatever their operands are
+                let type_ = match (cond, left, right) {
+                    (Some(_), TacOperand::Imm(..), operand) | (Some(_), operand, _) => operand_type(operand),
+                    (None, ..) => operand_type(dst),
+                };
+                let left = self.value(left, type_);
+                let right = self.value(right, type_);
+                let result = match cond {
+                    Some(cond) => format!("{} {} {}", left, cond, right),
+                    None => {
+                        let op = match op {
+                            TacBinaryOp::Add => "add",
+                            TacBinaryOp::Sub => "sub",
+                            TacBinaryOp::Mul => "mul",
+                            TacBinaryOp::Div => "div",
+                            TacBinaryOp::Rem => "rem",
+                            // short-circuited into branches by the code generator
+                            _ => unreachable!(),
+                        };
+                        format!("drcc_{}{}({}, {})", op, bits(type_), left, right)
+                    },
+                };
+                self.assign(dst, result);
+            },
+            TacInsn::Label(label) if !self.targets.contains(label) => (),
+            TacInsn::Label(label) => {
+                // a label has to be followed by a statement, even at the end of the function
+                let label = self.label(label);
+                self.output.push_str(&format!("{}:;\n", label));
+            },
+            TacInsn::Jump(label) => {
+                let label = self.label(label);
+                self.line(format!("goto {};", label));
+            },
+            TacInsn::BranchIfZero { src, label } |
+            TacInsn::BranchNotZero { src, label } => {
+                let cond = if let TacInsn::BranchIfZero { .. } = insn { "==" } else { "!=" };
+                let src = self.value(src, operand_type(src));
+                let label = self.label(label);
+                self.line(format!("if ({} {} 0) goto {};", src, cond, label));
+            },
+            TacInsn::FuncCall { target, args, dst } => {
+                // the prototypes convert the arguments
+                let args = args.iter()
+                    .map(|arg| self.value(arg, operand_type(arg)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let call = format!("{}({})", self.tac.strtb.get(*target).unwrap(), args);
+                let return_type = self.tac.functions[target].type_().return_type;
+                if let DataType::Void = return_type {
+                    self.line(format!("{};", call));
+                } else {
+                    self.assign(dst, call);
+                }
+            },
+            TacInsn::Move { src, dst } => {
+                let src = self.value(src, operand_type(dst));
+                self.assign(dst, src);
+            },
+            TacInsn::Truncate { src, dst } => {
+                let src = self.value(src, DataType::Long);
+                self.assign(dst, format!("drcc_trunc({})", src));
+            },
+            TacInsn::SignExt { src, dst } => {
+                let src = self.value(src, DataType::Int);
+                self.assign(dst, format!("(int64_t) {}", src));
+            },
+            TacInsn::Loc(..) => (),
+        }
+    }
+
+    /// The value of an operand, as `type_` if it is a constant.
+    fn value(&self, operand: &TacOperand, type_: DataType) -> String {
+        match operand {
+            TacOperand::Imm(val) => constant(val.value(), type_),
+            TacOperand::Var { name, local_id: Some(id), .. } => format!("{}__{}", self.tac.strtb.get(*name).unwrap(), id),
+            TacOperand::Var { name, local_id: None, .. } => self.tac.strtb.get(*name).unwrap().to_string(),
+            TacOperand::Temp(id, _) => format!("_t{}", id),
+        }
+    }
+
+    fn assign(&mut self, dst: &TacOperand, value: String) {
+        let dst = self.value(dst, operand_type(dst));
+        self.line(format!("{} = {};", dst, value));
+    }
+
+    fn label(&self, label: &TacLabelOperand) -> String {
+        match label {
+            TacLabelOperand::AutoGen(TacAutoGenLabel::Branch(id)) => format!("bra{}", id),
+            TacLabelOperand::AutoGen(TacAutoGenLabel::Continue(id)) => format!("con{}", id),
+            TacLabelOperand::AutoGen(TacAutoGenLabel::Break(id)) => format!("brk{}", id),
+            TacLabelOperand::Named { name, id } => format!("{}__{}", self.tac.strtb.get(*name).unwrap(), id),
+        }
+    }
+
+    fn line(&mut self, line: String) {
+        self.output.push_str(&format!("    {}\n", line));
+    }
+}
+
+fn bits(type_: DataType) -> usize {
+    match type_ {
+        DataType::Int => 32,
+        _ => 64,
+    }
+}
+
+fn operand_type(operand: &TacOperand) -> DataType {
+    match operand {
+        TacOperand::Imm(Constant::Int(..)) => DataType::Int,
+        TacOperand::Imm(Constant::Long(..)) => DataType::Long,
+        TacOperand::Var { data_type, .. } |
+        TacOperand::Temp(_, data_type) => *data_type,
+    }
+}
@@ -0,0 +1,31 @@
+//! Portable C backend, lowering TAC into C99 for hosts without a RISC-V toolchain,
+//! so the output of the optimizer can be compiled with the host's compiler and cross-checked.
+//! - `int` and `long` are `int32_t` and `int64_t`, arithmetic wraps around through helpers on the unsigned types
+//! - each parameter, local and temporary is a local, static variables are globals
+//! - labels, jumps and branches are kept as they are, with `goto`
+
+mod emit;
+
+pub use emit::emit;
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+    use std::process::Command;
+
+    use crate::driver::{compile_c, Options};
+    use crate::host;
+
+    #[test]
+    fn test_c() {
+        // the output built by the host compiler runs like the program itself
+        host::check("c", &[], |dir, name, source, opt_level| {
+            let options = Options { opt_level, file_name: Some(format!("{}.c", name)), ..Options::default() };
+            let c = dir.join(format!("{}.{}.c", name, opt_level));
+            let exe = dir.join(format!("{}.{}.c.out", name, opt_level));
+            write(&c, compile_c(source.to_string(), &options).unwrap()).unwrap();
+            host::cc(&c, &exe);
+            Command::new(exe)
+        });
+    }
+}
@@ -0,0 +1,26 @@
+//! Graphviz helpers shared by the dumps of the IRs.
+
+/// A node label of left-justified lines, with quotes and backslashes escaped.
+pub fn dot_label<S: AsRef<str>>(lines: impl IntoIterator<Item = S>) -> String {
+    let mut label = String::new();
+    for line in lines {
+        for c in line.as_ref().chars() {
+            match c {
+                '"' | '\\' => {
+                    label.push('\\');
+                    label.push(c);
+                },
+                '\t' => label.push(' '),
+                _ => label.push(c),
+            }
+        }
+        label.push_str("\\l");
+    }
+    label
+}
+
+/// A distinct fill color for each of `count` things, as a Graphviz HSV triple.
+pub fn dot_color(index: usize, count: usize) -> String {
+    let hue = index as f64 / count.max(1) as f64;
+    format!("{:.3} 0.35 1.000", hue)
+}
@@ -11,6 +11,7 @@ pub enum Error {
     Lex(String),
     Parse(String),
     Semantic(String),
+    Link(String),
     // General errors
     Errors(Vec<Error>),
     Unimplemented,
@@ -37,6 +38,7 @@ impl Display for Error {
             Error::Lex(err) => write!(f, "Lexer error: {}", err),
             Error::Parse(err) => write!(f, "Parser error: {}", err),
             Error::Semantic(err) => write!(f, "Semantic error: {}", err),
+            Error::Link(err) => write!(f, "Linker error: {}", err),
             Error::Errors(errors) => {
                 for (i, error) in errors.iter().enumerate() {
                     write!(f, "{}\n", error)?;
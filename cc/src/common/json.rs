@@ -0,0 +1,601 @@
+//! A minimal JSON value, written and parsed by hand for the IR dumps.
+//! Numbers are integers only, objects keep their fields in order.
+
+use std::fmt::Display;
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Values laid out on a line of their own up to this width, broken up over lines otherwise.
+const LINE_WIDTH: usize = 100;
+
+impl Json {
+    pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Json)>) -> Self {
+        Json::Object(fields.into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect())
+    }
+
+    pub fn array<T: Into<Json>>(items: impl IntoIterator<Item = T>) -> Self {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+
+    /// `{"kind": kind, ...fields}`, how enum variants are written.
+    pub fn variant<'a>(kind: &str, fields: impl IntoIterator<Item = (&'a str, Json)>) -> Self {
+        let mut object = vec![("kind".to_string(), Json::from(kind))];
+        object.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+        Json::Object(object)
+    }
+
+    /// An enum variant by its name, `GtEq` as `"gt_eq"`, any fields left out.
+    pub fn unit(value: impl std::fmt::Debug) -> Self {
+        let mut name = String::new();
+        let debug = format!("{:?}", value);
+        for (idx, c) in debug.chars().take_while(char::is_ascii_alphanumeric).enumerate() {
+            if c.is_ascii_uppercase() {
+                if idx > 0 {
+                    name.push('_');
+                }
+                name.push(c.to_ascii_lowercase());
+            } else {
+                name.push(c);
+            }
+        }
+        Json::Str(name)
+    }
+
+    /// The one of `values` written as `self` with `unit`.
+    pub fn to_unit<T: std::fmt::Debug + Copy>(&self, values: &[T]) -> Result<T> {
+        values.iter()
+            .find(|&&value| Json::unit(value) == *self)
+            .copied()
+            .ok_or_else(|| self.expected(&format!("one of {}", Json::array(values.iter().map(|&value| Json::unit(value))).compact())))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The field `key`, which must be there.
+    pub fn field(&self, key: &str) -> Result<&Json> {
+        match self {
+            Json::Object(..) => self.get(key)
+                .ok_or_else(|| Error::Other(format!("JSON: missing field '{}' in {}", key, self.excerpt()))),
+            _ => Err(self.expected("an object")),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        match self {
+            Json::Str(string) => Ok(string),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64> {
+        match self {
+            Json::Int(int) => Ok(*int),
+            _ => Err(self.expected("an integer")),
+        }
+    }
+
+    pub fn as_usize(&self) -> Result<usize> {
+        usize::try_from(self.as_int()?).map_err(|_| self.expected("a non-negative integer"))
+    }
+
+    pub fn as_array(&self) -> Result<&[Json]> {
+        match self {
+            Json::Array(items) => Ok(items),
+            _ => Err(self.expected("an array")),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+
+    /// The `kind` of an enum variant.
+    pub fn kind(&self) -> Result<&str> {
+        self.field("kind")?.as_str()
+    }
+
+    pub fn expected(&self, what: &str) -> Error {
+        Error::Other(format!("JSON: expected {}, found {}", what, self.excerpt()))
+    }
+
+    fn excerpt(&self) -> String {
+        let compact = self.compact();
+        match compact.char_indices().nth(60) {
+            Some((idx, _)) => format!("{}...", &compact[..idx]),
+            None => compact,
+        }
+    }
+
+    fn compact(&self) -> String {
+        match self {
+            Json::Null => "null".to_string(),
+            Json::Bool(value) => value.to_string(),
+            Json::Int(value) => value.to_string(),
+            Json::Str(string) => quote(string),
+            Json::Array(items) => format!("[{}]", items.iter()
+                .map(Json::compact)
+                .collect::<Vec<_>>()
+                .join(", ")),
+            Json::Object(fields) => format!("{{{}}}", fields.iter()
+                .map(|(key, value)| format!("{}: {}", quote(key), value.compact()))
+                .collect::<Vec<_>>()
+                .join(", ")),
+        }
+    }
+
+    fn write(&self, output: &mut String, indent: usize) {
+        let compact = self.compact();
+        let empty = matches!(self, Json::Array(items) if items.is_empty())
+            || matches!(self, Json::Object(fields) if fields.is_empty());
+        if empty || indent + compact.len() <= LINE_WIDTH {
+            output.push_str(&compact);
+            return;
+        }
+
+        let pad = " ".repeat(indent + 2);
+        match self {
+            Json::Array(items) => {
+                output.push_str("[\n");
+                for (idx, item) in items.iter().enumerate() {
+                    output.push_str(&pad);
+                    item.write(output, indent + 2);
+                    output.push_str(if idx + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                output.push_str(&" ".repeat(indent));
+                output.push(']');
+            },
+            Json::Object(fields) => {
+                output.push_str("{\n");
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    output.push_str(&pad);
+                    output.push_str(&quote(key));
+                    output.push_str(": ");
+                    value.write(output, indent + 2);
+                    output.push_str(if idx + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                output.push_str(&" ".repeat(indent));
+                output.push('}');
+            },
+            _ => output.push_str(&compact),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("trailing characters after the value"));
+        }
+        Ok(value)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        self.write(&mut output, 0);
+        write!(f, "{}", output)
+    }
+}
+
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Nesting past this is rejected rather than overflowing the stack of the recursive parser.
+const MAX_DEPTH: usize = 256;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: &str) -> Error {
+        Error::parse(format!("JSON: {}", msg), Span::new(self.line, self.column))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+            self.bump();
+        }
+    }
+
+    /// `depth` is the number of arrays and objects the value is nested in.
+    fn value(&mut self, depth: usize) -> Result<Json> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{' | '[') if depth == MAX_DEPTH => Err(self.error(&format!("nested deeper than {} levels", MAX_DEPTH))),
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => self.string().map(Json::Str),
+            Some('-' | '0'..='9') => self.int(),
+            Some('a'..='z') => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_lowercase()) {
+                    self.bump();
+                }
+                match self.chars[start..self.pos].iter().collect::<String>().as_str() {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    word => Err(self.error(&format!("unexpected '{}'", word))),
+                }
+            },
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('u') => {
+                        let code = self.hex4()?;
+                        // a surrogate pair spells out a character beyond the BMP
+                        let code = if (0xd800..0xdc00).contains(&code) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4()?;
+                            0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                        } else {
+                            code
+                        };
+                        string.push(char::from_u32(code).ok_or_else(|| self.error("invalid escape"))?);
+                    },
+                    _ => return Err(self.error("invalid escape")),
+                },
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.bump()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn int(&mut self) -> Result<Json> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.bump();
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+        }
+        if self.peek().is_some_and(|c| matches!(c, '.' | 'e' | 'E')) {
+            return Err(self.error("only integers are supported"));
+        }
+        self.chars[start..self.pos].iter()
+            .collect::<String>()
+            .parse()
+            .map(Json::Int)
+            .map_err(|_| self.error("invalid integer"))
+    }
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Self {
+        Json::Str(string.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(string: String) -> Self {
+        Json::Str(string)
+    }
+}
+
+impl From<&String> for Json {
+    fn from(string: &String) -> Self {
+        Json::Str(string.clone())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Self {
+        Json::Int(value)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<isize> for Json {
+    fn from(value: isize) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+// The types shared by the IRs.
+
+impl From<Span> for Json {
+    fn from(span: Span) -> Self {
+        Json::object([
+            ("line", span.line.into()),
+            ("column", span.column.into()),
+            ("length", span.length.into()),
+        ])
+    }
+}
+
+impl TryFrom<&Json> for Span {
+    type Error = Error;
+
+    fn try_from(json: &Json) -> Result<Self> {
+        let span = Span::new(json.field("line")?.as_usize()?, json.field("column")?.as_usize()?);
+        match json.get("length") {
+            Some(length) if !length.is_null() => Ok(span.with_length(length.as_usize()?)),
+            _ => Ok(span),
+        }
+    }
+}
+
+impl From<DataType> for Json {
+    fn from(data_type: DataType) -> Self {
+        Json::Str(data_type.to_string())
+    }
+}
+
+impl TryFrom<&Json> for DataType {
+    type Error = Error;
+
+    fn try_from(json: &Json) -> Result<Self> {
+        match json.as_str()? {
+            "int" => Ok(DataType::Int),
+            "long" => Ok(DataType::Long),
+            "void" => Ok(DataType::Void),
+            "indeterminate" => Ok(DataType::Indeterminate),
+            _ => Err(json.expected("a type")),
+        }
+    }
+}
+
+impl From<Linkage> for Json {
+    fn from(linkage: Linkage) -> Self {
+        Json::Str(linkage.to_string())
+    }
+}
+
+impl TryFrom<&Json> for Linkage {
+    type Error = Error;
+
+    fn try_from(json: &Json) -> Result<Self> {
+        match json.as_str()? {
+            "internal" => Ok(Linkage::Internal),
+            "external" => Ok(Linkage::External),
+            _ => Err(json.expected("a linkage")),
+        }
+    }
+}
+
+impl From<StorageClass> for Json {
+    fn from(storage_class: StorageClass) -> Self {
+        match storage_class {
+            StorageClass::Static => "static".into(),
+            StorageClass::Extern => "extern".into(),
+            StorageClass::Unspecified => "unspecified".into(),
+        }
+    }
+}
+
+impl From<Constant> for Json {
+    fn from(constant: Constant) -> Self {
+        match constant {
+            Constant::Int(value) => Json::object([("type", "int".into()), ("value", value.into())]),
+            Constant::Long(value) => Json::object([("type", "long".into()), ("value", value.into())]),
+        }
+    }
+}
+
+impl TryFrom<&Json> for Constant {
+    type Error = Error;
+
+    fn try_from(json: &Json) -> Result<Self> {
+        let value = json.field("value")?;
+        match DataType::try_from(json.field("type")?)? {
+            DataType::Int => i32::try_from(value.as_int()?)
+                .map(Constant::Int)
+                .map_err(|_| value.expected("an int")),
+            DataType::Long => Ok(Constant::Long(value.as_int()?)),
+            _ => Err(json.expected("an int or long constant")),
+        }
+    }
+}
+
+impl From<InitVal> for Json {
+    fn from(init: InitVal) -> Self {
+        match init {
+            InitVal::Const(constant) => constant.into(),
+            InitVal::Tentative => "tentative".into(),
+            InitVal::None => Json::Null,
+        }
+    }
+}
+
+impl TryFrom<&Json> for InitVal {
+    type Error = Error;
+
+    fn try_from(json: &Json) -> Result<Self> {
+        match json {
+            Json::Null => Ok(InitVal::None),
+            Json::Str(string) if string == "tentative" => Ok(InitVal::Tentative),
+            _ => Constant::try_from(json).map(InitVal::Const),
+        }
+    }
+}
+
+impl From<&FuncType> for Json {
+    fn from(type_: &FuncType) -> Self {
+        Json::object([
+            ("return_type", type_.return_type.into()),
+            ("param_types", Json::array(type_.param_types.iter().copied())),
+        ])
+    }
+}
+
+impl TryFrom<&Json> for FuncType {
+    type Error = Error;
+
+    fn try_from(json: &Json) -> Result<Self> {
+        Ok(FuncType {
+            return_type: json.field("return_type")?.try_into()?,
+            param_types: json.field("param_types")?
+                .as_array()?
+                .iter()
+                .map(DataType::try_from)
+                .collect::<Result<_>>()?,
+        })
+    }
+}
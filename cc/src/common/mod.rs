@@ -2,6 +2,8 @@ mod span;
 mod token;
 mod error;
 mod string_pool;
+mod dot;
+mod json;
 
 use std::{fmt::Display, ops::{Add, Div, Mul, Rem, Sub}};
 
@@ -9,6 +11,8 @@ pub use span::Span;
 pub use token::{RawToken, Token, TokenType};
 pub use error::{Error, Result};
 pub use string_pool::{StringPool, StrDescriptor};
+pub use dot::{dot_label, dot_color};
+pub use json::Json;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DataType {
@@ -56,6 +60,25 @@ impl DataType {
     }
 }
 
+/// Sizes of the C integer types, `int` is 32-bit and `long long` 64-bit in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataModel {
+    /// 64-bit `long`
+    #[default]
+    Lp64,
+    /// 32-bit `long`, the same type as `int`
+    Ilp32,
+}
+
+impl DataModel {
+    pub fn long(&self) -> DataType {
+        match self {
+            DataModel::Lp64 => DataType::Long,
+            DataModel::Ilp32 => DataType::Int,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FuncType {
     pub return_type: DataType,
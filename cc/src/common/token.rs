@@ -26,6 +26,7 @@ pub enum RawToken {
     // [0-9]+
     IntLiteral(i32),
     LongLiteral(i64),
+    LongLongLiteral(i64),
     // [a-zA-Z_][a-zA-Z0-9_]*
     Identifier(StrDescriptor),
 
@@ -77,6 +78,7 @@ pub enum TokenType {
     Void,
     IntLiteral,
     LongLiteral,
+    LongLongLiteral,
     Identifier,
 
     Nothing,
@@ -138,6 +140,7 @@ impl Token {
             RawToken::Void => TokenType::Void,
             RawToken::IntLiteral(_) => TokenType::IntLiteral,
             RawToken::LongLiteral(_) => TokenType::LongLiteral,
+            RawToken::LongLongLiteral(_) => TokenType::LongLongLiteral,
             RawToken::Identifier(_) => TokenType::Identifier,
             RawToken::Nothing => TokenType::Nothing,
         }
@@ -240,7 +243,8 @@ impl RawToken {
     pub fn as_constant(self) -> Constant {
         match self {
             RawToken::IntLiteral(value) => Constant::Int(value),
-            RawToken::LongLiteral(value) => Constant::Long(value),
+            RawToken::LongLiteral(value) |
+            RawToken::LongLongLiteral(value) => Constant::Long(value),
             _ => panic!("Internal error: expected a constant token, found {:?}", self),
         }
     }
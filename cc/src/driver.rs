@@ -0,0 +1,283 @@
+//! Compiler driver, running all the stages from C source to RISC-V (RV64 or RV32) or x86-64 assembly,
+//! WebAssembly text, an object file, LLVM IR or C, and linking objects into an executable.
+//! Graphviz dumps of the CFGs and interference graphs are generated along the way on request,
+//! and every IR can be written out as JSON, the TAC also read back in from it.
+
+use crate::{
+    common::*,
+    lex::Lexer,
+    ast::{AstParser, AstTopLevel},
+    sem::{HirParser, HirTopLevel},
+    tac::{TacCodeGen, TacTopLevel},
+    lir::{LirCodeGen, LirTopLevel, RegAllocator, MachineModel},
+    asm::{CodeGen as AsmCodeGen, AsmTopLevel, Xlen},
+    x86::{CodeGen as X86CodeGen, X86TopLevel},
+    elf::Object,
+};
+
+/// The machine code is generated for, `riscv64-linux` by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    #[default]
+    Riscv64,
+    Riscv32,
+    X86_64,
+    Wasm32,
+}
+
+impl Target {
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "riscv64" | "riscv64-linux" => Some(Target::Riscv64),
+            "riscv32" | "riscv32-unknown-elf" => Some(Target::Riscv32),
+            "x86_64" | "x86_64-linux" => Some(Target::X86_64),
+            "wasm32" | "wasm32-unknown-unknown" => Some(Target::Wasm32),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Riscv64 => "riscv64-linux",
+            Target::Riscv32 => "riscv32",
+            Target::X86_64 => "x86_64-linux",
+            Target::Wasm32 => "wasm32",
+        }
+    }
+
+    /// `long` is 32-bit on RV32 and WebAssembly (ILP32), and 64-bit otherwise (LP64).
+    pub fn data_model(&self) -> DataModel {
+        match self {
+            Target::Riscv32 | Target::Wasm32 => DataModel::Ilp32,
+            Target::Riscv64 | Target::X86_64 => DataModel::Lp64,
+        }
+    }
+
+    /// The width of the RISC-V registers.
+    pub fn xlen(&self) -> Xlen {
+        match self {
+            Target::Riscv32 => Xlen::Rv32,
+            Target::Riscv64 | Target::X86_64 | Target::Wasm32 => Xlen::Rv64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `--target=riscv64-linux|riscv32|x86_64-linux|wasm32`, the RISC-V only options are ignored for the others
+    pub target: Target,
+    /// `-O<level>`, tac optimizations and the peephole pass are enabled from level 1
+    pub opt_level: u8,
+    /// `-fregalloc=linear|graph`, by default linear scan below level 2 and graph coloring from it
+    pub regalloc: Option<RegAllocator>,
+    /// `-fomit-frame-pointer`, address the frame off `sp` and keep `s0` free of frame setup
+    pub omit_frame_pointer: bool,
+    /// `-fschedule-insns`, list scheduling of basic blocks after register allocation, by default from level 2
+    pub schedule_insns: Option<bool>,
+    /// `-mtune=generic|sifive-u74`, the machine model instructions are scheduled for
+    pub tune: MachineModel,
+    /// `-g`, `.loc` directives and DWARF debug info, on riscv64-linux
+    pub debug_info: bool,
+    /// name of the source file in debug info
+    pub file_name: Option<String>,
+    /// `--dump-cfg=dot`, Graphviz CFGs of every function, see `dump`
+    pub dump_cfg: bool,
+    /// `--dump-rig=dot`, Graphviz interference graphs of every function, see `dump`
+    pub dump_rig: bool,
+    /// the source is TAC in the JSON form of `--emit=json-tac` rather than C, for `.json` inputs
+    pub tac_input: bool,
+}
+
+impl Options {
+    pub fn regalloc(&self) -> RegAllocator {
+        self.regalloc.unwrap_or(if self.opt_level >= 2 {
+            RegAllocator::Graph
+        } else {
+            RegAllocator::Linear
+        })
+    }
+
+    /// The machine model to schedule for, if scheduling is enabled.
+    pub fn schedule(&self) -> Option<&MachineModel> {
+        self.schedule_insns
+            .unwrap_or(self.opt_level >= 2)
+            .then_some(&self.tune)
+    }
+}
+
+/// Compiles a translation unit into assembly code, a WebAssembly text module for wasm32.
+pub fn compile(source: String, options: &Options) -> Result<String> {
+    match options.target {
+        Target::Riscv64 | Target::Riscv32 => Ok(compile_asm(source, options)?.emit()),
+        Target::X86_64 => Ok(compile_x86(source, options)?.emit()),
+        Target::Wasm32 => Ok(crate::wasm::emit(&compile_tac(source, options)?)),
+    }
+}
+
+/// Compiles and assembles a translation unit into an ELF relocatable object.
+/// Only RV64 has an assembler.
+pub fn compile_object(source: String, options: &Options) -> Result<Vec<u8>> {
+    if options.target != Target::Riscv64 {
+        return Err(Error::Other(format!("objects can't be generated for {}, use -S", options.target.name())));
+    }
+    Ok(compile_asm(source, options)?.assemble().to_bytes())
+}
+
+/// Compiles a translation unit into an LLVM IR module, from its TAC.
+pub fn compile_llvm(source: String, options: &Options) -> Result<String> {
+    let tac = compile_tac(source, options)?;
+    let file = options.file_name.as_deref().unwrap_or("<source>");
+    Ok(crate::llvm::emit(&tac, file))
+}
+
+/// Compiles a translation unit into C99, from its TAC.
+pub fn compile_c(source: String, options: &Options) -> Result<String> {
+    let tac = compile_tac(source, options)?;
+    let file = options.file_name.as_deref().unwrap_or("<source>");
+    Ok(crate::c::emit(&tac, file))
+}
+
+/// A Graphviz graph of one function.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    pub function: String,
+    /// `tac` or `lir` for CFGs, `rig` for interference graphs
+    pub kind: &'static str,
+    pub dot: String,
+}
+
+/// Graphviz graphs of every function in a translation unit,
+/// its TAC and LIR CFGs with `dump_cfg` and its interference graphs with `dump_rig`.
+/// Only RISC-V targets have a LIR, the others get their TAC CFGs alone.
+pub fn dump(source: String, options: &Options) -> Result<Vec<Dump>> {
+    let tac = compile_tac(source, options)?;
+
+    let mut dumps = vec![];
+    if options.dump_cfg {
+        for (name, dot) in tac.dump_cfg() {
+            let function = tac.strtb.get(name).unwrap().clone();
+            dumps.push(Dump { function, kind: "tac", dot });
+        }
+    }
+    if !matches!(options.target, Target::Riscv64 | Target::Riscv32) || !(options.dump_cfg || options.dump_rig) {
+        return Ok(dumps);
+    }
+
+    let codegen_parse = LirCodeGen::new();
+    let (lir, codegen_regalloc) = codegen_parse.parse_with(tac, options.target.xlen());
+    let (lir, _, alloc_dumps) = codegen_regalloc.alloc_dumped(lir, options.regalloc());
+    for alloc_dump in alloc_dumps {
+        let function = lir.strtb.get(alloc_dump.name).unwrap().clone();
+        if options.dump_cfg {
+            dumps.push(Dump { function: function.clone(), kind: "lir", dot: alloc_dump.cfg });
+        }
+        if options.dump_rig {
+            dumps.push(Dump { function, kind: "rig", dot: alloc_dump.rig });
+        }
+    }
+    Ok(dumps)
+}
+
+/// An IR to write out as JSON, `--emit=json-{ast,hir,tac,lir,asm}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ir {
+    Ast,
+    Hir,
+    Tac,
+    /// after register allocation and canonicalization, on RISC-V targets
+    Lir,
+    /// as it would be emitted, on RISC-V targets
+    Asm,
+}
+
+/// Compiles a translation unit as far as `ir` and writes it out as JSON, names and spans included.
+/// TAC input has neither an AST nor a HIR.
+pub fn compile_json(source: String, options: &Options, ir: Ir) -> Result<String> {
+    let riscv = matches!(options.target, Target::Riscv64 | Target::Riscv32);
+    let json = match ir {
+        Ir::Ast | Ir::Hir if options.tac_input => {
+            return Err(Error::Other("TAC input has no AST or HIR to emit".to_string()));
+        },
+        Ir::Lir | Ir::Asm if !riscv => {
+            return Err(Error::Other(format!("{} has no LIR or RISC-V assembly to emit", options.target.name())));
+        },
+        Ir::Ast => compile_ast(source, options)?.to_json(),
+        Ir::Hir => compile_hir(source, options)?.to_json(),
+        Ir::Tac => compile_tac(source, options)?.to_json(),
+        Ir::Lir => compile_lir(source, options)?.to_json(),
+        Ir::Asm => compile_asm(source, options)?.to_json(),
+    };
+    Ok(format!("{}\n", json))
+}
+
+/// Links objects, along with the runtime, into a static executable.
+pub fn link(objects: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let objects = objects.iter()
+        .map(|bytes| Object::from_bytes(bytes))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(crate::link::link(objects)?.to_bytes())
+}
+
+fn compile_ast(source: String, options: &Options) -> Result<AstTopLevel> {
+    let lexer = Lexer::new(source);
+    let (tokens, strtb) = lexer.lex()?;
+
+    let parser = AstParser::new(tokens, strtb);
+    parser.parse_prog_with(options.target.data_model())
+}
+
+fn compile_hir(source: String, options: &Options) -> Result<HirTopLevel> {
+    let ast = compile_ast(source, options)?;
+
+    let parser = HirParser::new();
+    parser.parse(ast)
+}
+
+fn compile_tac(source: String, options: &Options) -> Result<TacTopLevel> {
+    let codegen = TacCodeGen::new();
+    let (mut tac, optimizer) = if options.tac_input {
+        codegen.parse_json(&source)?
+    } else {
+        codegen.parse_with(compile_hir(source, options)?, options.debug_info)
+    };
+    if options.opt_level >= 1 {
+        tac = optimizer.optimize_all(tac);
+    }
+    Ok(tac)
+}
+
+fn compile_lir(source: String, options: &Options) -> Result<LirTopLevel> {
+    let tac = compile_tac(source, options)?;
+
+    let codegen_parse = LirCodeGen::new();
+    let (lir, codegen_regalloc) = codegen_parse.parse_with(tac, options.target.xlen());
+    let (lir, codegen_spill) = codegen_regalloc.alloc_with(lir, options.regalloc());
+    let (lir, codegen_canonic) = codegen_spill.spill_with(lir, options.omit_frame_pointer);
+    Ok(codegen_canonic.canonic_with(lir, options.schedule()))
+}
+
+fn compile_asm(source: String, options: &Options) -> Result<AsmTopLevel> {
+    let lir = compile_lir(source, options)?;
+
+    let codegen = AsmCodeGen::new();
+    let (mut asm, optimizer) = codegen.parse(lir);
+    if options.opt_level >= 1 {
+        asm = optimizer.peephole(asm);
+    }
+
+    // the debug info is laid out for 64-bit addresses
+    if options.debug_info && options.target == Target::Riscv64 {
+        asm.source_file = Some(options.file_name.clone().unwrap_or_else(|| "<source>".to_string()));
+    }
+
+    Ok(asm.relax())
+}
+
+fn compile_x86(source: String, options: &Options) -> Result<X86TopLevel> {
+    let tac = compile_tac(source, options)?;
+
+    let codegen_parse = X86CodeGen::new();
+    let (x86, codegen_regalloc) = codegen_parse.parse(tac);
+    let (x86, codegen_frame) = codegen_regalloc.alloc(x86);
+    Ok(codegen_frame.frame(x86))
+}
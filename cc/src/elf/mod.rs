@@ -0,0 +1,201 @@
+//! ELF64 relocatable objects and executables for RISC-V.
+//! `Object` is a plain description of the sections, symbols and relocations,
+//! the assembler (`asm/encode.rs`) fills it in and `write.rs` lays it out in the file format,
+//! `read.rs` reads it back for the linker (`link`), which puts together an `Executable`.
+
+mod write;
+mod read;
+mod reloc;
+
+/// `e_machine` of RISC-V
+pub const EM_RISCV: u16 = 243;
+/// `e_flags`, double-precision floating-point ABI (lp64d), as the C library expects
+pub const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x4;
+/// segments are mapped in pages of this size, so file offsets and addresses agree below it
+pub const PAGE_SIZE: u64 = 0x1000;
+
+#[derive(Debug, Default)]
+pub struct Object {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+}
+
+/// A statically linked program, with everything at its final address.
+#[derive(Debug)]
+pub struct Executable {
+    pub entry: u64,
+    /// sections with their addresses, each loaded as a segment of its own
+    pub sections: Vec<(Section, u64)>,
+    /// addresses as values, sections index into `Executable::sections`
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Text,
+    Data,
+    Rodata,
+    /// takes no room in the file
+    Bss,
+    /// DWARF debug info, not loaded
+    DebugInfo,
+    DebugAbbrev,
+    DebugLine,
+}
+
+impl SectionKind {
+    pub fn is_debug(self) -> bool {
+        matches!(self, SectionKind::DebugInfo | SectionKind::DebugAbbrev | SectionKind::DebugLine)
+    }
+}
+
+#[derive(Debug)]
+pub struct Section {
+    pub kind: SectionKind,
+    /// contents, all zero for `.bss`
+    pub data: Vec<u8>,
+    pub align: usize,
+    pub relocs: Vec<Reloc>,
+}
+
+impl Section {
+    pub fn new(kind: SectionKind) -> Self {
+        Section {
+            kind,
+            data: vec![],
+            align: 1,
+            relocs: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            SectionKind::Text => ".text",
+            SectionKind::Data => ".data",
+            SectionKind::Rodata => ".rodata",
+            SectionKind::Bss => ".bss",
+            SectionKind::DebugInfo => ".debug_info",
+            SectionKind::DebugAbbrev => ".debug_abbrev",
+            SectionKind::DebugLine => ".debug_line",
+        }
+    }
+
+    /// Pads the contents to `align` and returns the offset there.
+    pub fn align_to(&mut self, align: usize) -> usize {
+        self.align = self.align.max(align);
+        let offset = self.data.len().next_multiple_of(align);
+        self.data.resize(offset, 0);
+        offset
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Local,
+    Global,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    NoType,
+    Object,
+    Func,
+    /// the start of a section, nameless
+    Section,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    /// index into `Object::sections`, `None` if defined elsewhere
+    pub section: Option<usize>,
+    pub value: u64,
+    pub size: u64,
+    pub binding: Binding,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reloc {
+    pub offset: u64,
+    /// index into `Object::symbols`
+    pub symbol: usize,
+    pub kind: RelocKind,
+    pub addend: i64,
+}
+
+/// `R_RISCV_*` relocation types, with their numbers in the psABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocKind {
+    /// 32-bit absolute address, or offset into a debug section
+    Abs32 = 1,
+    /// 64-bit absolute address
+    Abs64 = 2,
+    /// B-type branch offset
+    Branch = 16,
+    /// J-type `jal` offset
+    Jal = 17,
+    /// `auipc` and `jalr` pair of a `call` or `tail`
+    CallPlt = 19,
+    /// `auipc` of a pc-relative address
+    PcrelHi20 = 23,
+    /// I-type low part of a pc-relative address, the symbol is the `auipc` it pairs with
+    PcrelLo12I = 24,
+    /// `lui` of an absolute address
+    Hi20 = 26,
+    /// I-type low part of an absolute address
+    Lo12I = 27,
+    /// S-type low part of an absolute address
+    Lo12S = 28,
+}
+
+impl Object {
+    /// Index of the section of `kind`, added if there's none yet.
+    pub fn section(&mut self, kind: SectionKind) -> usize {
+        match self.sections.iter().position(|section| section.kind == kind) {
+            Some(idx) => idx,
+            None => {
+                self.sections.push(Section::new(kind));
+                self.sections.len() - 1
+            },
+        }
+    }
+
+    /// Index of the symbol of `section` itself, added if there's none yet.
+    pub fn section_symbol(&mut self, section: usize) -> usize {
+        let found = self.symbols.iter()
+            .position(|symbol| symbol.kind == SymbolKind::Section && symbol.section == Some(section));
+        match found {
+            Some(idx) => idx,
+            None => {
+                self.symbols.push(Symbol {
+                    name: String::new(),
+                    section: Some(section),
+                    value: 0,
+                    size: 0,
+                    binding: Binding::Local,
+                    kind: SymbolKind::Section,
+                });
+                self.symbols.len() - 1
+            },
+        }
+    }
+
+    /// Index of the symbol called `name`, added as an undefined global if there's none yet.
+    pub fn symbol(&mut self, name: &str) -> usize {
+        match self.symbols.iter().position(|symbol| symbol.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.symbols.push(Symbol {
+                    name: name.to_string(),
+                    section: None,
+                    value: 0,
+                    size: 0,
+                    binding: Binding::Global,
+                    kind: SymbolKind::NoType,
+                });
+                self.symbols.len() - 1
+            },
+        }
+    }
+}
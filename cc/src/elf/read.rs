@@ -0,0 +1,190 @@
+//! Reads a relocatable object back into an `Object`, for the linker.
+//! Only `.text`, `.data`, `.rodata`, `.bss` and the DWARF `.debug_info`, `.debug_abbrev` and `.debug_line`
+//! are kept, other sections are skipped
+//! along with the symbols defined in them, and relocations against those are refused.
+
+use crate::common::*;
+use super::{
+    Object,
+    Section,
+    SectionKind,
+    Symbol,
+    Binding,
+    SymbolKind,
+    Reloc,
+    RelocKind,
+    EM_RISCV,
+};
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+struct SectionHeader {
+    name: u32,
+    type_: u32,
+    offset: usize,
+    size: usize,
+    link: usize,
+    info: usize,
+    align: usize,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn slice(&self, at: usize, len: usize) -> Result<&[u8]> {
+        at.checked_add(len)
+            .and_then(|end| self.bytes.get(at..end))
+            .ok_or_else(|| Error::Link("truncated object file".to_string()))
+    }
+
+    fn u16(&self, at: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.slice(at, 2)?.try_into().unwrap()))
+    }
+
+    fn u32(&self, at: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.slice(at, 4)?.try_into().unwrap()))
+    }
+
+    fn u64(&self, at: usize) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.slice(at, 8)?.try_into().unwrap()))
+    }
+
+    /// The NUL-terminated string at `at`.
+    fn str(&self, at: usize) -> Result<String> {
+        let bytes = self.bytes.get(at..).unwrap_or_default();
+        let len = bytes.iter().position(|&b| b == 0)
+            .ok_or_else(|| Error::Link("unterminated string in object file".to_string()))?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+impl Object {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Object> {
+        let reader = Reader { bytes };
+        // ELFCLASS64, ELFDATA2LSB, ET_REL
+        if reader.slice(0, 6)? != b"\x7fELF\x02\x01" || reader.u16(16)? != 1 || reader.u16(18)? != EM_RISCV {
+            return Err(Error::Link("not a RISC-V ELF64 relocatable object".to_string()));
+        }
+
+        let shoff = reader.u64(0x28)? as usize;
+        let shentsize = reader.u16(0x3a)? as usize;
+        let shnum = reader.u16(0x3c)? as usize;
+        let shstrndx = reader.u16(0x3e)? as usize;
+        let headers = (0..shnum)
+            .map(|idx| {
+                let at = shoff + idx * shentsize;
+                Ok(SectionHeader {
+                    name: reader.u32(at)?,
+                    type_: reader.u32(at + 4)?,
+                    offset: reader.u64(at + 0x18)? as usize,
+                    size: reader.u64(at + 0x20)? as usize,
+                    link: reader.u32(at + 0x28)? as usize,
+                    info: reader.u32(at + 0x2c)? as usize,
+                    align: reader.u64(at + 0x30)? as usize,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let shstrtab = headers.get(shstrndx)
+            .ok_or_else(|| Error::Link("missing section names".to_string()))?;
+
+        let mut obj = Object::default();
+        // our index of each section in the file, if it's kept
+        let mut section_map = vec![None; shnum];
+        for (idx, header) in headers.iter().enumerate() {
+            let kind = match reader.str(shstrtab.offset + header.name as usize)?.as_str() {
+                ".text" => SectionKind::Text,
+                ".data" => SectionKind::Data,
+                ".rodata" => SectionKind::Rodata,
+                ".bss" => SectionKind::Bss,
+                ".debug_info" => SectionKind::DebugInfo,
+                ".debug_abbrev" => SectionKind::DebugAbbrev,
+                ".debug_line" => SectionKind::DebugLine,
+                _ => continue,
+            };
+            let mut section = Section::new(kind);
+            section.align = header.align.max(1);
+            section.data = match kind {
+                SectionKind::Bss => vec![0; header.size],
+                _ => reader.slice(header.offset, header.size)?.to_vec(),
+            };
+            section_map[idx] = Some(obj.sections.len());
+            obj.sections.push(section);
+        }
+
+        // our index of each symbol in the file, if it's kept
+        let mut symbol_map = vec![];
+        if let Some(symtab) = headers.iter().find(|header| header.type_ == SHT_SYMTAB) {
+            let strtab = headers.get(symtab.link)
+                .ok_or_else(|| Error::Link("missing symbol names".to_string()))?;
+            for at in (symtab.offset..symtab.offset + symtab.size).step_by(24) {
+                let name = reader.str(strtab.offset + reader.u32(at)? as usize)?;
+                let info = reader.slice(at + 4, 1)?[0];
+                let shndx = reader.u16(at + 6)? as usize;
+                let section = match shndx {
+                    0 => None,
+                    _ => match section_map.get(shndx) {
+                        Some(&Some(section)) => Some(section),
+                        _ => {
+                            symbol_map.push(None);
+                            continue;
+                        },
+                    },
+                };
+                // the null symbol is skipped
+                if at == symtab.offset || info & 0xf == STT_FILE {
+                    symbol_map.push(None);
+                    continue;
+                }
+                let kind = match info & 0xf {
+                    1 => SymbolKind::Object,
+                    2 => SymbolKind::Func,
+                    STT_SECTION => SymbolKind::Section,
+                    _ => SymbolKind::NoType,
+                };
+                let binding = match info >> 4 {
+                    0 => Binding::Local,
+                    _ => Binding::Global,
+                };
+                symbol_map.push(Some(obj.symbols.len()));
+                obj.symbols.push(Symbol {
+                    name,
+                    section,
+                    value: reader.u64(at + 8)?,
+                    size: reader.u64(at + 16)?,
+                    binding,
+                    kind,
+                });
+            }
+        }
+
+        for header in headers.iter().filter(|header| header.type_ == SHT_RELA) {
+            let Some(&Some(section)) = section_map.get(header.info) else {
+                continue;
+            };
+            for at in (header.offset..header.offset + header.size).step_by(24) {
+                let info = reader.u64(at + 8)?;
+                let type_ = info as u32;
+                let kind = RelocKind::from_type(type_)
+                    .ok_or_else(|| Error::Link(format!("unsupported relocation type {}", type_)))?;
+                let symbol = symbol_map.get((info >> 32) as usize)
+                    .copied()
+                    .flatten()
+                    .ok_or_else(|| Error::Link("relocation against a symbol in an unsupported section".to_string()))?;
+                obj.sections[section].relocs.push(Reloc {
+                    offset: reader.u64(at)?,
+                    symbol,
+                    kind,
+                    addend: reader.u64(at + 16)? as i64,
+                });
+            }
+        }
+
+        Ok(obj)
+    }
+}
@@ -0,0 +1,87 @@
+//! Filling relocated values into instructions, for the assembler's own branches and for the linker.
+
+use super::RelocKind;
+
+impl RelocKind {
+    pub fn from_type(type_: u32) -> Option<Self> {
+        let kind = match type_ {
+            1 => RelocKind::Abs32,
+            2 => RelocKind::Abs64,
+            16 => RelocKind::Branch,
+            17 => RelocKind::Jal,
+            19 => RelocKind::CallPlt,
+            23 => RelocKind::PcrelHi20,
+            24 => RelocKind::PcrelLo12I,
+            26 => RelocKind::Hi20,
+            27 => RelocKind::Lo12I,
+            28 => RelocKind::Lo12S,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Fills `value` into the instruction at the start of `bytes`, or both of them for `CallPlt`,
+    /// or into the word there for `Abs32` and `Abs64`.
+    /// `value` is the offset from the instruction for pc-relative kinds, and the address for absolute ones;
+    /// for `PcrelLo12I` it's the offset its `auipc` adds.
+    /// Returns `false` if `value` is out of reach, leaving `bytes` alone.
+    pub fn apply(self, bytes: &mut [u8], value: i64) -> bool {
+        let lo = value << 52 >> 52;
+        let hi = (value - lo) >> 12;
+        let fits_hi20 = (-(1 << 19)..1 << 19).contains(&hi);
+        match self {
+            RelocKind::Abs32 => {
+                let Ok(word) = u32::try_from(value) else {
+                    return false;
+                };
+                set(bytes, 0, word);
+            },
+            RelocKind::Abs64 => bytes[..8].copy_from_slice(&value.to_le_bytes()),
+            RelocKind::Branch => {
+                if !(-4096..4096).contains(&value) || value % 2 != 0 {
+                    return false;
+                }
+                let imm = value as u32;
+                let imm = (imm >> 12 & 1) << 31 | (imm >> 5 & 0x3f) << 25 | (imm >> 1 & 0xf) << 8 | (imm >> 11 & 1) << 7;
+                set(bytes, 0, get(bytes, 0) & !0xfe000f80 | imm);
+            },
+            RelocKind::Jal => {
+                if !(-(1 << 20)..1 << 20).contains(&value) || value % 2 != 0 {
+                    return false;
+                }
+                let imm = value as u32;
+                let imm = (imm >> 20 & 1) << 31 | (imm >> 1 & 0x3ff) << 21 | (imm >> 11 & 1) << 20 | (imm >> 12 & 0xff) << 12;
+                set(bytes, 0, get(bytes, 0) & 0xfff | imm);
+            },
+            RelocKind::CallPlt => {
+                if !fits_hi20 {
+                    return false;
+                }
+                set(bytes, 0, get(bytes, 0) & 0xfff | (hi as u32) << 12);
+                set(bytes, 4, get(bytes, 4) & 0xfffff | (lo as u32) << 20);
+            },
+            RelocKind::PcrelHi20 | RelocKind::Hi20 => {
+                if !fits_hi20 {
+                    return false;
+                }
+                set(bytes, 0, get(bytes, 0) & 0xfff | (hi as u32) << 12);
+            },
+            RelocKind::PcrelLo12I | RelocKind::Lo12I => {
+                set(bytes, 0, get(bytes, 0) & 0xfffff | (lo as u32) << 20);
+            },
+            RelocKind::Lo12S => {
+                let imm = lo as u32 & 0xfff;
+                set(bytes, 0, get(bytes, 0) & 0x1fff07f | (imm >> 5) << 25 | (imm & 0x1f) << 7);
+            },
+        }
+        true
+    }
+}
+
+fn get(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn set(bytes: &mut [u8], at: usize, word: u32) {
+    bytes[at..at + 4].copy_from_slice(&word.to_le_bytes());
+}
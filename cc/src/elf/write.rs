@@ -0,0 +1,347 @@
+//! Lays out an `Object` as an ELF64 little-endian relocatable file:
+//! the header, the contents of each section, a `.rela` section for each one with relocations,
+//! `.symtab`, `.strtab` and `.shstrtab`, followed by the section headers.
+//! An `Executable` has a program header after the ELF header, loading each section but the debug ones
+//! as a segment, and no relocations.
+
+use super::{
+    Object,
+    Executable,
+    SectionKind,
+    Symbol,
+    Binding,
+    SymbolKind,
+    EM_RISCV,
+    EF_RISCV_FLOAT_ABI_DOUBLE,
+    PAGE_SIZE,
+};
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+const RELA_SIZE: usize = 24;
+
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+#[derive(Default)]
+struct SectionHeader {
+    name: u32,
+    type_: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+/// Names, each terminated by a NUL, starting with the empty one.
+struct StrTab {
+    data: Vec<u8>,
+}
+
+impl StrTab {
+    fn new() -> Self {
+        StrTab { data: vec![0] }
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        if name.is_empty() {
+            return 0;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend(name.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+impl Object {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sym_index = symbol_order(&self.symbols);
+        let mut shstrtab = StrTab::new();
+        let mut out = vec![0; EHDR_SIZE];
+        let mut headers = vec![SectionHeader::default()];
+
+        // section headers: contents, then relocations, symbols and strings
+        for section in self.sections.iter() {
+            let (type_, flags) = section_type(section.kind);
+            let offset = align(&mut out, section.align);
+            if section.kind != SectionKind::Bss {
+                out.extend(&section.data);
+            }
+            headers.push(SectionHeader {
+                name: shstrtab.add(section.name()),
+                type_,
+                flags,
+                offset: offset as u64,
+                size: section.data.len() as u64,
+                align: section.align as u64,
+                ..SectionHeader::default()
+            });
+        }
+
+        let rela_count = self.sections.iter().filter(|section| !section.relocs.is_empty()).count();
+        let symtab_idx = 1 + self.sections.len() + rela_count;
+        for (idx, section) in self.sections.iter().enumerate() {
+            if section.relocs.is_empty() {
+                continue;
+            }
+            let offset = align(&mut out, 8);
+            for reloc in section.relocs.iter() {
+                let info = (sym_index[reloc.symbol] as u64) << 32 | reloc.kind as u64;
+                out.extend(reloc.offset.to_le_bytes());
+                out.extend(info.to_le_bytes());
+                out.extend(reloc.addend.to_le_bytes());
+            }
+            headers.push(SectionHeader {
+                name: shstrtab.add(&format!(".rela{}", section.name())),
+                type_: SHT_RELA,
+                flags: SHF_INFO_LINK,
+                offset: offset as u64,
+                size: (section.relocs.len() * RELA_SIZE) as u64,
+                link: symtab_idx as u32,
+                info: (idx + 1) as u32,
+                align: 8,
+                entsize: RELA_SIZE as u64,
+                ..SectionHeader::default()
+            });
+        }
+
+        write_symbols(&mut out, &self.symbols, &sym_index, &mut headers, &mut shstrtab);
+        finish(out, headers, shstrtab, ET_REL, 0, 0)
+    }
+}
+
+impl Executable {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sym_index = symbol_order(&self.symbols);
+        let mut shstrtab = StrTab::new();
+        // debug sections come last
+        let loaded = self.sections.iter()
+            .filter(|(section, _)| !section.kind.is_debug())
+            .count();
+        let mut out = vec![0; EHDR_SIZE + PHDR_SIZE * loaded];
+        let mut headers = vec![SectionHeader::default()];
+
+        for (idx, (section, addr)) in self.sections.iter().enumerate() {
+            let (type_, flags) = section_type(section.kind);
+            let offset = if section.kind.is_debug() {
+                align(&mut out, section.align)
+            } else {
+                // the same offset into a page as the address
+                let len = out.len() as u64;
+                (len + addr.wrapping_sub(len) % PAGE_SIZE) as usize
+            };
+            out.resize(offset, 0);
+            if section.kind != SectionKind::Bss {
+                out.extend(&section.data);
+            }
+            headers.push(SectionHeader {
+                name: shstrtab.add(section.name()),
+                type_,
+                flags,
+                addr: *addr,
+                offset: offset as u64,
+                size: section.data.len() as u64,
+                align: section.align as u64,
+                ..SectionHeader::default()
+            });
+
+            let segment_flags = match section.kind {
+                SectionKind::Text => PF_R | PF_X,
+                SectionKind::Rodata => PF_R,
+                SectionKind::Data | SectionKind::Bss => PF_R | PF_W,
+                SectionKind::DebugInfo | SectionKind::DebugAbbrev | SectionKind::DebugLine => continue,
+            };
+            let file_size = if section.kind == SectionKind::Bss { 0 } else { section.data.len() };
+            let phdr = &mut out[EHDR_SIZE + PHDR_SIZE * idx..][..PHDR_SIZE];
+            let mut fields = vec![];
+            fields.extend(PT_LOAD.to_le_bytes());
+            fields.extend(segment_flags.to_le_bytes());
+            fields.extend((offset as u64).to_le_bytes());
+            // p_vaddr, p_paddr
+            fields.extend(addr.to_le_bytes());
+            fields.extend(addr.to_le_bytes());
+            fields.extend((file_size as u64).to_le_bytes());
+            fields.extend((section.data.len() as u64).to_le_bytes());
+            fields.extend(PAGE_SIZE.to_le_bytes());
+            phdr.copy_from_slice(&fields);
+        }
+
+        write_symbols(&mut out, &self.symbols, &sym_index, &mut headers, &mut shstrtab);
+        finish(out, headers, shstrtab, ET_EXEC, self.entry, loaded)
+    }
+}
+
+fn section_type(kind: SectionKind) -> (u32, u64) {
+    match kind {
+        SectionKind::Text => (SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR),
+        SectionKind::Data => (SHT_PROGBITS, SHF_ALLOC | SHF_WRITE),
+        SectionKind::Rodata => (SHT_PROGBITS, SHF_ALLOC),
+        SectionKind::Bss => (SHT_NOBITS, SHF_ALLOC | SHF_WRITE),
+        SectionKind::DebugInfo | SectionKind::DebugAbbrev | SectionKind::DebugLine => (SHT_PROGBITS, 0),
+    }
+}
+
+/// Indices of `symbols` in `.symtab`: local symbols go first, after the null symbol.
+fn symbol_order(symbols: &[Symbol]) -> Vec<usize> {
+    let mut order = (0..symbols.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| symbols[idx].binding == Binding::Global);
+    let mut sym_index = vec![0; symbols.len()];
+    for (new, &old) in order.iter().enumerate() {
+        sym_index[old] = new + 1;
+    }
+    sym_index
+}
+
+/// Writes `.symtab` and `.strtab`, which come right after the headers there are so far.
+fn write_symbols(
+    out: &mut Vec<u8>,
+    symbols: &[Symbol],
+    sym_index: &[usize],
+    headers: &mut Vec<SectionHeader>,
+    shstrtab: &mut StrTab,
+) {
+    let mut order = vec![0; symbols.len()];
+    for (old, &new) in sym_index.iter().enumerate() {
+        order[new - 1] = old;
+    }
+    // `sh_info` of `.symtab` is the first global one
+    let first_global = 1 + symbols.iter()
+        .filter(|symbol| symbol.binding == Binding::Local)
+        .count();
+
+    let mut strtab = StrTab::new();
+    let offset = align(out, 8);
+    out.extend([0; SYM_SIZE]);
+    for &idx in order.iter() {
+        let symbol = &symbols[idx];
+        let bind = match symbol.binding {
+            Binding::Local => 0u8,
+            Binding::Global => 1,
+        };
+        let type_ = match symbol.kind {
+            SymbolKind::NoType => 0u8,
+            SymbolKind::Object => 1,
+            SymbolKind::Func => 2,
+            SymbolKind::Section => 3,
+        };
+        let shndx = symbol.section.map_or(0, |section| section + 1) as u16;
+        out.extend(strtab.add(&symbol.name).to_le_bytes());
+        out.push(bind << 4 | type_);
+        out.push(0);
+        out.extend(shndx.to_le_bytes());
+        out.extend(symbol.value.to_le_bytes());
+        out.extend(symbol.size.to_le_bytes());
+    }
+    headers.push(SectionHeader {
+        name: shstrtab.add(".symtab"),
+        type_: SHT_SYMTAB,
+        offset: offset as u64,
+        size: ((symbols.len() + 1) * SYM_SIZE) as u64,
+        link: (headers.len() + 1) as u32,
+        info: first_global as u32,
+        align: 8,
+        entsize: SYM_SIZE as u64,
+        ..SectionHeader::default()
+    });
+
+    let offset = out.len();
+    out.extend(&strtab.data);
+    headers.push(SectionHeader {
+        name: shstrtab.add(".strtab"),
+        type_: SHT_STRTAB,
+        offset: offset as u64,
+        size: strtab.data.len() as u64,
+        align: 1,
+        ..SectionHeader::default()
+    });
+}
+
+/// Appends `.shstrtab` and the section headers, and fills in the ELF header.
+fn finish(
+    mut out: Vec<u8>,
+    mut headers: Vec<SectionHeader>,
+    mut shstrtab: StrTab,
+    type_: u16,
+    entry: u64,
+    phnum: usize,
+) -> Vec<u8> {
+    let name = shstrtab.add(".shstrtab");
+    let offset = out.len();
+    out.extend(&shstrtab.data);
+    headers.push(SectionHeader {
+        name,
+        type_: SHT_STRTAB,
+        offset: offset as u64,
+        size: shstrtab.data.len() as u64,
+        align: 1,
+        ..SectionHeader::default()
+    });
+
+    let shoff = align(&mut out, 8);
+    for header in headers.iter() {
+        out.extend(header.name.to_le_bytes());
+        out.extend(header.type_.to_le_bytes());
+        out.extend(header.flags.to_le_bytes());
+        out.extend(header.addr.to_le_bytes());
+        out.extend(header.offset.to_le_bytes());
+        out.extend(header.size.to_le_bytes());
+        out.extend(header.link.to_le_bytes());
+        out.extend(header.info.to_le_bytes());
+        out.extend(header.align.to_le_bytes());
+        out.extend(header.entsize.to_le_bytes());
+    }
+
+    let (phoff, phentsize) = if phnum > 0 { (EHDR_SIZE, PHDR_SIZE) } else { (0, 0) };
+    let mut ehdr = vec![];
+    // ELFCLASS64, ELFDATA2LSB, EV_CURRENT, ELFOSABI_NONE
+    ehdr.extend(b"\x7fELF");
+    ehdr.extend([2, 1, 1, 0]);
+    ehdr.extend([0; 8]);
+    ehdr.extend(type_.to_le_bytes());
+    ehdr.extend(EM_RISCV.to_le_bytes());
+    ehdr.extend(1u32.to_le_bytes());
+    ehdr.extend(entry.to_le_bytes());
+    ehdr.extend((phoff as u64).to_le_bytes());
+    ehdr.extend((shoff as u64).to_le_bytes());
+    ehdr.extend(EF_RISCV_FLOAT_ABI_DOUBLE.to_le_bytes());
+    ehdr.extend((EHDR_SIZE as u16).to_le_bytes());
+    ehdr.extend((phentsize as u16).to_le_bytes());
+    ehdr.extend((phnum as u16).to_le_bytes());
+    ehdr.extend((SHDR_SIZE as u16).to_le_bytes());
+    ehdr.extend((headers.len() as u16).to_le_bytes());
+    // e_shstrndx, the last one
+    ehdr.extend(((headers.len() - 1) as u16).to_le_bytes());
+    out[..EHDR_SIZE].copy_from_slice(&ehdr);
+
+    out
+}
+
+/// Pads `out` to a multiple of `align` and returns its length.
+fn align(out: &mut Vec<u8>, align: usize) -> usize {
+    let len = out.len().next_multiple_of(align.max(1));
+    out.resize(len, 0);
+    len
+}
@@ -0,0 +1,82 @@
+//! Running the programs in `testprogs` on the host, to check the backends against the host compiler.
+//! The tests for a backend whose output needs a tool that isn't installed are skipped.
+
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_dir_all};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Exit code and standard output of a run.
+pub type Outcome = (i32, Vec<u8>);
+
+/// Whether `tool` can be run.
+pub fn has(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok()
+}
+
+/// Runs `command`, panicking if it couldn't be started, was killed or runs for longer than `TIMEOUT`,
+/// as a miscompiled loop may never end.
+pub fn run(command: &mut Command) -> Outcome {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    let mut child = command.stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|err| panic!("{:?}: {}", command, err));
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            panic!("{:?}: timed out", command);
+        }
+        sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().unwrap();
+    let code = output.status.code().unwrap_or_else(|| panic!("{:?}: {}", command, output.status));
+    (code, output.stdout)
+}
+
+/// Builds `input` with the host `cc` into `output`.
+pub fn cc(input: &Path, output: &Path) {
+    let (code, _) = run(Command::new("cc").arg("-w").arg(input).arg("-o").arg(output));
+    assert_eq!(code, 0, "cc {}", input.display());
+}
+
+/// Checks that every program with a `main` but those in `skip` behaves the same at -O0 and -O2
+/// as when built by the host compiler.
+/// `build` writes the backend's output for `source` at the given level into the scratch directory,
+/// under the program's name, and returns the command that runs it.
+pub fn check(test: &str, skip: &[&str], build: impl Fn(&Path, &str, &str, u8) -> Command) {
+    if !has("cc") {
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("drcc-{}-{}", test, std::process::id()));
+    create_dir_all(&dir).unwrap();
+
+    for (name, path) in programs() {
+        let source = read_to_string(&path).unwrap();
+        if skip.contains(&name.as_str()) || !source.contains("main(") {
+            continue;
+        }
+        let reference = dir.join(format!("{}.ref", name));
+        cc(&path, &reference);
+        let expected = run(&mut Command::new(&reference));
+        for opt_level in [0, 2] {
+            let outcome = run(&mut build(&dir, &name, &source, opt_level));
+            assert_eq!(outcome, expected, "{} at -O{}", name, opt_level);
+        }
+    }
+
+    remove_dir_all(&dir).unwrap();
+}
+
+/// The C sources in `testprogs`, by name.
+fn programs() -> Vec<(String, PathBuf)> {
+    let mut programs = read_dir("../testprogs").unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .map(|path| (path.file_stem().unwrap().to_string_lossy().into_owned(), path))
+        .collect::<Vec<_>>();
+    programs.sort();
+    programs
+}
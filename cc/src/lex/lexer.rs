@@ -121,11 +121,18 @@ impl Lexer {
         
         let integer_str: String = self.input[start_position..self.position].iter().collect();
         match self.input[self.position] {
-            'l'|'L' => {
+            suffix @ ('l'|'L') => {
                 self.advance();
                 let long_value = integer_str.parse::<i64>()
                     .map_err(|e| Error::Lex(format!("Invalid long integer: {e:?}")))?;
-                let raw = RawToken::LongLiteral(long_value);
+                // `ll` or `LL`, but not mixed case
+                let raw = if self.peek() == Some(suffix) {
+                    self.advance();
+                    span.length = Some(span.length.unwrap_or(0) + 1);
+                    RawToken::LongLongLiteral(long_value)
+                } else {
+                    RawToken::LongLiteral(long_value)
+                };
                 span.length = Some(span.length.unwrap_or(0) + 1);
                 Ok(Token::new(raw, span))
             },
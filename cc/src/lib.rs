@@ -6,6 +6,19 @@ mod sem;
 mod tac;
 mod lir;
 mod asm;
+mod llvm;
+mod c;
+mod wasm;
+mod x86;
+mod elf;
+mod link;
 mod common;
+mod driver;
+#[cfg(test)]
+mod host;
 #[macro_use]
-mod macros;
\ No newline at end of file
+mod macros;
+
+pub use driver::{compile, compile_object, compile_llvm, compile_c, compile_json, dump, link, Dump, Ir, Options, Target};
+pub use lir::{RegAllocator, MachineModel};
+pub use common::{Error, Result};
@@ -0,0 +1,261 @@
+//! Static linker, putting objects and the runtime together into an executable.
+//! - members of the runtime are pulled in like from an archive: `_start` always, the others when referenced
+//! - global symbols are resolved across objects, defining one twice or never is an error
+//! - sections of the same kind are concatenated, `.text` first from `BASE_ADDR`, each kind on pages of its own;
+//!   debug sections aren't loaded, addresses in them are offsets from their start
+//! - relocations are applied at the final addresses
+
+mod runtime;
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    common::*,
+    elf::{
+        Object,
+        Executable,
+        Section,
+        SectionKind,
+        Symbol,
+        Binding,
+        Reloc,
+        RelocKind,
+        PAGE_SIZE,
+    },
+};
+
+/// where the first section is loaded, as GNU ld does for RISC-V
+const BASE_ADDR: u64 = 0x10000;
+const ENTRY: &str = "_start";
+
+pub fn link(mut objects: Vec<Object>) -> Result<Executable> {
+    let mut members = runtime::members();
+    loop {
+        let undefined = undefined_symbols(&objects);
+        let pulled = members.iter().position(|member| member.symbols.iter()
+            .any(|symbol| is_definition(symbol) && undefined.contains(symbol.name.as_str())));
+        let Some(idx) = pulled else { break };
+        objects.push(members.remove(idx));
+    }
+
+    let mut errors = vec![];
+    let mut globals = HashMap::new();
+    for (obj_idx, obj) in objects.iter().enumerate() {
+        for (sym_idx, symbol) in obj.symbols.iter().enumerate() {
+            if is_definition(symbol) && globals.insert(symbol.name.as_str(), (obj_idx, sym_idx)).is_some() {
+                errors.push(Error::Link(format!("multiple definition of `{}`", symbol.name)));
+            }
+        }
+    }
+    for name in undefined_symbols(&objects) {
+        errors.push(Error::Link(format!("undefined reference to `{}`", name)));
+    }
+    if !errors.is_empty() {
+        return Err(Error::Errors(errors));
+    }
+
+    // addresses of the sections of each object
+    let mut bases = objects.iter()
+        .map(|obj| vec![0; obj.sections.len()])
+        .collect::<Vec<_>>();
+    let mut sections: Vec<(Section, u64)> = vec![];
+    let mut addr = BASE_ADDR;
+    for kind in [
+        SectionKind::Text,
+        SectionKind::Rodata,
+        SectionKind::Data,
+        SectionKind::Bss,
+        SectionKind::DebugInfo,
+        SectionKind::DebugAbbrev,
+        SectionKind::DebugLine,
+    ] {
+        let mut output = Section::new(kind);
+        let mut inputs = vec![];
+        for (obj_idx, obj) in objects.iter().enumerate() {
+            for (sec_idx, section) in obj.sections.iter().enumerate() {
+                if section.kind == kind {
+                    let offset = output.align_to(section.align);
+                    output.data.extend(&section.data);
+                    inputs.push((obj_idx, sec_idx, offset));
+                }
+            }
+        }
+        if inputs.is_empty() {
+            continue;
+        }
+        if kind.is_debug() {
+            for (obj_idx, sec_idx, offset) in inputs {
+                bases[obj_idx][sec_idx] = offset as u64;
+            }
+            sections.push((output, 0));
+            continue;
+        }
+        for (obj_idx, sec_idx, offset) in inputs {
+            bases[obj_idx][sec_idx] = addr + offset as u64;
+        }
+        let size = output.data.len() as u64;
+        sections.push((output, addr));
+        addr = (addr + size).next_multiple_of(PAGE_SIZE);
+    }
+    let address = |obj_idx: usize, sym_idx: usize| {
+        let symbol = &objects[obj_idx].symbols[sym_idx];
+        let (obj_idx, symbol) = match symbol.section {
+            Some(_) => (obj_idx, symbol),
+            None => {
+                let (obj_idx, sym_idx) = globals[symbol.name.as_str()];
+                (obj_idx, &objects[obj_idx].symbols[sym_idx])
+            },
+        };
+        bases[obj_idx][symbol.section.unwrap()] + symbol.value
+    };
+
+    // (output section, offset in it, relocation, value to fill in)
+    let mut patches = vec![];
+    for (obj_idx, obj) in objects.iter().enumerate() {
+        for (sec_idx, section) in obj.sections.iter().enumerate() {
+            let base = bases[obj_idx][sec_idx];
+            let output = output_of(&sections, section.kind);
+            let start = sections[output].1;
+            let pcrel_value = |reloc: &Reloc| {
+                address(obj_idx, reloc.symbol) as i64 + reloc.addend - (base + reloc.offset) as i64
+            };
+            // what each `auipc` adds, for the `%pcrel_lo`s referring to it
+            let pcrel_hi = section.relocs.iter()
+                .filter(|reloc| reloc.kind == RelocKind::PcrelHi20)
+                .map(|reloc| (reloc.offset, pcrel_value(reloc)))
+                .collect::<HashMap<_, _>>();
+
+            for reloc in section.relocs.iter() {
+                let value = match reloc.kind {
+                    RelocKind::Branch | RelocKind::Jal | RelocKind::CallPlt | RelocKind::PcrelHi20 =>
+                        pcrel_value(reloc),
+                    RelocKind::Hi20 | RelocKind::Lo12I | RelocKind::Lo12S | RelocKind::Abs32 | RelocKind::Abs64 =>
+                        address(obj_idx, reloc.symbol) as i64 + reloc.addend,
+                    RelocKind::PcrelLo12I => {
+                        let auipc = &obj.symbols[reloc.symbol];
+                        *pcrel_hi.get(&auipc.value).ok_or_else(|| Error::Link(format!(
+                            "`{}` is not at a R_RISCV_PCREL_HI20 relocation", auipc.name
+                        )))?
+                    },
+                };
+                patches.push((output, base + reloc.offset - start, reloc, value, &obj.symbols[reloc.symbol].name));
+            }
+        }
+    }
+    for (output, offset, reloc, value, name) in patches {
+        if !reloc.kind.apply(&mut sections[output].0.data[offset as usize..], value) {
+            errors.push(Error::Link(format!("relocation {:?} against `{}` out of range", reloc.kind, name)));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Errors(errors));
+    }
+
+    let mut symbols = vec![];
+    for (obj_idx, obj) in objects.iter().enumerate() {
+        for (sym_idx, symbol) in obj.symbols.iter().enumerate() {
+            let Some(section) = symbol.section else { continue };
+            if symbol.name.is_empty() || symbol.name.starts_with(".L") {
+                continue;
+            }
+            symbols.push(Symbol {
+                section: Some(output_of(&sections, obj.sections[section].kind)),
+                value: address(obj_idx, sym_idx),
+                ..symbol.clone()
+            });
+        }
+    }
+
+    let (obj_idx, sym_idx) = globals[ENTRY];
+    Ok(Executable {
+        entry: address(obj_idx, sym_idx),
+        sections,
+        symbols,
+    })
+}
+
+fn output_of(sections: &[(Section, u64)], kind: SectionKind) -> usize {
+    sections.iter().position(|(section, _)| section.kind == kind).unwrap()
+}
+
+fn is_definition(symbol: &Symbol) -> bool {
+    symbol.binding == Binding::Global && symbol.section.is_some()
+}
+
+/// Global symbols referenced but defined nowhere, the entry point included.
+fn undefined_symbols(objects: &[Object]) -> BTreeSet<&str> {
+    let defined = objects.iter()
+        .flat_map(|obj| obj.symbols.iter())
+        .filter(|symbol| is_definition(symbol))
+        .map(|symbol| symbol.name.as_str())
+        .collect::<BTreeSet<_>>();
+    objects.iter()
+        .flat_map(|obj| obj.symbols.iter())
+        .filter(|symbol| symbol.section.is_none())
+        .map(|symbol| symbol.name.as_str())
+        .chain([ENTRY])
+        .filter(|name| !defined.contains(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{compile_object, Options};
+
+    fn object(source: &str) -> Object {
+        let bytes = compile_object(source.to_string(), &Options::default()).unwrap();
+        Object::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_link() {
+        let main = object("int twice(int x); extern int base; int main(void) { return twice(base) + 1; }");
+        let lib = object("int base = 21; static int unused; int twice(int x) { return x + x; }");
+        let exe = link(vec![main, lib]).unwrap();
+        let symbol = |name: &str| exe.symbols.iter().find(|symbol| symbol.name == name).unwrap();
+
+        // only what's referenced comes from the runtime
+        assert!(exe.symbols.iter().any(|symbol| symbol.name == "exit"));
+        assert!(exe.symbols.iter().all(|symbol| symbol.name != "putchar"));
+        assert_eq!(exe.entry, symbol("_start").value);
+        assert_eq!(exe.sections[0].1, BASE_ADDR);
+        let data = symbol("base").section.unwrap();
+        assert_eq!(exe.sections[data].1 % PAGE_SIZE, 0);
+
+        // `call twice` in main: auipc ra, hi; jalr ra, lo(ra)
+        let (text, start) = &exe.sections[0];
+        let main = symbol("main");
+        let code = &text.data[(main.value - start) as usize..(main.value - start + main.size) as usize];
+        let words = code.chunks(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let call = words.iter().position(|&word| word & 0xfff == 0x097).unwrap();
+        let offset = (words[call] & 0xfffff000) as i32 as i64 + (words[call + 1] as i32 >> 20) as i64;
+        assert_eq!(main.value as i64 + 4 * call as i64 + offset, symbol("twice").value as i64);
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let main = || object("int f(void); int main(void) { return f(); }");
+        let Err(Error::Errors(errors)) = link(vec![main()]) else { panic!() };
+        assert!(matches!(&errors[..], [Error::Link(msg)] if msg.contains("undefined reference to `f`")));
+
+        let Err(Error::Errors(errors)) = link(vec![main(), main(), object("int f(void) { return 0; }")]) else { panic!() };
+        assert!(matches!(&errors[..], [Error::Link(msg)] if msg.contains("multiple definition of `main`")));
+    }
+
+    #[test]
+    fn test_link_debug_info() {
+        let options = Options { debug_info: true, ..Options::default() };
+        let bytes = compile_object("int main(void) { return 0; }".to_string(), &options).unwrap();
+        let exe = link(vec![Object::from_bytes(&bytes).unwrap()]).unwrap();
+
+        // not loaded, and the compilation unit starts where `main` is
+        let (info, addr) = exe.sections.iter().find(|(section, _)| section.kind == SectionKind::DebugInfo).unwrap();
+        assert_eq!(*addr, 0);
+        let main = exe.symbols.iter().find(|symbol| symbol.name == "main").unwrap();
+        assert!(info.data.windows(8).any(|bytes| bytes == main.value.to_le_bytes()));
+        assert_eq!(exe.sections.iter().filter(|(section, _)| section.kind.is_debug()).count(), 3);
+    }
+}
@@ -0,0 +1,113 @@
+//! The runtime linked into executables, on top of Linux system calls.
+//! Each function is a member of its own, pulled in only when it's referenced, except `_start`:
+//! - `_start` calls `main` and exits with what it returns
+//! - `exit(status)` ends the process (`exit_group`)
+//! - `putchar(c)` writes `c` to stdout and returns it as an unsigned char, or -1 if it failed
+//! - `getchar()` reads a byte from stdin, or returns -1 at end of file or on error
+
+use std::collections::HashMap;
+
+use crate::{
+    common::*,
+    asm::{
+        AsmTopLevel,
+        AsmFunction,
+        AsmDataSegment,
+        AsmBssSegment,
+        AsmInsn as Insn,
+        AsmLabelOperand as LabelOperand,
+        Register,
+        Xlen,
+    },
+    elf::Object,
+};
+
+const SYS_READ: i64 = 63;
+const SYS_WRITE: i64 = 64;
+const SYS_EXIT_GROUP: i64 = 94;
+
+pub(super) fn members() -> Vec<Object> {
+    use Insn::*;
+    use Register::*;
+
+    let int = |param_types| FuncType { return_type: DataType::Int, param_types };
+    let failed = LabelOperand::AutoGen(0);
+
+    vec![
+        member("_start", int(vec![]), |strtb| vec![
+            // argc and argv, right at the stack pointer
+            Ld(A0, Sp, 0),
+            Addi(A1, Sp, 8),
+            Call(strtb.intern("main".to_string())),
+            Tail(strtb.intern("exit".to_string())),
+        ]),
+        member("exit", FuncType { return_type: DataType::Void, param_types: vec![DataType::Int] }, |_| vec![
+            Li(A7, SYS_EXIT_GROUP),
+            Ecall,
+        ]),
+        member("putchar", int(vec![DataType::Int]), |_| vec![
+            // the lowest byte comes first
+            Addi(Sp, Sp, -16),
+            Sw(A0, Sp, 0),
+            Li(A0, 1),
+            Mv(A1, Sp),
+            Li(A2, 1),
+            Li(A7, SYS_WRITE),
+            Ecall,
+            Li(T0, 1),
+            Bne(A0, T0, failed),
+            Lw(A0, Sp, 0),
+            Slli(A0, A0, 56),
+            Srli(A0, A0, 56),
+            Addi(Sp, Sp, 16),
+            Ret,
+            Label(failed),
+            Li(A0, -1),
+            Addi(Sp, Sp, 16),
+            Ret,
+        ]),
+        member("getchar", int(vec![]), |_| vec![
+            // the upper bytes stay zero
+            Addi(Sp, Sp, -16),
+            Sw(Zero, Sp, 0),
+            Li(A0, 0),
+            Mv(A1, Sp),
+            Li(A2, 1),
+            Li(A7, SYS_READ),
+            Ecall,
+            Li(T0, 1),
+            Bne(A0, T0, failed),
+            Lw(A0, Sp, 0),
+            Addi(Sp, Sp, 16),
+            Ret,
+            Label(failed),
+            Li(A0, -1),
+            Addi(Sp, Sp, 16),
+            Ret,
+        ]),
+    ]
+}
+
+/// An object defining just the function `name`.
+fn member(name: &str, func_type: FuncType, body: impl FnOnce(&mut StringPool) -> Vec<Insn>) -> Object {
+    let mut strtb = StringPool::new();
+    let name = strtb.intern(name.to_string());
+    let func = AsmFunction {
+        name,
+        func_type,
+        body: body(&mut strtb),
+        linkage: Linkage::External,
+        span: Span::new(0, 0),
+        vars: vec![],
+    };
+    let top = AsmTopLevel {
+        functions: HashMap::from([(name, func)]),
+        data_seg: AsmDataSegment::new(),
+        bss_seg: AsmBssSegment::new(),
+        externs: HashMap::new(),
+        strtb,
+        source_file: None,
+        xlen: Xlen::Rv64,
+    };
+    top.assemble()
+}
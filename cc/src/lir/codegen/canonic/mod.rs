@@ -1,6 +1,5 @@
-//! We split the canonicalization pass into two parts:
-//! 1.  flatten memory accesses
-//! 2.  fix immediate values
+//! After register allocation every operand is a register already,
+//! except for immediates, which are turned into the instructions taking them.
 
 use std::{collections::HashMap, marker::PhantomData};
 
@@ -18,17 +17,22 @@ use super::{
     Canonic,
     CodeGen,
     Insn,
+    MachineModel,
 };
 
-mod mem;
 mod imm;
 
 impl CodeGen<Canonic> {
-    pub fn canonic(mut self, lir: TopLevel) -> TopLevel {
+    pub fn canonic(self, lir: TopLevel) -> TopLevel {
+        self.canonic_with(lir, None)
+    }
+
+    /// Canonicalizes the instructions, then schedules each basic block for `model` if given.
+    pub fn canonic_with(mut self, lir: TopLevel, model: Option<&MachineModel>) -> TopLevel {
         let mut c_funcs = HashMap::new();
 
         for (name, func) in lir.functions {
-            let func = self.canonic_func(func);
+            let func = self.canonic_func(func, model);
             c_funcs.insert(name, func);
         }
         
@@ -36,20 +40,26 @@ impl CodeGen<Canonic> {
             functions: c_funcs,
             data_seg: lir.data_seg,
             bss_seg: lir.bss_seg,
+            externs: lir.externs,
             strtb: lir.strtb,
+            xlen: lir.xlen,
         }
     }
 
     fn canonic_func(
         &mut self,
         func: Function,
+        model: Option<&MachineModel>,
     ) -> Function {
         let mut func = func;
         self.cur_func = Some(func.name);
 
-        let c_insns = func.body.into_iter()
+        let mut c_insns = func.body.into_iter()
             .flat_map(|insn| self.canonic_insn(insn))
             .collect();
+        if let Some(model) = model {
+            c_insns = self.schedule(c_insns, model);
+        }
 
         self.cur_func = None;
 
@@ -62,13 +72,6 @@ impl CodeGen<Canonic> {
         &mut self,
         insn: Insn,
     ) -> Vec<Insn> {
-        let cmem_insn = self.canonic_mem(insn);
-
-        let cimm_insn = cmem_insn
-            .into_iter()
-            .flat_map(|insn| self.canonic_imm(insn))
-            .collect();
-
-        cimm_insn
+        self.canonic_imm(insn)
     }
 }
\ No newline at end of file
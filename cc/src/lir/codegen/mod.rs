@@ -1,14 +1,20 @@
 mod parse;
+mod pair;
 mod regalloc;
 mod spill;
 mod canonic;
+mod schedule;
+
+pub use regalloc::{RegAllocator, AllocDump};
+pub use schedule::MachineModel;
 
 use std::{collections::HashMap, marker::PhantomData};
 
-use crate::{asm::Register, common::*, tac::TacLabelOperand};
+use crate::{asm::{Register, Xlen}, common::*, tac::TacLabelOperand};
 use super::{
     TopLevel,
     Function,
+    DebugVar,
     StaticVar,
     DataSegment,
     BssSegment,
@@ -34,6 +40,7 @@ pub struct CodeGen<Stage = Parse> {
 pub struct FuncContext {
     pub name: StrDescriptor,
     pub type_: FuncType,
+    pub xlen: Xlen,
 
     // following fields will be used through all stages.
     pub next_v_reg: usize,
@@ -44,22 +51,50 @@ pub struct FuncContext {
     pub vmap: HashMap<usize, usize>,
     // Map spilled virtual registers to frame offsets
     pub mmap: HashMap<usize, isize>,
+    // Map the virtual registers of 64-bit values on RV32 to the ones holding their upper halves
+    pub hmap: HashMap<usize, usize>,
     // registers that need to be saved across function calls
     // (register, frame_offset)
     pub callee_saved: Option<Vec<(Register, isize)>>,
 }
 
 impl FuncContext {
-    pub fn new(name: StrDescriptor, type_: FuncType) -> Self {
+    pub fn new(name: StrDescriptor, type_: FuncType, xlen: Xlen) -> Self {
+        let word = xlen.bytes() as isize;
         FuncContext {
             name,
             type_,
+            xlen,
             next_v_reg: 0,
-            frame_size: 16,
+            frame_size: 2 * xlen.bytes(),
             tmap: HashMap::new(),
             vmap: HashMap::new(),
             mmap: HashMap::new(),
-            callee_saved: Some(vec![(Register::S0, -16), (Register::Ra, -8)]),
+            hmap: HashMap::new(),
+            callee_saved: Some(vec![(Register::S0, -2 * word), (Register::Ra, -word)]),
+        }
+    }
+
+    /// Whether values of `data_type` take a pair of registers.
+    pub fn is_pair(&self, data_type: DataType) -> bool {
+        data_type.size() > self.xlen.bytes()
+    }
+
+    /// Number of argument registers the parameters are passed in.
+    pub fn arg_regs(&self) -> usize {
+        arg_slots(&self.type_.param_types, self.xlen).0
+            .iter()
+            .flatten()
+            .filter(|slot| matches!(slot, ArgSlot::Reg(_)))
+            .count()
+    }
+
+    /// Registers the return value is passed in, `a0` is taken as live at the exit of `void` functions too.
+    pub fn ret_regs(&self) -> &'static [Register] {
+        if self.is_pair(self.type_.return_type) {
+            &[Register::A0, Register::A1]
+        } else {
+            &[Register::A0]
         }
     }
 
@@ -68,9 +103,9 @@ impl FuncContext {
             if callee_saved.iter().any(|(r, _)| *r == reg) {
                 return;
             }
-            let offset = -(self.frame_size as isize + 8);
+            let offset = -((self.frame_size + self.xlen.bytes()) as isize);
             callee_saved.push((reg, offset));
-            self.frame_size += 8;
+            self.frame_size += self.xlen.bytes();
         } else {
             panic!("Internal error: callee_saved is None, cannot push register");
         }
@@ -103,6 +138,18 @@ impl FuncContext {
         );
     }
 
+    /// The virtual register holding the upper half of the 64-bit value in `v_reg`, on RV32.
+    pub fn high_vreg(&mut self, v_reg: usize) -> usize {
+        match self.hmap.get(&v_reg) {
+            Some(&high) => high,
+            None => {
+                let high = self.alloc_v_reg();
+                self.hmap.insert(v_reg, high);
+                high
+            },
+        }
+    }
+
     pub fn temp_vreg(&self, temp_id: usize) -> Option<usize> {
         self.tmap.get(&temp_id).copied()
     }
@@ -112,6 +159,42 @@ impl FuncContext {
     }
 }
 
+/// Where a register-sized word of an argument is passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgSlot {
+    Reg(Register),
+    /// offset into the arguments on the stack
+    Stack(isize),
+}
+
+/// The slots of each argument, its low word first, and the bytes taken on the stack, 16-byte aligned.
+/// Words go in `a0` to `a7` and then on the stack, in slots of XLEN bytes.
+/// On RV32 a 64-bit value takes two words, 8-byte aligned on the stack,
+/// and has its upper half on the stack when only `a7` is left for it.
+pub fn arg_slots(types: &[DataType], xlen: Xlen) -> (Vec<Vec<ArgSlot>>, usize) {
+    let word = xlen.bytes();
+    let mut next_reg = 0;
+    let mut stack_size: usize = 0;
+    let slots = types.iter()
+        .map(|data_type| {
+            let words = data_type.size().div_ceil(word);
+            if next_reg == 8 && words > 1 {
+                stack_size = stack_size.next_multiple_of(data_type.align());
+            }
+            (0..words)
+                .map(|_| if next_reg < 8 {
+                    next_reg += 1;
+                    ArgSlot::Reg(Register::a(next_reg - 1))
+                } else {
+                    stack_size += word;
+                    ArgSlot::Stack((stack_size - word) as isize)
+                })
+                .collect()
+        })
+        .collect();
+    (slots, stack_size.next_multiple_of(16))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Parse;
 
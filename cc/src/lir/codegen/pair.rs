@@ -0,0 +1,337 @@
+//! 64-bit values on RV32, kept in pairs of virtual registers.
+//! The lower half is in the register the value is mapped to, and the upper half in the one `hmap` maps that to.
+//! Both halves of a result are computed into new registers before the destination is written,
+//! as it may be one of the sources, e.g. `a = a + b`
+//! ->      add     v4, v0, v2      (lower halves)
+//!         sltu    v5, v4, v0      (carry)
+//!         add     v6, v1, v3
+//!         add     v7, v6, v5
+//!         mv      v0, v4
+//!         mv      v1, v7
+//! Divisions and remainders call `__divdi3` and `__moddi3` from libgcc.
+
+use crate::asm::Register;
+use crate::common::*;
+use crate::tac::{
+    TacInsn,
+    TacBinaryOp,
+    TacUnaryOp,
+    TacOperand,
+};
+use super::{
+    CodeGen,
+    Parse,
+    LabelOperand,
+    LabelSignature,
+    Insn,
+    IntermediateInsn,
+    Operand,
+};
+
+/// The runtime functions 64-bit divisions call on RV32.
+#[derive(Debug, Clone, Copy)]
+pub struct Libcalls {
+    pub div: StrDescriptor,
+    pub rem: StrDescriptor,
+}
+
+impl CodeGen<Parse> {
+    /// The instructions for `insn` if it operates on a 64-bit value on RV32, `None` otherwise.
+    /// Calls are left to `parse_insn`, which passes and returns pairs as well.
+    pub(super) fn parse_pair_insn(
+        &mut self,
+        insn: &TacInsn,
+        libcalls: Option<Libcalls>,
+    ) -> Option<Vec<Insn>> {
+        use Insn::*;
+
+        let xlen = self.cur_cx().xlen;
+        let is_pair = |operand: TacOperand| operand.data_type().size() > xlen.bytes();
+
+        let mut insns = vec![];
+        match *insn {
+            TacInsn::Move { src, dst } if is_pair(dst) => {
+                let (lo, hi) = self.read_pair(src, &mut insns);
+                self.write_pair(dst, lo, hi, &mut insns);
+            },
+            TacInsn::Return(val) if is_pair(val) => {
+                let (lo, hi) = self.read_pair(val, &mut insns);
+                insns.extend([
+                    Mv(Operand::PhysReg(Register::A0), lo),
+                    Mv(Operand::PhysReg(Register::A1), hi),
+                    Intermediate(IntermediateInsn::Epilogue),
+                ]);
+            },
+            TacInsn::Unary { op: TacUnaryOp::Not, src, dst } if is_pair(src) => {
+                let (lo, hi) = self.read_pair(src, &mut insns);
+                let (dst_op, _) = self.parse_operand(dst);
+                let either = self.new_v_reg();
+                insns.extend([
+                    Or(either, lo, hi),
+                    Seqz(dst_op, either),
+                ]);
+            },
+            TacInsn::Unary { op, src, dst } if is_pair(src) => {
+                let (lo, hi) = self.read_pair(src, &mut insns);
+                let (res_lo, res_hi) = (self.new_v_reg(), self.new_v_reg());
+                match op {
+                    TacUnaryOp::Pos => insns.extend([
+                        Mv(res_lo, lo),
+                        Mv(res_hi, hi),
+                    ]),
+                    TacUnaryOp::Complement => insns.extend([
+                        Not(res_lo, lo),
+                        Not(res_hi, hi),
+                    ]),
+                    TacUnaryOp::Negate => {
+                        // the upper half borrows from the lower one unless that is 0
+                        let neg_hi = self.new_v_reg();
+                        let borrow = self.new_v_reg();
+                        insns.extend([
+                            Neg(res_lo, lo),
+                            Neg(neg_hi, hi),
+                            Snez(borrow, lo),
+                            Sub(res_hi, neg_hi, borrow),
+                        ]);
+                    },
+                    TacUnaryOp::Not => unreachable!(),
+                }
+                self.write_pair(dst, res_lo, res_hi, &mut insns);
+            },
+            TacInsn::Binary { op, left, right, dst } if is_pair(left) => {
+                let left = self.read_pair(left, &mut insns);
+                let right = self.read_pair(right, &mut insns);
+                match op {
+                    TacBinaryOp::Add | TacBinaryOp::Sub | TacBinaryOp::Mul |
+                    TacBinaryOp::Div | TacBinaryOp::Rem => {
+                        let (lo, hi) = self.pair_arith(op, left, right, libcalls, &mut insns);
+                        self.write_pair(dst, lo, hi, &mut insns);
+                    },
+                    _ => {
+                        // comparisons yield an int
+                        let (dst_op, _) = self.parse_operand(dst);
+                        let comparison = self.pair_comparison(op, left, right, dst_op);
+                        insns.extend(comparison);
+                    },
+                }
+            },
+            TacInsn::BranchIfZero { src, label } |
+            TacInsn::BranchNotZero { src, label } if is_pair(src) => {
+                let (lo, hi) = self.read_pair(src, &mut insns);
+                let signature = LabelSignature::from_tac(
+                    self.cur_cx().name,
+                    label,
+                );
+                let label = LabelOperand::AutoGen(self.map_label(signature));
+                let either = self.new_v_reg();
+                let zero = Operand::PhysReg(Register::Zero);
+                insns.push(Or(either, lo, hi));
+                insns.push(match insn {
+                    TacInsn::BranchIfZero { .. } => Beq(either, zero, label),
+                    _ => Bne(either, zero, label),
+                });
+            },
+            TacInsn::SignExt { src, dst } if is_pair(dst) => {
+                let (src_op, _) = self.parse_operand(src);
+                let (lo, hi) = (self.new_v_reg(), self.new_v_reg());
+                insns.extend([
+                    Mv(lo, src_op),
+                    Srai(hi, lo, xlen.bits() - 1),
+                ]);
+                self.write_pair(dst, lo, hi, &mut insns);
+            },
+            TacInsn::Truncate { src, dst } if is_pair(src) => {
+                let (lo, _) = self.read_pair(src, &mut insns);
+                let (dst_op, _) = self.parse_operand(dst);
+                insns.push(Mv(dst_op, lo));
+            },
+            _ => return None,
+        }
+        Some(insns)
+    }
+
+    /// The registers or immediates `operand` is passed in, its lower half first.
+    pub(super) fn parse_words(
+        &mut self,
+        operand: TacOperand,
+        insns: &mut Vec<Insn>,
+    ) -> Vec<Operand> {
+        if self.cur_cx().is_pair(operand.data_type()) {
+            let (lo, hi) = self.read_pair(operand, insns);
+            vec![lo, hi]
+        } else {
+            vec![self.parse_operand(operand).0]
+        }
+    }
+
+    /// The halves of a 64-bit operand, statics are loaded into new registers with the loads pushed into `insns`.
+    fn read_pair(
+        &mut self,
+        operand: TacOperand,
+        insns: &mut Vec<Insn>,
+    ) -> (Operand, Operand) {
+        match self.parse_operand(operand).0 {
+            Operand::Imm(val) => (Operand::Imm(val as i32 as i64), Operand::Imm(val >> 32)),
+            Operand::VirtReg(v_reg) => {
+                let high = self.cur_cx_mut().high_vreg(v_reg);
+                (Operand::VirtReg(v_reg), Operand::VirtReg(high))
+            },
+            Operand::Static(name) => {
+                let (lo, hi) = (self.new_v_reg(), self.new_v_reg());
+                insns.push(Insn::LoadStatic(lo, name, 0));
+                insns.push(Insn::LoadStatic(hi, name, 4));
+                (lo, hi)
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes the halves `lo` and `hi` into the 64-bit `dst`.
+    pub(super) fn write_pair(
+        &mut self,
+        dst: TacOperand,
+        lo: Operand,
+        hi: Operand,
+        insns: &mut Vec<Insn>,
+    ) {
+        match self.parse_operand(dst).0 {
+            Operand::VirtReg(v_reg) => {
+                let high = self.cur_cx_mut().high_vreg(v_reg);
+                insns.push(Insn::Mv(Operand::VirtReg(v_reg), lo));
+                insns.push(Insn::Mv(Operand::VirtReg(high), hi));
+            },
+            Operand::Static(name) => {
+                let (lo_addr, hi_addr) = (self.new_v_reg(), self.new_v_reg());
+                insns.push(Insn::StoreStatic(lo, lo_addr, name, 0));
+                insns.push(Insn::StoreStatic(hi, hi_addr, name, 4));
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// The halves of `left op right`, for the arithmetic operations.
+    fn pair_arith(
+        &mut self,
+        op: TacBinaryOp,
+        left: (Operand, Operand),
+        right: (Operand, Operand),
+        libcalls: Option<Libcalls>,
+        insns: &mut Vec<Insn>,
+    ) -> (Operand, Operand) {
+        use Insn::*;
+
+        let (lo, hi) = (self.new_v_reg(), self.new_v_reg());
+        match op {
+            TacBinaryOp::Add => {
+                // the sum of the lower halves wraps around exactly when it's less than either of them
+                let carry = self.new_v_reg();
+                let sum = self.new_v_reg();
+                insns.extend([
+                    Add(lo, left.0, right.0),
+                    Sltu(carry, lo, left.0),
+                    Add(sum, left.1, right.1),
+                    Add(hi, sum, carry),
+                ]);
+            },
+            TacBinaryOp::Sub => {
+                let borrow = self.new_v_reg();
+                let diff = self.new_v_reg();
+                insns.extend([
+                    Sub(lo, left.0, right.0),
+                    Sltu(borrow, left.0, right.0),
+                    Sub(diff, left.1, right.1),
+                    Sub(hi, diff, borrow),
+                ]);
+            },
+            TacBinaryOp::Mul => {
+                // the upper halves only contribute to the upper half of the product
+                let carry = self.new_v_reg();
+                let cross_lo = self.new_v_reg();
+                let cross_hi = self.new_v_reg();
+                let sum = self.new_v_reg();
+                insns.extend([
+                    Mul(lo, left.0, right.0),
+                    Mulhu(carry, left.0, right.0),
+                    Mul(cross_lo, left.0, right.1),
+                    Mul(cross_hi, left.1, right.0),
+                    Add(sum, carry, cross_lo),
+                    Add(hi, sum, cross_hi),
+                ]);
+            },
+            TacBinaryOp::Div | TacBinaryOp::Rem => {
+                let libcalls = libcalls.expect("Internal error: 64-bit division without runtime functions");
+                let target = if op == TacBinaryOp::Div { libcalls.div } else { libcalls.rem };
+                insns.extend([
+                    Mv(Operand::PhysReg(Register::A0), left.0),
+                    Mv(Operand::PhysReg(Register::A1), left.1),
+                    Mv(Operand::PhysReg(Register::A2), right.0),
+                    Mv(Operand::PhysReg(Register::A3), right.1),
+                    Call(target),
+                    Mv(lo, Operand::PhysReg(Register::A0)),
+                    Mv(hi, Operand::PhysReg(Register::A1)),
+                ]);
+            },
+            _ => unreachable!(),
+        }
+        (lo, hi)
+    }
+
+    /// `dst = left op right`, for the comparisons.
+    fn pair_comparison(
+        &mut self,
+        op: TacBinaryOp,
+        left: (Operand, Operand),
+        right: (Operand, Operand),
+        dst: Operand,
+    ) -> Vec<Insn> {
+        use Insn::*;
+
+        match op {
+            TacBinaryOp::Eq | TacBinaryOp::NotEq => {
+                let diff_lo = self.new_v_reg();
+                let diff_hi = self.new_v_reg();
+                let either = self.new_v_reg();
+                vec![
+                    Sub(diff_lo, left.0, right.0),
+                    Sub(diff_hi, left.1, right.1),
+                    Or(either, diff_lo, diff_hi),
+                    if op == TacBinaryOp::Eq { Seqz(dst, either) } else { Snez(dst, either) },
+                ]
+            },
+            TacBinaryOp::Ls => self.pair_less(left, right, dst),
+            TacBinaryOp::Gt => self.pair_less(right, left, dst),
+            // `a <= b` is `!(b < a)`, and `a >= b` is `!(a < b)`
+            TacBinaryOp::LsEq | TacBinaryOp::GtEq => {
+                let less = self.new_v_reg();
+                let mut insns = if op == TacBinaryOp::LsEq {
+                    self.pair_less(right, left, less)
+                } else {
+                    self.pair_less(left, right, less)
+                };
+                insns.push(Seqz(dst, less));
+                insns
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// `dst = left < right`, by the upper halves as signed, or by the lower ones as unsigned if those are equal.
+    fn pair_less(
+        &mut self,
+        left: (Operand, Operand),
+        right: (Operand, Operand),
+        dst: Operand,
+    ) -> Vec<Insn> {
+        use Insn::*;
+
+        let less = self.new_v_reg();
+        let done = LabelOperand::AutoGen(self.next_label());
+        vec![
+            Slt(less, left.1, right.1),
+            Bne(left.1, right.1, done),
+            Sltu(less, left.0, right.0),
+            Label(done),
+            Mv(dst, less),
+        ]
+    }
+}
@@ -4,21 +4,24 @@
 use std::collections::HashMap;
 use std::marker::PhantomData;
 
-use crate::asm::Register;
+use crate::asm::{Register, Xlen};
 use crate::common::*;
 use crate::tac::{
     TacTopLevel,
     TacFunction,
     TacStaticVar,
     TacInsn,
-    TacParam,
     TacBinaryOp,
     TacUnaryOp,
     TacOperand,
     TacLabelOperand,
     TacAutoGenLabel,
+    temp_reads,
 };
 use super::{
+    pair::Libcalls,
+    ArgSlot,
+    arg_slots,
     CodeGen,
     FuncContext,
     RegAlloc,
@@ -27,6 +30,7 @@ use super::{
     LabelSignature,
     TopLevel,  
     Function,
+    DebugVar,
     StaticVar,
     DataSegment,
     BssSegment,
@@ -36,11 +40,33 @@ use super::{
 };
 
 impl CodeGen<Parse> {
-    pub fn parse(mut self, tac: TacTopLevel) -> (TopLevel, CodeGen<RegAlloc>) {
+    pub fn parse(self, tac: TacTopLevel) -> (TopLevel, CodeGen<RegAlloc>) {
+        self.parse_with(tac, Xlen::default())
+    }
+
+    /// Parses for RV64 or RV32, where 64-bit values take pairs of registers, see `pair.rs`.
+    pub fn parse_with(mut self, tac: TacTopLevel, xlen: Xlen) -> (TopLevel, CodeGen<RegAlloc>) {
         let mut functions = HashMap::new();
         let mut data_seg = DataSegment::new();
         let mut bss_seg = BssSegment::new();
-        let strtb = tac.strtb;
+        let mut externs = HashMap::new();
+        let mut strtb = tac.strtb;
+
+        // 64-bit divisions on RV32 call into libgcc, unless these are defined here
+        let libcalls = (xlen == Xlen::Rv32).then(|| {
+            let libcalls = Libcalls {
+                div: strtb.intern("__divdi3".to_string()),
+                rem: strtb.intern("__moddi3".to_string()),
+            };
+            let type_ = FuncType {
+                return_type: DataType::Long,
+                param_types: vec![DataType::Long, DataType::Long],
+            };
+            for name in [libcalls.div, libcalls.rem] {
+                self.func_cxs.insert(name, FuncContext::new(name, type_.clone(), xlen));
+            }
+            libcalls
+        });
 
         // Parse static variables
         for (name, var) in tac.static_vars {
@@ -49,6 +75,7 @@ impl CodeGen<Parse> {
                 data_type: var.data_type,
                 initializer: var.initializer,
                 linkage: var.linkage,
+                span: var.span,
             };
             match var.initializer {
                 InitVal::Tentative => 
@@ -57,18 +84,20 @@ impl CodeGen<Parse> {
                     bss_seg.add(static_var),
                 InitVal::Const(_) =>
                     data_seg.add(static_var),
-                InitVal::None => {},
+                InitVal::None => {
+                    externs.insert(name, static_var);
+                },
             }
         }
 
         // Parse functions
         for (name, func) in tac.functions {
             let type_ = func.type_();
-            let cx = FuncContext::new(name, type_);
+            let cx = FuncContext::new(name, type_, xlen);
             self.func_cxs.insert(name, cx);
             self.cur_func = Some(name);
 
-            let parsed_func = self.parse_function(func);            
+            let parsed_func = self.parse_function(func, libcalls);            
             parsed_func.map(|func| functions.insert(func.name, func));
 
             self.cur_func = None;
@@ -78,7 +107,9 @@ impl CodeGen<Parse> {
             functions,
             bss_seg,
             data_seg,
+            externs,
             strtb,
+            xlen,
         }, CodeGen {
             func_cxs: self.func_cxs,
             cur_func: self.cur_func,
@@ -95,6 +126,7 @@ impl CodeGen<Parse> {
     fn parse_function(
         &mut self,
         func: TacFunction,
+        libcalls: Option<Libcalls>,
     ) -> Option<Function> {
         match func {
             TacFunction::Declared {..} => return None,
@@ -105,44 +137,95 @@ impl CodeGen<Parse> {
                 params,
                 local_vars,
                 body,
+                span,
             } => {
                 let mut insns = vec![];
+                insns.push(Insn::Intermediate(IntermediateInsn::Prologue));
+
+                // self-recursive tail calls jump back here, with the new arguments in place
+                let code = body.iter()
+                    .filter(|insn| !matches!(insn, TacInsn::Loc(..)))
+                    .collect::<Vec<_>>();
+                let has_self_tail_call = code.windows(2)
+                    .any(|pair| tail_call(pair[0], pair[1]) == Some(name));
+                let entry = has_self_tail_call.then(|| {
+                    let label = LabelOperand::AutoGen(self.next_label());
+                    insns.push(Insn::Label(label));
+                    label
+                });
+
                 let cx = self.cur_func
                     .as_ref()
                     .and_then(|name| self.func_cxs.get_mut(name))
                     .expect("Internal error: Current function context not found");
 
-                insns.push(Insn::Intermediate(IntermediateInsn::Prologue));
-
-                for (i, param) in params.iter().enumerate() {
+                let param_types = params.iter()
+                    .map(|param| param.data_type)
+                    .collect::<Vec<_>>();
+                let (slots, _) = arg_slots(&param_types, cx.xlen);
+                for (param, slots) in params.iter().zip(slots) {
                     let v_reg_id = cx.alloc_v_reg();
-                    let v_reg = Operand::VirtReg(v_reg_id);
-                    if i < 8 {
-                        insns.push(Insn::Mv(v_reg, Operand::PhysReg(Register::a(i))));
-                    } else {
-                        let size = param.data_type.size();
-                        let offset = get_param_offset(&params, i);
-                        // currenly we only have int and long, and no needs to consider the sign.
-                        let insn = match size {
-                            4 => Insn::Lw(v_reg, Operand::frame(offset, 4)),
-                            8 => Insn::Ld(v_reg, Operand::frame(offset, 8)),
-                            _ => unreachable!(),
-                        };
-                        insns.push(insn);
+                    let mut v_regs = vec![v_reg_id];
+                    if slots.len() > 1 {
+                        v_regs.push(cx.high_vreg(v_reg_id));
+                    }
+                    // currenly we only have int and long, and no needs to consider the sign.
+                    let size = param.data_type.size().min(cx.xlen.bytes());
+                    for (v_reg, slot) in v_regs.into_iter().zip(slots) {
+                        let v_reg = Operand::VirtReg(v_reg);
+                        insns.push(match slot {
+                            ArgSlot::Reg(reg) => Insn::Mv(v_reg, Operand::PhysReg(reg)),
+                            ArgSlot::Stack(offset) => Insn::load(v_reg, Operand::frame(offset, size)),
+                        });
                     }
                     cx.map_var2vreg(param.local_id, v_reg_id);
                 }       
 
                 let func_type = cx.type_.clone();
 
+                let temp_reads = temp_reads(&body);
                 let mut parsed_body = vec![];
-                for insn in body {
-                    self.parse_insn(insn).map(|parsed_insns| {
+                let mut body = body.into_iter().peekable();
+                while let Some(insn) = body.next() {
+                    // `Loc`s in between don't keep instructions from being fused
+                    let mut locs = vec![];
+                    while let Some(&TacInsn::Loc(span)) = body.peek() {
+                        locs.push(Insn::Loc(span));
+                        body.next();
+                    }
+                    if let Some(next) = body.peek()
+                        && let Some(insns) = self.parse_tail_call(&insn, next, entry)
+                            .or_else(|| self.parse_fused_branch(&insn, next, &temp_reads)) {
+                        parsed_body.extend(locs);
+                        parsed_body.extend(insns);
+                        body.next();
+                        continue;
+                    }
+                    self.parse_insn(insn, libcalls).map(|parsed_insns| {
                         parsed_body.extend(parsed_insns);
                     });
+                    parsed_body.extend(locs);
                 }
                 insns.extend(parsed_body);
 
+                // variables never accessed have no register
+                let cx = self.cur_cx();
+                let mut locals = local_vars.values()
+                    .filter_map(|var| var.span.map(|span| (var, span)))
+                    .collect::<Vec<_>>();
+                locals.sort_by_key(|(var, _)| var.local_id);
+                let vars = params.iter()
+                    .map(|param| (param.name, param.data_type, param.local_id, param.span, true))
+                    .chain(locals.into_iter().map(|(var, span)| (var.name, var.data_type, var.local_id, span, false)))
+                    .map(|(name, data_type, local_id, span, is_param)| DebugVar {
+                        name,
+                        data_type,
+                        span,
+                        is_param,
+                        location: cx.var_vreg(local_id).map(Operand::VirtReg),
+                    })
+                    .collect();
+
                 Some(Function {
                     name,
                     linkage,
@@ -150,6 +233,8 @@ impl CodeGen<Parse> {
                     body: insns,
                     frame_size: 0,
                     callee_saved: None,
+                    span,
+                    vars,
                 })
             }
         }
@@ -158,12 +243,19 @@ impl CodeGen<Parse> {
     fn parse_insn(
         &mut self,
         insn: TacInsn,
+        libcalls: Option<Libcalls>,
     ) -> Option<Vec<Insn>> {
         use Insn::*;
 
+        if let Some(insns) = self.parse_pair_insn(&insn, libcalls) {
+            return Some(insns);
+        }
+
         // here we infer according operations directly from the type size.
         // cz we only have int and long now.
         // but later we should consider other types.
+        // values narrower than the registers use the `w` instructions, which only exist on RV64.
+        let xlen = self.cur_cx().xlen;
     
         let insns = match insn {
             TacInsn::Move { src, dst } => {
@@ -183,23 +275,21 @@ impl CodeGen<Parse> {
                 src, 
                 dst 
             } => {
-                if let TacUnaryOp::Pos = op {
-                    return None;
-                }
-                let (src_op, type__) = self.parse_operand(src);
-                let (dst_op, type_) = self.parse_operand(dst);
-                assert_eq!(type_, type__);
-                let size = type_.size();
-                match (op, size) {
-                    (TacUnaryOp::Not, 4) => vec![
+                let (src_op, src_type) = self.parse_operand(src);
+                let (dst_op, dst_type) = self.parse_operand(dst);
+                // `!` of a long yields an int
+                assert!(src_type == dst_type || matches!(op, TacUnaryOp::Not));
+                let word = src_type.size() < xlen.bytes();
+                match (op, word) {
+                    (TacUnaryOp::Pos, _) => vec![Mv(dst_op, src_op)],
+                    (TacUnaryOp::Not, true) => vec![
                         Sextw(dst_op, src_op),
                         Seqz(dst_op, dst_op),
                     ],
-                    (TacUnaryOp::Not, 8) => vec![Seqz(dst_op, src_op)],
+                    (TacUnaryOp::Not, false) => vec![Seqz(dst_op, src_op)],
                     (TacUnaryOp::Complement, _) => vec![Not(dst_op, src_op)],
-                    (TacUnaryOp::Negate, 4) => vec![Negw(dst_op, src_op)],
-                    (TacUnaryOp::Negate, 8) => vec![Neg(dst_op, src_op)],
-                    _ => unreachable!(),
+                    (TacUnaryOp::Negate, true) => vec![Negw(dst_op, src_op)],
+                    (TacUnaryOp::Negate, false) => vec![Neg(dst_op, src_op)],
                 }
             },
             TacInsn::Binary { 
@@ -212,69 +302,74 @@ impl CodeGen<Parse> {
                 let (right_op, right_type) = self.parse_operand(right);
                 let (dst_op, dst_type) = self.parse_operand(dst);
                 assert_eq!(left_type, right_type);
-                assert_eq!(left_type, dst_type);
-
-                let size = left_type.size();
-                match (op, size) {
-                    (TacBinaryOp::Add, 4) => vec![Addw(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Add, 8) => vec![Add(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Sub, 4) => vec![Subw(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Sub, 8) => vec![Sub(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Mul, 4) => vec![Mulw(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Mul, 8) => vec![Mul(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Div, 4) => vec![Divw(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Div, 8) => vec![Div(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Rem, 4) => vec![Remw(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Rem, 8) => vec![Rem(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Eq, 4) => vec![
+                // comparisons of longs yield an int
+                assert!(left_type == dst_type || matches!(op,
+                    TacBinaryOp::Eq | TacBinaryOp::NotEq |
+                    TacBinaryOp::Ls | TacBinaryOp::Gt |
+                    TacBinaryOp::LsEq | TacBinaryOp::GtEq
+                ));
+
+                let word = left_type.size() < xlen.bytes();
+                match (op, word) {
+                    (TacBinaryOp::Add, true) => vec![Addw(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Add, false) => vec![Add(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Sub, true) => vec![Subw(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Sub, false) => vec![Sub(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Mul, true) => vec![Mulw(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Mul, false) => vec![Mul(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Div, true) => vec![Divw(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Div, false) => vec![Div(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Rem, true) => vec![Remw(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Rem, false) => vec![Rem(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Eq, true) => vec![
                         Sextw(left_op, left_op),
                         Sextw(right_op, right_op),
                         Sub(dst_op, left_op, right_op),
                         Seqz(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::Eq, 8) => vec![
+                    (TacBinaryOp::Eq, false) => vec![
                         Sub(dst_op, left_op, right_op),
                         Seqz(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::NotEq, 4) => vec![
+                    (TacBinaryOp::NotEq, true) => vec![
                         Sextw(left_op, left_op),
                         Sextw(right_op, right_op),
                         Sub(dst_op, left_op, right_op),
                         Snez(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::NotEq, 8) => vec![
+                    (TacBinaryOp::NotEq, false) => vec![
                         Sub(dst_op, left_op, right_op),
                         Snez(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::Ls, 4) => vec![
+                    (TacBinaryOp::Ls, true) => vec![
                         Sextw(left_op, left_op),
                         Sextw(right_op, right_op),
                         Slt(dst_op, left_op, right_op),
                     ],
-                    (TacBinaryOp::Ls, 8) => vec![Slt(dst_op, left_op, right_op)],
-                    (TacBinaryOp::Gt, 4) => vec![
+                    (TacBinaryOp::Ls, false) => vec![Slt(dst_op, left_op, right_op)],
+                    (TacBinaryOp::Gt, true) => vec![
                         Sextw(left_op, left_op),
                         Sextw(right_op, right_op),
                         Sgt(dst_op, left_op, right_op),
                     ],
-                    (TacBinaryOp::Gt, 8) => vec![Sgt(dst_op, left_op, right_op)],
-                    (TacBinaryOp::LsEq, 4) => vec![
+                    (TacBinaryOp::Gt, false) => vec![Sgt(dst_op, left_op, right_op)],
+                    (TacBinaryOp::LsEq, true) => vec![
                         Sextw(left_op, left_op),
                         Sextw(right_op, right_op),
                         Sgt(dst_op, left_op, right_op),
                         Seqz(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::LsEq, 8) => vec![
+                    (TacBinaryOp::LsEq, false) => vec![
                         Sgt(dst_op, left_op, right_op),
                         Seqz(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::GtEq, 4) => vec![
+                    (TacBinaryOp::GtEq, true) => vec![
                         Sextw(left_op, left_op),
                         Sextw(right_op, right_op),
                         Slt(dst_op, left_op, right_op),
                         Seqz(dst_op, dst_op),
                     ],
-                    (TacBinaryOp::GtEq, 8) => vec![
+                    (TacBinaryOp::GtEq, false) => vec![
                         Slt(dst_op, left_op, right_op),
                         Seqz(dst_op, dst_op),
                     ],
@@ -345,57 +440,62 @@ impl CodeGen<Parse> {
                 dst 
             } => {
                 let mut insns = vec![];
-                let (dst_op, dst_type) = self.parse_operand(dst);
-                let mut arg_ops = args.into_iter()
-                    .map(|arg| self.parse_operand(arg))
+                let arg_types = args.iter()
+                    .map(|arg| arg.data_type())
                     .collect::<Vec<_>>();
-                for i in 0..8 {
-                    if i < arg_ops.len() {
-                        insns.push(Mv(Operand::PhysReg(Register::a(i)), arg_ops[i].0));
+                let (slots, stack_size) = arg_slots(&arg_types, xlen);
+                let arg_words = args.into_iter()
+                    .map(|arg| self.parse_words(arg, &mut insns))
+                    .collect::<Vec<_>>();
+
+                let mut stores = vec![];
+                for ((words, slots), type_) in arg_words.into_iter().zip(slots).zip(arg_types) {
+                    let size = type_.size().min(xlen.bytes());
+                    for (word, slot) in words.into_iter().zip(slots) {
+                        match slot {
+                            ArgSlot::Reg(reg) => insns.push(Mv(Operand::PhysReg(reg), word)),
+                            ArgSlot::Stack(offset) => stores.push(Insn::store(word, Operand::stack(offset, size))),
+                        }
                     }
                 }
-                let len = arg_ops.len();
 
-                if len > 8 {
-                    let stack_size = (arg_ops.len() - 8) * 8;
-                    let padded_size = (stack_size + 15) & !15;
+                if stack_size > 0 {
                     insns.push(Insn::Addi(
                         Operand::PhysReg(Register::Sp),
                         Operand::PhysReg(Register::Sp),
-                        -(padded_size as i64),
+                        -(stack_size as i64),
                     ));
-                    for (i, (op, type_)) in arg_ops.into_iter().enumerate().skip(8) {
-                        let offset = (i - 8) * 8;
-                        let insn = match type_.size() {
-                            4 => Insn::Sw(op, Operand::stack(offset as isize, 4)),
-                            8 => Insn::Sd(op, Operand::stack(offset as isize, 8)),
-                            _ => unreachable!(),
-                        };
-                        insns.push(insn);
-                    }
+                    insns.extend(stores);
                 }
 
                 insns.push(Insn::Call(target));
-                insns.push(Insn::Mv(dst_op, Operand::PhysReg(Register::A0)));
+                if self.cur_cx().is_pair(dst.data_type()) {
+                    let (lo, hi) = (self.new_v_reg(), self.new_v_reg());
+                    insns.push(Insn::Mv(lo, Operand::PhysReg(Register::A0)));
+                    insns.push(Insn::Mv(hi, Operand::PhysReg(Register::A1)));
+                    self.write_pair(dst, lo, hi, &mut insns);
+                } else {
+                    let (dst_op, _) = self.parse_operand(dst);
+                    insns.push(Insn::Mv(dst_op, Operand::PhysReg(Register::A0)));
+                }
 
-                if len > 8 {
-                    let stack_size = (len - 8) * 8;
-                    let padded_size = (stack_size + 15) & !15;
+                if stack_size > 0 {
                     insns.push(Insn::Addi(
                         Operand::PhysReg(Register::Sp),
                         Operand::PhysReg(Register::Sp),
-                        padded_size as i64,
+                        stack_size as i64,
                     ));
                 }
 
                 insns                
-            }
+            },
+            TacInsn::Loc(span) => vec![Loc(span)],
         };
 
         Some(insns)
     }
 
-    fn parse_operand(
+    pub(super) fn parse_operand(
         &mut self,
         operand: TacOperand,
     ) -> (Operand, DataType) {
@@ -434,12 +534,116 @@ impl CodeGen<Parse> {
         (op, operand.data_type())
     }
 
+    /// `dst = call f(args); return dst` leaves the frame before jumping to `f`, which then returns to our caller.
+    /// Arguments on the stack are put in our own incoming area, so they have to fit in there.
+    /// Self-recursive ones become loops by jumping back to `entry` instead.
+    fn parse_tail_call(
+        &mut self,
+        insn: &TacInsn,
+        next: &TacInsn,
+        entry: Option<LabelOperand>,
+    ) -> Option<Vec<Insn>> {
+        let target = tail_call(insn, next)?;
+        let TacInsn::FuncCall { args, .. } = insn else { unreachable!() };
+
+        let cx = self.cur_cx();
+        let xlen = cx.xlen;
+        let is_self = target == cx.name;
+        let arg_types = args.iter()
+            .map(|arg| arg.data_type())
+            .collect::<Vec<_>>();
+        let (slots, stack_size) = arg_slots(&arg_types, xlen);
+        if !is_self && stack_size > arg_slots(&cx.type_.param_types, xlen).1 {
+            return None;
+        }
+
+        let mut insns = vec![];
+        let arg_words = args.iter()
+            .map(|arg| self.parse_words(*arg, &mut insns))
+            .collect::<Vec<_>>();
+        let mut stores = vec![];
+        for ((words, slots), type_) in arg_words.into_iter().zip(slots).zip(arg_types) {
+            let size = type_.size().min(xlen.bytes());
+            for (word, slot) in words.into_iter().zip(slots) {
+                match slot {
+                    ArgSlot::Reg(reg) => insns.push(Insn::Mv(Operand::PhysReg(reg), word)),
+                    // where our own stack parameters are, above the frame
+                    ArgSlot::Stack(offset) => stores.push(Insn::store(word, Operand::frame(offset, size))),
+                }
+            }
+        }
+        insns.extend(stores);
+
+        insns.push(match entry {
+            Some(entry) if is_self => Insn::J(entry),
+            _ => Insn::Intermediate(IntermediateInsn::TailCall(target)),
+        });
+        Some(insns)
+    }
+
+    /// `t = a < b; branch_if_zero t, L` -> `bge a, b, L`, comparing right in the branch,
+    /// as long as `t` isn't read anywhere else.
+    fn parse_fused_branch(
+        &mut self,
+        insn: &TacInsn,
+        next: &TacInsn,
+        temp_reads: &HashMap<usize, usize>,
+    ) -> Option<Vec<Insn>> {
+        let TacInsn::Binary { op, left, right, dst } = *insn else { return None };
+        let (src, label, if_zero) = match *next {
+            TacInsn::BranchIfZero { src, label } => (src, label, true),
+            TacInsn::BranchNotZero { src, label } => (src, label, false),
+            _ => return None,
+        };
+        let TacOperand::Temp(temp_id, _) = dst else { return None };
+        if src != dst || temp_reads.get(&temp_id) != Some(&1) {
+            return None;
+        }
+        // 64-bit values on RV32 are compared a half at a time
+        let cx = self.cur_cx();
+        if cx.is_pair(left.data_type()) {
+            return None;
+        }
+        let word = left.data_type().size() < cx.xlen.bytes();
+        // the condition the branch is taken on
+        let negated = negate_comparison(op)?;
+        let op = if if_zero { negated } else { op };
+
+        let (left_op, _) = self.parse_operand(left);
+        let (right_op, _) = self.parse_operand(right);
+        let signature = LabelSignature::from_tac(
+            self.cur_cx().name,
+            label,
+        );
+        let label = LabelOperand::AutoGen(self.map_label(signature));
+
+        let mut insns = vec![];
+        if word {
+            insns.push(Insn::Sextw(left_op, left_op));
+            insns.push(Insn::Sextw(right_op, right_op));
+        }
+        insns.push(match op {
+            TacBinaryOp::Eq => Insn::Beq(left_op, right_op, label),
+            TacBinaryOp::NotEq => Insn::Bne(left_op, right_op, label),
+            TacBinaryOp::Ls => Insn::Blt(left_op, right_op, label),
+            TacBinaryOp::GtEq => Insn::Bge(left_op, right_op, label),
+            TacBinaryOp::Gt => Insn::Blt(right_op, left_op, label),
+            TacBinaryOp::LsEq => Insn::Bge(right_op, left_op, label),
+            _ => unreachable!(),
+        });
+        Some(insns)
+    }
+
     fn alloc_v_reg(&mut self) -> usize {
         let cx = self.cur_cx_mut();
         let v_reg = cx.alloc_v_reg();
         v_reg
     }
 
+    pub(super) fn new_v_reg(&mut self) -> Operand {
+        Operand::VirtReg(self.alloc_v_reg())
+    }
+
     pub fn next_label(&mut self) -> usize {
         let label = self.next_label;
         self.next_label += 1;
@@ -457,24 +661,23 @@ impl CodeGen<Parse> {
 
 }
 
-fn get_param_offset(
-    params: &Vec<TacParam>,
-    param_id: usize,
-) -> isize {
-    if param_id < 8 {
-        panic!("Internal error: Parameter ID {} is less than 8, which is not supported", param_id);
-    }
-    if param_id >= params.len() {
-        panic!("Internal error: Parameter ID {} is out of bounds for the number of parameters", param_id);
+/// The callee, if `insn` is a call whose result is returned right away by `next`.
+fn tail_call(insn: &TacInsn, next: &TacInsn) -> Option<StrDescriptor> {
+    match (insn, next) {
+        (TacInsn::FuncCall { target, dst, .. }, TacInsn::Return(val)) if dst == val => Some(*target),
+        _ => None,
     }
+}
 
-    let mut cur_offset = 0;
-    for i in 8..param_id {
-        let prev_param = &params[i - 1];
-        let param_size = prev_param.data_type.size();
-        let slot_size = (param_size + 7) & !7;
-        cur_offset += slot_size;
+/// The comparison true exactly when `op` is false, `None` for other operations.
+fn negate_comparison(op: TacBinaryOp) -> Option<TacBinaryOp> {
+    match op {
+        TacBinaryOp::Eq => Some(TacBinaryOp::NotEq),
+        TacBinaryOp::NotEq => Some(TacBinaryOp::Eq),
+        TacBinaryOp::Ls => Some(TacBinaryOp::GtEq),
+        TacBinaryOp::GtEq => Some(TacBinaryOp::Ls),
+        TacBinaryOp::Gt => Some(TacBinaryOp::LsEq),
+        TacBinaryOp::LsEq => Some(TacBinaryOp::Gt),
+        _ => None,
     }
-
-    cur_offset as isize
-}
\ No newline at end of file
+}
@@ -1,6 +1,8 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::{collections::{BTreeSet, HashMap, HashSet}, marker::PhantomData};
 
-use crate::{asm::Register, common::*, lir::codegen::regalloc::{AnalyzeResult, GeneralReg, Rig}};
+use crate::{asm::Register, common::*, lir::codegen::regalloc::{AnalyzeResult, GeneralReg, LinearScan, RegAllocator, Rig}};
+use super::spill_code::SpillState;
+use super::dot::{AllocDump, AllocTrace};
 use super::{
     CodeGen,
     Spill,
@@ -9,16 +11,41 @@ use super::{
     Function,
     Insn,
     Operand,
+    map_operands,
 };
 
 impl CodeGen<RegAlloc> {
-    pub fn alloc(mut self, lir: TopLevel) -> (TopLevel, CodeGen<Spill>) {
+    pub fn alloc(self, lir: TopLevel) -> (TopLevel, CodeGen<Spill>) {
+        self.alloc_with(lir, RegAllocator::default())
+    }
+
+    pub fn alloc_with(self, lir: TopLevel, allocator: RegAllocator) -> (TopLevel, CodeGen<Spill>) {
+        let (lir, codegen, _) = self.alloc_traced(lir, allocator, false);
+        (lir, codegen)
+    }
+
+    /// Allocates as `alloc_with`, along with Graphviz dumps of every function.
+    pub fn alloc_dumped(self, lir: TopLevel, allocator: RegAllocator) -> (TopLevel, CodeGen<Spill>, Vec<AllocDump>) {
+        self.alloc_traced(lir, allocator, true)
+    }
+
+    fn alloc_traced(
+        mut self,
+        lir: TopLevel,
+        allocator: RegAllocator,
+        dump: bool,
+    ) -> (TopLevel, CodeGen<Spill>, Vec<AllocDump>) {
         let mut alloced_funcs = HashMap::new();
+        let mut traces = vec![];
 
         for (name, func) in lir.functions {
             self.cur_func = Some(name);
 
-            let mut func = self.alloc_func(func);
+            let mut trace = dump.then(AllocTrace::default);
+            let mut func = self.alloc_func(func, allocator, trace.as_mut());
+            if let Some(trace) = trace {
+                traces.push((name, trace));
+            }
 
             func.callee_saved = self.cur_cx_mut()
                 .callee_saved
@@ -28,33 +55,104 @@ impl CodeGen<RegAlloc> {
 
             self.cur_func = None;
         }
- 
-        (TopLevel {
+
+        let lir = TopLevel {
             functions: alloced_funcs,
             data_seg: lir.data_seg,
             bss_seg: lir.bss_seg,
+            externs: lir.externs,
             strtb: lir.strtb,
-        }, CodeGen {
+            xlen: lir.xlen,
+        };
+
+        let mut dumps = traces.into_iter()
+            .map(|(name, trace)| trace.render(name, &lir, &self.func_cxs))
+            .collect::<Vec<_>>();
+        dumps.sort_by_key(|dump| lir.strtb.get(dump.name).cloned());
+
+        (lir, CodeGen {
             func_cxs: self.func_cxs,
             cur_func: self.cur_func,
             next_label: self.next_label,
             lmap: self.lmap,
             _stage: PhantomData,
-        })
+        }, dumps)
     }
 
-    pub fn alloc_func(
+    pub(super) fn alloc_func(
         &mut self,
         func: Function,
+        allocator: RegAllocator,
+        mut trace: Option<&mut AllocTrace>,
     ) -> Function {
         let mut func = func;
-        let rig = Rig::base(&self.func_cxs);
-        let AnalyzeResult {
-            map,
-        } = rig.analyze(&func);
+        func.body = self.legalize(func.body);
 
+        // allocate, spill what's left unallocated and try again,
+        // until every virtual register gets a physical one.
+        let mut spill_state = SpillState::default();
+        let map = loop {
+            let AnalyzeResult {
+                map,
+            } = match allocator {
+                RegAllocator::Graph => Rig::base(&self.func_cxs).analyze(&func, &spill_state.unspillable),
+                RegAllocator::Linear => LinearScan::new(&self.func_cxs).analyze(&func, &spill_state.unspillable),
+            };
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.record(Rig::base(&self.func_cxs).build(&func, &spill_state.unspillable), &map);
+            }
+
+            let spilled = map.iter()
+                .filter_map(|(reg, color)| match (reg, color) {
+                    (GeneralReg::Virt(v_reg), None) => Some(*v_reg),
+                    _ => None,
+                })
+                .collect::<HashSet<_>>();
+            if spilled.is_empty() {
+                break map;
+            }
+
+            // registers live for a single access are never spilled, so this terminates
+            assert!(spilled.is_disjoint(&spill_state.unspillable),
+                "Internal error: short-lived virtual registers spilled");
+            func.body = self.insert_spill_code(func.body, &spilled, &mut spill_state);
+        };
+
+        if let Some(trace) = trace {
+            trace.body = func.body.clone();
+        }
         func.body = self.rewrite_insns(func.body, &map);
 
+        // spilled variables are kept in their slots, those never colored are optimized out
+        let cx = self.cur_cx();
+        for var in func.vars.iter_mut() {
+            let Some(Operand::VirtReg(v_reg)) = var.location else { continue };
+            var.location = match (cx.mmap.get(&v_reg), map.get(&GeneralReg::Virt(v_reg))) {
+                (Some(&offset), _) => Some(Operand::frame(offset, var.data_type.size())),
+                (None, Some(Some(reg))) => Some(Operand::PhysReg(*reg)),
+                _ => None,
+            };
+        }
+
+        // only the callee-saved registers left after coalescing need saving,
+        // s0 and sp are taken care of by the prologue itself.
+        let mut used = BTreeSet::new();
+        for insn in func.body.iter() {
+            map_operands(*insn, |operand, _| {
+                match operand {
+                    Operand::PhysReg(Register::S0 | Register::Sp) => {},
+                    Operand::PhysReg(reg) if reg.is_callee_saved() => {
+                        used.insert(reg);
+                    },
+                    _ => {},
+                }
+                operand
+            });
+        }
+        for reg in used {
+            self.cur_cx_mut().push_callee_saved(reg);
+        }
+
         func
     }
 
@@ -64,111 +162,12 @@ impl CodeGen<RegAlloc> {
         map: &HashMap<GeneralReg, Option<Register>>,
     ) -> Vec<Insn> {
         insns.into_iter()
-            .map(|insn| self.rewrite_insn(insn, map))
+            .map(|insn| map_operands(insn, |operand, _| self.rewrite_operand(operand, map)))
+            // coalesced moves
+            .filter(|insn| !matches!(insn, Insn::Mv(dst, src) if dst == src))
             .collect()
     }
 
-    fn rewrite_insn(
-        &mut self,
-        insn: Insn,
-        map: &HashMap<GeneralReg, Option<Register>>,
-    ) -> Insn {
-        match insn {
-            Insn::Add(dst, src1, src2) |
-            Insn::Addw(dst, src1, src2) |
-            Insn::Sub(dst, src1, src2) |
-            Insn::Subw(dst, src1, src2) |
-            Insn::Mul(dst, src1, src2) |
-            Insn::Mulw(dst, src1, src2) |
-            Insn::Div(dst, src1, src2) |
-            Insn::Divw(dst, src1, src2) |
-            Insn::Rem(dst, src1, src2) |
-            Insn::Remw(dst, src1, src2) |
-            Insn::Slt(dst, src1, src2) |
-            Insn::Sgt(dst, src1, src2) =>{
-                let dst = self.rewrite_operand(dst, map);
-                let src1 = self.rewrite_operand(src1, map);
-                let src2 = self.rewrite_operand(src2, map);
-                match insn {
-                    Insn::Add(..) => Insn::Add(dst, src1, src2),
-                    Insn::Addw(..) => Insn::Addw(dst, src1, src2),
-                    Insn::Sub(..) => Insn::Sub(dst, src1, src2),
-                    Insn::Subw(..) => Insn::Subw(dst, src1, src2),
-                    Insn::Mul(..) => Insn::Mul(dst, src1, src2),
-                    Insn::Mulw(..) => Insn::Mulw(dst, src1, src2),
-                    Insn::Div(..) => Insn::Div(dst, src1, src2),
-                    Insn::Divw(..) => Insn::Divw(dst, src1, src2),
-                    Insn::Rem(..) => Insn::Rem(dst, src1, src2),
-                    Insn::Remw(..) => Insn::Remw(dst, src1, src2),
-                    Insn::Slt(..) => Insn::Slt(dst, src1, src2),
-                    Insn::Sgt(..) => Insn::Sgt(dst, src1, src2),
-                    _ => unreachable!(),
-                }
-            },
-            Insn::Mv(dst, src) |
-            Insn::Neg(dst, src) |
-            Insn::Negw(dst, src) |
-            Insn::Sextw(dst, src) |
-            Insn::Seqz(dst, src) |
-            Insn::Snez(dst, src) |
-            Insn::Not(dst, src) => {
-                let dst = self.rewrite_operand(dst, map);
-                let src = self.rewrite_operand(src, map);
-                match insn {
-                    Insn::Mv(..) => Insn::Mv(dst, src),
-                    Insn::Neg(..) => Insn::Neg(dst, src),
-                    Insn::Negw(..) => Insn::Negw(dst, src),
-                    Insn::Sextw(..) => Insn::Sextw(dst, src),
-                    Insn::Seqz(..) => Insn::Seqz(dst, src),
-                    Insn::Snez(..) => Insn::Snez(dst, src),
-                    Insn::Not(..) => Insn::Not(dst, src),
-                    _ => unreachable!(),
-                }
-            },
-            Insn::Beq(src1, src2, label) |
-            Insn::Bne(src1, src2, label) => {
-                let src1 = self.rewrite_operand(src1, map);
-                let src2 = self.rewrite_operand(src2, map);
-                match insn {
-                    Insn::Beq(..) => Insn::Beq(src1, src2, label),
-                    Insn::Bne(..) => Insn::Bne(src1, src2, label),
-                    _ => unreachable!(),
-                }
-            },
-            Insn::Ret |
-            Insn::Addi(..) |
-            Insn::Addiw(..) |
-            Insn::La(..) |
-            Insn::Li(..) |
-            Insn::Call(..) |
-            Insn::Label(..) |
-            Insn::J(..) |
-            Insn::Intermediate(..) => insn,
-            Insn::Ld(reg, mem) |
-            Insn::Lw(reg, mem) |
-            Insn::Sd(reg, mem) |
-            Insn::Sw(reg, mem) => {
-                let reg = self.rewrite_operand(reg, map);
-                match insn {
-                    Insn::Ld(..) => Insn::Ld(reg, mem),
-                    Insn::Lw(..) => Insn::Lw(reg, mem),
-                    Insn::Sd(..) => Insn::Sd(reg, mem),
-                    Insn::Sw(..) => Insn::Sw(reg, mem),
-                    _ => unreachable!(),
-                }
-            },
-            Insn::LoadStatic(reg, name) |
-            Insn::StoreStatic(reg, name) => {
-                let reg = self.rewrite_operand(reg, map);
-                match insn {
-                    Insn::LoadStatic(..) => Insn::LoadStatic(reg, name),
-                    Insn::StoreStatic(..) => Insn::StoreStatic(reg, name),
-                    _ => unreachable!(),
-                }
-            }
-        }
-    }
-
     fn rewrite_operand(
         &mut self,
         operand: Operand,
@@ -179,23 +178,10 @@ impl CodeGen<RegAlloc> {
             Operand::Mem{..}|
             Operand::PhysReg(..)|
             Operand::Static(..) => operand,
-            Operand::VirtReg(vreg_id) => {
-                let vreg = GeneralReg::Virt(vreg_id);
-                if let Some(reg) = map.get(&vreg) {
-                    if let Some(reg) = reg {
-                        if reg.is_callee_saved() {
-                            self.cur_cx_mut()
-                                .push_callee_saved(*reg);
-                        }
-
-                        Operand::PhysReg(*reg)
-                    } else {
-                        Operand::VirtReg(vreg_id)
-                    }
-                } else {
-                    Operand::VirtReg(vreg_id)
-                }
-            }
+            Operand::VirtReg(vreg_id) => match map.get(&GeneralReg::Virt(vreg_id)) {
+                Some(Some(reg)) => Operand::PhysReg(*reg),
+                _ => operand,
+            },
         }
     }
 }
\ No newline at end of file
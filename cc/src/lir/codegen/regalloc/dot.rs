@@ -0,0 +1,268 @@
+//! Graphviz export of what the register allocator saw of each function,
+//! for `--dump-cfg=dot` and `--dump-rig=dot`.
+//! - the CFG of the final round, every block listing its instructions
+//!   between the registers live into and out of it
+//! - the interference graph of every round, each in a cluster of its own,
+//!   with nodes filled in the color of their register, spilled ones dashed,
+//!   and virtual registers labeled with their spill costs.
+//!   Physical registers all interfere with each other, those edges are left out.
+//!
+//! The graphs are the same for the linear-scan allocator, only the colors come from its intervals.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{asm::Register, common::*};
+use super::live_analysis::{self, Graph, InsnId, LiveReg, Node, NodeId};
+use super::{
+    Rig,
+    RigNode,
+    GeneralReg,
+    TopLevel,
+    Insn,
+    FuncContext,
+};
+
+/// Graphviz graphs of a function, as its registers were allocated.
+#[derive(Debug)]
+pub struct AllocDump {
+    pub name: StrDescriptor,
+    /// `digraph` of the control flow
+    pub cfg: String,
+    /// `graph` of the interference between registers
+    pub rig: String,
+}
+
+/// What the allocator saw of a function, rendered once the whole unit is allocated.
+#[derive(Debug, Default)]
+pub(super) struct AllocTrace {
+    /// the body of the final round, before virtual registers are rewritten
+    pub body: Vec<Insn>,
+    /// the interference graph of each round, colored
+    rounds: Vec<HashMap<GeneralReg, RigNode>>,
+}
+
+impl AllocTrace {
+    /// Records a round of allocation, `rig` colored as in `map`.
+    pub fn record(&mut self, rig: Rig, map: &HashMap<GeneralReg, Option<Register>>) {
+        let mut nodes = rig.nodes;
+        for node in nodes.values_mut() {
+            node.color = match node.reg {
+                GeneralReg::Phys(reg) => Some(reg),
+                GeneralReg::Virt(_) => map.get(&node.reg).copied().flatten(),
+            };
+        }
+        self.rounds.push(nodes);
+    }
+
+    pub fn render(
+        &self,
+        name: StrDescriptor,
+        lir: &TopLevel,
+        func_cxs: &HashMap<StrDescriptor, FuncContext>,
+    ) -> AllocDump {
+        AllocDump {
+            name,
+            cfg: self.cfg_dot(name, lir, func_cxs),
+            rig: self.rig_dot(name, lir),
+        }
+    }
+
+    fn cfg_dot(
+        &self,
+        name: StrDescriptor,
+        lir: &TopLevel,
+        func_cxs: &HashMap<StrDescriptor, FuncContext>,
+    ) -> String {
+        let cfg = Graph::build(&self.body);
+        let live = live_analysis::analysis(&cfg, func_cxs, name);
+
+        let mut output = format!("digraph \"{}.lir\" {{\n", lir.strtb.get(name).unwrap());
+        output.push_str("\tnode [shape=box, fontname=\"monospace\"];\n");
+        for (&id, node) in cfg.nodes.iter() {
+            let label = match node {
+                Node::Entry { .. } => "entry\\l".to_string(),
+                Node::Exit { .. } => "exit\\l".to_string(),
+                Node::BasicBlock(block) => {
+                    let live_in = &live.block_infos[&block.id];
+                    // what is live after the last instruction, or through an empty block
+                    let live_out = block.insns.len().checked_sub(1)
+                        .and_then(|last| live.insn_infos.get(&InsnId::new(block.id, last)))
+                        .unwrap_or(live_in);
+                    let insns = block.insns.iter()
+                        .map(|insn| format!("  {}", lir.emit_insn(insn)));
+                    dot_label(
+                        std::iter::once(format!("live-in: {}", regs(live_in)))
+                            .chain(insns)
+                            .chain(std::iter::once(format!("live-out: {}", regs(live_out))))
+                    )
+                },
+            };
+            output.push_str(&format!("\t{} [label=\"{}\"];\n", node_name(id), label));
+        }
+        for (&id, node) in cfg.nodes.iter() {
+            let successors = match node {
+                Node::Entry { successors } => successors,
+                Node::BasicBlock(block) => &block.successors,
+                Node::Exit { .. } => continue,
+            };
+            for &succ in successors {
+                output.push_str(&format!("\t{} -> {};\n", node_name(id), node_name(succ)));
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    fn rig_dot(&self, name: StrDescriptor, lir: &TopLevel) -> String {
+        let mut output = format!("graph \"{}.rig\" {{\n", lir.strtb.get(name).unwrap());
+        output.push_str("\tnode [style=filled, fontname=\"monospace\"];\n");
+        for (round, nodes) in self.rounds.iter().enumerate() {
+            output.push_str(&format!("\tsubgraph cluster_{} {{\n", round));
+            output.push_str(&format!("\t\tlabel=\"round {}\";\n", round));
+
+            // physical registers only matter where virtual ones interfere with them
+            let shown = nodes.values()
+                .filter(|node| match node.reg {
+                    GeneralReg::Virt(_) => true,
+                    GeneralReg::Phys(_) => node.neighbors.iter().any(|n| matches!(n, GeneralReg::Virt(_))),
+                })
+                .map(|node| node.reg)
+                .collect::<BTreeSet<_>>();
+
+            for reg in shown.iter() {
+                let node = &nodes[reg];
+                let attrs = match (node.reg, node.color) {
+                    (GeneralReg::Phys(phys), _) => format!(
+                        "label=\"{}\", shape=box, fillcolor=\"{}\"",
+                        dot_label([phys.to_string()]),
+                        fill(phys),
+                    ),
+                    (GeneralReg::Virt(v_reg), Some(color)) => format!(
+                        "label=\"{}\", fillcolor=\"{}\"",
+                        dot_label([format!("v{}: {}", v_reg, color), cost(node.spill_cost)]),
+                        fill(color),
+                    ),
+                    (GeneralReg::Virt(v_reg), None) => format!(
+                        "label=\"{}\", style=dashed",
+                        dot_label([format!("v{}: spilled", v_reg), cost(node.spill_cost)]),
+                    ),
+                };
+                output.push_str(&format!("\t\t{} [{}];\n", rig_node_name(round, *reg), attrs));
+            }
+            for reg in shown.iter() {
+                let neighbors = nodes[reg].neighbors.iter()
+                    .filter(|&&n| n > *reg && shown.contains(&n))
+                    .filter(|n| matches!((reg, n), (GeneralReg::Virt(_), _) | (_, GeneralReg::Virt(_))))
+                    .collect::<BTreeSet<_>>();
+                for neighbor in neighbors {
+                    output.push_str(&format!(
+                        "\t\t{} -- {};\n",
+                        rig_node_name(round, *reg),
+                        rig_node_name(round, *neighbor),
+                    ));
+                }
+            }
+            output.push_str("\t}\n");
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+fn regs(live: &LiveReg) -> String {
+    live.iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(reg_name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn reg_name(reg: GeneralReg) -> String {
+    match reg {
+        GeneralReg::Phys(reg) => reg.to_string(),
+        GeneralReg::Virt(v_reg) => format!("v{}", v_reg),
+    }
+}
+
+fn cost(spill_cost: usize) -> String {
+    match spill_cost {
+        usize::MAX => "cost inf".to_string(),
+        cost => format!("cost {}", cost),
+    }
+}
+
+fn fill(reg: Register) -> String {
+    Register::ALLOC_REGS.iter()
+        .position(|r| **r == reg)
+        .map_or("white".to_string(), |idx| dot_color(idx, Register::ALLOC_REGS.len()))
+}
+
+fn node_name(id: NodeId) -> String {
+    match id {
+        NodeId::Entry => "entry".to_string(),
+        NodeId::BasicBlock(id) => format!("b{}", id),
+        NodeId::Exit => "exit".to_string(),
+    }
+}
+
+fn rig_node_name(round: usize, reg: GeneralReg) -> String {
+    format!("r{}_{}", round, reg_name(reg))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::driver::{dump, Dump, Options};
+
+    fn dumps(source: &str) -> Vec<Dump> {
+        let options = Options {
+            opt_level: 1,
+            dump_cfg: true,
+            dump_rig: true,
+            ..Options::default()
+        };
+        dump(source.to_string(), &options).unwrap()
+    }
+
+    #[test]
+    fn test_dump() {
+        // more values live across calls than there are callee-saved registers
+        let decls = (0..14)
+            .map(|i| format!("int u{} = id(x + {});", i, i))
+            .collect::<String>();
+        let sum = (0..14)
+            .map(|i| format!("u{}", i))
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let source = format!(
+            "int id(int x); int across(int x) {{ {} return {}; }} int main(void) {{ return across(1); }}",
+            decls,
+            sum,
+        );
+
+        let dumps = dumps(&source);
+        let kinds = dumps.iter()
+            .map(|dump| (dump.function.as_str(), dump.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, [("across", "tac"), ("main", "tac"), ("across", "lir"), ("across", "rig"), ("main", "lir"), ("main", "rig")]);
+
+        let tac = &dumps[0].dot;
+        assert!(tac.starts_with("digraph \"across.tac\" {\n"));
+        assert!(tac.contains("live-in: %[i32]x.0\\l"));
+        assert!(tac.contains("call id"));
+        assert!(tac.contains("entry -> b0;\n"));
+        assert!(tac.contains("[style=dashed, color=gray, constraint=false];\n"));
+
+        let lir = &dumps[2].dot;
+        assert!(lir.starts_with("digraph \"across.lir\" {\n"));
+        assert!(lir.contains("  call id\\l"));
+        assert!(lir.contains("live-out: a0\\l"));
+
+        let rig = &dumps[3].dot;
+        assert!(rig.starts_with("graph \"across.rig\" {\n"));
+        assert!(rig.contains("subgraph cluster_1 {\n"));
+        assert!(rig.contains(": spilled\\lcost "));
+        assert!(rig.contains(" -- "));
+        assert!(rig.ends_with("\t}\n}\n"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Operand legalization, before register allocation.
+//! Every operand but the source of `mv` must end up in a register,
+//! so statics and immediates are moved into fresh virtual registers:
+//! e.g.    add v0, x, 5000
+//! ->      load_static v1, x
+//! ->      mv  v2, 5000
+//! ->      add v0, v1, v2
+//! Doing this before allocation, rather than in canonicalization,
+//! means no registers have to be reserved as scratch registers,
+//! only `Register::SCRATCH` for frame offsets, which aren't known until the frame is laid out.
+
+use crate::{asm::Register, common::*};
+use super::{
+    CodeGen,
+    RegAlloc,
+    Insn,
+    Operand,
+    Access,
+    map_operands,
+};
+
+impl CodeGen<RegAlloc> {
+    pub(super) fn legalize(
+        &mut self,
+        insns: Vec<Insn>,
+    ) -> Vec<Insn> {
+        insns.into_iter()
+            .flat_map(|insn| self.legalize_insn(insn))
+            .collect()
+    }
+
+    fn legalize_insn(
+        &mut self,
+        insn: Insn,
+    ) -> Vec<Insn> {
+        use Insn::*;
+
+        let mut insns = vec![];
+
+        if let Some(reduced) = self.reduce_strength(insn) {
+            for insn in reduced {
+                insns.extend(self.legalize_insn(insn));
+            }
+            return insns;
+        }
+
+        match insn {
+            Sextw(Operand::Imm(_), _) => {
+                // type conversions of constants during tac generation,
+                // there's nothing to extend in place.
+            },
+            Sextw(Operand::Static(dst), Operand::Static(src)) if dst == src => {
+                // `lw` already sign-extends int statics when they're loaded
+            },
+            Sextw(dst, Operand::Imm(val)) =>
+                return self.legalize_insn(Mv(dst, Operand::Imm(val as i32 as i64))),
+            Mv(Operand::Static(name), src) => {
+                let src = self.legalize_read(src, &mut insns);
+                let addr = self.new_v_reg();
+                insns.push(StoreStatic(src, addr, name, 0));
+            },
+            Mv(dst, Operand::Static(name)) =>
+                insns.push(LoadStatic(dst, name, 0)),
+            Mv(_, Operand::Imm(_)) =>
+                insns.push(insn),
+            Add(dst, left, Operand::Imm(imm)) |
+            Add(dst, Operand::Imm(imm), left) if fits_imm12(imm) && !matches!(left, Operand::Imm(_)) => {
+                let left = self.legalize_read(left, &mut insns);
+                self.legalize_write(Addi(dst, left, imm), &mut insns);
+            },
+            Addw(dst, left, Operand::Imm(imm)) |
+            Addw(dst, Operand::Imm(imm), left) if fits_imm12(imm) && !matches!(left, Operand::Imm(_)) => {
+                let left = self.legalize_read(left, &mut insns);
+                self.legalize_write(Addiw(dst, left, imm as i32), &mut insns);
+            },
+            Sub(dst, left, Operand::Imm(imm)) if fits_imm12(imm.wrapping_neg()) && !matches!(left, Operand::Imm(_)) => {
+                let left = self.legalize_read(left, &mut insns);
+                self.legalize_write(Addi(dst, left, -imm), &mut insns);
+            },
+            Subw(dst, left, Operand::Imm(imm)) if fits_imm12(imm.wrapping_neg()) && !matches!(left, Operand::Imm(_)) => {
+                let left = self.legalize_read(left, &mut insns);
+                self.legalize_write(Addiw(dst, left, -imm as i32), &mut insns);
+            },
+            _ => {
+                let mut reads = vec![];
+                let insn = map_operands(insn, |operand, access| match access {
+                    Access::Read => self.legalize_read(operand, &mut reads),
+                    Access::Write => operand,
+                });
+                insns.extend(reads);
+                self.legalize_write(insn, &mut insns);
+            },
+        }
+
+        insns
+    }
+
+    /// Moves a source operand into a register, with the moving instructions pushed into `insns`.
+    fn legalize_read(
+        &mut self,
+        operand: Operand,
+        insns: &mut Vec<Insn>,
+    ) -> Operand {
+        match operand {
+            Operand::Imm(0) => Operand::PhysReg(Register::Zero),
+            Operand::Imm(val) => {
+                let reg = self.new_v_reg();
+                insns.push(Insn::Mv(reg, Operand::Imm(val)));
+                reg
+            },
+            Operand::Static(name) => {
+                let reg = self.new_v_reg();
+                insns.push(Insn::LoadStatic(reg, name, 0));
+                reg
+            },
+            _ => operand,
+        }
+    }
+
+    /// Pushes `insn` into `insns`, writing a static destination through a register.
+    fn legalize_write(
+        &mut self,
+        insn: Insn,
+        insns: &mut Vec<Insn>,
+    ) {
+        let mut store = None;
+        let insn = map_operands(insn, |operand, access| match (operand, access) {
+            (Operand::Static(name), Access::Write) => {
+                let reg = self.new_v_reg();
+                let addr = self.new_v_reg();
+                store = Some(Insn::StoreStatic(reg, addr, name, 0));
+                reg
+            },
+            _ => operand,
+        });
+        insns.push(insn);
+        insns.extend(store);
+    }
+
+    pub(super) fn new_v_reg(&mut self) -> Operand {
+        Operand::VirtReg(self.cur_cx_mut().alloc_v_reg())
+    }
+}
+
+fn fits_imm12(imm: i64) -> bool {
+    (-2048..2048).contains(&imm)
+}
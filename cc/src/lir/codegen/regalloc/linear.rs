@@ -0,0 +1,346 @@
+//! Linear-scan register allocation, a fast alternative to graph coloring.
+//! Instructions are numbered in layout order, the `i`th one reads its operands at point `2 * i`
+//! and writes its results at point `2 * i + 1`. A register's lifetime is the set of points it's
+//! live at, as ranges with lifetime holes between them (e.g. a value that is dead in one branch).
+//! Lifetimes are visited by their start points and packed into the first physical register
+//! whose lifetimes don't overlap, holes included, which is how second-chance binpacking
+//! reuses registers. When nothing fits, either the new lifetime or the cheaper ones in its way
+//! are spilled, and every segment of a spilled register gets a second chance at a register
+//! once spill code is inserted and allocation runs again.
+//! No interference graph is built, so this scales to large functions, at the cost of code quality:
+//! moves are only removed when a hint from a `mv` happens to fit.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::{asm::Register, common::*};
+use super::{
+    LinearScan,
+    GeneralReg,
+    Function,
+    Insn,
+    IntermediateInsn,
+    Operand,
+    FuncContext,
+    AnalyzeResult,
+    Access,
+    map_operands,
+    spill_weights,
+    save_cost,
+    live_analysis::{self, Graph, InsnId, LiveAnalysis},
+};
+
+/// Inclusive ranges of points, sorted and disjoint.
+type Lifetime = Vec<(usize, usize)>;
+
+impl<'a> LinearScan<'a> {
+    pub fn new(func_cxs: &'a HashMap<StrDescriptor, FuncContext>) -> Self {
+        LinearScan {
+            func_cxs,
+        }
+    }
+
+    /// Allocates registers for `func`, virtual registers in `unspillable` are never chosen for spilling.
+    pub fn analyze(
+        self,
+        func: &Function,
+        unspillable: &HashSet<usize>,
+    ) -> AnalyzeResult {
+        let lifetimes = self.lifetimes(func);
+        let hints = move_hints(func);
+        let weights = spill_weights(func);
+        let spill_cost = |v_reg: usize| if unspillable.contains(&v_reg) {
+            usize::MAX
+        } else {
+            weights.get(&v_reg).copied().unwrap_or(0)
+        };
+
+        // physical registers used by the function itself are taken at those points
+        let mut occupied: HashMap<Register, BTreeMap<usize, (usize, GeneralReg)>> = HashMap::new();
+        for reg in Register::ALLOC_REGS {
+            let ranges = occupied.entry(*reg).or_default();
+            for &(start, end) in lifetimes.get(&GeneralReg::Phys(*reg)).into_iter().flatten() {
+                ranges.insert(start, (end, GeneralReg::Phys(*reg)));
+            }
+        }
+
+        let mut order = lifetimes.iter()
+            .filter_map(|(reg, lifetime)| match reg {
+                GeneralReg::Virt(v_reg) => Some((lifetime[0].0, *v_reg)),
+                GeneralReg::Phys(_) => None,
+            })
+            .collect::<Vec<_>>();
+        order.sort();
+
+        let mut assigned: HashMap<usize, Register> = HashMap::new();
+        // callee-saved registers already given out, they're saved in the prologue anyway
+        let mut saved: HashSet<Register> = HashSet::new();
+        for (_, v_reg) in order {
+            let lifetime = &lifetimes[&GeneralReg::Virt(v_reg)];
+
+            // registers a `mv` connects us to come first, so the move can be removed
+            let hinted = hints.get(&v_reg)
+                .into_iter()
+                .flatten()
+                .filter_map(|hint| match hint {
+                    GeneralReg::Phys(reg) => Some(*reg),
+                    GeneralReg::Virt(v_reg) => assigned.get(v_reg).copied(),
+                });
+            let mut regs = Register::ALLOC_REGS.iter()
+                .map(|reg| **reg)
+                .collect::<Vec<_>>();
+            regs.sort_by_key(|reg| save_cost(*reg, &saved));
+            let candidates = hinted
+                .chain(regs)
+                .filter(|reg| occupied.contains_key(reg));
+
+            let mut cheapest: Option<(usize, Register, Vec<usize>)> = None;
+            let mut color = None;
+            for reg in candidates {
+                let in_the_way = overlapping(&occupied[&reg], lifetime);
+                if in_the_way.is_empty() {
+                    color = Some(reg);
+                    break;
+                }
+                // only virtual registers can be evicted
+                let evicted = in_the_way.iter()
+                    .map(|owner| match owner {
+                        GeneralReg::Virt(v_reg) => Some(*v_reg),
+                        GeneralReg::Phys(_) => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(evicted) = evicted {
+                    let cost = evicted.iter().fold(0usize, |cost, &v_reg| cost.saturating_add(spill_cost(v_reg)));
+                    if cheapest.as_ref().is_none_or(|(min, ..)| cost < *min) {
+                        cheapest = Some((cost, reg, evicted));
+                    }
+                }
+            }
+
+            let color = match (color, cheapest) {
+                (Some(reg), _) => reg,
+                (None, Some((cost, reg, evicted))) if cost < spill_cost(v_reg) => {
+                    let ranges = occupied.get_mut(&reg).unwrap();
+                    for v_reg in evicted {
+                        assigned.remove(&v_reg);
+                        for (start, _) in lifetimes[&GeneralReg::Virt(v_reg)].iter() {
+                            ranges.remove(start);
+                        }
+                    }
+                    reg
+                },
+                // spilled
+                _ => continue,
+            };
+
+            if color.is_callee_saved() {
+                saved.insert(color);
+            }
+            assigned.insert(v_reg, color);
+            let ranges = occupied.get_mut(&color).unwrap();
+            for &(start, end) in lifetime.iter() {
+                ranges.insert(start, (end, GeneralReg::Virt(v_reg)));
+            }
+        }
+
+        let map = lifetimes.into_keys()
+            .map(|reg| match reg {
+                GeneralReg::Phys(phys) => (reg, Some(phys)),
+                GeneralReg::Virt(v_reg) => (reg, assigned.get(&v_reg).copied()),
+            })
+            .collect();
+
+        AnalyzeResult {
+            map,
+        }
+    }
+
+    fn lifetimes(&self, func: &Function) -> HashMap<GeneralReg, Lifetime> {
+        use live_analysis::Node as CfgNode;
+
+        let cfg = Graph::build(&func.body);
+        let live_result = LiveAnalysis::new(&cfg, self.func_cxs, func.name).analyze();
+
+        let mut points: HashMap<GeneralReg, BTreeSet<usize>> = HashMap::new();
+        let mut add = |reg: GeneralReg, point: usize| {
+            points.entry(reg).or_default().insert(point);
+        };
+
+        // basic blocks are numbered in layout order
+        let mut index = 0;
+        for node in cfg.nodes.values() {
+            let CfgNode::BasicBlock(block) = node else { continue };
+            for (inblock_id, &insn) in block.insns.iter().enumerate() {
+                // not a point of its own, it would fill the holes of lifetimes after jumps
+                if let Insn::Loc(..) = insn {
+                    continue;
+                }
+                let (read, write) = (2 * index, 2 * index + 1);
+
+                let live_regs = live_result.insn_infos.get(&InsnId::new(block.id, inblock_id))
+                    .expect("Internal error: Insn not found in live analysis");
+                for reg in live_regs.iter() {
+                    add(reg, write);
+                    add(reg, write + 1);
+                }
+
+                let (reads, writes) = self.accesses(insn);
+                for reg in reads {
+                    add(reg, read);
+                }
+                for reg in writes {
+                    add(reg, write);
+                }
+                // the address is built while the value is still needed
+                if let Insn::StoreStatic(_, Operand::VirtReg(addr), ..) = insn {
+                    add(GeneralReg::Virt(*addr), read);
+                }
+
+                index += 1;
+            }
+        }
+
+        points.into_iter()
+            .map(|(reg, points)| {
+                let mut lifetime: Lifetime = vec![];
+                for point in points {
+                    match lifetime.last_mut() {
+                        Some((_, end)) if *end + 1 == point => *end = point,
+                        _ => lifetime.push((point, point)),
+                    }
+                }
+                (reg, lifetime)
+            })
+            .collect()
+    }
+
+    /// Registers read and written by `insn`, calls included.
+    fn accesses(&self, insn: &Insn) -> (Vec<GeneralReg>, Vec<GeneralReg>) {
+        let mut reads = vec![];
+        let mut writes = vec![];
+
+        if let Insn::Call(target) | Insn::Intermediate(IntermediateInsn::TailCall(target)) = insn {
+            let func_cx = self.func_cxs.get(target)
+                .expect("Internal error: Function context not found");
+            reads.extend((0..func_cx.arg_regs()).map(|i| GeneralReg::Phys(Register::a(i))));
+        }
+        if let Insn::Call(..) = insn {
+            // caller-saved registers are clobbered
+            writes.extend(Register::iter()
+                .filter(|reg| reg.is_caller_saved())
+                .map(GeneralReg::Phys));
+        }
+
+        map_operands(*insn, |operand, access| {
+            if let Ok(reg) = GeneralReg::try_from(operand) {
+                match access {
+                    Access::Read => reads.push(reg),
+                    Access::Write => writes.push(reg),
+                }
+            }
+            operand
+        });
+
+        (reads, writes)
+    }
+}
+
+/// Owners of the ranges in `ranges` overlapping `lifetime`.
+fn overlapping(
+    ranges: &BTreeMap<usize, (usize, GeneralReg)>,
+    lifetime: &Lifetime,
+) -> Vec<GeneralReg> {
+    let mut owners = vec![];
+    for &(start, end) in lifetime.iter() {
+        // ranges are disjoint, the ones starting before `end` end in the same order
+        for (_, &(other_end, owner)) in ranges.range(..=end).rev() {
+            if other_end < start {
+                break;
+            }
+            if !owners.contains(&owner) {
+                owners.push(owner);
+            }
+        }
+    }
+    owners
+}
+
+/// The registers each virtual register is moved from or into.
+fn move_hints(func: &Function) -> HashMap<usize, Vec<GeneralReg>> {
+    let mut hints: HashMap<usize, Vec<GeneralReg>> = HashMap::new();
+    for insn in func.body.iter() {
+        let Insn::Mv(dst, src) = insn else { continue };
+        let (Ok(dst), Ok(src)) = (GeneralReg::try_from(*dst), GeneralReg::try_from(*src)) else { continue };
+        if let GeneralReg::Virt(v_reg) = dst {
+            hints.entry(v_reg).or_default().push(src);
+        }
+        if let GeneralReg::Virt(v_reg) = src {
+            hints.entry(v_reg).or_default().push(dst);
+        }
+    }
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::Register;
+    use crate::lir::{codegen::{regalloc::RegAllocator, RegAlloc}, LirCodeGen};
+
+    use super::*;
+    use super::super::TopLevel;
+
+    fn gen_lir(path: &str) -> (TopLevel, LirCodeGen<RegAlloc>) {
+        let input = std::fs::read_to_string(path).unwrap();
+        let lexer = crate::lex::Lexer::new(input);
+        let (tokens, strtb) = lexer.lex().unwrap();
+
+        let parser = crate::ast::AstParser::new(tokens, strtb);
+        let ast = parser.parse_prog().unwrap();
+
+        let parser = crate::sem::HirParser::new();
+        let hir = parser.parse(ast).unwrap();
+
+        let parser = crate::tac::TacCodeGen::new();
+        let (tac, _opt) = parser.parse(hir);
+
+        let codegen_parse = LirCodeGen::new();
+        codegen_parse.parse(tac)
+    }
+
+    fn func<'a>(lir: &'a TopLevel, name: &str) -> &'a Function {
+        lir.functions.values()
+            .find(|func| lir.strtb.get(func.name).unwrap() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_linear_scan() {
+        let (lir, codegen_regalloc) = gen_lir("../testprogs/func.c");
+        let (lir, _) = codegen_regalloc.alloc_with(lir, RegAllocator::Linear);
+
+        for func in lir.functions.values() {
+            for insn in func.body.iter() {
+                map_operands(*insn, |operand, _| {
+                    assert!(!matches!(operand, Operand::VirtReg(_)), "{:?} is not allocated", insn);
+                    operand
+                });
+            }
+        }
+
+        // `a + b`: the parameters and the result follow the hints of their moves
+        assert!(func(&lir, "another").body.iter()
+            .all(|insn| !matches!(insn, Insn::Mv(_, Operand::PhysReg(_) | Operand::VirtReg(_)))));
+    }
+
+    #[test]
+    fn test_linear_scan_spill() {
+        let (lir, codegen_regalloc) = gen_lir("../testprogs/spill.c");
+        let (lir, _) = codegen_regalloc.alloc_with(lir, RegAllocator::Linear);
+
+        let is_spill = |insn: &Insn| matches!(insn,
+            Insn::Ld(_, Operand::Mem { base: Register::S0, .. }) |
+            Insn::Sd(_, Operand::Mem { base: Register::S0, .. }));
+
+        assert!(func(&lir, "wide").body.iter().all(|insn| !is_spill(insn)));
+        assert!(func(&lir, "across").body.iter().any(is_spill));
+    }
+}
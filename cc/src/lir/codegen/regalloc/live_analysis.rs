@@ -201,8 +201,8 @@ impl<'a> Graph<'a> {
                     cur_block = vec![insn];
                     label_map.insert(*label, NodeId::BasicBlock(cur_block_id));
                 },
-                Insn::J(..)|Insn::Intermediate(IntermediateInsn::Epilogue)|
-                Insn::Beq(..)|Insn::Bne(..) => {
+                Insn::J(..)|Insn::Intermediate(IntermediateInsn::Epilogue | IntermediateInsn::TailCall(..))|
+                Insn::Beq(..)|Insn::Bne(..)|Insn::Blt(..)|Insn::Bge(..) => {
                     cur_block.push(insn);
                     nodes.insert(
                         NodeId::BasicBlock(cur_block_id), 
@@ -260,7 +260,7 @@ impl<'a> Graph<'a> {
                         NodeId::BasicBlock(*id + 1)
                     };
                     match last_insn {
-                        Insn::Intermediate(IntermediateInsn::Epilogue) =>
+                        Insn::Intermediate(IntermediateInsn::Epilogue | IntermediateInsn::TailCall(..)) =>
                             to_add.push((NodeId::BasicBlock(*id), NodeId::Exit)),
                         Insn::J(label) => {
                             let to_id = label_map.get(label)
@@ -268,7 +268,9 @@ impl<'a> Graph<'a> {
                             to_add.push((NodeId::BasicBlock(*id), *to_id));
                         },
                         Insn::Beq(.., label) |
-                        Insn::Bne(.., label) => {
+                        Insn::Bne(.., label) |
+                        Insn::Blt(.., label) |
+                        Insn::Bge(.., label) => {
                             let branch_id = label_map.get(label)
                                 .expect("Internal error: Branch label not found in label map");
                             to_add.push((NodeId::BasicBlock(*id), *branch_id));
@@ -329,6 +331,8 @@ impl<'a> Graph<'a> {
 pub struct LiveAnalysis<'a, 'b> {
     cfg: &'a Graph<'b>,
     func_cxs: &'a HashMap<StrDescriptor, FuncContext>,
+    // the function analyzed, its return value is live at the exit
+    name: StrDescriptor,
     block_infos: HashMap<usize, LiveReg>,
     insn_infos: HashMap<InsnId, LiveReg>,
 }
@@ -337,10 +341,12 @@ impl<'a, 'b> LiveAnalysis<'a, 'b> {
     pub fn new(
         cfg: &'a Graph<'b>,
         func_cxs: &'a HashMap<StrDescriptor, FuncContext>,
+        name: StrDescriptor,
     ) -> Self {
         LiveAnalysis {
             cfg,
             func_cxs,
+            name,
             block_infos: HashMap::new(),
             insn_infos: HashMap::new(),
         }
@@ -386,15 +392,19 @@ impl<'a, 'b> LiveAnalysis<'a, 'b> {
                 Insn::Subw(dst, src1, src2) |
                 Insn::Mul(dst, src1, src2) |
                 Insn::Mulw(dst, src1, src2) |
+                Insn::Mulh(dst, src1, src2) |
+                Insn::Mulhu(dst, src1, src2) |
                 Insn::Div(dst, src1, src2) |
                 Insn::Divw(dst, src1, src2) |
                 Insn::Rem(dst, src1, src2) |
                 Insn::Remw(dst, src1, src2) |
                 Insn::Slt(dst, src1, src2) |
-                Insn::Sgt(dst, src1, src2) => {
-                    (*dst).try_into().map(|reg| current.remove(reg));
-                    (*src1).try_into().map(|reg| current.add(reg));
-                    (*src2).try_into().map(|reg| current.add(reg));
+                Insn::Sgt(dst, src1, src2) |
+                Insn::Sltu(dst, src1, src2) |
+                Insn::Or(dst, src1, src2) => {
+                    if let Ok(reg) = GeneralReg::try_from(*dst) { current.remove(reg); }
+                    if let Ok(reg) = GeneralReg::try_from(*src1) { current.add(reg); }
+                    if let Ok(reg) = GeneralReg::try_from(*src2) { current.add(reg); }
                 },
                 Insn::Mv(dst, src) |
                 Insn::Neg(dst, src) |
@@ -402,39 +412,57 @@ impl<'a, 'b> LiveAnalysis<'a, 'b> {
                 Insn::Negw(dst, src) |
                 Insn::Sextw(dst, src) |
                 Insn::Seqz(dst, src) |
-                Insn::Snez(dst, src) => {
-                    (*dst).try_into().map(|reg| current.remove(reg));
-                    (*src).try_into().map(|reg| current.add(reg));
+                Insn::Snez(dst, src) |
+                Insn::Addi(dst, src, ..) |
+                Insn::Addiw(dst, src, ..) |
+                Insn::Slli(dst, src, ..) |
+                Insn::Slliw(dst, src, ..) |
+                Insn::Srli(dst, src, ..) |
+                Insn::Srliw(dst, src, ..) |
+                Insn::Srai(dst, src, ..) |
+                Insn::Sraiw(dst, src, ..) => {
+                    if let Ok(reg) = GeneralReg::try_from(*dst) { current.remove(reg); }
+                    if let Ok(reg) = GeneralReg::try_from(*src) { current.add(reg); }
                 },
                 Insn::Beq(src1, src2, ..) |
-                Insn::Bne(src1, src2, ..) => {
-                    (*src1).try_into().map(|reg| current.add(reg));
-                    (*src2).try_into().map(|reg| current.add(reg));
+                Insn::Bne(src1, src2, ..) |
+                Insn::Blt(src1, src2, ..) |
+                Insn::Bge(src1, src2, ..) => {
+                    if let Ok(reg) = GeneralReg::try_from(*src1) { current.add(reg); }
+                    if let Ok(reg) = GeneralReg::try_from(*src2) { current.add(reg); }
                 },
                 Insn::Ret |
-                Insn::Addi(..) |
-                Insn::Addiw(..) |
                 Insn::La(..) |
                 Insn::Li(..) => unreachable!(),
                 Insn::Ld(reg, mem) |
                 Insn::Lw(reg, mem) => {
-                    (*reg).try_into().map(|reg| current.remove(reg));
+                    if let Ok(reg) = GeneralReg::try_from(*reg) { current.remove(reg); }
                     assert!(matches!(mem, Operand::Mem {..}));
                 },
                 Insn::Sd(reg, mem) |
                 Insn::Sw(reg, mem) => {
-                    (*reg).try_into().map(|reg| current.add(reg));
+                    if let Ok(reg) = GeneralReg::try_from(*reg) { current.add(reg); }
                     assert!(matches!(mem, Operand::Mem {..}));
                 },
-                Insn::LoadStatic(reg, name) => {
-                    (*reg).try_into().map(|reg| current.remove(reg));
+                Insn::LoadStatic(reg, ..) => {
+                    if let Ok(reg) = GeneralReg::try_from(*reg) { current.remove(reg); }
                 },
-                Insn::StoreStatic(reg, name) => {
-                    (*reg).try_into().map(|reg| current.add(reg));
+                Insn::StoreStatic(reg, addr, ..) => {
+                    if let Ok(reg) = GeneralReg::try_from(*addr) { current.remove(reg); }
+                    if let Ok(reg) = GeneralReg::try_from(*reg) { current.add(reg); }
+                },
+                Insn::Intermediate(IntermediateInsn::TailCall(target)) => {
+                    let func_cx = self.func_cxs.get(target)
+                        .expect("Internal error: Function context not found");
+
+                    for i in 0..func_cx.arg_regs() {
+                        current.add(GeneralReg::Phys(Register::a(i)));
+                    }
                 },
                 Insn::Intermediate(..) |
                 Insn::J(..) |
-                Insn::Label(..) => {
+                Insn::Label(..) |
+                Insn::Loc(..) => {
                     ;
                 }
                 Insn::Call(target) => {
@@ -447,8 +475,7 @@ impl<'a, 'b> LiveAnalysis<'a, 'b> {
                     let func_cx = self.func_cxs.get(target)
                         .expect("Internal error: Function context not found");
 
-                    let arg_len = func_cx.type_.param_types.len().min(8);
-                    for i in 0..arg_len {
+                    for i in 0..func_cx.arg_regs() {
                         current.add(GeneralReg::Phys(Register::a(i)));
                     }
                 }
@@ -467,7 +494,14 @@ impl<'a, 'b> LiveAnalysis<'a, 'b> {
         for &succ_id in block.successors.iter() {
             match succ_id {
                 NodeId::Entry => panic!("Internal error: Entry node cannot be a successor"),
-                NodeId::Exit => initial.add(GeneralReg::Phys(Register::A0)), // return value register
+                NodeId::Exit => {
+                    // return value registers
+                    let func_cx = self.func_cxs.get(&self.name)
+                        .expect("Internal error: Function context not found");
+                    for &reg in func_cx.ret_regs() {
+                        initial.add(GeneralReg::Phys(reg));
+                    }
+                },
                 NodeId::BasicBlock(succ_id) => {
                     let succ_live = self.retrieve_block_liveregs(succ_id)
                         .expect("Internal error: Block live registers not found");
@@ -546,7 +580,8 @@ impl<'a, 'b> LiveAnalysis<'a, 'b> {
 pub fn analysis(
     cfg: &Graph,
     func_cxs: &HashMap<StrDescriptor, FuncContext>,
+    name: StrDescriptor,
 ) -> AnalyzeResult {
-    let live_analysis = LiveAnalysis::new(cfg, func_cxs);
+    let live_analysis = LiveAnalysis::new(cfg, func_cxs, name);
     live_analysis.analyze()
 }
\ No newline at end of file
@@ -9,18 +9,28 @@ use super::{
     TopLevel,
     Function,
     Insn,
+    IntermediateInsn,
     Operand,
     FuncContext,
 };
 
 mod rig;
+mod linear;
 mod alloc;
+mod legalize;
+mod strength;
+mod spill_code;
 mod live_analysis;
+mod dot;
+
+pub use dot::AllocDump;
 
 /// Register interference graph, per function.
 #[derive(Debug)]
 pub struct Rig<'a> {
     pub nodes: HashMap<GeneralReg, RigNode>,
+    /// `(dst, src)` of every register-to-register move, candidates for coalescing
+    pub moves: Vec<(GeneralReg, GeneralReg)>,
     pub func_cxs: &'a HashMap<StrDescriptor, FuncContext>,
 }
 
@@ -44,7 +54,7 @@ impl RigNode {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum GeneralReg {
     Phys(Register),
     Virt(usize),
@@ -62,7 +72,238 @@ impl TryFrom<Operand> for GeneralReg {
     }
 }
 
+/// Register allocation algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegAllocator {
+    /// graph coloring with move coalescing, slower but produces better code
+    #[default]
+    Graph,
+    /// linear scan over lifetimes with holes, fast on large functions
+    Linear,
+}
+
+/// Linear-scan allocator, per function.
+#[derive(Debug)]
+pub struct LinearScan<'a> {
+    pub func_cxs: &'a HashMap<StrDescriptor, FuncContext>,
+}
+
 #[derive(Debug)]
 pub struct AnalyzeResult {
     pub map: HashMap<GeneralReg, Option<Register>>,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Rewrites the register operands of `insn` with `f`.
+/// Operands read are visited before operands written,
+/// memory operands and immediates in the instruction itself are left alone.
+pub fn map_operands(
+    insn: Insn,
+    mut f: impl FnMut(Operand, Access) -> Operand,
+) -> Insn {
+    use Access::*;
+
+    match insn {
+        Insn::Add(dst, src1, src2) |
+        Insn::Addw(dst, src1, src2) |
+        Insn::Sub(dst, src1, src2) |
+        Insn::Subw(dst, src1, src2) |
+        Insn::Mul(dst, src1, src2) |
+        Insn::Mulw(dst, src1, src2) |
+        Insn::Mulh(dst, src1, src2) |
+        Insn::Mulhu(dst, src1, src2) |
+        Insn::Div(dst, src1, src2) |
+        Insn::Divw(dst, src1, src2) |
+        Insn::Rem(dst, src1, src2) |
+        Insn::Remw(dst, src1, src2) |
+        Insn::Slt(dst, src1, src2) |
+        Insn::Sgt(dst, src1, src2) |
+        Insn::Sltu(dst, src1, src2) |
+        Insn::Or(dst, src1, src2) => {
+            let src1 = f(src1, Read);
+            let src2 = f(src2, Read);
+            let dst = f(dst, Write);
+            match insn {
+                Insn::Add(..) => Insn::Add(dst, src1, src2),
+                Insn::Addw(..) => Insn::Addw(dst, src1, src2),
+                Insn::Sub(..) => Insn::Sub(dst, src1, src2),
+                Insn::Subw(..) => Insn::Subw(dst, src1, src2),
+                Insn::Mul(..) => Insn::Mul(dst, src1, src2),
+                Insn::Mulw(..) => Insn::Mulw(dst, src1, src2),
+                Insn::Mulh(..) => Insn::Mulh(dst, src1, src2),
+                Insn::Mulhu(..) => Insn::Mulhu(dst, src1, src2),
+                Insn::Div(..) => Insn::Div(dst, src1, src2),
+                Insn::Divw(..) => Insn::Divw(dst, src1, src2),
+                Insn::Rem(..) => Insn::Rem(dst, src1, src2),
+                Insn::Remw(..) => Insn::Remw(dst, src1, src2),
+                Insn::Slt(..) => Insn::Slt(dst, src1, src2),
+                Insn::Sgt(..) => Insn::Sgt(dst, src1, src2),
+                Insn::Sltu(..) => Insn::Sltu(dst, src1, src2),
+                Insn::Or(..) => Insn::Or(dst, src1, src2),
+                _ => unreachable!(),
+            }
+        },
+        Insn::Mv(dst, src) |
+        Insn::Neg(dst, src) |
+        Insn::Negw(dst, src) |
+        Insn::Sextw(dst, src) |
+        Insn::Seqz(dst, src) |
+        Insn::Snez(dst, src) |
+        Insn::Not(dst, src) => {
+            let src = f(src, Read);
+            let dst = f(dst, Write);
+            match insn {
+                Insn::Mv(..) => Insn::Mv(dst, src),
+                Insn::Neg(..) => Insn::Neg(dst, src),
+                Insn::Negw(..) => Insn::Negw(dst, src),
+                Insn::Sextw(..) => Insn::Sextw(dst, src),
+                Insn::Seqz(..) => Insn::Seqz(dst, src),
+                Insn::Snez(..) => Insn::Snez(dst, src),
+                Insn::Not(..) => Insn::Not(dst, src),
+                _ => unreachable!(),
+            }
+        },
+        Insn::Addi(dst, src, imm) => {
+            let src = f(src, Read);
+            Insn::Addi(f(dst, Write), src, imm)
+        },
+        Insn::Addiw(dst, src, imm) => {
+            let src = f(src, Read);
+            Insn::Addiw(f(dst, Write), src, imm)
+        },
+        Insn::Slli(dst, src, shamt) |
+        Insn::Slliw(dst, src, shamt) |
+        Insn::Srli(dst, src, shamt) |
+        Insn::Srliw(dst, src, shamt) |
+        Insn::Srai(dst, src, shamt) |
+        Insn::Sraiw(dst, src, shamt) => {
+            let src = f(src, Read);
+            let dst = f(dst, Write);
+            match insn {
+                Insn::Slli(..) => Insn::Slli(dst, src, shamt),
+                Insn::Slliw(..) => Insn::Slliw(dst, src, shamt),
+                Insn::Srli(..) => Insn::Srli(dst, src, shamt),
+                Insn::Srliw(..) => Insn::Srliw(dst, src, shamt),
+                Insn::Srai(..) => Insn::Srai(dst, src, shamt),
+                Insn::Sraiw(..) => Insn::Sraiw(dst, src, shamt),
+                _ => unreachable!(),
+            }
+        },
+        Insn::Beq(src1, src2, label) |
+        Insn::Bne(src1, src2, label) |
+        Insn::Blt(src1, src2, label) |
+        Insn::Bge(src1, src2, label) => {
+            let src1 = f(src1, Read);
+            let src2 = f(src2, Read);
+            match insn {
+                Insn::Beq(..) => Insn::Beq(src1, src2, label),
+                Insn::Bne(..) => Insn::Bne(src1, src2, label),
+                Insn::Blt(..) => Insn::Blt(src1, src2, label),
+                Insn::Bge(..) => Insn::Bge(src1, src2, label),
+                _ => unreachable!(),
+            }
+        },
+        Insn::Ld(dst, mem) => Insn::Ld(f(dst, Write), mem),
+        Insn::Lw(dst, mem) => Insn::Lw(f(dst, Write), mem),
+        Insn::Sd(src, mem) => Insn::Sd(f(src, Read), mem),
+        Insn::Sw(src, mem) => Insn::Sw(f(src, Read), mem),
+        Insn::Li(dst, imm) => Insn::Li(f(dst, Write), imm),
+        Insn::La(dst, name) => Insn::La(f(dst, Write), name),
+        Insn::LoadStatic(dst, name, offset) => Insn::LoadStatic(f(dst, Write), name, offset),
+        Insn::StoreStatic(src, addr, name, offset) => {
+            let src = f(src, Read);
+            Insn::StoreStatic(src, f(addr, Write), name, offset)
+        },
+        Insn::Call(..) |
+        Insn::Ret |
+        Insn::Label(..) |
+        Insn::J(..) |
+        Insn::Loc(..) |
+        Insn::Intermediate(..) => insn,
+    }
+}
+
+/// Virtual registers read and written by `insn`.
+pub fn virt_regs(insn: &Insn) -> (Vec<usize>, Vec<usize>) {
+    let mut reads = vec![];
+    let mut writes = vec![];
+    map_operands(*insn, |operand, access| {
+        if let Operand::VirtReg(v_reg) = operand {
+            match access {
+                Access::Read => reads.push(v_reg),
+                Access::Write => writes.push(v_reg),
+            }
+        }
+        operand
+    });
+    (reads, writes)
+}
+
+/// Accesses to each virtual register in `func`, every access weighs 10 to the power of its loop depth.
+pub fn spill_weights(func: &Function) -> HashMap<usize, usize> {
+    let mut weights = HashMap::new();
+    for (insn, depth) in func.body.iter().zip(loop_depths(&func.body)) {
+        let weight = 10usize.saturating_pow(depth as u32);
+        let (reads, writes) = virt_regs(insn);
+        for v_reg in reads.into_iter().chain(writes) {
+            let total = weights.entry(v_reg).or_insert(0usize);
+            *total = total.saturating_add(weight);
+        }
+    }
+    weights
+}
+
+/// Preference between free registers, lower is better:
+/// caller-saved registers cost nothing outside calls, which values live across can't use anyway,
+/// and a callee-saved register costs a save and a restore only the first time it's used.
+pub fn save_cost(reg: Register, saved: &HashSet<Register>) -> usize {
+    match reg {
+        _ if !reg.is_callee_saved() => 0,
+        _ if saved.contains(&reg) => 1,
+        _ => 2,
+    }
+}
+
+/// Loop nesting depth of each instruction.
+/// Loops are recognized by their back edges in layout order, a jump or branch to a label
+/// before it encloses everything in between, which holds for the structured loops we generate.
+pub fn loop_depths(insns: &[Insn]) -> Vec<usize> {
+    let labels = insns.iter()
+        .enumerate()
+        .filter_map(|(idx, insn)| match insn {
+            Insn::Label(label) => Some((*label, idx)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    // the furthest back edge to each header, `continue`s jumping back don't nest another loop
+    let mut loops: HashMap<usize, usize> = HashMap::new();
+    for (idx, insn) in insns.iter().enumerate() {
+        let (Insn::J(label) | Insn::Beq(.., label) | Insn::Bne(.., label) | Insn::Blt(.., label) | Insn::Bge(.., label)) = insn else { continue };
+        match labels.get(label) {
+            Some(&header) if header <= idx => {
+                let end = loops.entry(header).or_insert(idx);
+                *end = (*end).max(idx);
+            },
+            _ => {},
+        }
+    }
+
+    let mut deltas = vec![0isize; insns.len() + 1];
+    for (header, end) in loops {
+        deltas[header] += 1;
+        deltas[end + 1] -= 1;
+    }
+    deltas.iter()
+        .take(insns.len())
+        .scan(0isize, |depth, delta| {
+            *depth += delta;
+            Some(*depth as usize)
+        })
+        .collect()
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use crate::{asm::Register, common::*, lir::codegen::regalloc::live_analysis::{self, Graph, InsnId, LiveAnalysis, LiveReg}};
 use super::{
@@ -11,6 +11,8 @@ use super::{
     Operand,
     FuncContext,
     AnalyzeResult,
+    spill_weights,
+    save_cost,
 };
 
 // We'll split the procedure into several parts:
@@ -18,21 +20,15 @@ use super::{
 // 2. Add virtual registers into the graph.
 // 3. Liveness analysis, with a control flow graph.
 // 4. Add edges between virtual registers.
+// 5. Color the graph, coalescing moves where it is safe to do so.
 impl<'a> Rig<'a> {
+    /// Colors the registers of `func`, virtual registers in `unspillable` are never chosen for spilling.
     pub fn analyze(
         self,
-        func: &Function, 
+        func: &Function,
+        unspillable: &HashSet<usize>,
     ) -> AnalyzeResult {
-        let mut rig = self;
-        rig.add_virtreg(func);
-
-        let cfg = Graph::build(&func.body);
-        let live_analysis = LiveAnalysis::new(&cfg, rig.func_cxs);
-        let live_result = live_analysis.analyze();
-        rig.add_edges(&cfg, live_result.insn_infos);
-        rig.calc_spill_cost();
-
-        let rig = rig.color();
+        let rig = self.build(func, unspillable).color();
 
         let map = rig.nodes.into_iter()
             .map(|(reg, node)| 
@@ -45,15 +41,32 @@ impl<'a> Rig<'a> {
         }
     }
 
+    /// The interference graph of `func` with spill costs, nothing colored yet.
+    pub fn build(
+        self,
+        func: &Function,
+        unspillable: &HashSet<usize>,
+    ) -> Self {
+        let mut rig = self;
+        rig.add_virtreg(func);
+
+        let cfg = Graph::build(&func.body);
+        let live_analysis = LiveAnalysis::new(&cfg, rig.func_cxs, func.name);
+        let live_result = live_analysis.analyze();
+        rig.add_edges(&cfg, live_result.insn_infos);
+        rig.calc_spill_cost(func, unspillable);
+
+        rig
+    }
+
     pub fn base(func_cxs: &'a HashMap<StrDescriptor, FuncContext>) -> Self {
         // build a base interference graph
         // we won't use following registers:
         // - zero, ra, sp, gp, tp, s0/fp, cz these serve special purposes
-        // - t5, t6, cz we'll keep them as scratch registers when loading and storing virtual registers
         let mut nodes = HashMap::new();
 
         for i in 0..32 {
-            if matches!(i, 0..=4|8|30|31) {
+            if matches!(i, 0..=4|8) {
                 continue;
             }
             let reg = GeneralReg::Phys(Register::x(i));
@@ -74,6 +87,7 @@ impl<'a> Rig<'a> {
 
         Self {
             nodes,
+            moves: vec![],
             func_cxs,
         }
     }
@@ -83,7 +97,8 @@ impl<'a> Rig<'a> {
     }
 
     fn add_edge(&mut self, reg1: GeneralReg, reg2: GeneralReg) {
-        if reg1 == reg2 {
+        // registers we never allocate, say, zero and sp, are not in the graph
+        if reg1 == reg2 || !self.contains(reg1) || !self.contains(reg2) {
             return;
         }
         if let Some(node1) = self.nodes.get_mut(&reg1) {
@@ -118,26 +133,58 @@ impl<'a> Rig<'a> {
                             Insn::Subw(dst, ..) |
                             Insn::Mul(dst, ..) |
                             Insn::Mulw(dst, ..) |
+                            Insn::Mulh(dst, ..) |
+                            Insn::Mulhu(dst, ..) |
                             Insn::Div(dst, ..) |
                             Insn::Divw(dst, ..) |
                             Insn::Rem(dst, ..) |
                             Insn::Remw(dst, ..) |
                             Insn::Slt(dst, ..) |
                             Insn::Sgt(dst, ..) |
+                            Insn::Sltu(dst, ..) |
+                            Insn::Or(dst, ..) |
                             Insn::LoadStatic(dst, ..) |
                             Insn::Ld(dst, ..) |
                             Insn::Lw(dst, ..) |
+                            Insn::Addi(dst, ..) |
+                            Insn::Addiw(dst, ..) |
+                            Insn::Slli(dst, ..) |
+                            Insn::Slliw(dst, ..) |
+                            Insn::Srli(dst, ..) |
+                            Insn::Srliw(dst, ..) |
+                            Insn::Srai(dst, ..) |
+                            Insn::Sraiw(dst, ..) |
                             Insn::Neg(dst, ..) |
                             Insn::Negw(dst, ..) |
-                            Insn::Mv(dst, ..) |
                             Insn::Not(dst, ..) |
                             Insn::Sextw(dst, ..) |
                             Insn::Seqz(dst, ..) |
                             Insn::Snez(dst, ..) => {
+                                let Ok(dst) = GeneralReg::try_from(*dst) else { continue };
+                                for live_reg in live_regs.iter() {
+                                    self.add_edge(dst, live_reg);
+                                }
+                            },
+                            Insn::Mv(dst, src) => {
+                                // the source of a move doesn't interfere with its destination,
+                                // otherwise the two could never be coalesced.
+                                let Ok(dst) = GeneralReg::try_from(*dst) else { continue };
+                                let src = GeneralReg::try_from(*src).ok();
+                                for live_reg in live_regs.iter().filter(|&reg| Some(reg) != src) {
+                                    self.add_edge(dst, live_reg);
+                                }
+                                if let Some(src) = src.filter(|&src| self.contains(dst) && self.contains(src)) {
+                                    self.moves.push((dst, src));
+                                }
+                            },
+                            Insn::StoreStatic(src, addr, ..) => {
+                                // the address is built while the value is still needed
+                                let Ok(addr) = GeneralReg::try_from(*addr) else { continue };
                                 for live_reg in live_regs.iter() {
-                                    (*dst).try_into().map(|updated_reg| {
-                                        self.add_edge(updated_reg, live_reg);
-                                    });
+                                    self.add_edge(addr, live_reg);
+                                }
+                                if let Ok(src) = GeneralReg::try_from(*src) {
+                                    self.add_edge(addr, src);
                                 }
                             },
                             Insn::Call(target) => {
@@ -151,19 +198,19 @@ impl<'a> Rig<'a> {
                                     }
                                 }
                             },
-                            Insn::Addi(..) |
-                            Insn::Addiw(..) |
                             Insn::La(..) |
                             Insn::Li(..) |
                             Insn::Ret => unreachable!(),
                             Insn::Beq(..) |
                             Insn::Bne(..) |
+                            Insn::Blt(..) |
+                            Insn::Bge(..) |
                             Insn::J(..) |
                             Insn::Label(..) |
+                            Insn::Loc(..) |
                             Insn::Intermediate(..) |
                             Insn::Sd(..) |
-                            Insn::Sw(..) |
-                            Insn::StoreStatic(..) => {
+                            Insn::Sw(..) => {
                                 ;
                             },
                         }
@@ -173,16 +220,21 @@ impl<'a> Rig<'a> {
         }
     }
 
-    fn calc_spill_cost(&mut self) {
+    fn calc_spill_cost(&mut self, func: &Function, unspillable: &HashSet<usize>) {
         // we use a simple heuristic here:
-        // the spill cost is the number of neighbors in the interference graph.
-        // for hard registers, we set the spill cost to an infinite value.
+        // the spill cost is the number of times a register is read or written,
+        // since each of them becomes a memory access once it's spilled,
+        // with accesses inside loops weighing 10 times more per nesting level.
+        // for hard registers and the short-lived registers created by spilling,
+        // we set the spill cost to an infinite value.
+        let weights = spill_weights(func);
+
         for node in self.nodes.values_mut() {
-            if let GeneralReg::Phys(_) = node.reg {
-                node.spill_cost = usize::MAX;
-            } else {
-                node.spill_cost = node.neighbors.len();
-            }
+            node.spill_cost = match node.reg {
+                GeneralReg::Phys(_) => usize::MAX,
+                GeneralReg::Virt(v_reg) if unspillable.contains(&v_reg) => usize::MAX,
+                GeneralReg::Virt(v_reg) => weights.get(&v_reg).copied().unwrap_or(0),
+            };
         }
     }
 }
@@ -198,18 +250,24 @@ impl<'a> Rig<'a> {
                 Insn::Subw(dst, src1, src2) |
                 Insn::Mul(dst, src1, src2) |
                 Insn::Mulw(dst, src1, src2) |
+                Insn::Mulh(dst, src1, src2) |
+                Insn::Mulhu(dst, src1, src2) |
                 Insn::Div(dst, src1, src2) |
                 Insn::Divw(dst, src1, src2) |
                 Insn::Rem(dst, src1, src2) |
                 Insn::Remw(dst, src1, src2) |
                 Insn::Slt(dst, src1, src2) |
-                Insn::Sgt(dst, src1, src2) => {
+                Insn::Sgt(dst, src1, src2) |
+                Insn::Sltu(dst, src1, src2) |
+                Insn::Or(dst, src1, src2) => {
                     self.proc_operand(dst);
                     self.proc_operand(src1);
                     self.proc_operand(src2);
                 },
                 Insn::Beq(src1, src2, ..) |
-                Insn::Bne(src1, src2, ..) => {
+                Insn::Bne(src1, src2, ..) |
+                Insn::Blt(src1, src2, ..) |
+                Insn::Bge(src1, src2, ..) => {
                     self.proc_operand(src1);
                     self.proc_operand(src2);
                 },
@@ -219,7 +277,15 @@ impl<'a> Rig<'a> {
                 Insn::Negw(dst, src) |
                 Insn::Sextw(dst, src) |
                 Insn::Seqz(dst, src) |
-                Insn::Snez(dst, src) => {
+                Insn::Snez(dst, src) |
+                Insn::Addi(dst, src, ..) |
+                Insn::Addiw(dst, src, ..) |
+                Insn::Slli(dst, src, ..) |
+                Insn::Slliw(dst, src, ..) |
+                Insn::Srli(dst, src, ..) |
+                Insn::Srliw(dst, src, ..) |
+                Insn::Srai(dst, src, ..) |
+                Insn::Sraiw(dst, src, ..) => {
                     self.proc_operand(dst);
                     self.proc_operand(src);
                 },
@@ -227,13 +293,17 @@ impl<'a> Rig<'a> {
                 Insn::Intermediate(..) |
                 Insn::Ret |
                 Insn::J(..) |
-                Insn::Label(..) => {
+                Insn::Label(..) |
+                Insn::Loc(..) => {
                     ;
                 },
-                Insn::La(reg, name) |
-                Insn::LoadStatic(reg, name) |
-                Insn::StoreStatic(reg, name) => {
+                Insn::La(reg, ..) |
+                Insn::LoadStatic(reg, ..) => {
+                    self.proc_operand(reg);
+                },
+                Insn::StoreStatic(reg, addr, ..) => {
                     self.proc_operand(reg);
+                    self.proc_operand(addr);
                 },
                 Insn::Ld(reg, mem) |
                 Insn::Lw(reg, mem) |
@@ -242,9 +312,7 @@ impl<'a> Rig<'a> {
                     self.proc_operand(reg);
                     assert!(matches!(mem, Operand::Mem {..}));
                 },
-                Insn::Li(..) |
-                Insn::Addi(..) |
-                Insn::Addiw(..) => unreachable!(),
+                Insn::Li(..) => unreachable!(),
             }
         }
     }
@@ -266,104 +334,377 @@ impl<'a> Rig<'a> {
     }
 }
 
+// 5. Color the graph with iterated register coalescing (George & Appel).
+//
+// Nodes of low degree are simplified (pushed onto the select stack) first, but
+// only once they are no longer move-related. In between, moves are coalesced
+// if that can't make the graph uncolorable: with the Briggs test when both
+// ends are virtual, with the George test when one end is a physical register.
+// When neither simplify nor coalesce can proceed, the moves of a low-degree
+// node are frozen (given up), and as a last resort a node is optimistically
+// pushed as a potential spill. Coalesced nodes share the color of their alias.
 impl<'a> Rig<'a> {
-    fn color(mut self) -> Self {
-        let mut nodes: Vec<_> = self.nodes
-            .into_iter()
-            .map(|(_, node)| node)
-            .collect();
-        
-        let colored = Self::color_inner(nodes);
+    fn color(self) -> Self {
+        let nodes = Coloring::new(self.nodes, &self.moves).run();
 
-        let nodes = colored.into_iter()
-            .map(|node| {
-                let reg = node.reg;
-                (reg, node)
+        Self {
+            nodes,
+            moves: self.moves,
+            func_cxs: self.func_cxs,
+        }
+    }
+}
+
+const K: usize = Register::ALLOC_REGS.len();
+
+#[derive(Debug)]
+struct Coloring<'m> {
+    nodes: HashMap<GeneralReg, RigNode>,
+    moves: &'m [(GeneralReg, GeneralReg)],
+    degree: HashMap<GeneralReg, usize>,
+    // indices into `moves` of the moves each node takes part in
+    move_list: HashMap<GeneralReg, BTreeSet<usize>>,
+    alias: HashMap<GeneralReg, GeneralReg>,
+
+    // low-degree nodes that aren't move-related
+    simplify_worklist: BTreeSet<GeneralReg>,
+    // low-degree move-related nodes
+    freeze_worklist: BTreeSet<GeneralReg>,
+    // high-degree nodes
+    spill_worklist: BTreeSet<GeneralReg>,
+    coalesced: BTreeSet<GeneralReg>,
+    select_stack: Vec<GeneralReg>,
+    on_stack: HashSet<GeneralReg>,
+
+    // moves that may be coalesced
+    worklist_moves: BTreeSet<usize>,
+    // moves not yet ready for coalescing
+    active_moves: BTreeSet<usize>,
+}
+
+impl<'m> Coloring<'m> {
+    fn new(
+        nodes: HashMap<GeneralReg, RigNode>,
+        moves: &'m [(GeneralReg, GeneralReg)],
+    ) -> Self {
+        // physical registers are precolored, their degree is considered infinite
+        let degree = nodes.values()
+            .map(|node| match node.reg {
+                GeneralReg::Phys(_) => (node.reg, usize::MAX),
+                GeneralReg::Virt(_) => (node.reg, node.neighbors.len()),
             })
             .collect();
 
-        Self {
+        let mut coloring = Coloring {
             nodes,
-            func_cxs: self.func_cxs,
+            moves,
+            degree,
+            move_list: HashMap::new(),
+            alias: HashMap::new(),
+            simplify_worklist: BTreeSet::new(),
+            freeze_worklist: BTreeSet::new(),
+            spill_worklist: BTreeSet::new(),
+            coalesced: BTreeSet::new(),
+            select_stack: vec![],
+            on_stack: HashSet::new(),
+            worklist_moves: BTreeSet::new(),
+            active_moves: BTreeSet::new(),
+        };
+
+        for (idx, &(dst, src)) in moves.iter().enumerate() {
+            if coalescing_candidate(dst, src) {
+                coloring.move_list.entry(dst).or_default().insert(idx);
+                coloring.move_list.entry(src).or_default().insert(idx);
+                coloring.worklist_moves.insert(idx);
+            }
+        }
+
+        let mut virt_regs = coloring.nodes.keys()
+            .filter(|reg| matches!(reg, GeneralReg::Virt(_)))
+            .cloned()
+            .collect::<Vec<_>>();
+        virt_regs.sort();
+        for reg in virt_regs {
+            if coloring.degree[&reg] >= K {
+                coloring.spill_worklist.insert(reg);
+            } else if coloring.move_related(reg) {
+                coloring.freeze_worklist.insert(reg);
+            } else {
+                coloring.simplify_worklist.insert(reg);
+            }
         }
+
+        coloring
     }
 
-    fn color_inner(nodes: Vec<RigNode>) -> Vec<RigNode> {
-        if nodes.len() == 0 {
-            return nodes;
+    fn run(mut self) -> HashMap<GeneralReg, RigNode> {
+        loop {
+            if let Some(reg) = self.simplify_worklist.pop_first() {
+                self.simplify(reg);
+            } else if let Some(idx) = self.worklist_moves.pop_first() {
+                self.coalesce(idx);
+            } else if let Some(reg) = self.freeze_worklist.pop_first() {
+                self.freeze(reg);
+            } else if !self.spill_worklist.is_empty() {
+                self.select_spill();
+            } else {
+                break;
+            }
         }
-        
-        let (mut to_prune, unpruned) = Self::prune(nodes);
 
-        let mut unpruned = Self::color_inner(unpruned);
-    
-        // color the pruned node
-        if let Some(node) = &mut to_prune {
-            let colors = Register::ALLOC_REGS.iter()
-                .filter(|r| !node.neighbors.contains(&GeneralReg::Phys(***r)))
+        self.assign_colors();
+        self.nodes
+    }
+
+    fn is_phys(reg: GeneralReg) -> bool {
+        matches!(reg, GeneralReg::Phys(_))
+    }
+
+    /// Neighbors still in the graph.
+    fn adjacent(&self, reg: GeneralReg) -> Vec<GeneralReg> {
+        let mut adjacent = self.nodes[&reg].neighbors.iter()
+            .filter(|reg| !self.on_stack.contains(reg) && !self.coalesced.contains(reg))
+            .cloned()
+            .collect::<Vec<_>>();
+        adjacent.sort();
+        adjacent
+    }
+
+    fn interferes(&self, reg1: GeneralReg, reg2: GeneralReg) -> bool {
+        self.nodes[&reg1].neighbors.contains(&reg2)
+    }
+
+    /// Moves of `reg` that are still candidates for coalescing.
+    fn node_moves(&self, reg: GeneralReg) -> Vec<usize> {
+        self.move_list.get(&reg)
+            .map(|moves| moves.iter()
+                .filter(|idx| self.active_moves.contains(idx) || self.worklist_moves.contains(idx))
                 .cloned()
-                .collect::<Vec<_>>();
+                .collect())
+            .unwrap_or_default()
+    }
+
+    fn move_related(&self, reg: GeneralReg) -> bool {
+        !self.node_moves(reg).is_empty()
+    }
+
+    fn get_alias(&self, reg: GeneralReg) -> GeneralReg {
+        let mut reg = reg;
+        while let Some(&alias) = self.alias.get(&reg) {
+            reg = alias;
+        }
+        reg
+    }
+
+    fn simplify(&mut self, reg: GeneralReg) {
+        self.select_stack.push(reg);
+        self.on_stack.insert(reg);
+        for neighbor in self.adjacent(reg) {
+            self.decrement_degree(neighbor);
+        }
+    }
 
-            if let Some(color) = colors.first() {
-                node.color = Some(**color);
+    fn decrement_degree(&mut self, reg: GeneralReg) {
+        if Self::is_phys(reg) {
+            return;
+        }
+        let degree = self.degree[&reg];
+        self.degree.insert(reg, degree - 1);
+
+        // it just became a low-degree node
+        if degree == K {
+            let mut regs = self.adjacent(reg);
+            regs.push(reg);
+            self.enable_moves(&regs);
+            self.spill_worklist.remove(&reg);
+            if self.move_related(reg) {
+                self.freeze_worklist.insert(reg);
+            } else {
+                self.simplify_worklist.insert(reg);
             }
-        } else { unreachable!() }
+        }
+    }
+
+    /// Moves of `regs` that were blocked may be coalescable now.
+    fn enable_moves(&mut self, regs: &[GeneralReg]) {
+        for &reg in regs {
+            for idx in self.node_moves(reg) {
+                if self.active_moves.remove(&idx) {
+                    self.worklist_moves.insert(idx);
+                }
+            }
+        }
+    }
 
-        if let Some(node) = to_prune {
-            unpruned.push(node);
+    fn coalesce(&mut self, idx: usize) {
+        let (dst, src) = self.moves[idx];
+        let (dst, src) = (self.get_alias(dst), self.get_alias(src));
+        // if there's a physical register, it's always `u`
+        let (u, v) = if Self::is_phys(src) { (src, dst) } else { (dst, src) };
+
+        if u == v {
+            self.add_worklist(u);
+        } else if Self::is_phys(v) || self.interferes(u, v) {
+            // constrained, never coalescable
+            self.add_worklist(u);
+            self.add_worklist(v);
+        } else if self.can_coalesce(u, v) {
+            self.combine(u, v);
+            self.add_worklist(u);
+        } else {
+            self.active_moves.insert(idx);
         }
+    }
 
-        unpruned
+    fn can_coalesce(&self, u: GeneralReg, v: GeneralReg) -> bool {
+        if Self::is_phys(u) {
+            // George: every neighbor of `v` is either harmless or already a neighbor of `u`
+            self.adjacent(v).into_iter()
+                .all(|t| Self::is_phys(t) || self.degree[&t] < K || self.interferes(t, u))
+        } else {
+            // Briggs: the merged node has fewer than K neighbors of significant degree
+            let mut adjacent = self.adjacent(u);
+            adjacent.extend(self.adjacent(v));
+            adjacent.sort();
+            adjacent.dedup();
+            adjacent.into_iter()
+                .filter(|reg| self.degree[reg] >= K)
+                .count() < K
+        }
     }
-    
-    fn prune(
-        nodes: Vec<RigNode>,
-    ) -> (
-        Option<RigNode>, // pruned
-        Vec<RigNode>, // unpruned
-    ) {
-        if nodes.len() == 0 {
-            return (None, vec![]);
+
+    fn add_worklist(&mut self, reg: GeneralReg) {
+        if !Self::is_phys(reg) && !self.move_related(reg) && self.degree[&reg] < K {
+            self.freeze_worklist.remove(&reg);
+            self.simplify_worklist.insert(reg);
         }
+    }
 
-        let mut to_prune = None;
-        let mut unpruned = vec![];
+    /// Merges `v` into `u`.
+    fn combine(&mut self, u: GeneralReg, v: GeneralReg) {
+        if !self.freeze_worklist.remove(&v) {
+            self.spill_worklist.remove(&v);
+        }
+        self.coalesced.insert(v);
+        self.alias.insert(v, u);
 
-        for node in nodes {
-            if let None = to_prune {
-                if node.neighbors.len() < Register::ALLOC_REGS.len() {
-                    to_prune = Some(node);
-                } else {
-                    unpruned.push(node);
-                }
+        let v_moves = self.move_list.get(&v).cloned().unwrap_or_default();
+        self.move_list.entry(u).or_default().extend(v_moves);
+        self.enable_moves(&[v]);
+
+        for t in self.adjacent(v) {
+            self.add_edge_degree(t, u);
+            self.decrement_degree(t);
+        }
+
+        if self.degree[&u] >= K && self.freeze_worklist.remove(&u) {
+            self.spill_worklist.insert(u);
+        }
+    }
+
+    fn add_edge_degree(&mut self, reg1: GeneralReg, reg2: GeneralReg) {
+        if reg1 == reg2 || self.interferes(reg1, reg2) {
+            return;
+        }
+        for (from, to) in [(reg1, reg2), (reg2, reg1)] {
+            self.nodes.get_mut(&from).unwrap().neighbors.insert(to);
+            if !Self::is_phys(from) {
+                *self.degree.get_mut(&from).unwrap() += 1;
+            }
+        }
+    }
+
+    fn freeze(&mut self, reg: GeneralReg) {
+        self.simplify_worklist.insert(reg);
+        self.freeze_moves(reg);
+    }
+
+    /// Gives up coalescing the moves of `reg`.
+    fn freeze_moves(&mut self, reg: GeneralReg) {
+        for idx in self.node_moves(reg) {
+            let (dst, src) = self.moves[idx];
+            let other = if self.get_alias(src) == self.get_alias(reg) {
+                self.get_alias(dst)
             } else {
-                unpruned.push(node);
+                self.get_alias(src)
+            };
+            self.active_moves.remove(&idx);
+            self.worklist_moves.remove(&idx);
+
+            if !Self::is_phys(other)
+                && !self.move_related(other)
+                && self.degree[&other] < K
+                && self.freeze_worklist.remove(&other) {
+                self.simplify_worklist.insert(other);
             }
         }
+    }
 
-        if let None = to_prune {
-            // spill
-            let mut metric = usize::MAX;
-            let mut to_prune_idx = 0;
-            for (idx, node) in unpruned.iter().enumerate() {
-                if node.spill_cost < metric {
-                    metric = node.spill_cost;
-                    to_prune_idx = idx;
-                }
+    fn select_spill(&mut self) {
+        // the node with the lowest spill cost per neighbor, which may still
+        // get a color if its neighbors end up sharing colors.
+        let reg = self.spill_worklist.iter()
+            .min_by(|a, b| {
+                let cost = |reg: &GeneralReg| self.nodes[reg].spill_cost as u128;
+                let degree = |reg: &GeneralReg| self.degree[reg].max(1) as u128;
+                (cost(a) * degree(b)).cmp(&(cost(b) * degree(a)))
+            })
+            .cloned()
+            .unwrap();
+        self.spill_worklist.remove(&reg);
+        self.simplify_worklist.insert(reg);
+        self.freeze_moves(reg);
+    }
+
+    fn color_of(&self, reg: GeneralReg) -> Option<Register> {
+        match self.get_alias(reg) {
+            GeneralReg::Phys(reg) => Some(reg),
+            alias => self.nodes[&alias].color,
+        }
+    }
+
+    fn assign_colors(&mut self) {
+        // callee-saved registers already given out, they're saved in the prologue anyway
+        let mut saved = HashSet::new();
+        while let Some(reg) = self.select_stack.pop() {
+            let used = self.nodes[&reg].neighbors.iter()
+                .filter_map(|&neighbor| self.color_of(neighbor))
+                .collect::<HashSet<_>>();
+
+            // uncolored virtual registers are spilled later
+            let color = Register::ALLOC_REGS.iter()
+                .map(|reg| **reg)
+                .filter(|reg| !used.contains(reg))
+                .min_by_key(|reg| save_cost(*reg, &saved));
+            if let Some(color) = color.filter(Register::is_callee_saved) {
+                saved.insert(color);
             }
-            to_prune = Some(unpruned.remove(to_prune_idx));
+            self.nodes.get_mut(&reg).unwrap().color = color;
+        }
+
+        for reg in self.coalesced.clone() {
+            let color = self.color_of(reg);
+            self.nodes.get_mut(&reg).unwrap().color = color;
         }
 
-        (to_prune, unpruned)
+        for node in self.nodes.values_mut() {
+            if let GeneralReg::Phys(reg) = node.reg {
+                node.color = Some(reg);
+            }
+        }
     }
+}
 
+/// A move between two physical registers can't be coalesced.
+fn coalescing_candidate(dst: GeneralReg, src: GeneralReg) -> bool {
+    dst != src && !(Coloring::is_phys(dst) && Coloring::is_phys(src))
 }
 
 #[cfg(test)]
 mod tests {
     use std::hash::Hash;
 
-    use crate::lir::{codegen::{regalloc::live_analysis::Graph, RegAlloc}, LirCodeGen};
+    use crate::lir::{codegen::{regalloc::{live_analysis::Graph, RegAllocator}, RegAlloc}, LirCodeGen};
 
     use super::*;
 
@@ -412,4 +753,136 @@ mod tests {
         let (lir, codegen_regalloc) = gen_lir(path);
         let rig = Rig::base(&codegen_regalloc.func_cxs);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_coloring() {
+        let path = "../testprogs/func.c";
+        let (lir, codegen_regalloc) = gen_lir(path);
+        for func in lir.functions.values() {
+            let mut rig = Rig::base(&codegen_regalloc.func_cxs);
+            rig.add_virtreg(func);
+            let cfg = Graph::build(&func.body);
+            let live_result = LiveAnalysis::new(&cfg, rig.func_cxs, func.name).analyze();
+            rig.add_edges(&cfg, live_result.insn_infos);
+            rig.calc_spill_cost(func, &HashSet::new());
+            let interference = rig.nodes.clone();
+
+            let rig = rig.color();
+            for (reg, node) in interference.iter() {
+                let color = rig.nodes[reg].color;
+                for neighbor in node.neighbors.iter() {
+                    assert!(color.is_none() || color != rig.nodes[neighbor].color,
+                        "{:?} and {:?} interfere but share {:?}", reg, neighbor, color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let path = "../testprogs/func.c";
+        let (lir, codegen_regalloc) = gen_lir(path);
+        let (lir, _) = codegen_regalloc.alloc(lir);
+
+        // `a + b`: both parameters and the result are coalesced with a0/a1
+        let another = lir.functions.values()
+            .find(|func| lir.strtb.get(func.name).unwrap() == "another")
+            .unwrap();
+        assert!(another.body.iter()
+            .all(|insn| !matches!(insn, Insn::Mv(_, Operand::PhysReg(_) | Operand::VirtReg(_)))));
+
+        // no moves of a register to itself are left anywhere
+        for func in lir.functions.values() {
+            assert!(func.body.iter().all(|insn| !matches!(insn, Insn::Mv(dst, src) if dst == src)));
+        }
+    }
+
+    #[test]
+    fn test_spill() {
+        let path = "../testprogs/spill.c";
+        let (lir, codegen_regalloc) = gen_lir(path);
+        let (lir, _) = codegen_regalloc.alloc(lir);
+
+        let func = |name: &str| lir.functions.values()
+            .find(|func| lir.strtb.get(func.name).unwrap() == name)
+            .unwrap();
+        let is_spill = |insn: &Insn| matches!(insn,
+            Insn::Ld(_, Operand::Mem { base: Register::S0, .. }) |
+            Insn::Sd(_, Operand::Mem { base: Register::S0, .. }));
+
+        let operands = |insn: &Insn| {
+            let mut operands = vec![];
+            crate::lir::codegen::regalloc::map_operands(*insn, |operand, _| {
+                operands.push(operand);
+                operand
+            });
+            operands
+        };
+
+        // every virtual register gets a physical one, statics and immediates included
+        for func in lir.functions.values() {
+            for insn in func.body.iter() {
+                assert!(operands(insn).iter().all(|operand| !matches!(operand, Operand::VirtReg(_) | Operand::Static(_))),
+                    "{:?} is not allocated", insn);
+            }
+        }
+
+        // 20 values live at once fit in registers, t5 included, the scratch never handed out
+        let wide = func("wide");
+        assert!(wide.body.iter().all(|insn| !is_spill(insn)));
+        assert!(wide.body.iter()
+            .flat_map(operands)
+            .any(|operand| matches!(operand, Operand::PhysReg(Register::T5))));
+        assert!(wide.body.iter()
+            .flat_map(operands)
+            .all(|operand| operand != Operand::PhysReg(Register::SCRATCH)));
+
+        // 30 values live across calls don't fit in the callee-saved registers
+        let across = func("across");
+        assert!(across.body.iter().any(is_spill));
+    }
+
+    #[test]
+    fn test_loop_depths() {
+        let path = "../testprogs/nested_loop.c";
+        let (lir, _) = gen_lir(path);
+        let main = lir.functions.values().next().unwrap();
+        let depths = crate::lir::codegen::regalloc::loop_depths(&main.body);
+
+        assert_eq!(depths.first(), Some(&0));
+        assert_eq!(depths.last(), Some(&0));
+        assert_eq!(depths.iter().max(), Some(&2));
+    }
+
+    #[test]
+    fn test_callee_saved() {
+        for allocator in [RegAllocator::Graph, RegAllocator::Linear] {
+            let path = "../testprogs/spill.c";
+            let (lir, codegen_regalloc) = gen_lir(path);
+            let (lir, _) = codegen_regalloc.alloc_with(lir, allocator);
+
+            for func in lir.functions.values() {
+                let mut used = HashSet::new();
+                for insn in func.body.iter() {
+                    crate::lir::codegen::regalloc::map_operands(*insn, |operand, _| {
+                        if let Operand::PhysReg(reg) = operand {
+                            used.insert(reg);
+                        }
+                        operand
+                    });
+                }
+
+                // exactly the callee-saved registers in the body are saved, besides s0 and ra
+                let saved = func.callee_saved.iter()
+                    .flatten()
+                    .map(|(reg, _)| *reg)
+                    .filter(|reg| !matches!(reg, Register::S0 | Register::Ra))
+                    .collect::<HashSet<_>>();
+                let expected = used.into_iter()
+                    .filter(|reg| reg.is_callee_saved() && !matches!(reg, Register::S0 | Register::Sp))
+                    .collect::<HashSet<_>>();
+                assert_eq!(saved, expected);
+            }
+        }
+    }
+}
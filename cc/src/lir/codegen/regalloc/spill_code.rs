@@ -0,0 +1,189 @@
+//! Spill code insertion.
+//! A virtual register that couldn't be colored is kept in a frame slot instead,
+//! and its live range is split into segments that end at calls and block boundaries:
+//! e.g.    add v0, v1, v2          (v0 spilled)
+//!         call f
+//!         add v3, v0, v0
+//! ->      add v4, v1, v2
+//! ->      sd  v4, -24(s0)
+//!         call f
+//! ->      ld  v5, -24(s0)
+//! (`sw` and `lw` on RV32, where the slots are 4 bytes)
+//! ->      add v3, v5, v5
+//! Every segment gets its own short-lived virtual register, so none of them lives across a call.
+//! A segment register that is spilled again is reloaded and stored around every single access,
+//! and those registers are never spilled. Registers that only ever hold one constant
+//! are recomputed with `mv v, imm` (`li` after canonicalization) instead of being reloaded.
+
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    CodeGen,
+    RegAlloc,
+    Insn,
+    Operand,
+    Access,
+    map_operands,
+    virt_regs,
+};
+
+#[derive(Debug, Default)]
+pub(super) struct SpillState {
+    /// registers created for a segment of a spilled live range
+    split: HashSet<usize>,
+    /// registers created for a single access, they're never spilled
+    pub unspillable: HashSet<usize>,
+    /// the originally spilled register, whose frame slot a created register shares
+    origin: HashMap<usize, usize>,
+    /// the constant a register is rematerialized from
+    remat: HashMap<usize, i64>,
+}
+
+impl CodeGen<RegAlloc> {
+    pub(super) fn insert_spill_code(
+        &mut self,
+        insns: Vec<Insn>,
+        spilled: &HashSet<usize>,
+        state: &mut SpillState,
+    ) -> Vec<Insn> {
+        for &v_reg in spilled.iter() {
+            if let Some(constant) = constant_of(v_reg, &insns) {
+                state.remat.entry(v_reg).or_insert(constant);
+            }
+        }
+
+        // the register holding each spilled register in the current segment
+        let mut current: HashMap<usize, Operand> = HashMap::new();
+        let mut s_insns = vec![];
+
+        for insn in insns {
+            if matches!(insn, Insn::Label(..) | Insn::Call(..) | Insn::Intermediate(..)) {
+                current.clear();
+            }
+
+            match insn {
+                // recomputed before every segment instead
+                Insn::Mv(Operand::VirtReg(v_reg), Operand::Imm(_))
+                    if spilled.contains(&v_reg) && state.remat.contains_key(&v_reg) => {
+                    current.remove(&v_reg);
+                    continue;
+                },
+                // reloading from or storing to its own slot, the slot is already up to date
+                Insn::Ld(Operand::VirtReg(v_reg), mem) |
+                Insn::Lw(Operand::VirtReg(v_reg), mem) |
+                Insn::Sd(Operand::VirtReg(v_reg), mem) |
+                Insn::Sw(Operand::VirtReg(v_reg), mem)
+                    if spilled.contains(&v_reg) && Some(mem) == self.slot_of(v_reg, state) => {
+                    current.remove(&v_reg);
+                    continue;
+                },
+                _ => {},
+            }
+
+            let mut before = vec![];
+            let mut after = vec![];
+            let s_insn = map_operands(insn, |operand, access| {
+                let Operand::VirtReg(v_reg) = operand else { return operand };
+                if !spilled.contains(&v_reg) {
+                    return operand;
+                }
+                if let (Some(&reg), Access::Read) = (current.get(&v_reg), access) {
+                    return reg;
+                }
+
+                let reg = self.split_v_reg(v_reg, state);
+                match access {
+                    Access::Read => before.push(match state.remat.get(&v_reg) {
+                        Some(&constant) => Insn::Mv(reg, Operand::Imm(constant)),
+                        None => Insn::load(reg, self.spill_slot(v_reg, state)),
+                    }),
+                    Access::Write => {
+                        assert!(!state.remat.contains_key(&v_reg),
+                            "Internal error: rematerialized v_reg {} is written", v_reg);
+                        after.push(Insn::store(reg, self.spill_slot(v_reg, state)));
+                    },
+                }
+                if !state.split.contains(&v_reg) {
+                    current.insert(v_reg, reg);
+                }
+                reg
+            });
+
+            s_insns.extend(before);
+            s_insns.push(s_insn);
+            s_insns.extend(after);
+
+            if matches!(s_insn, Insn::J(..) | Insn::Beq(..) | Insn::Bne(..) | Insn::Blt(..) | Insn::Bge(..)) {
+                current.clear();
+            }
+        }
+
+        s_insns
+    }
+
+    /// Creates a register standing for `v_reg` in one segment, or one access if `v_reg` is a segment itself.
+    fn split_v_reg(
+        &mut self,
+        v_reg: usize,
+        state: &mut SpillState,
+    ) -> Operand {
+        let reg = self.cur_cx_mut().alloc_v_reg();
+        if state.split.contains(&v_reg) {
+            state.unspillable.insert(reg);
+        } else {
+            state.split.insert(reg);
+        }
+        let origin = state.origin.get(&v_reg).copied().unwrap_or(v_reg);
+        state.origin.insert(reg, origin);
+        if let Some(&constant) = state.remat.get(&v_reg) {
+            state.remat.insert(reg, constant);
+        }
+        Operand::VirtReg(reg)
+    }
+
+    fn slot_of(
+        &self,
+        v_reg: usize,
+        state: &SpillState,
+    ) -> Option<Operand> {
+        let origin = state.origin.get(&v_reg).copied().unwrap_or(v_reg);
+        let cx = self.cur_cx();
+        cx.mmap
+            .get(&origin)
+            .map(|&offset| Operand::frame(offset, cx.xlen.bytes()))
+    }
+
+    fn spill_slot(
+        &mut self,
+        v_reg: usize,
+        state: &SpillState,
+    ) -> Operand {
+        if let Some(slot) = self.slot_of(v_reg, state) {
+            return slot;
+        }
+        let origin = state.origin.get(&v_reg).copied().unwrap_or(v_reg);
+        let cx = self.cur_cx_mut();
+        let width = cx.xlen.bytes();
+        let offset = -((cx.frame_size + width) as isize);
+        cx.frame_size += width;
+        cx.map_vreg2frame(origin, offset);
+        Operand::frame(offset, width)
+    }
+}
+
+/// The constant `v_reg` holds, if all its definitions move the same immediate into it.
+fn constant_of(v_reg: usize, insns: &[Insn]) -> Option<i64> {
+    let mut constant = None;
+    for insn in insns {
+        let (_, writes) = virt_regs(insn);
+        if !writes.contains(&v_reg) {
+            continue;
+        }
+        match insn {
+            Insn::Mv(_, Operand::Imm(val)) if constant.is_none_or(|c| c == *val) =>
+                constant = Some(*val),
+            _ => return None,
+        }
+    }
+    constant
+}
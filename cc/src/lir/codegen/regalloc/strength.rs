@@ -0,0 +1,292 @@
+//! Strength reduction of multiplications, divisions and remainders by constants, before legalization.
+//! - multiplications by `±2^k`, `2^a + 2^b` and `2^a - 2^b` become shifts and adds
+//!   e.g. `mulw v0, v1, 10` -> `slli v2, v1, 3; slli v3, v1, 1; addw v0, v2, v3`
+//! - divisions by `±2^k` shift right, after adding `2^k - 1` to negative dividends so they round towards zero
+//! - divisions by other constants multiply by a magic number and keep the upper half
+//!   (Granlund and Montgomery, as in Hacker's Delight, chapter 10)
+//!   e.g. `divw v0, v1, 7` -> `sext.w v2, v1; mv v3, 2454267027; mul v2, v2, v3; srai v2, v2, 34; srli v3, v2, 63; addw v0, v2, v3`
+//! - remainders are what's left after subtracting the quotient times the divisor
+//!
+//! 32-bit operations only look at the lower 32 bits of their sources, like `mulw` and `divw` do,
+//! and leave a sign-extended result. On RV32 the full-width operations are the 32-bit ones.
+//! Only the last instruction writes the destination.
+
+use super::{
+    CodeGen,
+    RegAlloc,
+    Insn,
+    Operand,
+};
+
+impl CodeGen<RegAlloc> {
+    /// The cheaper instruction sequence computing `insn`, if it takes a constant to multiply or divide by.
+    pub(super) fn reduce_strength(&mut self, insn: Insn) -> Option<Vec<Insn>> {
+        use Insn::*;
+
+        let (dst, src, imm) = match insn {
+            Mul(dst, src, Operand::Imm(imm)) |
+            Mul(dst, Operand::Imm(imm), src) |
+            Mulw(dst, src, Operand::Imm(imm)) |
+            Mulw(dst, Operand::Imm(imm), src) |
+            Div(dst, src, Operand::Imm(imm)) |
+            Divw(dst, src, Operand::Imm(imm)) |
+            Rem(dst, src, Operand::Imm(imm)) |
+            Remw(dst, src, Operand::Imm(imm)) if !matches!(src, Operand::Imm(_)) => (dst, src, imm),
+            _ => return None,
+        };
+        let word = matches!(insn, Mulw(..) | Divw(..) | Remw(..));
+        let bits = if word { 32 } else { self.cur_cx().xlen.bits() };
+        let imm = if bits == 32 { imm as i32 as i64 } else { imm };
+
+        // statics are read once
+        let mut insns = vec![];
+        let src = match src {
+            Operand::Static(_) => {
+                let reg = self.new_v_reg();
+                insns.push(Mv(reg, src));
+                reg
+            },
+            _ => src,
+        };
+
+        let reduced = match insn {
+            Mul(..) | Mulw(..) => self.mul_by_const(dst, src, imm, word, bits)?,
+            Div(..) | Divw(..) => self.div_by_const(dst, src, imm, word, bits)?,
+            Rem(..) | Remw(..) => {
+                // the sign of the remainder follows the dividend only
+                let divisor = imm.unsigned_abs();
+                if divisor <= 1 || (bits == 32 && divisor > 1 << 31) {
+                    return None;
+                }
+                let quotient = self.new_v_reg();
+                let product = self.new_v_reg();
+                let mut reduced = self.div_by_const(quotient, src, divisor as i64, word, bits)?;
+                reduced.extend(self.mul_by_const(product, quotient, divisor as i64, word, bits)
+                    .unwrap_or_else(|| {
+                        let divisor_reg = self.new_v_reg();
+                        vec![
+                            Mv(divisor_reg, Operand::Imm(divisor as i64)),
+                            if word { Mulw(product, quotient, divisor_reg) } else { Mul(product, quotient, divisor_reg) },
+                        ]
+                    }));
+                reduced.push(if word { Subw(dst, src, product) } else { Sub(dst, src, product) });
+                reduced
+            },
+            _ => unreachable!(),
+        };
+        insns.extend(reduced);
+        Some(insns)
+    }
+
+    fn mul_by_const(
+        &mut self,
+        dst: Operand,
+        src: Operand,
+        imm: i64,
+        word: bool,
+        bits: u32,
+    ) -> Option<Vec<Insn>> {
+        use Insn::*;
+
+        let mask = u64::MAX >> (64 - bits);
+        let value = imm as u64 & mask;
+        let negated = imm.wrapping_neg() as u64 & mask;
+
+        let add = |dst, src1, src2| if word { Addw(dst, src1, src2) } else { Add(dst, src1, src2) };
+        let sub = |dst, src1, src2| if word { Subw(dst, src1, src2) } else { Sub(dst, src1, src2) };
+        let neg = |dst, src| if word { Negw(dst, src) } else { Neg(dst, src) };
+        let slli = |dst, src, shamt| if word { Slliw(dst, src, shamt) } else { Slli(dst, src, shamt) };
+
+        let insns = if value == 0 {
+            vec![Mv(dst, Operand::Imm(0))]
+        } else if value.is_power_of_two() {
+            vec![slli(dst, src, value.trailing_zeros())]
+        } else if negated.is_power_of_two() {
+            let shifted = self.new_v_reg();
+            vec![
+                Slli(shifted, src, negated.trailing_zeros()),
+                neg(dst, shifted),
+            ]
+        } else if value.count_ones() == 2 {
+            // 2^a + 2^b
+            let low = value.trailing_zeros();
+            let high = 63 - value.leading_zeros();
+            let (mut insns, high_reg) = self.shifted(src, high);
+            let (low_insns, low_reg) = self.shifted(src, low);
+            insns.extend(low_insns);
+            insns.push(add(dst, high_reg, low_reg));
+            insns
+        } else if ((value >> value.trailing_zeros()) + 1).is_power_of_two() {
+            // 2^a - 2^b
+            let low = value.trailing_zeros();
+            let high = low + (value >> low).trailing_ones();
+            if high >= bits {
+                return None;
+            }
+            let (mut insns, high_reg) = self.shifted(src, high);
+            let (low_insns, low_reg) = self.shifted(src, low);
+            insns.extend(low_insns);
+            insns.push(sub(dst, high_reg, low_reg));
+            insns
+        } else {
+            return None;
+        };
+        Some(insns)
+    }
+
+    fn div_by_const(
+        &mut self,
+        dst: Operand,
+        src: Operand,
+        imm: i64,
+        word: bool,
+        bits: u32,
+    ) -> Option<Vec<Insn>> {
+        use Insn::*;
+
+        let divisor = imm.unsigned_abs();
+        if divisor == 0 || (bits == 32 && divisor > 1 << 31) {
+            return None;
+        }
+
+        let quotient = if imm < 0 { self.new_v_reg() } else { dst };
+        let mut insns = if divisor == 1 {
+            vec![if word { Sextw(quotient, src) } else { Mv(quotient, src) }]
+        } else if divisor.is_power_of_two() {
+            // (src + (src < 0 ? 2^k - 1 : 0)) >> k
+            let k = divisor.trailing_zeros();
+            let sign = self.new_v_reg();
+            let bias = self.new_v_reg();
+            let biased = self.new_v_reg();
+            if word {
+                vec![
+                    Sraiw(sign, src, 31),
+                    Srliw(bias, sign, 32 - k),
+                    Addw(biased, src, bias),
+                    Sraiw(quotient, biased, k),
+                ]
+            } else {
+                vec![
+                    Srai(sign, src, bits - 1),
+                    Srli(bias, sign, bits - k),
+                    Add(biased, src, bias),
+                    Srai(quotient, biased, k),
+                ]
+            }
+        } else {
+            let (magic, shift) = magic(divisor, bits);
+            let multiplier = self.new_v_reg();
+            let high = self.new_v_reg();
+            let truncated = self.new_v_reg();
+            let sign = self.new_v_reg();
+            if word {
+                // the whole product of a 32-bit dividend and an unsigned 32-bit magic number fits in 64 bits
+                let extended = self.new_v_reg();
+                vec![
+                    Sextw(extended, src),
+                    Mv(multiplier, Operand::Imm(magic as i64)),
+                    Mul(high, extended, multiplier),
+                    Srai(truncated, high, 32 + shift),
+                    // rounds negative quotients up, towards zero
+                    Srli(sign, truncated, 63),
+                    Addw(quotient, truncated, sign),
+                ]
+            } else {
+                // sign-extended from `bits`, as the register holds it
+                let signed_magic = ((magic << (64 - bits)) as i64) >> (64 - bits);
+                let mut insns = vec![
+                    Mv(multiplier, Operand::Imm(signed_magic)),
+                    Mulh(high, src, multiplier),
+                ];
+                let high = if signed_magic < 0 {
+                    // the magic number is above 2^(bits - 1), what's been multiplied by is 2^bits less
+                    let corrected = self.new_v_reg();
+                    insns.push(Add(corrected, high, src));
+                    corrected
+                } else {
+                    high
+                };
+                insns.extend([
+                    Srai(truncated, high, shift),
+                    Srli(sign, truncated, bits - 1),
+                    Add(quotient, truncated, sign),
+                ]);
+                insns
+            }
+        };
+        if imm < 0 {
+            insns.push(if word { Negw(dst, quotient) } else { Neg(dst, quotient) });
+        }
+        Some(insns)
+    }
+
+    /// `src << shamt`, in a new register unless it's `src` itself.
+    fn shifted(&mut self, src: Operand, shamt: u32) -> (Vec<Insn>, Operand) {
+        if shamt == 0 {
+            return (vec![], src);
+        }
+        let reg = self.new_v_reg();
+        (vec![Insn::Slli(reg, src, shamt)], reg)
+    }
+}
+
+/// The magic number `m` and shift `s` with `n / d == (n * m) >> (bits + s)` for every `bits`-bit `n >= 0`,
+/// negative quotients are one less and have to be rounded up. `d` is at least 3 and no power of two.
+fn magic(divisor: u64, bits: u32) -> (u64, u32) {
+    let divisor = divisor as u128;
+    let two_n1 = 1u128 << (bits - 1);
+    let anc = two_n1 - 1 - two_n1 % divisor;
+    let mut p = bits - 1;
+    let (mut q1, mut r1) = (two_n1 / anc, two_n1 % anc);
+    let (mut q2, mut r2) = (two_n1 / divisor, two_n1 % divisor);
+    loop {
+        p += 1;
+        q1 *= 2;
+        r1 *= 2;
+        if r1 >= anc {
+            q1 += 1;
+            r1 -= anc;
+        }
+        q2 *= 2;
+        r2 *= 2;
+        if r2 >= divisor {
+            q2 += 1;
+            r2 -= divisor;
+        }
+        let delta = divisor - r2;
+        if q1 > delta || (q1 == delta && r1 != 0) {
+            break;
+        }
+    }
+    ((q2 + 1) as u64, p - bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic() {
+        // Hacker's Delight, table 10-1
+        assert_eq!(magic(3, 32), (0x55555556, 0));
+        assert_eq!(magic(7, 32), (0x92492493, 2));
+        assert_eq!(magic(7, 64), (0x4924924924924925, 1));
+
+        for divisor in [3u64, 5, 6, 7, 10, 12, 25, 641, 1000, 65537, 2147483647] {
+            let (m, s) = magic(divisor, 32);
+            for n in [0i64, 1, 2, 99, 1000, 65535, -1, -7, -100, -65536, i32::MAX as i64, i32::MIN as i64] {
+                let q = (n * m as i64) >> (32 + s);
+                let q = q + (q < 0) as i64;
+                assert_eq!(q, n / divisor as i64, "{n} / {divisor}");
+            }
+        }
+        for divisor in [3u64, 7, 10, 1000, 1 << 40 | 1, i64::MAX as u64] {
+            let (m, s) = magic(divisor, 64);
+            for n in [0i64, 1, 99, -1, -100, i64::MAX, i64::MIN, i64::MIN + 1] {
+                let q = ((n as i128 * m as i128) >> (64 + s)) as i64;
+                let q = q + (q < 0) as i64;
+                assert_eq!(q, n / divisor as i64, "{n} / {divisor}");
+            }
+        }
+    }
+}
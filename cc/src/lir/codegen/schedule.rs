@@ -0,0 +1,344 @@
+//! List scheduling of basic blocks, after register allocation.
+//! Instructions are reordered within the straight-line code between labels, branches and calls,
+//! so that results aren't consumed right after long-latency loads, multiplications and divisions:
+//! e.g.    ld  a0, -24(s0)             (3 cycles on the U74)
+//!         addw a1, a0, a2
+//!         li  a3, 1
+//! ->      ld  a0, -24(s0)
+//! ->      li  a3, 1
+//! ->      addw a1, a0, a2
+//! Register dependences (read-after-write, write-after-read and write-after-write) and
+//! memory dependences between stores and other accesses to the same location are kept,
+//! among the ready instructions the one on the longest path to the end of the block goes first.
+
+use std::collections::HashMap;
+
+use crate::{asm::Register, common::*};
+use super::{
+    CodeGen,
+    Canonic,
+    Insn,
+    Operand,
+    regalloc::{map_operands, Access},
+};
+
+/// Latencies and issue width of the pipeline we schedule for, in cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineModel {
+    /// instructions issued per cycle
+    pub issue_width: usize,
+    pub alu: usize,
+    pub load: usize,
+    pub mul: usize,
+    pub div: usize,
+}
+
+impl MachineModel {
+    /// Every instruction takes a cycle, which keeps the original order.
+    pub fn generic() -> Self {
+        MachineModel {
+            issue_width: 1,
+            alu: 1,
+            load: 1,
+            mul: 1,
+            div: 1,
+        }
+    }
+
+    /// SiFive U74, dual-issue in-order, divisions take a varying number of cycles.
+    pub fn sifive_u74() -> Self {
+        MachineModel {
+            issue_width: 2,
+            alu: 1,
+            load: 3,
+            mul: 3,
+            div: 20,
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "generic" => Some(Self::generic()),
+            "sifive-u74" => Some(Self::sifive_u74()),
+            _ => None,
+        }
+    }
+
+    fn latency(&self, insn: &Insn) -> usize {
+        match insn {
+            Insn::Ld(..) | Insn::Lw(..) => self.load,
+            // `lui` first
+            Insn::LoadStatic(..) => self.load + self.alu,
+            Insn::Mul(..) | Insn::Mulw(..) | Insn::Mulh(..) | Insn::Mulhu(..) => self.mul,
+            Insn::Div(..) | Insn::Divw(..) | Insn::Rem(..) | Insn::Remw(..) => self.div,
+            _ => self.alu,
+        }
+    }
+}
+
+impl Default for MachineModel {
+    fn default() -> Self {
+        Self::sifive_u74()
+    }
+}
+
+/// A memory location, frame slots are told apart by their offsets from the same base register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Location {
+    Slot { base: Register, offset: isize, size: usize },
+    Static(StrDescriptor),
+}
+
+impl Location {
+    fn may_alias(self, other: Location) -> bool {
+        match (self, other) {
+            (Location::Static(a), Location::Static(b)) => a == b,
+            (Location::Slot { base: b1, offset: o1, size: s1 }, Location::Slot { base: b2, offset: o2, size: s2 }) =>
+                b1 != b2 || (o1 < o2 + s2 as isize && o2 < o1 + s1 as isize),
+            // there are no pointers, statics are never on the stack
+            _ => false,
+        }
+    }
+}
+
+struct Node {
+    insn: Insn,
+    /// (successor, latency)
+    succs: Vec<(usize, usize)>,
+    preds_left: usize,
+    earliest: usize,
+    height: usize,
+}
+
+impl CodeGen<Canonic> {
+    pub(super) fn schedule(
+        &mut self,
+        insns: Vec<Insn>,
+        model: &MachineModel,
+    ) -> Vec<Insn> {
+        let mut s_insns = vec![];
+        let mut block = vec![];
+        for insn in insns {
+            if is_barrier(&insn) {
+                s_insns.extend(schedule_block(std::mem::take(&mut block), model));
+                s_insns.push(insn);
+            } else {
+                block.push(insn);
+            }
+        }
+        s_insns.extend(schedule_block(block, model));
+        s_insns
+    }
+}
+
+/// Control flow and calls stay where they are, the code between them is scheduled.
+fn is_barrier(insn: &Insn) -> bool {
+    matches!(insn,
+        Insn::Label(..) | Insn::J(..) | Insn::Beq(..) | Insn::Bne(..) | Insn::Blt(..) | Insn::Bge(..) |
+        Insn::Call(..) | Insn::Ret | Insn::Intermediate(..))
+}
+
+/// `Loc`s aren't scheduled, each one stays right before the instruction it was in front of.
+fn schedule_block(block: Vec<Insn>, model: &MachineModel) -> Vec<Insn> {
+    let mut insns = vec![];
+    let mut locs = vec![];
+    let mut pending = vec![];
+    for insn in block {
+        match insn {
+            Insn::Loc(..) => pending.push(insn),
+            _ => {
+                locs.push(std::mem::take(&mut pending));
+                insns.push(insn);
+            },
+        }
+    }
+
+    let mut scheduled = vec![];
+    for idx in schedule_order(&insns, model) {
+        scheduled.append(&mut locs[idx]);
+        scheduled.push(insns[idx]);
+    }
+    scheduled.extend(pending);
+    scheduled
+}
+
+/// The order to issue `insns` in, by their indices.
+fn schedule_order(insns: &[Insn], model: &MachineModel) -> Vec<usize> {
+    if insns.len() < 2 {
+        return (0..insns.len()).collect();
+    }
+
+    let mut nodes = insns.iter()
+        .map(|&insn| Node {
+            insn,
+            succs: vec![],
+            preds_left: 0,
+            earliest: 0,
+            height: 0,
+        })
+        .collect::<Vec<_>>();
+
+    // dependences on earlier instructions
+    let mut last_write: HashMap<Register, usize> = HashMap::new();
+    let mut reads_since: HashMap<Register, Vec<usize>> = HashMap::new();
+    let mut mem_accesses: Vec<(usize, Location, bool)> = vec![];
+    for (idx, insn) in insns.iter().enumerate() {
+        let mut deps = vec![];
+        let (reads, writes) = registers(insn);
+        for reg in reads.iter() {
+            if let Some(&writer) = last_write.get(reg) {
+                deps.push((writer, model.latency(&insns[writer])));
+            }
+        }
+        for reg in writes.iter() {
+            if let Some(&writer) = last_write.get(reg) {
+                deps.push((writer, 1));
+            }
+            for &reader in reads_since.get(reg).into_iter().flatten() {
+                deps.push((reader, 0));
+            }
+        }
+        if let Some((location, store)) = memory(insn) {
+            for &(other, other_location, other_store) in mem_accesses.iter() {
+                if (store || other_store) && location.may_alias(other_location) {
+                    deps.push((other, usize::from(other_store)));
+                }
+            }
+            mem_accesses.push((idx, location, store));
+        }
+
+        for reg in reads {
+            reads_since.entry(reg).or_default().push(idx);
+        }
+        for reg in writes {
+            last_write.insert(reg, idx);
+            reads_since.remove(&reg);
+        }
+
+        for (pred, latency) in deps {
+            if pred != idx {
+                nodes[pred].succs.push((idx, latency));
+                nodes[idx].preds_left += 1;
+            }
+        }
+    }
+
+    // length of the longest path to the end of the block
+    for idx in (0..nodes.len()).rev() {
+        let own = model.latency(&nodes[idx].insn);
+        nodes[idx].height = nodes[idx].succs.iter()
+            .map(|&(succ, latency)| latency + nodes[succ].height)
+            .fold(own, usize::max);
+    }
+
+    let mut scheduled = vec![];
+    let mut ready = (0..nodes.len())
+        .filter(|&idx| nodes[idx].preds_left == 0)
+        .collect::<Vec<_>>();
+    let mut cycle = 0;
+    while !ready.is_empty() {
+        for _ in 0..model.issue_width {
+            // the highest one issuable this cycle, the earlier one on ties
+            let best = ready.iter()
+                .enumerate()
+                .filter(|&(_, &idx)| nodes[idx].earliest <= cycle)
+                .max_by_key(|&(_, &idx)| (nodes[idx].height, std::cmp::Reverse(idx)))
+                .map(|(pos, _)| pos);
+            let Some(pos) = best else { break };
+
+            let idx = ready.swap_remove(pos);
+            scheduled.push(idx);
+            for (succ, latency) in nodes[idx].succs.clone() {
+                let node = &mut nodes[succ];
+                node.earliest = node.earliest.max(cycle + latency);
+                node.preds_left -= 1;
+                if node.preds_left == 0 {
+                    ready.push(succ);
+                }
+            }
+        }
+        cycle += 1;
+    }
+
+    assert_eq!(scheduled.len(), insns.len(), "Internal error: dependence cycle in a basic block");
+    scheduled
+}
+
+/// Physical registers read and written by `insn`, base registers of memory operands included.
+fn registers(insn: &Insn) -> (Vec<Register>, Vec<Register>) {
+    let mut reads = vec![];
+    let mut writes = vec![];
+    map_operands(*insn, |operand, access| {
+        match (operand, access) {
+            (Operand::PhysReg(Register::Zero), _) => {},
+            (Operand::PhysReg(reg), Access::Read) => reads.push(reg),
+            (Operand::PhysReg(reg), Access::Write) => writes.push(reg),
+            _ => {},
+        }
+        operand
+    });
+    if let Insn::Ld(_, Operand::Mem { base, .. }) |
+        Insn::Lw(_, Operand::Mem { base, .. }) |
+        Insn::Sd(_, Operand::Mem { base, .. }) |
+        Insn::Sw(_, Operand::Mem { base, .. }) = insn {
+        reads.push(*base);
+    }
+    (reads, writes)
+}
+
+/// The location `insn` accesses, and whether it's written.
+fn memory(insn: &Insn) -> Option<(Location, bool)> {
+    match *insn {
+        Insn::Ld(_, Operand::Mem { base, offset, size }) |
+        Insn::Lw(_, Operand::Mem { base, offset, size }) =>
+            Some((Location::Slot { base, offset, size }, false)),
+        Insn::Sd(_, Operand::Mem { base, offset, size }) |
+        Insn::Sw(_, Operand::Mem { base, offset, size }) =>
+            Some((Location::Slot { base, offset, size }, true)),
+        Insn::LoadStatic(_, name, _) => Some((Location::Static(name), false)),
+        Insn::StoreStatic(_, _, name, _) => Some((Location::Static(name), true)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Register::*;
+
+    fn reg(reg: Register) -> Operand {
+        Operand::PhysReg(reg)
+    }
+
+    #[test]
+    fn test_schedule_block() {
+        let model = MachineModel::sifive_u74();
+
+        // independent instructions fill the load delay
+        let block = vec![
+            Insn::Ld(reg(A0), Operand::frame(-24, 8)),
+            Insn::Addw(reg(A1), reg(A0), reg(A2)),
+            Insn::Li(reg(A3), 1),
+            Insn::Li(reg(A4), 2),
+        ];
+        let scheduled = schedule_block(block.clone(), &model);
+        assert_eq!(scheduled[0], block[0]);
+        assert_eq!(scheduled[3], block[1]);
+
+        // a store to the slot stays before the load, the write of a4 after its read
+        let block = vec![
+            Insn::Sd(reg(A0), Operand::frame(-24, 8)),
+            Insn::Ld(reg(A1), Operand::frame(-24, 8)),
+            Insn::Mul(reg(A2), reg(A4), reg(A4)),
+            Insn::Li(reg(A4), 3),
+        ];
+        let scheduled = schedule_block(block.clone(), &model);
+        let pos = |insn: &Insn| scheduled.iter().position(|s| s == insn).unwrap();
+        assert!(pos(&block[0]) < pos(&block[1]));
+        assert!(pos(&block[2]) < pos(&block[3]));
+
+        // the generic model keeps the order
+        assert_eq!(schedule_block(block.clone(), &MachineModel::generic()), block);
+    }
+}
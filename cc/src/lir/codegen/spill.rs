@@ -1,6 +1,6 @@
 use std::{collections::HashMap, marker::PhantomData};
 
-use crate::common::*;
+use crate::{asm::Register, common::*};
 use super::{
     TopLevel,
     Function,
@@ -16,19 +16,27 @@ use super::{
     Canonic,
     Spill,
 };
+use super::regalloc::map_operands;
 
 impl CodeGen<Spill> {
-    pub fn spill(mut self, lir: TopLevel) -> (TopLevel, CodeGen<Canonic>) {
+    pub fn spill(self, lir: TopLevel) -> (TopLevel, CodeGen<Canonic>) {
+        self.spill_with(lir, false)
+    }
+
+    /// Lays out the frames, with `omit_frame_pointer` the frame is addressed off `sp` and `s0` isn't set up.
+    pub fn spill_with(mut self, lir: TopLevel, omit_frame_pointer: bool) -> (TopLevel, CodeGen<Canonic>) {
         let mut s_funcs = HashMap::new();
         for (name, func) in lir.functions {
-            let func = self.spill_func(func);
+            let func = self.spill_func(func, omit_frame_pointer);
             s_funcs.insert(name, func);
         }
         (TopLevel {
             functions: s_funcs,
             data_seg: lir.data_seg,
             bss_seg: lir.bss_seg,
+            externs: lir.externs,
             strtb: lir.strtb,
+            xlen: lir.xlen,
         }, CodeGen {
             func_cxs: self.func_cxs,
             cur_func: self.cur_func,
@@ -41,211 +49,115 @@ impl CodeGen<Spill> {
     fn spill_func(
         &mut self,
         func: Function,
+        omit_frame_pointer: bool,
     ) -> Function {
+        // spill code has been inserted during register allocation,
+        // what's left is laying out the frame.
         let mut func = func;
         self.cur_func = Some(func.name);
 
-        let s_insns = func.body.into_iter()
-            .map(|insn| self.spill_insn(insn))
-            .collect();
+        assert!(func.body.iter().all(|insn| !has_virt_reg(insn)),
+            "Internal error: virtual registers left after register allocation");
+
+        // leaf functions never clobber ra
+        let leaf = func.body.iter().all(|insn| !matches!(insn, Insn::Call(..)));
+        let mut callee_saved = func.callee_saved
+            .take()
+            .expect("Internal error: callee-saved registers not collected");
+        callee_saved.retain(|&(reg, _)| match reg {
+            Register::Ra => !leaf,
+            Register::S0 => !omit_frame_pointer,
+            _ => true,
+        });
+
+        // pack the slots still in use right below s0, a register wide each
+        let width = self.cur_cx().xlen.bytes() as isize;
+        let mut offsets = callee_saved.iter()
+            .map(|&(_, offset)| offset)
+            .chain(func.body.iter().filter_map(frame_offset))
+            .filter(|&offset| offset < 0)
+            .collect::<Vec<_>>();
+        offsets.sort_by(|a, b| b.cmp(a));
+        offsets.dedup();
+        let packed = offsets.iter()
+            .enumerate()
+            .map(|(idx, &offset)| (offset, -width * (idx as isize + 1)))
+            .collect::<HashMap<_, _>>();
+        let pack = |offset: isize| packed.get(&offset).copied().unwrap_or(offset);
+
+        for (_, offset) in callee_saved.iter_mut() {
+            *offset = pack(*offset);
+        }
 
         // align the frame size to 16 bytes
-        let cx = self.cur_cx_mut();
-        cx.frame_size = (cx.frame_size + 15) / 16 * 16;
+        let frame_size = (width as usize * offsets.len()).div_ceil(16) * 16;
+
+        // s0 is sp at entry, and sp moves further down around calls passing arguments on the stack
+        let mut sp_delta = 0;
+        for insn in func.body.iter_mut() {
+            if let Insn::Addi(Operand::PhysReg(Register::Sp), Operand::PhysReg(Register::Sp), imm) = *insn {
+                sp_delta -= imm as isize;
+                continue;
+            }
+            *insn = map_mem(*insn, |operand| match operand {
+                Operand::Mem { base: Register::S0, offset, size } if omit_frame_pointer =>
+                    Operand::stack(frame_size as isize + sp_delta + pack(offset), size),
+                Operand::Mem { base: Register::S0, offset, size } =>
+                    Operand::frame(pack(offset), size),
+                _ => operand,
+            });
+        }
 
-        func.body = s_insns;
-        func.frame_size = cx.frame_size;
+        for var in func.vars.iter_mut() {
+            var.location = var.location.map(|location| match location {
+                Operand::Mem { base: Register::S0, offset, size } if omit_frame_pointer =>
+                    Operand::stack(frame_size as isize + pack(offset), size),
+                Operand::Mem { base: Register::S0, offset, size } =>
+                    Operand::frame(pack(offset), size),
+                _ => location,
+            });
+        }
+
+        let cx = self.cur_cx_mut();
+        cx.frame_size = frame_size;
+        cx.callee_saved = Some(callee_saved.clone());
 
+        func.frame_size = frame_size;
+        func.callee_saved = Some(callee_saved);
 
         self.cur_func = None;
 
-        func        
+        func
     }
+}
 
-    fn spill_insn(
-        &mut self,
-        insn: Insn,
-    ) -> Insn {
-        match insn {
-            Insn::Intermediate(_) => insn,
-            Insn::Add(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Add(dst, left, right)
-            },
-            Insn::Addw(dst, left, right) => {
-                let dst = self.spill_operand(dst, 4);
-                let left = self.spill_operand(left, 4);
-                let right = self.spill_operand(right, 4);
-                Insn::Addw(dst, left, right)
-            },
-            Insn::Addi(dst, src, imm) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 8);
-                Insn::Addi(dst, src, imm)
-            },
-            Insn::Sub(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Sub(dst, left, right)
-            },
-            Insn::Subw(dst, left, right) => {
-                let dst = self.spill_operand(dst, 4);
-                let left = self.spill_operand(left, 4);
-                let right = self.spill_operand(right, 4);
-                Insn::Subw(dst, left, right)
-            },
-            Insn::Mul(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Mul(dst, left, right)
-            },
-            Insn::Mulw(dst, left, right) => {
-                let dst = self.spill_operand(dst, 4);
-                let left = self.spill_operand(left, 4);
-                let right = self.spill_operand(right, 4);
-                Insn::Mulw(dst, left, right)
-            },
-            Insn::Div(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Div(dst, left, right)
-            },
-            Insn::Divw(dst, left, right) => {
-                let dst = self.spill_operand(dst, 4);
-                let left = self.spill_operand(left, 4);
-                let right = self.spill_operand(right, 4);
-                Insn::Divw(dst, left, right)
-            },
-            Insn::Rem(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Rem(dst, left, right)
-            },
-            Insn::Remw(dst, left, right) => {
-                let dst = self.spill_operand(dst, 4);
-                let left = self.spill_operand(left, 4);
-                let right = self.spill_operand(right, 4);
-                Insn::Remw(dst, left, right)
-            },
-            Insn::Beq(left, right, label) => {
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Beq(left, right, label)
-            },
-            Insn::Bne(left, right, label) => {
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Bne(left, right, label)
-            },
-            Insn::Slt(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Slt(dst, left, right)
-            },
-            Insn::Sgt(dst, left, right) => {
-                let dst = self.spill_operand(dst, 8);
-                let left = self.spill_operand(left, 8);
-                let right = self.spill_operand(right, 8);
-                Insn::Sgt(dst, left, right)
-            },
-            Insn::Seqz(dst, src) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 8);
-                Insn::Seqz(dst, src)
-            },
-            Insn::Snez(dst, src) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 8);
-                Insn::Snez(dst, src)
-            },
-            Insn::Sextw(dst, src) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 4);
-                Insn::Sextw(dst, src)
-            },
-            Insn::Label(label) => Insn::Label(label),
-            Insn::Call(name) => Insn::Call(name),
-            Insn::Ret => Insn::Ret,
-            Insn::J(label) => Insn::J(label),
-            Insn::Ld(dst, mem) => {
-                let dst = self.spill_operand(dst, 8);
-                let mem = self.spill_operand(mem, 8);
-                Insn::Ld(dst, mem)
-            },
-            Insn::Lw(dst, mem) => {
-                let dst = self.spill_operand(dst, 4);
-                let mem = self.spill_operand(mem, 4);
-                Insn::Lw(dst, mem)
-            },
-            Insn::Sd(src, mem) => {
-                let src = self.spill_operand(src, 8);
-                let mem = self.spill_operand(mem, 8);
-                Insn::Sd(src, mem)
-            },
-            Insn::Sw(src, mem) => {
-                let src = self.spill_operand(src, 4);
-                let mem = self.spill_operand(mem, 4);
-                Insn::Sw(src, mem)
-            },
-            Insn::Mv(dst, src) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 8);
-                Insn::Mv(dst, src)
-            },
-            Insn::Neg(dst, src) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 8);
-                Insn::Neg(dst, src)
-            },
-            Insn::Negw(dst, src) => {
-                let dst = self.spill_operand(dst, 4);
-                let src = self.spill_operand(src, 4);
-                Insn::Negw(dst, src)
-            },
-            Insn::Not(dst, src) => {
-                let dst = self.spill_operand(dst, 8);
-                let src = self.spill_operand(src, 8);
-                Insn::Not(dst, src)
-            },
-            _ => unreachable!(),
-        }
+/// Offset of the frame slot `insn` accesses.
+fn frame_offset(insn: &Insn) -> Option<isize> {
+    match insn {
+        Insn::Ld(_, Operand::Mem { base: Register::S0, offset, .. }) |
+        Insn::Lw(_, Operand::Mem { base: Register::S0, offset, .. }) |
+        Insn::Sd(_, Operand::Mem { base: Register::S0, offset, .. }) |
+        Insn::Sw(_, Operand::Mem { base: Register::S0, offset, .. }) => Some(*offset),
+        _ => None,
     }
+}
 
-    fn spill_operand(
-        &mut self,
-        operand: Operand,
-        size: usize,
-    ) -> Operand {
-        match operand {
-            Operand::VirtReg(v_reg_id) => {
-                let offset = self.spill_vreg(v_reg_id);
-                Operand::frame(offset, size)
-            },
-            _ => operand,
-        }
+/// Like `map_operands`, but on the memory operand of loads and stores.
+fn map_mem(insn: Insn, f: impl FnOnce(Operand) -> Operand) -> Insn {
+    match insn {
+        Insn::Ld(reg, mem) => Insn::Ld(reg, f(mem)),
+        Insn::Lw(reg, mem) => Insn::Lw(reg, f(mem)),
+        Insn::Sd(reg, mem) => Insn::Sd(reg, f(mem)),
+        Insn::Sw(reg, mem) => Insn::Sw(reg, f(mem)),
+        _ => insn,
     }
 }
 
-impl CodeGen<Spill> {
-    fn spill_vreg(
-        &mut self,
-        v_reg: usize,
-    ) -> isize {
-        let cx = self.cur_cx_mut();
-        if let Some(&offset) = cx.mmap.get(&v_reg) {
-            return offset;
-        } else {
-            let offset = -(cx.frame_size as isize + 8);
-            cx.frame_size += 8;
-            cx.map_vreg2frame(v_reg, offset);
-            return offset;
-        }
-    }
-}
\ No newline at end of file
+fn has_virt_reg(insn: &Insn) -> bool {
+    let mut found = false;
+    map_operands(*insn, |operand, _| {
+        found |= matches!(operand, Operand::VirtReg(_));
+        operand
+    });
+    found
+}
@@ -115,8 +115,24 @@ impl TopLevel {
                 output.push_str(&format!("addi\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), imm)),
             Addiw(rd, rs1, imm) =>
                 output.push_str(&format!("addiw\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), imm)),
+            Slli(rd, rs1, shamt) =>
+                output.push_str(&format!("slli\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), shamt)),
+            Slliw(rd, rs1, shamt) =>
+                output.push_str(&format!("slliw\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), shamt)),
+            Srli(rd, rs1, shamt) =>
+                output.push_str(&format!("srli\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), shamt)),
+            Srliw(rd, rs1, shamt) =>
+                output.push_str(&format!("srliw\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), shamt)),
+            Srai(rd, rs1, shamt) =>
+                output.push_str(&format!("srai\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), shamt)),
+            Sraiw(rd, rs1, shamt) =>
+                output.push_str(&format!("sraiw\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), shamt)),
             Mul(rd, rs1, rs2) =>
                 output.push_str(&format!("mul\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
+            Mulh(rd, rs1, rs2) =>
+                output.push_str(&format!("mulh\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
+            Mulhu(rd, rs1, rs2) =>
+                output.push_str(&format!("mulhu\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
             Mulw(rd, rs1, rs2) =>
                 output.push_str(&format!("mulw\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
             Div(rd, rs1, rs2) =>
@@ -131,6 +147,10 @@ impl TopLevel {
                 output.push_str(&format!("slt\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
             Sgt(rd, rs1, rs2) =>
                 output.push_str(&format!("sgt\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
+            Sltu(rd, rs1, rs2) =>
+                output.push_str(&format!("sltu\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
+            Or(rd, rs1, rs2) =>
+                output.push_str(&format!("or\t{}, {}, {}", self.emit_operand(rd), self.emit_operand(rs1), self.emit_operand(rs2))),
             Seqz(rd, rs) =>
                 output.push_str(&format!("seqz\t{}, {}", self.emit_operand(rd), self.emit_operand(rs))),
             Snez(rd, rs) =>
@@ -139,18 +159,24 @@ impl TopLevel {
                 output.push_str(&format!("sext.w\t{}, {}", self.emit_operand(rd), self.emit_operand(rs))),
             Label(label) =>
                 output.push_str(&format!("{}:", self.emit_label_operand(label))),
+            Loc(span) =>
+                output.push_str(&format!("loc\t{}:{}", span.line, span.column)),
             J(label) =>
                 output.push_str(&format!("j\t{}", self.emit_label_operand(label))),
             Beq(rs1, rs2, label) =>
                 output.push_str(&format!("beq\t{}, {}, {}", self.emit_operand(rs1), self.emit_operand(rs2), self.emit_label_operand(label))),
             Bne(rs1, rs2, label) =>
                 output.push_str(&format!("bne\t{}, {}, {}", self.emit_operand(rs1), self.emit_operand(rs2), self.emit_label_operand(label))),
+            Blt(rs1, rs2, label) =>
+                output.push_str(&format!("blt\t{}, {}, {}", self.emit_operand(rs1), self.emit_operand(rs2), self.emit_label_operand(label))),
+            Bge(rs1, rs2, label) =>
+                output.push_str(&format!("bge\t{}, {}, {}", self.emit_operand(rs1), self.emit_operand(rs2), self.emit_label_operand(label))),
             Call(name) =>
                 output.push_str(&format!("call\t{}", self.strtb.get(*name).unwrap())),
-            LoadStatic(rd, name) => 
-                output.push_str(&format!("load_static\t{}, {}", self.emit_operand(rd), self.strtb.get(*name).unwrap())),
-            StoreStatic(rs, name) =>
-                output.push_str(&format!("store_static\t{}, {}", self.emit_operand(rs), self.strtb.get(*name).unwrap())),
+            LoadStatic(rd, name, offset) => 
+                output.push_str(&format!("load_static\t{}, {}", self.emit_operand(rd), self.emit_static(*name, *offset))),
+            StoreStatic(rs, addr, name, offset) =>
+                output.push_str(&format!("store_static\t{}, {}, {}", self.emit_operand(rs), self.emit_operand(addr), self.emit_static(*name, *offset))),
             Ret =>
                 output.push_str("ret"),
             Lw(rd, mem) =>
@@ -176,6 +202,8 @@ impl TopLevel {
             Intermediate(insn) => match insn {
                 IntermediateInsn::Prologue => output.push_str("prologue;"),
                 IntermediateInsn::Epilogue => output.push_str("epilogue;"),
+                IntermediateInsn::TailCall(name) =>
+                    output.push_str(&format!("tail_call\t{};", self.strtb.get(*name).unwrap())),
             }
         }
 
@@ -194,6 +222,16 @@ impl TopLevel {
         }
     }
 
+    /// `name`, or `name+offset` into it.
+    fn emit_static(&self, name: StrDescriptor, offset: isize) -> String {
+        let name = self.strtb.get(name).unwrap();
+        if offset == 0 {
+            name.to_string()
+        } else {
+            format!("{}+{}", name, offset)
+        }
+    }
+
     pub fn emit_label_operand(&self, label: &LabelOperand) -> String {
         use LabelOperand::*;
 
@@ -0,0 +1,135 @@
+//! JSON serialization of the LIR, names resolved through the string pool.
+//! Instructions are `{"op": mnemonic, "operands": [...]}`, in the order they are written in assembly.
+
+use crate::common::*;
+use super::{
+    Operand,
+    Insn,
+    Function,
+    DebugVar,
+    LabelOperand,
+    StaticVar,
+    TopLevel,
+    IntermediateInsn,
+};
+
+impl TopLevel {
+    pub fn to_json(&self) -> Json {
+        let mut functions = self.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|func| self.strtb.get(func.name));
+        let static_vars = |items: &std::collections::HashMap<StrDescriptor, StaticVar>| {
+            let mut vars = items.values().collect::<Vec<_>>();
+            vars.sort_by_key(|var| self.strtb.get(var.name));
+            Json::Array(vars.into_iter().map(|var| self.json_static_var(var)).collect())
+        };
+
+        Json::object([
+            ("xlen", self.xlen.bits().into()),
+            ("data", static_vars(&self.data_seg.items)),
+            ("bss", static_vars(&self.bss_seg.items)),
+            ("externs", static_vars(&self.externs)),
+            ("functions", Json::Array(functions.into_iter().map(|func| self.json_function(func)).collect())),
+        ])
+    }
+
+    fn name(&self, name: StrDescriptor) -> Json {
+        self.strtb.get(name).unwrap().into()
+    }
+
+    fn json_static_var(&self, var: &StaticVar) -> Json {
+        Json::object([
+            ("name", self.name(var.name)),
+            ("data_type", var.data_type.into()),
+            ("linkage", var.linkage.into()),
+            ("initializer", var.initializer.into()),
+            ("span", var.span.into()),
+        ])
+    }
+
+    fn json_function(&self, func: &Function) -> Json {
+        Json::object([
+            ("name", self.name(func.name)),
+            ("type", (&func.func_type).into()),
+            ("linkage", func.linkage.into()),
+            ("frame_size", func.frame_size.into()),
+            ("callee_saved", func.callee_saved.as_ref().map_or(Json::Null, |saved| Json::Array(saved.iter()
+                .map(|(reg, offset)| Json::object([
+                    ("reg", reg.to_string().into()),
+                    ("offset", (*offset).into()),
+                ]))
+                .collect()))),
+            ("vars", Json::Array(func.vars.iter().map(|var| self.json_debug_var(var)).collect())),
+            ("body", Json::Array(func.body.iter().map(|insn| self.json_insn(insn)).collect())),
+            ("span", func.span.into()),
+        ])
+    }
+
+    fn json_debug_var(&self, var: &DebugVar) -> Json {
+        Json::object([
+            ("name", self.name(var.name)),
+            ("data_type", var.data_type.into()),
+            ("is_param", var.is_param.into()),
+            ("location", var.location.map_or(Json::Null, |location| self.json_operand(location))),
+            ("span", var.span.into()),
+        ])
+    }
+
+    fn json_insn(&self, insn: &Insn) -> Json {
+        let op = |operand: Operand| self.json_operand(operand);
+        let (mnemonic, operands) = match *insn {
+            Insn::Add(rd, rs1, rs2) | Insn::Addw(rd, rs1, rs2) |
+            Insn::Sub(rd, rs1, rs2) | Insn::Subw(rd, rs1, rs2) |
+            Insn::Mul(rd, rs1, rs2) | Insn::Mulw(rd, rs1, rs2) |
+            Insn::Mulh(rd, rs1, rs2) | Insn::Mulhu(rd, rs1, rs2) |
+            Insn::Div(rd, rs1, rs2) | Insn::Divw(rd, rs1, rs2) |
+            Insn::Rem(rd, rs1, rs2) | Insn::Remw(rd, rs1, rs2) |
+            Insn::Slt(rd, rs1, rs2) | Insn::Sgt(rd, rs1, rs2) |
+            Insn::Sltu(rd, rs1, rs2) | Insn::Or(rd, rs1, rs2) => (Json::unit(insn), vec![op(rd), op(rs1), op(rs2)]),
+            Insn::Addi(rd, rs1, imm) => (Json::unit(insn), vec![op(rd), op(rs1), imm.into()]),
+            Insn::Addiw(rd, rs1, imm) => (Json::unit(insn), vec![op(rd), op(rs1), imm.into()]),
+            Insn::Slli(rd, rs1, shamt) | Insn::Slliw(rd, rs1, shamt) |
+            Insn::Srli(rd, rs1, shamt) | Insn::Srliw(rd, rs1, shamt) |
+            Insn::Srai(rd, rs1, shamt) | Insn::Sraiw(rd, rs1, shamt) => (Json::unit(insn), vec![op(rd), op(rs1), shamt.into()]),
+            Insn::Seqz(rd, rs) | Insn::Snez(rd, rs) | Insn::Sextw(rd, rs) |
+            Insn::Lw(rd, rs) | Insn::Sw(rd, rs) | Insn::Ld(rd, rs) | Insn::Sd(rd, rs) |
+            Insn::Mv(rd, rs) | Insn::Neg(rd, rs) | Insn::Negw(rd, rs) | Insn::Not(rd, rs) => (Json::unit(insn), vec![op(rd), op(rs)]),
+            Insn::Label(label) | Insn::J(label) => (Json::unit(insn), vec![self.json_label(label)]),
+            Insn::Beq(rs1, rs2, label) | Insn::Bne(rs1, rs2, label) |
+            Insn::Blt(rs1, rs2, label) | Insn::Bge(rs1, rs2, label) => (Json::unit(insn), vec![op(rs1), op(rs2), self.json_label(label)]),
+            Insn::Call(name) => (Json::unit(insn), vec![self.name(name)]),
+            Insn::Ret => (Json::unit(insn), vec![]),
+            Insn::Li(rd, imm) => (Json::unit(insn), vec![op(rd), imm.into()]),
+            Insn::La(rd, name) => (Json::unit(insn), vec![op(rd), self.name(name)]),
+            Insn::LoadStatic(rd, name, offset) => (Json::unit(insn), vec![op(rd), self.name(name), offset.into()]),
+            Insn::StoreStatic(rs, scratch, name, offset) => (Json::unit(insn), vec![op(rs), op(scratch), self.name(name), offset.into()]),
+            Insn::Intermediate(intermediate @ (IntermediateInsn::Prologue | IntermediateInsn::Epilogue)) => (Json::unit(intermediate), vec![]),
+            Insn::Intermediate(intermediate @ IntermediateInsn::TailCall(name)) => (Json::unit(intermediate), vec![self.name(name)]),
+            Insn::Loc(span) => (Json::unit(insn), vec![span.into()]),
+        };
+        Json::object([
+            ("op", mnemonic),
+            ("operands", Json::Array(operands)),
+        ])
+    }
+
+    fn json_operand(&self, operand: Operand) -> Json {
+        match operand {
+            Operand::VirtReg(id) => Json::variant("virt", [("id", id.into())]),
+            Operand::PhysReg(reg) => Json::variant("reg", [("reg", reg.to_string().into())]),
+            Operand::Imm(value) => Json::variant("imm", [("value", value.into())]),
+            Operand::Mem { base, offset, size } => Json::variant("mem", [
+                ("base", base.to_string().into()),
+                ("offset", offset.into()),
+                ("size", size.into()),
+            ]),
+            Operand::Static(name) => Json::variant("static", [("name", self.name(name))]),
+        }
+    }
+
+    fn json_label(&self, label: LabelOperand) -> Json {
+        match label {
+            LabelOperand::AutoGen(id) => Json::variant("auto_gen", [("id", id.into())]),
+            LabelOperand::Named(name) => Json::variant("named", [("name", self.name(name))]),
+        }
+    }
+}
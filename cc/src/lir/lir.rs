@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::{asm::Register, common::*, tac::{TacAutoGenLabel, TacLabelOperand}};
+use crate::{asm::{Register, Xlen}, common::*, tac::{TacAutoGenLabel, TacLabelOperand}};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operand {
@@ -41,16 +41,28 @@ pub enum Insn {
     Addi(Operand, Operand, i64),
     Addw(Operand, Operand, Operand),
     Addiw(Operand, Operand, i32),
+    Slli(Operand, Operand, u32),
+    Slliw(Operand, Operand, u32),
+    Srli(Operand, Operand, u32),
+    Srliw(Operand, Operand, u32),
+    Srai(Operand, Operand, u32),
+    Sraiw(Operand, Operand, u32),
     Sub(Operand, Operand, Operand),
     Subw(Operand, Operand, Operand),
     Mul(Operand, Operand, Operand),
     Mulw(Operand, Operand, Operand),
+    /// upper 64 bits of the signed 128-bit product
+    Mulh(Operand, Operand, Operand),
+    /// upper half of the unsigned product
+    Mulhu(Operand, Operand, Operand),
     Div(Operand, Operand, Operand),
     Divw(Operand, Operand, Operand),
     Rem(Operand, Operand, Operand),
     Remw(Operand, Operand, Operand),
     Slt(Operand, Operand, Operand),
     Sgt(Operand, Operand, Operand),
+    Sltu(Operand, Operand, Operand),
+    Or(Operand, Operand, Operand),
     Seqz(Operand, Operand),
     Snez(Operand, Operand),
     Sextw(Operand, Operand),
@@ -58,6 +70,8 @@ pub enum Insn {
     J(LabelOperand),
     Beq(Operand, Operand, LabelOperand),
     Bne(Operand, Operand, LabelOperand),
+    Blt(Operand, Operand, LabelOperand),
+    Bge(Operand, Operand, LabelOperand),
     Call(StrDescriptor),
     Ret,
     Lw(Operand, Operand),
@@ -71,10 +85,34 @@ pub enum Insn {
     Negw(Operand, Operand),
     Not(Operand, Operand),
 
-    LoadStatic(Operand, StrDescriptor),
-    StoreStatic(Operand, StrDescriptor),
+    /// (destination, name, offset), the offset is 4 for the upper half of a 64-bit static on RV32
+    LoadStatic(Operand, StrDescriptor, isize),
+    /// (value, address scratch, name, offset), the scratch is clobbered
+    StoreStatic(Operand, Operand, StrDescriptor, isize),
 
     Intermediate(IntermediateInsn),
+    /// the source location of what follows, only generated with `-g`
+    Loc(Span),
+}
+
+impl Insn {
+    /// `lw` or `ld`, by the size of `mem`.
+    pub fn load(dst: Operand, mem: Operand) -> Self {
+        match mem {
+            Operand::Mem { size: 4, .. } => Insn::Lw(dst, mem),
+            Operand::Mem { size: 8, .. } => Insn::Ld(dst, mem),
+            _ => unreachable!(),
+        }
+    }
+
+    /// `sw` or `sd`, by the size of `mem`.
+    pub fn store(src: Operand, mem: Operand) -> Self {
+        match mem {
+            Operand::Mem { size: 4, .. } => Insn::Sw(src, mem),
+            Operand::Mem { size: 8, .. } => Insn::Sd(src, mem),
+            _ => unreachable!(),
+        }
+    }
 }
 
 /// Instructions that are used during the intermediate stages of code generation.
@@ -83,6 +121,8 @@ pub enum Insn {
 pub enum IntermediateInsn {
     Prologue,
     Epilogue,
+    /// epilogue, then jump to the function instead of returning, with the arguments already in place
+    TailCall(StrDescriptor),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -116,6 +156,20 @@ pub struct Function {
     pub body: Vec<Insn>,
     pub frame_size: usize,
     pub callee_saved: Option<Vec<(Register, isize)>>,
+    pub span: Span,
+    /// parameters first, in order, then local variables
+    pub vars: Vec<DebugVar>,
+}
+
+/// A parameter or local variable and where it's kept, for debug info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugVar {
+    pub name: StrDescriptor,
+    pub data_type: DataType,
+    pub span: Span,
+    pub is_param: bool,
+    /// its register, or its frame slot once spilled, `None` if optimized out
+    pub location: Option<Operand>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -124,6 +178,7 @@ pub struct StaticVar {
     pub data_type: DataType,
     pub linkage: Linkage,
     pub initializer: InitVal,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -141,7 +196,10 @@ pub struct TopLevel {
     pub functions: HashMap<StrDescriptor, Function>,
     pub data_seg: DataSegment,
     pub bss_seg: BssSegment,
+    /// variables only declared here, defined by another translation unit
+    pub externs: HashMap<StrDescriptor, StaticVar>,
     pub strtb: StringPool,
+    pub xlen: Xlen,
 }
 
 impl DataSegment {
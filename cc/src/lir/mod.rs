@@ -14,11 +14,13 @@
 mod lir;
 mod codegen;
 mod emit;
+mod json;
 
 use lir::{
     Operand,
     Insn,
     Function,
+    DebugVar,
     LabelOperand,
     LabelSignature,
     StaticVar,
@@ -29,10 +31,12 @@ use lir::{
 
 pub use codegen::FuncContext as LirFuncContext;
 pub use codegen::CodeGen as LirCodeGen;
+pub use codegen::{RegAllocator, MachineModel, AllocDump};
 pub use lir::{
     Operand as LirOperand,
     Insn as LirInsn,
     Function as LirFunction,
+    DebugVar as LirDebugVar,
     StaticVar as LirStaticVar,
     LabelOperand as LirLabelOperand,
     TopLevel as LirTopLevel,
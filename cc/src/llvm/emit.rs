@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    common::*,
+    tac::{
+        TacTopLevel,
+        TacFunction,
+        TacStaticVar,
+        TacInsn,
+        TacOperand,
+        TacUnaryOp,
+        TacBinaryOp,
+        TacLabelOperand,
+        TacAutoGenLabel,
+    },
+};
+
+/// Lowers a translation unit into an LLVM IR module, named after `file`.
+/// Functions and globals are emitted sorted by name, so the output is stable.
+pub fn emit(tac: &TacTopLevel, file: &str) -> String {
+    let mut output = String::new();
+    output.push_str(&format!("; ModuleID = '{}'\n", file));
+    output.push_str(&format!("source_filename = \"{}\"\n", file.escape_default()));
+
+    let signatures = tac.functions.iter()
+        .map(|(&name, func)| (name, signature(func)))
+        .collect::<HashMap<_, _>>();
+
+    let mut static_vars = tac.static_vars.values()
+        .map(|var| (tac.strtb.get(var.name).unwrap(), var))
+        .collect::<Vec<_>>();
+    static_vars.sort_by_key(|&(name, _)| name);
+    if !static_vars.is_empty() {
+        output.push('\n');
+    }
+    for (name, var) in static_vars {
+        output.push_str(&emit_static_var(name, var));
+    }
+
+    let mut functions = tac.functions.iter()
+        .map(|(&name, func)| (tac.strtb.get(name).unwrap(), func))
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|&(name, _)| name);
+    for (name, func) in functions {
+        output.push('\n');
+        match func {
+            TacFunction::Declared { type_, .. } => {
+                let params = type_.param_types.iter()
+                    .map(|&type_| ir_type(type_))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                output.push_str(&format!("declare {} @{}({})\n", ir_type(type_.return_type), name, params));
+            },
+            TacFunction::Defined { .. } => {
+                let emitter = FuncEmitter::new(tac, &signatures);
+                output.push_str(&emitter.emit(name, func));
+            },
+        }
+    }
+
+    output
+}
+
+fn emit_static_var(name: &str, var: &TacStaticVar) -> String {
+    let type_ = ir_type(var.data_type);
+    let align = var.data_type.align();
+    let linkage = match var.linkage {
+        Linkage::Internal => "internal",
+        Linkage::External => "dso_local",
+    };
+    match var.initializer {
+        InitVal::Const(val) => format!("@{} = {} global {} {}, align {}\n", name, linkage, type_, val, align),
+        InitVal::Tentative => format!("@{} = {} global {} 0, align {}\n", name, linkage, type_, align),
+        InitVal::None => format!("@{} = external global {}, align {}\n", name, type_, align),
+    }
+}
+
+fn signature(func: &TacFunction) -> FuncType {
+    match func {
+        TacFunction::Declared { type_, .. } => type_.clone(),
+        TacFunction::Defined { return_type, params, .. } => FuncType {
+            return_type: *return_type,
+            param_types: params.iter().map(|param| param.data_type).collect(),
+        },
+    }
+}
+
+fn ir_type(type_: DataType) -> &'static str {
+    match type_ {
+        DataType::Int => "i32",
+        DataType::Long => "i64",
+        DataType::Void => "void",
+        DataType::Indeterminate => unreachable!(),
+    }
+}
+
+/// Lowers one function the way clang does without optimizations:
+/// every parameter, local and temporary gets a stack slot from an `alloca`,
+/// operands are loaded from their slots and results stored back,
+/// so `mem2reg` is left to build the SSA form.
+///
+/// Slots are named after what they hold, `%name.id` for variables and `%.tN` for temporaries,
+/// labels become blocks named `.bra3` or `.name.id` for those in the source,
+/// and the values in between are numbered from `%1`, after the unnamed entry block.
+struct FuncEmitter<'a> {
+    tac: &'a TacTopLevel,
+    signatures: &'a HashMap<StrDescriptor, FuncType>,
+    output: String,
+    next_value: usize,
+    /// whether the current block has been terminated, everything up to the next label is dead
+    terminated: bool,
+}
+
+impl<'a> FuncEmitter<'a> {
+    fn new(tac: &'a TacTopLevel, signatures: &'a HashMap<StrDescriptor, FuncType>) -> Self {
+        Self {
+            tac,
+            signatures,
+            output: String::new(),
+            next_value: 1,
+            terminated: false,
+        }
+    }
+
+    fn emit(mut self, name: &str, func: &TacFunction) -> String {
+        let TacFunction::Defined { return_type, linkage, params, local_vars, body, .. } = func else {
+            unreachable!()
+        };
+
+        let linkage = match linkage {
+            Linkage::Internal => "internal",
+            Linkage::External => "dso_local",
+        };
+        let param_list = params.iter()
+            .map(|param| format!("{} %{}", ir_type(param.data_type), self.tac.strtb.get(param.name).unwrap()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.output.push_str(&format!("define {} {} @{}({}) {{\n", linkage, ir_type(*return_type), name, param_list));
+
+        let mut vars = BTreeMap::new();
+        for param in params {
+            vars.insert(param.local_id, (param.name, param.data_type));
+        }
+        for var in local_vars.values() {
+            vars.insert(var.local_id, (var.name, var.data_type));
+        }
+        let mut temps = BTreeMap::new();
+        for operand in body.iter().flat_map(TacInsn::operands) {
+            if let TacOperand::Temp(id, data_type) = *operand {
+                temps.entry(id).or_insert(data_type);
+            }
+        }
+        for (&id, &(name, data_type)) in &vars {
+            let slot = format!("%{}.{}", self.tac.strtb.get(name).unwrap(), id);
+            self.alloca(&slot, data_type);
+        }
+        for (&id, &data_type) in &temps {
+            self.alloca(&format!("%.t{}", id), data_type);
+        }
+        for param in params {
+            let name = self.tac.strtb.get(param.name).unwrap();
+            let type_ = ir_type(param.data_type);
+            self.line(format!(
+                "store {} %{}, ptr %{}.{}, align {}",
+                type_, name, name, param.local_id, param.data_type.align(),
+            ));
+        }
+
+        for insn in body {
+            self.emit_insn(insn, *return_type);
+        }
+        // falling off the end, which the `ret 0` the code generator appends rules out
+        if !self.terminated {
+            self.line("unreachable".to_string());
+        }
+
+        self.output.push_str("}\n");
+        self.output
+    }
+
+    fn emit_insn(&mut self, insn: &TacInsn, return_type: DataType) {
+        if self.terminated && !matches!(insn, TacInsn::Label(..)) {
+            return;
+        }
+
+        match insn {
+            TacInsn::Return(val) => {
+                let val = self.load(val, return_type);
+                self.terminate(format!("ret {} {}", ir_type(return_type), val));
+            },
+            TacInsn::Unary { op, src, dst } => {
+                // `!` produces an `int`, the others keep the type of their operand
+                let type_ = match op {
+                    TacUnaryOp::Not => operand_type(src),
+                    _ => operand_type(dst),
+                };
+                let src = self.load(src, type_);
+                let result = match op {
+                    TacUnaryOp::Pos => src,
+                    TacUnaryOp::Negate => self.value(format!("sub {} 0, {}", ir_type(type_), src)),
+                    TacUnaryOp::Complement => self.value(format!("xor {} {}, -1", ir_type(type_), src)),
+                    TacUnaryOp::Not => {
+                        let cond = self.value(format!("icmp eq {} {}, 0", ir_type(type_), src));
+                        self.value(format!("zext i1 {} to {}", cond, ir_type(operand_type(dst))))
+                    },
+                };
+                self.store(&result, dst);
+            },
+            TacInsn::Binary { op, left, right, dst } => {
+                let cond = match op {
+                    TacBinaryOp::Ls => Some("slt"),
+                    TacBinaryOp::Gt => Some("sgt"),
+                    TacBinaryOp::LsEq => Some("sle"),
+                    TacBinaryOp::GtEq => Some("sge"),
+                    TacBinaryOp::Eq => Some("eq"),
+                    TacBinaryOp::NotEq => Some("ne"),
+                    _ => None,
+                };
+                // comparisons produce an `int`, whatever their operands are
+                let type_ = match (cond, left, right) {
+                    (Some(_), TacOperand::Imm(..), operand) | (Some(_), operand, _) => operand_type(operand),
+                    (None, ..) => operand_type(dst),
+                };
+                let left = self.load(left, type_);
+                let right = self.load(right, type_);
+                let result = match cond {
+                    Some(cond) => {
+                        let cond = self.value(format!("icmp {} {} {}, {}", cond, ir_type(type_), left, right));
+                        self.value(format!("zext i1 {} to {}", cond, ir_type(operand_type(dst))))
+                    },
+                    None => {
+                        let op = match op {
+                            TacBinaryOp::Add => "add",
+                            TacBinaryOp::Sub => "sub",
+                            TacBinaryOp::Mul => "mul",
+                            TacBinaryOp::Div => "sdiv",
+                            TacBinaryOp::Rem => "srem",
+                            // short-circuited into branches by the code generator
+                            _ => unreachable!(),
+                        };
+                        self.value(format!("{} {} {}, {}", op, ir_type(type_), left, right))
+                    },
+                };
+                self.store(&result, dst);
+            },
+            TacInsn::Label(label) => {
+                let label = self.label(label);
+                if !self.terminated {
+                    self.line(format!("br label %{}", label));
+                }
+                self.output.push_str(&format!("\n{}:\n", label));
+                self.terminated = false;
+            },
+            TacInsn::Jump(label) => {
+                let label = self.label(label);
+                self.terminate(format!("br label %{}", label));
+            },
+            TacInsn::BranchIfZero { src, label } |
+            TacInsn::BranchNotZero { src, label } => {
+                let cond = if let TacInsn::BranchIfZero { .. } = insn { "eq" } else { "ne" };
+                let type_ = operand_type(src);
+                let src = self.load(src, type_);
+                let cond = self.value(format!("icmp {} {} {}, 0", cond, ir_type(type_), src));
+                let label = self.label(label);
+                // the fall-through block is unnamed and takes the next number
+                let next = self.next_value;
+                self.line(format!("br i1 {}, label %{}, label %{}", cond, label, next));
+                self.output.push_str(&format!("\n{}:\n", next));
+                self.next_value += 1;
+            },
+            TacInsn::FuncCall { target, args, dst } => {
+                let signature = &self.signatures[target];
+                let return_type = signature.return_type;
+                let args = args.iter()
+                    .zip(signature.param_types.clone())
+                    .map(|(arg, type_)| format!("{} {}", ir_type(type_), self.load(arg, type_)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let call = format!("call {} @{}({})", ir_type(return_type), self.tac.strtb.get(*target).unwrap(), args);
+                if let DataType::Void = return_type {
+                    self.line(call);
+                } else {
+                    let result = self.value(call);
+                    self.store(&result, dst);
+                }
+            },
+            TacInsn::Move { src, dst } => {
+                let src = self.load(src, operand_type(dst));
+                self.store(&src, dst);
+            },
+            TacInsn::Truncate { src, dst } => {
+                let src = self.load(src, DataType::Long);
+                let result = self.value(format!("trunc i64 {} to i32", src));
+                self.store(&result, dst);
+            },
+            TacInsn::SignExt { src, dst } => {
+                let src = self.load(src, DataType::Int);
+                let result = self.value(format!("sext i32 {} to i64", src));
+                self.store(&result, dst);
+            },
+            TacInsn::Loc(..) => (),
+        }
+    }
+
+    fn alloca(&mut self, slot: &str, type_: DataType) {
+        self.line(format!("{} = alloca {}, align {}", slot, ir_type(type_), type_.align()));
+    }
+
+    /// The value of an operand, as `type_` if it is a constant.
+    fn load(&mut self, operand: &TacOperand, type_: DataType) -> String {
+        match operand {
+            TacOperand::Imm(val) => val.to_string(),
+            _ => {
+                let type_ = operand_type(operand);
+                let slot = self.slot(operand);
+                self.value(format!("load {}, ptr {}, align {}", ir_type(type_), slot, type_.align()))
+            },
+        }
+    }
+
+    fn store(&mut self, value: &str, dst: &TacOperand) {
+        let type_ = operand_type(dst);
+        let slot = self.slot(dst);
+        self.line(format!("store {} {}, ptr {}, align {}", ir_type(type_), value, slot, type_.align()));
+    }
+
+    fn slot(&self, operand: &TacOperand) -> String {
+        match operand {
+            TacOperand::Var { name, local_id: Some(id), .. } => format!("%{}.{}", self.tac.strtb.get(*name).unwrap(), id),
+            TacOperand::Var { name, local_id: None, .. } => format!("@{}", self.tac.strtb.get(*name).unwrap()),
+            TacOperand::Temp(id, _) => format!("%.t{}", id),
+            TacOperand::Imm(..) => unreachable!(),
+        }
+    }
+
+    fn label(&self, label: &TacLabelOperand) -> String {
+        match label {
+            TacLabelOperand::AutoGen(TacAutoGenLabel::Branch(id)) => format!(".bra{}", id),
+            TacLabelOperand::AutoGen(TacAutoGenLabel::Continue(id)) => format!(".con{}", id),
+            TacLabelOperand::AutoGen(TacAutoGenLabel::Break(id)) => format!(".brk{}", id),
+            TacLabelOperand::Named { name, id } => format!(".{}.{}", self.tac.strtb.get(*name).unwrap(), id),
+        }
+    }
+
+    /// Emits an instruction defining the next numbered value.
+    fn value(&mut self, rhs: String) -> String {
+        let value = format!("%{}", self.next_value);
+        self.next_value += 1;
+        self.line(format!("{} = {}", value, rhs));
+        value
+    }
+
+    fn terminate(&mut self, line: String) {
+        self.line(line);
+        self.terminated = true;
+    }
+
+    fn line(&mut self, line: String) {
+        self.output.push_str(&format!("  {}\n", line));
+    }
+}
+
+fn operand_type(operand: &TacOperand) -> DataType {
+    match operand {
+        TacOperand::Imm(Constant::Int(..)) => DataType::Int,
+        TacOperand::Imm(Constant::Long(..)) => DataType::Long,
+        TacOperand::Var { data_type, .. } |
+        TacOperand::Temp(_, data_type) => *data_type,
+    }
+}
@@ -0,0 +1,46 @@
+//! LLVM IR backend, lowering TAC into a textual module the way clang does at `-O0`,
+//! so TAC can be run through `lli` and compared against clang's output.
+//! - `int` and `long` are `i32` and `i64`, `Truncate` and `SignExt` are `trunc` and `sext`
+//! - each parameter, local and temporary gets an `alloca`, static variables are globals
+//! - labels start blocks, conditional branches fall through into a fresh block
+//! - pointers are opaque (`ptr`), as clang emits them from LLVM 15 on
+
+mod emit;
+
+pub use emit::emit;
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+    use std::process::Command;
+
+    use crate::driver::{compile_llvm, Options};
+    use crate::host;
+
+    #[test]
+    fn test_llvm() {
+        if !host::has("lli") {
+            return;
+        }
+        // pointers are typed by default before LLVM 15
+        let version = Command::new("lli").arg("--version").output().unwrap().stdout;
+        let major = String::from_utf8_lossy(&version)
+            .split("LLVM version ")
+            .nth(1)
+            .and_then(|version| version.split('.').next()?.parse::<u32>().ok())
+            .unwrap_or(u32::MAX);
+
+        // the module the interpreter runs behaves like the program
+        host::check("llvm", &[], |dir, name, source, opt_level| {
+            let options = Options { opt_level, file_name: Some(format!("{}.c", name)), ..Options::default() };
+            let ir = dir.join(format!("{}.{}.ll", name, opt_level));
+            write(&ir, compile_llvm(source.to_string(), &options).unwrap()).unwrap();
+            let mut lli = Command::new("lli");
+            if major < 15 {
+                lli.arg("-opaque-pointers");
+            }
+            lli.arg(ir);
+            lli
+        });
+    }
+}
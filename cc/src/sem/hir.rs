@@ -92,6 +92,8 @@ pub struct Function {
     pub params: Vec<Param>,
     pub linkage: Linkage,
     pub body: Option<Vec<BlockItem>>,
+    // where the function is defined, or first declared if it isn't
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -100,6 +102,8 @@ pub struct StaticVar {
     pub data_type: DataType,
     pub linkage: Linkage,
     pub initializer: InitVal,
+    // where the variable is defined, or first declared if it isn't
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -107,6 +111,7 @@ pub struct Param {
     pub name: StrDescriptor,
     pub data_type: DataType,
     pub local_id: usize,
+    pub span: Span,
 }
 
 impl Param {
@@ -196,8 +201,12 @@ pub enum Stmt {
         span: Span,
         expr: Box<TypedExpr>,
     },
-    Expr(Box<TypedExpr>),
+    Expr {
+        span: Span,
+        expr: Box<TypedExpr>,
+    },
     If {
+        span: Span,
         condition: Box<TypedExpr>,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
@@ -0,0 +1,187 @@
+//! JSON serialization of the HIR, names resolved through the string pool.
+//! Functions and static variables are sorted by name, expressions carry their types.
+
+use crate::common::*;
+use super::{
+    TopLevel,
+    LocalVarDecl,
+    Function,
+    StaticVar,
+    BlockItem,
+    Stmt,
+    ForInit,
+    TypedExpr,
+    Expr,
+    Variable,
+};
+
+impl TopLevel {
+    pub fn to_json(&self) -> Json {
+        let mut funcs = self.funcs.values().collect::<Vec<_>>();
+        funcs.sort_by_key(|func| self.strtb.get(func.name));
+        let mut static_vars = self.static_vars.values().collect::<Vec<_>>();
+        static_vars.sort_by_key(|var| self.strtb.get(var.name));
+
+        Json::object([
+            ("static_vars", Json::Array(static_vars.into_iter().map(|var| self.json_static_var(var)).collect())),
+            ("funcs", Json::Array(funcs.into_iter().map(|func| self.json_func(func)).collect())),
+        ])
+    }
+
+    fn name(&self, name: StrDescriptor) -> Json {
+        self.strtb.get(name).unwrap().into()
+    }
+
+    fn json_static_var(&self, var: &StaticVar) -> Json {
+        Json::object([
+            ("name", self.name(var.name)),
+            ("data_type", var.data_type.into()),
+            ("linkage", var.linkage.into()),
+            ("initializer", var.initializer.into()),
+            ("span", var.span.into()),
+        ])
+    }
+
+    fn json_func(&self, func: &Function) -> Json {
+        Json::object([
+            ("name", self.name(func.name)),
+            ("type", (&func.type_).into()),
+            ("linkage", func.linkage.into()),
+            ("params", Json::Array(func.params.iter()
+                .map(|param| Json::object([
+                    ("name", self.name(param.name)),
+                    ("data_type", param.data_type.into()),
+                    ("local_id", param.local_id.into()),
+                    ("span", param.span.into()),
+                ]))
+                .collect())),
+            ("body", func.body.as_ref().map_or(Json::Null, |items| self.json_items(items))),
+            ("span", func.span.into()),
+        ])
+    }
+
+    fn json_local_var_decl(&self, decl: &LocalVarDecl) -> Json {
+        Json::variant("local_var_decl", [
+            ("name", self.name(decl.name)),
+            ("data_type", decl.data_type.into()),
+            ("local_id", decl.local_id.into()),
+            ("initializer", decl.initializer.as_ref().map_or(Json::Null, |expr| self.json_expr(expr))),
+            ("span", decl.span.into()),
+        ])
+    }
+
+    fn json_items(&self, items: &[BlockItem]) -> Json {
+        Json::Array(items.iter()
+            .map(|item| match item {
+                BlockItem::Declaration(decl) => self.json_local_var_decl(decl),
+                BlockItem::Statement(stmt) => self.json_stmt(stmt),
+            })
+            .collect())
+    }
+
+    fn json_stmt(&self, stmt: &Stmt) -> Json {
+        let expr = |expr: &Option<Box<TypedExpr>>| expr.as_ref().map_or(Json::Null, |expr| self.json_expr(expr));
+        match stmt {
+            Stmt::Return { span, expr } => Json::variant("return", [
+                ("expr", self.json_expr(expr)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Expr { span, expr } => Json::variant("expr", [
+                ("expr", self.json_expr(expr)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::If { span, condition, then_branch, else_branch } => Json::variant("if", [
+                ("condition", self.json_expr(condition)),
+                ("then_branch", self.json_stmt(then_branch)),
+                ("else_branch", else_branch.as_ref().map_or(Json::Null, |stmt| self.json_stmt(stmt))),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Break { span, loop_label } => Json::variant("break", [
+                ("loop_label", (*loop_label).into()),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Continue { span, loop_label } => Json::variant("continue", [
+                ("loop_label", (*loop_label).into()),
+                ("span", (*span).into()),
+            ]),
+            Stmt::While { span, controller, body, loop_label } => Json::variant("while", [
+                ("loop_label", (*loop_label).into()),
+                ("controller", self.json_expr(controller)),
+                ("body", self.json_stmt(body)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::DoWhile { span, body, controller, loop_label } => Json::variant("do_while", [
+                ("loop_label", (*loop_label).into()),
+                ("body", self.json_stmt(body)),
+                ("controller", self.json_expr(controller)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::For { span, initializer, controller, post, body, loop_label } => Json::variant("for", [
+                ("loop_label", (*loop_label).into()),
+                ("initializer", initializer.as_ref().map_or(Json::Null, |init| match init.as_ref() {
+                    ForInit::Declaration(decl) => self.json_local_var_decl(decl),
+                    ForInit::Expression(expr) => self.json_expr(expr),
+                })),
+                ("controller", expr(controller)),
+                ("post", expr(post)),
+                ("body", self.json_stmt(body)),
+                ("span", (*span).into()),
+            ]),
+            Stmt::Compound(items) => Json::variant("compound", [("items", self.json_items(items))]),
+            Stmt::Nil => Json::variant("nil", []),
+        }
+    }
+
+    fn json_expr(&self, expr: &TypedExpr) -> Json {
+        let mut json = match &expr.untyped {
+            Expr::IntegerLiteral(constant) => Json::variant("integer_literal", [("value", (*constant).into())]),
+            Expr::Var(Variable::Local { name, local_id, data_type }) => Json::variant("var", [
+                ("name", self.name(*name)),
+                ("local_id", (*local_id).into()),
+                ("data_type", (*data_type).into()),
+            ]),
+            Expr::Var(Variable::Static { name, data_type }) => Json::variant("var", [
+                ("name", self.name(*name)),
+                ("local_id", Json::Null),
+                ("data_type", (*data_type).into()),
+            ]),
+            Expr::Assignment { span, left, right } => Json::variant("assignment", [
+                ("left", self.json_expr(left)),
+                ("right", self.json_expr(right)),
+                ("span", (*span).into()),
+            ]),
+            Expr::FuncCall { name, span, args } => Json::variant("func_call", [
+                ("name", self.name(*name)),
+                ("args", Json::Array(args.iter().map(|arg| self.json_expr(arg)).collect())),
+                ("span", (*span).into()),
+            ]),
+            Expr::Ternary { span, condition, then_expr, else_expr } => Json::variant("ternary", [
+                ("condition", self.json_expr(condition)),
+                ("then_expr", self.json_expr(then_expr)),
+                ("else_expr", self.json_expr(else_expr)),
+                ("span", (*span).into()),
+            ]),
+            Expr::Group(expr) => Json::variant("group", [("expr", self.json_expr(expr))]),
+            Expr::Unary((op, span), expr) => Json::variant("unary", [
+                ("op", Json::unit(op)),
+                ("op_span", (*span).into()),
+                ("expr", self.json_expr(expr)),
+            ]),
+            Expr::Binary { op: (op, span), left, right } => Json::variant("binary", [
+                ("op", Json::unit(op)),
+                ("op_span", (*span).into()),
+                ("left", self.json_expr(left)),
+                ("right", self.json_expr(right)),
+            ]),
+            Expr::Cast { target, expr, span } => Json::variant("cast", [
+                ("target", (*target).into()),
+                ("expr", self.json_expr(expr)),
+                ("span", (*span).into()),
+            ]),
+        };
+        if let Json::Object(fields) = &mut json {
+            fields.insert(1, ("type".to_string(), expr.type_.into()));
+        }
+        json
+    }
+}
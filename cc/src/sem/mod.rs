@@ -14,6 +14,7 @@ mod parse;
 mod nresolve;
 mod lresolve;
 mod typecheck;
+mod json;
 
 use symtb::{
     SymbolTable,
@@ -70,7 +70,8 @@ impl Parser {
                         r_params.push(Param {
                             name: param.name,
                             data_type: param.data_type,
-                            local_id: param_id
+                            local_id: param_id,
+                            span: param.span,
                         });
                     }
 
@@ -100,6 +101,7 @@ impl Parser {
                     if body.is_some() {
                         prev.params = r_params;
                         prev.body = body;
+                        prev.span = name.1;
                     }
                 } else {
                     self.functions.insert(name.0, Function {
@@ -111,6 +113,7 @@ impl Parser {
                             _ => Linkage::External,
                         },
                         body,
+                        span: name.1,
                     });
                 }
 
@@ -168,6 +171,9 @@ impl Parser {
                                     return Err((SymError::LinkageMismatch(name.0), name.1)),
                                 _ => {}
                             }
+                            if !matches!(initializer, InitVal::None) && !matches!(prev.initializer, InitVal::Const(_)) {
+                                prev.span = name.1;
+                            }
                             match (prev.initializer, initializer) {
                                 (InitVal::None, init) => 
                                     prev.initializer = init,
@@ -186,6 +192,7 @@ impl Parser {
                                     _ => Linkage::External,
                                 },
                                 initializer,
+                                span: name.1,
                             });
                         }
 
@@ -234,9 +241,12 @@ impl Parser {
     ) -> Result<Stmt, (SymError, Span)> {
         match stmt {
             AstStmt::Nil => Ok(Stmt::Nil),
-            AstStmt::Expr(expr) => {
+            AstStmt::Expr { span, expr } => {
                 let expr = self.nresolve_expr(*expr)?;
-                Ok(Stmt::Expr(Box::new(expr)))
+                Ok(Stmt::Expr {
+                    span,
+                    expr: Box::new(expr),
+                })
             },
             AstStmt::Return { span, expr } => {
                 let expr = self.nresolve_expr(*expr)?;
@@ -245,13 +255,14 @@ impl Parser {
                     expr: Box::new(expr),
                 })
             },
-            AstStmt::If { condition, then_branch, else_branch} => {
+            AstStmt::If { span, condition, then_branch, else_branch} => {
                 let condition = self.nresolve_expr(*condition)?;
                 let then_branch = self.nresolve_stmt(*then_branch)?;
                 let else_branch = else_branch
                     .map(|stmt| self.nresolve_stmt(*stmt))
                     .transpose()?;
                 Ok(Stmt::If {
+                    span,
                     condition: Box::new(condition),
                     then_branch: Box::new(then_branch),
                     else_branch: else_branch.map(Box::new),
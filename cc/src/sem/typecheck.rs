@@ -191,11 +191,15 @@ impl<'a> TypeChecker<'a> {
                     expr: Box::new(unified_expr),
                 })
             }
-            Stmt::Expr(expr) => {
+            Stmt::Expr { span, expr } => {
                 let typed_expr = self.type_expr(*expr)?;
-                Ok(Stmt::Expr(Box::new(typed_expr)))
+                Ok(Stmt::Expr {
+                    span,
+                    expr: Box::new(typed_expr),
+                })
             },
             Stmt::If { 
+                span,
                 condition, 
                 then_branch, 
                 else_branch 
@@ -208,6 +212,7 @@ impl<'a> TypeChecker<'a> {
                     None
                 };
                 Ok(Stmt::If {
+                    span,
                     condition: Box::new(typed_condition),
                     then_branch: Box::new(typed_then),
                     else_branch: typed_else,
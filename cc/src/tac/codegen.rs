@@ -37,7 +37,13 @@ use super::{
 };
 
 impl CodeGen<Parse> {
-    pub fn parse(mut self, hir: HirTopLevel) -> (TopLevel, CodeGen<Opt>) {
+    pub fn parse(self, hir: HirTopLevel) -> (TopLevel, CodeGen<Opt>) {
+        self.parse_with(hir, false)
+    }
+
+    /// With `debug_info`, statements are preceded by `Loc`s of where they are in the source.
+    pub fn parse_with(mut self, hir: HirTopLevel, debug_info: bool) -> (TopLevel, CodeGen<Opt>) {
+        self.debug_info = debug_info;
         let mut functions = HashMap::new();
         let mut static_vars = HashMap::new();
         let strtb = hir.strtb;
@@ -49,6 +55,7 @@ impl CodeGen<Parse> {
                 data_type: var.data_type,
                 initializer: var.initializer,
                 linkage: var.linkage,
+                span: var.span,
             });
         }
 
@@ -86,8 +93,13 @@ impl CodeGen<Parse> {
                     // return 0 if it is the main function, otherwise:
                     // 1. undefined behavior, if the value is used by the caller
                     // 2. works fine, if the value is not used by the caller
-                    // hence, we insert a 'ret 0' instruction to make sure the standard is followed
-                    func_insns.push(Insn::Return(Operand::Imm(Constant::Int(0))));
+                    // hence, we insert a 'ret 0' instruction to make sure the standard is followed,
+                    // the zero being of the return type.
+                    let zero = match return_type {
+                        DataType::Long => Constant::Long(0),
+                        _ => Constant::Int(0),
+                    };
+                    func_insns.push(Insn::Return(Operand::Imm(zero)));
 
                     functions.insert(name, Function::Defined {
                         return_type,
@@ -96,6 +108,7 @@ impl CodeGen<Parse> {
                         params,
                         local_vars: self.cur_cx.take().unwrap().local_vars,
                         body: func_insns,
+                        span: function.span,
                     });
 
                     self.cur_cx = None;
@@ -110,6 +123,8 @@ impl CodeGen<Parse> {
         }, CodeGen {
             cur_cx: None,
             static_vars: self.static_vars,
+            summaries: self.summaries,
+            debug_info: self.debug_info,
             _stage: PhantomData,
         })
     }
@@ -395,6 +410,9 @@ impl CodeGen<Parse> {
         next_branch_label: &mut usize
     ) -> Vec<Insn> {
         let mut top_insns = vec![];
+        if let Some(span) = stmt_span(&stmt) {
+            self.mark(&mut top_insns, span);
+        }
         match stmt {
             HirStmt::Return { expr , ..} => {
                 let (operand, insns) = self.parse_expr(*expr, next_temp_id, next_branch_label);
@@ -408,13 +426,13 @@ impl CodeGen<Parse> {
                     };
                     top_insns.extend(insns);
             },
-            HirStmt::Expr(expr) => {
+            HirStmt::Expr { expr, .. } => {
                 let (operand, insns) = self.parse_expr(*expr, next_temp_id, next_branch_label);
                 if let Some(insns) = insns {
                     top_insns.extend(insns);
                 }
             },
-            HirStmt::If { condition, then_branch, else_branch } => {
+            HirStmt::If { condition, then_branch, else_branch, .. } => {
                 let (cond_operand, cond_insns) = self.parse_expr(*condition, next_temp_id, next_branch_label);
                 if let Some(cond_insns) = cond_insns {
                     top_insns.extend(cond_insns);
@@ -467,6 +485,7 @@ impl CodeGen<Parse> {
                 let brk_label = LabelOperand::AutoGen(AutoGenLabel::Break(loop_label));
 
                 top_insns.push(Insn::Label(con_label));
+                self.mark(&mut top_insns, span);
                 let (ctrl_operand, ctrl_insns) = self.parse_expr(*controller, next_temp_id, next_branch_label);
                 if let Some(ctrl_insns) = ctrl_insns {
                     top_insns.extend(ctrl_insns);
@@ -490,6 +509,7 @@ impl CodeGen<Parse> {
                 let body_insns = self.parse_stmt(*body, next_temp_id, next_branch_label);
                 top_insns.extend(body_insns);
                 top_insns.push(Insn::Label(con_label));
+                self.mark(&mut top_insns, span);
                 let (ctrl_operand, ctrl_insns) = self.parse_expr(*controller, next_temp_id, next_branch_label);
                 if let Some(ctrl_insns) = ctrl_insns {
                     top_insns.extend(ctrl_insns);
@@ -528,6 +548,7 @@ impl CodeGen<Parse> {
                     }
                 }
                 top_insns.push(Insn::Label(start_label));
+                self.mark(&mut top_insns, span);
                 if let Some(ctrl) = controller {
                     let (ctrl_operand, ctrl_insns) = self.parse_expr(*ctrl, next_temp_id, next_branch_label);
                     if let Some(ctrl_insns) = ctrl_insns {
@@ -541,6 +562,7 @@ impl CodeGen<Parse> {
                 let body_insns = self.parse_stmt(*body, next_temp_id, next_branch_label);
                 top_insns.extend(body_insns);
                 top_insns.push(Insn::Label(con_label));
+                self.mark(&mut top_insns, span);
                 if let Some(post) = post {
                     let (_, insns) = self.parse_expr(*post, next_temp_id, next_branch_label);
                     if let Some(insns) = insns {
@@ -566,8 +588,8 @@ impl CodeGen<Parse> {
                     name,
                     data_type,
                     local_id,
+                    span,
                     initializer,
-                    ..
                 } = local_var_decl;
 
                 let var = Operand::Var {
@@ -578,6 +600,7 @@ impl CodeGen<Parse> {
 
                 let mut insns = vec![];
                 if let Some(expr) = initializer {
+                    self.mark(&mut insns, span);
                     let (src_operand, expr_insns) = self.parse_expr(expr, next_temp_id, next_branch_label);
                     if let Some(expr_insns) = expr_insns {
                         insns.extend(expr_insns);
@@ -592,6 +615,7 @@ impl CodeGen<Parse> {
                     name,
                     local_id,
                     data_type,
+                    span: Some(span),
                 }).is_none());
 
                 insns
@@ -600,4 +624,27 @@ impl CodeGen<Parse> {
         }
 
     }
-}
\ No newline at end of file
+
+    /// Marks the instructions that follow as coming from `span`, with `-g`.
+    fn mark(&self, insns: &mut Vec<Insn>, span: Span) {
+        if self.debug_info {
+            insns.push(Insn::Loc(span));
+        }
+    }
+}
+
+/// Where the code of `stmt` starts, if it doesn't start with a label of a loop marked on its own.
+fn stmt_span(stmt: &HirStmt) -> Option<Span> {
+    match stmt {
+        HirStmt::Return { span, .. } |
+        HirStmt::Expr { span, .. } |
+        HirStmt::If { span, .. } |
+        HirStmt::Break { span, .. } |
+        HirStmt::Continue { span, .. } |
+        HirStmt::For { span, .. } => Some(*span),
+        HirStmt::While { .. } |
+        HirStmt::DoWhile { .. } |
+        HirStmt::Compound(..) |
+        HirStmt::Nil => None,
+    }
+}
@@ -72,6 +72,7 @@ impl TopLevel {
                 params,
                 local_vars,
                 body,
+                ..
             } => {
                 let params = params.iter()
                     .map(|param| format!(
@@ -119,7 +120,7 @@ impl TopLevel {
         output
     }
 
-    fn emit_insn(&self, insn: &Insn) -> String {
+    pub(super) fn emit_insn(&self, insn: &Insn) -> String {
         match insn {
             Insn::Return(val) => {
                 let val_str = self.emit_operand(val);
@@ -190,10 +191,11 @@ impl TopLevel {
                 format!("trunc\t{}, {}", self.emit_operand(dst), self.emit_operand(src)),
             Insn::SignExt { src, dst } => 
                 format!("sext\t{}, {}", self.emit_operand(dst), self.emit_operand(src)),
+            Insn::Loc(span) => format!("loc\t{}:{}", span.line, span.column),
         }
     }
 
-    fn emit_operand(&self, operand: &Operand) -> String {
+    pub(super) fn emit_operand(&self, operand: &Operand) -> String {
         match operand {
             Operand::Imm(imm) => match imm {
                 Constant::Int(val) => format!("$[i32]{}", val),
@@ -0,0 +1,579 @@
+//! JSON form of the TAC, written for every unit and parsed back for hand-written ones,
+//! so that they can be fed into the optimizer and the backends directly.
+//! Functions and static variables are sorted by name, local variables by id.
+//!
+//! Hand-written functions may leave out spans and `local_vars`:
+//! spans default to the start of the file, and the locals are those the body uses.
+//! Parsed units are checked for what semantic analysis would have guaranteed, see `validate`.
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+use crate::common::*;
+use super::{
+    Parse,
+    Opt,
+    CodeGen,
+    Operand,
+    Insn,
+    LocalVar,
+    Function,
+    StaticVar,
+    TopLevel,
+    UnaryOp,
+    BinaryOp,
+    Param,
+    LabelOperand,
+    AutoGenLabel,
+};
+
+const UNARY_OPS: [UnaryOp; 4] = [UnaryOp::Pos, UnaryOp::Negate, UnaryOp::Complement, UnaryOp::Not];
+
+const BINARY_OPS: [BinaryOp; 13] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Rem,
+    BinaryOp::Ls,
+    BinaryOp::Gt,
+    BinaryOp::GtEq,
+    BinaryOp::LsEq,
+    BinaryOp::Eq,
+    BinaryOp::NotEq,
+    BinaryOp::And,
+    BinaryOp::Or,
+];
+
+impl TopLevel {
+    pub fn to_json(&self) -> Json {
+        let mut functions = self.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|func| self.strtb.get(func.name()));
+        let mut static_vars = self.static_vars.values().collect::<Vec<_>>();
+        static_vars.sort_by_key(|var| self.strtb.get(var.name));
+
+        Json::object([
+            ("static_vars", Json::Array(static_vars.into_iter().map(|var| self.json_static_var(var)).collect())),
+            ("functions", Json::Array(functions.into_iter().map(|func| self.json_function(func)).collect())),
+        ])
+    }
+
+    fn name(&self, name: StrDescriptor) -> Json {
+        self.strtb.get(name).unwrap().into()
+    }
+
+    fn json_static_var(&self, var: &StaticVar) -> Json {
+        Json::object([
+            ("name", self.name(var.name)),
+            ("data_type", var.data_type.into()),
+            ("linkage", var.linkage.into()),
+            ("initializer", var.initializer.into()),
+            ("span", var.span.into()),
+        ])
+    }
+
+    fn json_function(&self, func: &Function) -> Json {
+        match func {
+            Function::Defined { return_type, linkage, name, params, local_vars, body, span } => {
+                let mut local_vars = local_vars.values().collect::<Vec<_>>();
+                local_vars.sort_by_key(|var| var.local_id);
+                Json::variant("defined", [
+                    ("name", self.name(*name)),
+                    ("return_type", (*return_type).into()),
+                    ("linkage", (*linkage).into()),
+                    ("params", Json::Array(params.iter()
+                        .map(|param| Json::object([
+                            ("name", self.name(param.name)),
+                            ("data_type", param.data_type.into()),
+                            ("local_id", param.local_id.into()),
+                            ("span", param.span.into()),
+                        ]))
+                        .collect())),
+                    ("local_vars", Json::Array(local_vars.into_iter()
+                        .map(|var| Json::object([
+                            ("name", self.name(var.name)),
+                            ("data_type", var.data_type.into()),
+                            ("local_id", var.local_id.into()),
+                            ("span", var.span.into()),
+                        ]))
+                        .collect())),
+                    ("body", Json::Array(body.iter().map(|insn| self.json_insn(insn)).collect())),
+                    ("span", (*span).into()),
+                ])
+            },
+            Function::Declared { linkage, name, type_ } => Json::variant("declared", [
+                ("name", self.name(*name)),
+                ("type", type_.into()),
+                ("linkage", (*linkage).into()),
+            ]),
+        }
+    }
+
+    fn json_insn(&self, insn: &Insn) -> Json {
+        match insn {
+            Insn::Return(src) => Json::variant("return", [("src", self.json_operand(src))]),
+            Insn::Unary { op, src, dst } => Json::variant("unary", [
+                ("op", Json::unit(op)),
+                ("src", self.json_operand(src)),
+                ("dst", self.json_operand(dst)),
+            ]),
+            Insn::Binary { op, left, right, dst } => Json::variant("binary", [
+                ("op", Json::unit(op)),
+                ("left", self.json_operand(left)),
+                ("right", self.json_operand(right)),
+                ("dst", self.json_operand(dst)),
+            ]),
+            Insn::Label(label) => Json::variant("label", [("label", self.json_label(label))]),
+            Insn::Jump(label) => Json::variant("jump", [("label", self.json_label(label))]),
+            Insn::BranchIfZero { src, label } => Json::variant("branch_if_zero", [
+                ("src", self.json_operand(src)),
+                ("label", self.json_label(label)),
+            ]),
+            Insn::BranchNotZero { src, label } => Json::variant("branch_not_zero", [
+                ("src", self.json_operand(src)),
+                ("label", self.json_label(label)),
+            ]),
+            Insn::FuncCall { target, args, dst } => Json::variant("func_call", [
+                ("target", self.name(*target)),
+                ("args", Json::Array(args.iter().map(|arg| self.json_operand(arg)).collect())),
+                ("dst", self.json_operand(dst)),
+            ]),
+            Insn::Move { src, dst } => Json::variant("move", [
+                ("src", self.json_operand(src)),
+                ("dst", self.json_operand(dst)),
+            ]),
+            Insn::Truncate { src, dst } => Json::variant("truncate", [
+                ("src", self.json_operand(src)),
+                ("dst", self.json_operand(dst)),
+            ]),
+            Insn::SignExt { src, dst } => Json::variant("sign_ext", [
+                ("src", self.json_operand(src)),
+                ("dst", self.json_operand(dst)),
+            ]),
+            Insn::Loc(span) => Json::variant("loc", [("span", (*span).into())]),
+        }
+    }
+
+    fn json_operand(&self, operand: &Operand) -> Json {
+        match operand {
+            Operand::Imm(constant) => Json::variant("imm", [
+                ("type", constant.data_type().into()),
+                ("value", match constant {
+                    Constant::Int(value) => (*value).into(),
+                    Constant::Long(value) => (*value).into(),
+                }),
+            ]),
+            Operand::Var { name, local_id, data_type } => Json::variant("var", [
+                ("name", self.name(*name)),
+                ("local_id", (*local_id).into()),
+                ("type", (*data_type).into()),
+            ]),
+            Operand::Temp(id, data_type) => Json::variant("temp", [
+                ("id", (*id).into()),
+                ("type", (*data_type).into()),
+            ]),
+        }
+    }
+
+    fn json_label(&self, label: &LabelOperand) -> Json {
+        match label {
+            LabelOperand::AutoGen(AutoGenLabel::Branch(id)) => Json::variant("branch", [("id", (*id).into())]),
+            LabelOperand::AutoGen(AutoGenLabel::Continue(id)) => Json::variant("continue", [("id", (*id).into())]),
+            LabelOperand::AutoGen(AutoGenLabel::Break(id)) => Json::variant("break", [("id", (*id).into())]),
+            LabelOperand::Named { name, id } => Json::variant("named", [
+                ("name", self.name(*name)),
+                ("id", (*id).into()),
+            ]),
+        }
+    }
+}
+
+impl CodeGen<Parse> {
+    /// Parses the TAC written by `TopLevel::to_json`, or by hand in the same form.
+    pub fn parse_json(mut self, text: &str) -> Result<(TopLevel, CodeGen<Opt>)> {
+        let json = Json::parse(text)?;
+        let mut strtb = StringPool::new();
+        let mut functions = HashMap::new();
+        let mut static_vars = HashMap::new();
+
+        for var in json.field("static_vars")?.as_array()? {
+            let name = read_name(var.field("name")?, &mut strtb)?;
+            static_vars.insert(name, StaticVar {
+                name,
+                data_type: var.field("data_type")?.try_into()?,
+                initializer: var.get("initializer").unwrap_or(&Json::Null).try_into()?,
+                linkage: var.field("linkage")?.try_into()?,
+                span: read_span(var)?,
+            });
+        }
+
+        for func in json.field("functions")?.as_array()? {
+            let function = self.read_function(func, &mut strtb)?;
+            let name = function.name();
+            if functions.insert(name, function).is_some() {
+                return Err(Error::Other(format!("JSON: function '{}' defined twice", strtb.get(name).unwrap())));
+            }
+        }
+
+        let top_level = TopLevel {
+            functions,
+            static_vars,
+            strtb,
+        };
+        validate(&top_level)?;
+
+        Ok((top_level, CodeGen {
+            cur_cx: None,
+            static_vars: self.static_vars,
+            summaries: self.summaries,
+            debug_info: self.debug_info,
+            _stage: PhantomData,
+        }))
+    }
+
+    fn read_function(&mut self, json: &Json, strtb: &mut StringPool) -> Result<Function> {
+        let name = read_name(json.field("name")?, strtb)?;
+        let linkage = json.field("linkage")?.try_into()?;
+        match json.kind()? {
+            "declared" => Ok(Function::Declared {
+                linkage,
+                name,
+                type_: json.field("type")?.try_into()?,
+            }),
+            "defined" => {
+                let params = json.field("params")?
+                    .as_array()?
+                    .iter()
+                    .map(|param| Ok(Param {
+                        name: read_name(param.field("name")?, strtb)?,
+                        data_type: param.field("data_type")?.try_into()?,
+                        local_id: param.field("local_id")?.as_usize()?,
+                        span: read_span(param)?,
+                    }))
+                    .collect::<Result<Vec<_>>>()?;
+                let body = json.field("body")?
+                    .as_array()?
+                    .iter()
+                    .map(|insn| self.read_insn(insn, strtb))
+                    .collect::<Result<Vec<_>>>()?;
+
+                let local_vars = match json.get("local_vars") {
+                    Some(local_vars) => local_vars.as_array()?
+                        .iter()
+                        .map(|var| {
+                            let local_id = var.field("local_id")?.as_usize()?;
+                            let span = match var.get("span") {
+                                Some(span) if !span.is_null() => Some(span.try_into()?),
+                                _ => None,
+                            };
+                            Ok((local_id, LocalVar {
+                                name: read_name(var.field("name")?, strtb)?,
+                                local_id,
+                                data_type: var.field("data_type")?.try_into()?,
+                                span,
+                            }))
+                        })
+                        .collect::<Result<HashMap<_, _>>>()?,
+                    None => locals_of(&params, &body),
+                };
+
+                Ok(Function::Defined {
+                    return_type: json.field("return_type")?.try_into()?,
+                    linkage,
+                    name,
+                    params,
+                    local_vars,
+                    body,
+                    span: read_span(json)?,
+                })
+            },
+            _ => Err(json.field("kind")?.expected("\"defined\" or \"declared\"")),
+        }
+    }
+
+    fn read_insn(&mut self, json: &Json, strtb: &mut StringPool) -> Result<Insn> {
+        let mut operand = |key: &str| self.read_operand(json.field(key)?, strtb);
+        let insn = match json.kind()? {
+            "return" => Insn::Return(operand("src")?),
+            "unary" => Insn::Unary {
+                op: json.field("op")?.to_unit(&UNARY_OPS)?,
+                src: operand("src")?,
+                dst: operand("dst")?,
+            },
+            "binary" => Insn::Binary {
+                op: json.field("op")?.to_unit(&BINARY_OPS)?,
+                left: operand("left")?,
+                right: operand("right")?,
+                dst: operand("dst")?,
+            },
+            "branch_if_zero" => Insn::BranchIfZero {
+                src: operand("src")?,
+                label: read_label(json.field("label")?, strtb)?,
+            },
+            "branch_not_zero" => Insn::BranchNotZero {
+                src: operand("src")?,
+                label: read_label(json.field("label")?, strtb)?,
+            },
+            "move" => Insn::Move { src: operand("src")?, dst: operand("dst")? },
+            "truncate" => Insn::Truncate { src: operand("src")?, dst: operand("dst")? },
+            "sign_ext" => Insn::SignExt { src: operand("src")?, dst: operand("dst")? },
+            "func_call" => Insn::FuncCall {
+                target: read_name(json.field("target")?, strtb)?,
+                args: json.field("args")?
+                    .as_array()?
+                    .iter()
+                    .map(|arg| self.read_operand(arg, strtb))
+                    .collect::<Result<_>>()?,
+                dst: self.read_operand(json.field("dst")?, strtb)?,
+            },
+            "label" => Insn::Label(read_label(json.field("label")?, strtb)?),
+            "jump" => Insn::Jump(read_label(json.field("label")?, strtb)?),
+            "loc" => Insn::Loc(json.field("span")?.try_into()?),
+            _ => return Err(json.field("kind")?.expected("an instruction")),
+        };
+        Ok(insn)
+    }
+
+    fn read_operand(&mut self, json: &Json, strtb: &mut StringPool) -> Result<Operand> {
+        let data_type = DataType::try_from(json.field("type")?)?;
+        match json.kind()? {
+            "imm" => Constant::try_from(json).map(Operand::Imm),
+            "temp" => Ok(Operand::Temp(json.field("id")?.as_usize()?, data_type)),
+            "var" => {
+                let name = read_name(json.field("name")?, strtb)?;
+                let local_id = match json.get("local_id") {
+                    Some(local_id) if !local_id.is_null() => Some(local_id.as_usize()?),
+                    _ => None,
+                };
+                if local_id.is_none() {
+                    self.static_vars.insert((name, data_type));
+                }
+                Ok(Operand::Var { name, local_id, data_type })
+            },
+            _ => Err(json.field("kind")?.expected("\"imm\", \"var\" or \"temp\"")),
+        }
+    }
+}
+
+fn read_name(json: &Json, strtb: &mut StringPool) -> Result<StrDescriptor> {
+    Ok(strtb.intern(json.as_str()?.to_string()))
+}
+
+fn read_span(json: &Json) -> Result<Span> {
+    match json.get("span") {
+        Some(span) if !span.is_null() => span.try_into(),
+        _ => Ok(Span::new(1, 1)),
+    }
+}
+
+fn read_label(json: &Json, strtb: &mut StringPool) -> Result<LabelOperand> {
+    let id = json.field("id")?.as_usize()?;
+    match json.kind()? {
+        "branch" => Ok(LabelOperand::AutoGen(AutoGenLabel::Branch(id))),
+        "continue" => Ok(LabelOperand::AutoGen(AutoGenLabel::Continue(id))),
+        "break" => Ok(LabelOperand::AutoGen(AutoGenLabel::Break(id))),
+        "named" => Ok(LabelOperand::Named { name: read_name(json.field("name")?, strtb)?, id }),
+        _ => Err(json.field("kind")?.expected("a label")),
+    }
+}
+
+/// The local variables of a function written without `local_vars`, those `body` uses besides `params`.
+fn locals_of(params: &[Param], body: &[Insn]) -> HashMap<usize, LocalVar> {
+    let params = params.iter().map(|param| param.local_id).collect::<HashSet<_>>();
+    let mut local_vars = HashMap::new();
+    for operand in body.iter().flat_map(Insn::operands) {
+        match *operand {
+            Operand::Var { name, local_id: Some(local_id), data_type } if !params.contains(&local_id) => {
+                local_vars.entry(local_id).or_insert(LocalVar { name, local_id, data_type, span: None });
+            },
+            _ => {},
+        }
+    }
+    local_vars
+}
+
+/// Rejects what semantic analysis rules out for TAC generated from C and the later stages count on,
+/// so that a mistake in a hand-written unit is reported here instead of panicking in them.
+fn validate(top_level: &TopLevel) -> Result<()> {
+    let strtb = &top_level.strtb;
+    for func in top_level.functions.values() {
+        let Function::Defined { return_type, name, params, local_vars, body, .. } = func else {
+            continue;
+        };
+        let error = |msg: String| Error::Other(format!("JSON: function '{}': {}", strtb.get(*name).unwrap(), msg));
+        let label_name = |label: &LabelOperand| match label {
+            LabelOperand::AutoGen(label) => format!("{:?}", label),
+            LabelOperand::Named { name, .. } => format!("'{}'", strtb.get(*name).unwrap()),
+        };
+
+        // jumps stay inside the body, so every path ends in a return when the body does
+        match body.iter().rev().find(|insn| !matches!(insn, Insn::Loc(..))) {
+            None => return Err(error("the body is empty".into())),
+            Some(Insn::Return(..) | Insn::Jump(..)) => {},
+            Some(_) => return Err(error("the body doesn't end in a return".into())),
+        }
+
+        let mut labels = HashSet::new();
+        for insn in body {
+            match insn {
+                Insn::Label(label) if !labels.insert(label) =>
+                    return Err(error(format!("label {} defined twice", label_name(label)))),
+                _ => {},
+            }
+        }
+
+        for insn in body {
+            match insn {
+                Insn::Jump(label) | Insn::BranchIfZero { label, .. } | Insn::BranchNotZero { label, .. } if !labels.contains(label) =>
+                    return Err(error(format!("label {} is not defined", label_name(label)))),
+                _ => {},
+            }
+
+            for operand in insn.operands() {
+                match *operand {
+                    _ if operand.data_type() == DataType::Void =>
+                        return Err(error(format!("operand {:?} is of type void", operand))),
+                    Operand::Var { name, local_id: None, .. } if !top_level.static_vars.contains_key(&name) =>
+                        return Err(error(format!("static variable '{}' is not declared", strtb.get(name).unwrap()))),
+                    Operand::Var { name, local_id: Some(local_id), .. }
+                        if !local_vars.contains_key(&local_id) && !params.iter().any(|param| param.local_id == local_id) =>
+                        return Err(error(format!("'{}' refers to local {}, which is neither a parameter nor a local variable",
+                            strtb.get(name).unwrap(), local_id))),
+                    _ => {},
+                }
+            }
+
+            let dst = match insn {
+                Insn::Unary { dst, .. }
+                | Insn::Binary { dst, .. }
+                | Insn::Move { dst, .. }
+                | Insn::Truncate { dst, .. }
+                | Insn::SignExt { dst, .. }
+                | Insn::FuncCall { dst, .. } => Some(dst),
+                _ => None,
+            };
+            if let Some(Operand::Imm(..)) = dst {
+                return Err(error(format!("{:?} assigns to an immediate", insn)));
+            }
+
+            // the types sccp, constant folding and the backends count on
+            let types_agree = match insn {
+                Insn::Return(src) => src.data_type() == *return_type,
+                Insn::Unary { op: UnaryOp::Not, dst, .. } => dst.data_type() == DataType::Int,
+                Insn::Unary { src, dst, .. } | Insn::Move { src, dst } => src.data_type() == dst.data_type(),
+                Insn::Binary { op, left, right, dst } => left.data_type() == right.data_type() && dst.data_type() == match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => left.data_type(),
+                    _ => DataType::Int,
+                },
+                Insn::Truncate { src, dst } => src.data_type() == DataType::Long && dst.data_type() == DataType::Int,
+                Insn::SignExt { src, dst } => src.data_type() == DataType::Int && dst.data_type() == DataType::Long,
+                Insn::FuncCall { target, args, dst } => {
+                    let Some(callee) = top_level.functions.get(target) else {
+                        return Err(error(format!("function '{}' is not declared", strtb.get(*target).unwrap())));
+                    };
+                    let type_ = callee.type_();
+                    type_.return_type == dst.data_type()
+                        && type_.param_types.iter().copied().eq(args.iter().map(|arg| arg.data_type()))
+                },
+                Insn::Label(..) | Insn::Jump(..) | Insn::BranchIfZero { .. } | Insn::BranchNotZero { .. } | Insn::Loc(..) => true,
+            };
+            if !types_agree {
+                return Err(error(format!("operand types of {:?} don't agree", insn)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::driver::{compile, compile_json, Ir, Options};
+
+    #[test]
+    fn test_roundtrip() {
+        let source = "static long g = 3; int add(int a, long b); \
+            int main(void) { int s = 0; for (int i = 0; i < 5; i = i + 1) s = s + i * (int) g; return add(s, 2L); }";
+        let options = Options { opt_level: 1, ..Options::default() };
+        let tac = compile_json(source.to_string(), &options, Ir::Tac).unwrap();
+
+        let options = Options { tac_input: true, ..Options::default() };
+        assert_eq!(compile_json(tac.clone(), &options, Ir::Tac).unwrap(), tac);
+        assert!(compile_json(tac, &options, Ir::Hir).is_err());
+
+        // at -O0 the implicit return after the last statement is still there, a long zero in a long function
+        let source = "long f(long x) { return x + 1l; } int main(void) { return (int) f(41l); }";
+        let tac = compile_json(source.to_string(), &Options::default(), Ir::Tac).unwrap();
+        assert!(tac.contains(r#""src": {"kind": "imm", "type": "long", "value": 0}"#), "{}", tac);
+        assert_eq!(compile_json(tac.clone(), &options, Ir::Tac).unwrap(), tac);
+    }
+
+    #[test]
+    fn test_hand_written() {
+        let var = r#"{"kind": "var", "name": "x", "local_id": 0, "type": "int"}"#;
+        let tac = format!(r#"{{"static_vars": [], "functions": [{{
+            "kind": "defined", "name": "main", "return_type": "int", "linkage": "external", "params": [],
+            "body": [
+                {{"kind": "move", "src": {{"kind": "imm", "type": "int", "value": 6}}, "dst": {var}}},
+                {{"kind": "binary", "op": "mul", "left": {var}, "right": {{"kind": "imm", "type": "int", "value": 7}},
+                  "dst": {{"kind": "temp", "id": 0, "type": "int"}}}},
+                {{"kind": "return", "src": {{"kind": "temp", "id": 0, "type": "int"}}}}
+            ]
+        }}]}}"#);
+        let options = Options { opt_level: 1, tac_input: true, ..Options::default() };
+        assert!(compile(tac.clone(), &options).unwrap().contains("li\ta0, 42\n"));
+
+        let bad = tac.replace("\"mul\"", "\"pow\"");
+        let err = compile(bad, &options).unwrap_err().to_string();
+        assert!(err.contains("expected one of"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid() {
+        // `main` with the local `x`, each case breaks it in one way
+        let unit = |body: &str| format!(r#"{{"static_vars": [], "functions": [{{
+            "kind": "defined", "name": "main", "return_type": "int", "linkage": "external", "params": [],
+            "local_vars": [{{"name": "x", "data_type": "int", "local_id": 0}}],
+            "body": [{body}]
+        }}]}}"#);
+        let ret = r#"{"kind": "return", "src": {"kind": "imm", "type": "int", "value": 0}}"#;
+        let x = r#"{"kind": "var", "name": "x", "local_id": 0, "type": "int"}"#;
+        let cases = [
+            (format!(r#"{{"kind": "jump", "label": {{"kind": "branch", "id": 1}}}}, {ret}"#),
+                "label Branch(1) is not defined"),
+            (format!(r#"{{"kind": "label", "label": {{"kind": "branch", "id": 1}}}},
+                {{"kind": "label", "label": {{"kind": "branch", "id": 1}}}}, {ret}"#),
+                "label Branch(1) defined twice"),
+            (format!(r#"{{"kind": "move", "src": {x}, "dst": {{"kind": "var", "name": "g", "local_id": null, "type": "int"}}}}, {ret}"#),
+                "static variable 'g' is not declared"),
+            (format!(r#"{{"kind": "func_call", "target": "f", "args": [], "dst": {x}}}, {ret}"#),
+                "function 'f' is not declared"),
+            (format!(r#"{{"kind": "binary", "op": "add", "left": {x}, "right": {{"kind": "imm", "type": "long", "value": 1}},
+                "dst": {x}}}, {ret}"#),
+                "don't agree"),
+            (format!(r#"{{"kind": "move", "src": {x}, "dst": {{"kind": "imm", "type": "int", "value": 1}}}}, {ret}"#),
+                "assigns to an immediate"),
+            (format!(r#"{{"kind": "move", "src": {x}, "dst": {{"kind": "temp", "id": 0, "type": "void"}}}}, {ret}"#),
+                "is of type void"),
+            ("".to_string(), "the body is empty"),
+            (format!(r#"{{"kind": "move", "src": {x}, "dst": {{"kind": "var", "name": "y", "local_id": 1, "type": "int"}}}}, {ret}"#),
+                "neither a parameter nor a local variable"),
+            (format!(r#"{ret}, {{"kind": "label", "label": {{"kind": "branch", "id": 1}}}}"#),
+                "doesn't end in a return"),
+        ];
+
+        let options = Options { opt_level: 1, tac_input: true, ..Options::default() };
+        assert!(compile(unit(ret), &options).is_ok());
+        for (body, msg) in cases {
+            let err = compile(unit(&body), &options).unwrap_err().to_string();
+            assert!(err.contains(msg), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_nesting() {
+        let options = Options { tac_input: true, ..Options::default() };
+        let err = compile("[".repeat(100_000), &options).unwrap_err().to_string();
+        assert!(err.contains("nested deeper than"), "{}", err);
+        assert!(crate::common::Json::parse(&format!("{}{}", "[".repeat(256), "]".repeat(256))).is_ok());
+    }
+}
@@ -7,6 +7,7 @@ mod codegen;
 mod opt;
 mod emit;
 mod tac;
+mod json;
 
 pub use tac::{
     Operand as TacOperand,
@@ -22,6 +23,12 @@ pub use tac::{
     AutoGenLabel as TacAutoGenLabel,
 };
 pub use CodeGen as TacCodeGen;
+pub use opt::{
+    Graph as TacGraph,
+    Node as TacNode,
+    NodeId as TacNodeId,
+    temp_reads,
+};
 
 use crate::common::*;
 use tac::{
@@ -37,6 +44,7 @@ use tac::{
     LabelOperand,
     AutoGenLabel,
 };
+use opt::Summaries;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Parse;
@@ -54,6 +62,10 @@ pub struct CodeGen<Stage = Parse> {
     pub cur_cx: Option<FuncContext>,
 
     pub static_vars: HashSet<(StrDescriptor, DataType)>,
+    /// filled in by the interprocedural analysis
+    pub summaries: Summaries,
+    /// `-g`, whether statements are marked with their source locations
+    pub debug_info: bool,
     _stage: PhantomData<Stage>,
 }
 
@@ -62,6 +74,8 @@ impl CodeGen {
         Self {
             cur_cx: None,
             static_vars: HashSet::new(),
+            summaries: Summaries::default(),
+            debug_info: false,
             _stage: PhantomData,
         }
     }
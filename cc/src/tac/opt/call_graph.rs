@@ -0,0 +1,99 @@
+//! Call graph over the functions of a translation unit.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::common::*;
+use super::{
+    Insn,
+    Function,
+};
+
+#[derive(Debug)]
+pub struct CallGraph {
+    /// caller -> callees, one entry per call site
+    pub callees: HashMap<StrDescriptor, Vec<StrDescriptor>>,
+}
+
+impl CallGraph {
+    pub fn build(functions: &HashMap<StrDescriptor, Function>) -> Self {
+        let callees = functions.iter()
+            .map(|(&name, func)| {
+                let callees = match func {
+                    Function::Declared { .. } => vec![],
+                    Function::Defined { body, .. } => body.iter()
+                        .filter_map(|insn| match insn {
+                            Insn::FuncCall { target, .. } => Some(*target),
+                            _ => None,
+                        })
+                        .collect(),
+                };
+                (name, callees)
+            })
+            .collect();
+
+        CallGraph { callees }
+    }
+
+    /// Functions sorted by name, so that results do not depend on hash order.
+    pub fn functions(&self) -> Vec<StrDescriptor> {
+        let mut functions = self.callees.keys().cloned().collect::<Vec<_>>();
+        functions.sort_by_key(|name| name.index());
+        functions
+    }
+
+    pub fn callees_of(&self, caller: StrDescriptor) -> &[StrDescriptor] {
+        self.callees.get(&caller).map(|callees| callees.as_slice()).unwrap_or(&[])
+    }
+
+    /// Number of call sites of `callee` in the whole translation unit.
+    pub fn call_sites(&self, callee: StrDescriptor) -> usize {
+        self.callees.values()
+            .map(|callees| callees.iter().filter(|&&name| name == callee).count())
+            .sum()
+    }
+
+    /// Functions transitively called by `func`, not including itself unless
+    /// it is recursive.
+    pub fn reachable_from(&self, func: StrDescriptor) -> HashSet<StrDescriptor> {
+        let mut visited = HashSet::new();
+        let mut stack = self.callees_of(func).to_vec();
+        while let Some(name) = stack.pop() {
+            if visited.insert(name) {
+                stack.extend(self.callees_of(name).iter().cloned());
+            }
+        }
+        visited
+    }
+
+    pub fn is_recursive(&self, func: StrDescriptor) -> bool {
+        self.reachable_from(func).contains(&func)
+    }
+
+    /// All functions with callees before their callers, as far as recursion allows.
+    pub fn postorder(&self) -> Vec<StrDescriptor> {
+        let mut visited = HashSet::new();
+        let mut order = vec![];
+
+        for root in self.functions() {
+            // (function, whether its callees have been pushed)
+            let mut stack = vec![(root, false)];
+            while let Some((name, expanded)) = stack.pop() {
+                if expanded {
+                    order.push(name);
+                    continue;
+                }
+                if !visited.insert(name) {
+                    continue;
+                }
+                stack.push((name, true));
+                for &callee in self.callees_of(name).iter().rev() {
+                    if !visited.contains(&callee) {
+                        stack.push((callee, false));
+                    }
+                }
+            }
+        }
+
+        order
+    }
+}
@@ -257,4 +257,180 @@ impl Graph {
 
         insns
     }
-}
\ No newline at end of file
+}
+
+static EMPTY: BTreeSet<NodeId> = BTreeSet::new();
+
+impl Graph {
+    pub fn successors(&self, id: NodeId) -> &BTreeSet<NodeId> {
+        match self.nodes.get(&id).expect("Internal error: Node not found in graph") {
+            Node::Entry { successors } |
+            Node::BasicBlock(BasicBlock { successors, .. }) => successors,
+            Node::Exit { .. } => &EMPTY,
+        }
+    }
+
+    pub fn predecessors(&self, id: NodeId) -> &BTreeSet<NodeId> {
+        match self.nodes.get(&id).expect("Internal error: Node not found in graph") {
+            Node::Exit { predecessors } |
+            Node::BasicBlock(BasicBlock { predecessors, .. }) => predecessors,
+            Node::Entry { .. } => &EMPTY,
+        }
+    }
+
+    /// Nodes reachable from the entry, in reverse postorder.
+    pub fn reverse_postorder(&self) -> Vec<NodeId> {
+        let mut visited = HashSet::new();
+        let mut postorder = vec![];
+        // (node, whether its successors have been pushed)
+        let mut stack = vec![(NodeId::Entry, false)];
+
+        while let Some((id, expanded)) = stack.pop() {
+            if expanded {
+                postorder.push(id);
+                continue;
+            }
+            if !visited.insert(id) {
+                continue;
+            }
+            stack.push((id, true));
+            for succ in self.successors(id).iter().rev() {
+                if !visited.contains(succ) {
+                    stack.push((*succ, false));
+                }
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+
+    /// Immediate dominators of all reachable nodes, computed with the
+    /// iterative algorithm of Cooper, Harvey and Kennedy.
+    /// The entry node is mapped to itself.
+    pub fn immediate_dominators(&self) -> HashMap<NodeId, NodeId> {
+        let rpo = self.reverse_postorder();
+        let order = rpo.iter()
+            .enumerate()
+            .map(|(idx, id)| (*id, idx))
+            .collect::<HashMap<_, _>>();
+
+        let mut idoms = HashMap::new();
+        idoms.insert(NodeId::Entry, NodeId::Entry);
+
+        let intersect = |idoms: &HashMap<NodeId, NodeId>, mut a: NodeId, mut b: NodeId| {
+            while a != b {
+                while order[&a] > order[&b] {
+                    a = idoms[&a];
+                }
+                while order[&b] > order[&a] {
+                    b = idoms[&b];
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &id in rpo.iter().skip(1) {
+                let new_idom = self.predecessors(id).iter()
+                    .filter(|pred| idoms.contains_key(*pred))
+                    .cloned()
+                    .reduce(|a, b| intersect(&idoms, a, b))
+                    .expect("Internal error: Reachable node without processed predecessor");
+                if idoms.get(&id) != Some(&new_idom) {
+                    idoms.insert(id, new_idom);
+                    changed = true;
+                }
+            }
+        }
+
+        idoms
+    }
+
+    /// Children of every reachable node in the dominator tree.
+    pub fn dominator_tree(&self) -> BTreeMap<NodeId, Vec<NodeId>> {
+        let mut tree: BTreeMap<NodeId, Vec<NodeId>> = BTreeMap::new();
+
+        for (id, idom) in self.immediate_dominators() {
+            tree.entry(id).or_default();
+            if id != idom {
+                tree.entry(idom).or_default().push(id);
+            }
+        }
+        for children in tree.values_mut() {
+            children.sort();
+        }
+
+        tree
+    }
+
+    /// Whether `a` dominates `b`, given the immediate dominators.
+    pub fn dominates(idoms: &HashMap<NodeId, NodeId>, a: NodeId, mut b: NodeId) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match idoms.get(&b) {
+                Some(&idom) if idom != b => b = idom,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Insn {
+    /// Every operand of the instruction, the one it assigns to last.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Insn::Return(src) |
+            Insn::BranchIfZero { src, .. } |
+            Insn::BranchNotZero { src, .. } => vec![src],
+            Insn::Unary { src, dst, .. } |
+            Insn::Move { src, dst } |
+            Insn::Truncate { src, dst } |
+            Insn::SignExt { src, dst } => vec![src, dst],
+            Insn::Binary { left, right, dst, .. } => vec![left, right, dst],
+            Insn::FuncCall { args, dst, .. } => args.iter().chain([dst]).collect(),
+            Insn::Label(..) | Insn::Jump(..) | Insn::Loc(..) => vec![],
+        }
+    }
+}
+
+/// The operand `insn` assigns to, if any.
+pub(super) fn def_of(insn: &Insn) -> Option<Operand> {
+    match insn {
+        Insn::Move { dst, .. } |
+        Insn::SignExt { dst, .. } |
+        Insn::Truncate { dst, .. } |
+        Insn::Unary { dst, .. } |
+        Insn::Binary { dst, .. } |
+        Insn::FuncCall { dst, .. } => Some(*dst),
+        _ => None,
+    }
+}
+
+/// The operands `insn` reads, immediates left out.
+pub(super) fn uses_of(insn: &Insn) -> Vec<Operand> {
+    let mut operands = insn.operands();
+    if def_of(insn).is_some() {
+        operands.pop();
+    }
+    operands.into_iter()
+        .filter(|operand| !matches!(operand, Operand::Imm(_)))
+        .copied()
+        .collect()
+}
+
+/// Number of times each temporary is read in `body`, for the instruction selectors
+/// to fuse an instruction into the one reading its result.
+pub fn temp_reads(body: &[Insn]) -> HashMap<usize, usize> {
+    let mut reads = HashMap::new();
+    for src in body.iter().flat_map(uses_of) {
+        if let Operand::Temp(id, _) = src {
+            *reads.entry(id).or_insert(0) += 1;
+        }
+    }
+    reads
+}
@@ -22,6 +22,7 @@ impl CodeGen<Opt> {
                 params,
                 local_vars,
                 body,
+                span,
             } => {
                 let mut opted_body = vec![];
                 
@@ -32,7 +33,11 @@ impl CodeGen<Opt> {
                             src, 
                             dst 
                         } => match (op, src, dst) {
-                            (UnaryOp::Pos, ..) => None,
+                            (UnaryOp::Pos, src, dst) =>
+                                Some(Insn::Move {
+                                    src,
+                                    dst,
+                                }),
                             (UnaryOp::Negate, Operand::Imm(constant), dst) =>
                                 Some(Insn::Move {
                                     src: Operand::Imm(constant.neg()),
@@ -174,6 +179,7 @@ impl CodeGen<Opt> {
                     params,
                     local_vars,
                     body: opted_body,
+                    span,
                 }
             }
         }
@@ -12,6 +12,7 @@ use super::{
     LabelOperand,
     Opt,
     CodeGen,
+    Summaries,
 };
 use super::cfg::{
     Node,
@@ -96,6 +97,7 @@ impl ReachingCopies {
 
 struct CopyPropagation<'a> {
     cfg: &'a Graph,
+    summaries: &'a Summaries,
     block_defs: HashMap<usize, ReachingCopies>,
     insn_defs: HashMap<InsnId, ReachingCopies>,
 
@@ -111,7 +113,7 @@ struct AnalysisResult {
 }
 
 impl<'a> CopyPropagation<'a> {
-    fn new(cfg: &'a Graph) -> Self {
+    fn new(cfg: &'a Graph, summaries: &'a Summaries) -> Self {
         fn initial_copies(cfg: &Graph) -> ReachingCopies {
             cfg.nodes
                 .values()
@@ -144,6 +146,7 @@ impl<'a> CopyPropagation<'a> {
 
         CopyPropagation {
             cfg,
+            summaries,
             block_defs: HashMap::new(),
             insn_defs: HashMap::new(),
             initial_copies,
@@ -207,14 +210,15 @@ impl<'a> CopyPropagation<'a> {
 
                     current.add(*dst, *src);
                 },
-                Insn::FuncCall { dst, .. } => {  
+                Insn::FuncCall { target, dst, .. } => {  
                     // not only do we need to remove conflicting copies,
-                    // but we also need to remove those that are related to static variables,
-                    // cz we don't know if the function will modify them, so we choose a 
-                    // conservative approach.
+                    // but we also need to remove those that are related to static variables
+                    // the function may modify.
                     let mut to_remove = vec![];
                     for (d, s) in current.iter() {
-                        if *dst == d || *dst == s || d.is_static() || s.is_static() {
+                        if *dst == d || *dst == s
+                            || self.summaries.may_modify(*target, d)
+                            || self.summaries.may_modify(*target, s) {
                             to_remove.push((d, s));
                         }
                     }
@@ -352,10 +356,11 @@ impl CodeGen<Opt> {
                 params,
                 local_vars,
                 body,
+                span,
             } => {
                 let cfg = Graph::build(body);
 
-                let analysis = CopyPropagation::new(&cfg);
+                let analysis = CopyPropagation::new(&cfg, &self.summaries);
                 let result = analysis.analyze();
 
                 let opted_cfg = rewrite_graph(cfg, &result.block_defs, &result.insn_defs);
@@ -369,6 +374,7 @@ impl CodeGen<Opt> {
                     body: opted_body,
                     linkage,
                     local_vars,
+                    span,
                 }
             }
         }
@@ -30,6 +30,7 @@ impl CodeGen<Opt> {
                 params,
                 local_vars,
                 body,
+                span,
             } => {
                 let mut cfg = Graph::build(body);
 
@@ -46,6 +47,7 @@ impl CodeGen<Opt> {
                     body: opted_body,
                     linkage,
                     local_vars,
+                    span,
                 }
             }
         }
@@ -13,6 +13,7 @@ use super::{
     LabelOperand,
     Opt,
     CodeGen,
+    Summaries,
 };
 use super::cfg::{
     Node,
@@ -74,6 +75,7 @@ struct LiveAnalysis<'a> {
     block_info: HashMap<usize, LiveVars>,
     insn_info: HashMap<InsnId, LiveVars>,
     static_vars: LiveVars,
+    summaries: &'a Summaries,
 }
 
 #[derive(Debug)]
@@ -83,12 +85,13 @@ struct AnalysisResult {
 }
 
 impl<'a> LiveAnalysis<'a> {
-    fn new(cfg: &'a Graph, static_vars: LiveVars) -> Self {
+    fn new(cfg: &'a Graph, static_vars: LiveVars, summaries: &'a Summaries) -> Self {
         LiveAnalysis {
             cfg,
             block_info: HashMap::new(),
             insn_info: HashMap::new(),
             static_vars,
+            summaries,
         }
     }
 
@@ -155,10 +158,9 @@ impl<'a> LiveAnalysis<'a> {
                         current.inner.insert(var);
                     });
                 },
-                Insn::FuncCall { dst, args, .. } => {
-                    // cz we don't know whether the function reads static variables,
-                    // we take a conservative approach - add all static variables
-                    // to the live set.
+                Insn::FuncCall { target, dst, args } => {
+                    // the static variables the callee may read are live,
+                    // which is all of them if we know nothing about the callee.
                     (*dst).try_into().map(|var| {
                         current.inner.remove(&var);
                     });
@@ -168,7 +170,11 @@ impl<'a> LiveAnalysis<'a> {
                         });
                     }
                     
-                    current.inner.extend(self.static_vars.inner.iter().cloned());
+                    current.inner.extend(
+                        self.static_vars.inner.iter()
+                            .filter(|&&var| self.summaries.may_read(*target, var.into()))
+                            .cloned()
+                    );
                 }
                 Insn::BranchIfZero { src, .. } |
                 Insn::BranchNotZero { src, .. } |
@@ -176,7 +182,6 @@ impl<'a> LiveAnalysis<'a> {
                     (*src).try_into().map(|var| {
                         current.inner.insert(var);
                     });
-                    println!("Live variable before {:?}: {:?}", insn, current.inner);
                 },
                 _ => {
                     // other instructions do not affect live variables
@@ -275,6 +280,53 @@ impl<'a> LiveAnalysis<'a> {
     }
 }
 
+/// Variables live into and out of each block of `cfg`, sorted for display.
+pub(super) fn block_liveness(
+    cfg: &Graph,
+    static_vars: &HashSet<(StrDescriptor, DataType)>,
+    summaries: &Summaries,
+) -> HashMap<usize, (Vec<Operand>, Vec<Operand>)> {
+    let static_vars = static_vars.iter()
+        .map(|&(name, data_type)| GeneralVar::Var {
+            name,
+            local_id: None,
+            data_type,
+        })
+        .collect::<HashSet<_>>();
+    let analysis = LiveAnalysis::new(cfg, LiveVars { inner: static_vars }, summaries);
+    let AnalysisResult {
+        block_info,
+        insn_info,
+    } = analysis.analyze();
+
+    let sorted = |live_vars: &LiveVars| {
+        let mut vars = live_vars.inner.iter()
+            .map(|&var| Operand::from(var))
+            .collect::<Vec<_>>();
+        vars.sort_by_key(|operand| match *operand {
+            Operand::Var { name, local_id, .. } => (0, name.index(), local_id.unwrap_or(0)),
+            Operand::Temp(id, _) => (1, id, 0),
+            Operand::Imm(..) => unreachable!(),
+        });
+        vars
+    };
+
+    cfg.nodes.values()
+        .filter_map(|node| match node {
+            Node::BasicBlock(block) => Some(block),
+            _ => None,
+        })
+        .map(|block| {
+            let live_in = &block_info[&block.id];
+            // what is live after the last instruction, or through an empty block
+            let live_out = block.insns.len().checked_sub(1)
+                .and_then(|last| insn_info.get(&InsnId::new(block.id, last)))
+                .unwrap_or(live_in);
+            (block.id, (sorted(live_in), sorted(live_out)))
+        })
+        .collect()
+}
+
 impl CodeGen<Opt> {
     pub fn deadstore_elimination(&mut self, func: Function) -> Function {
         match func {
@@ -286,6 +338,7 @@ impl CodeGen<Opt> {
                 params,
                 local_vars,
                 body,
+                span,
             } => {
                 let cfg = Graph::build(body);
 
@@ -298,13 +351,13 @@ impl CodeGen<Opt> {
                     .collect::<HashSet<_>>();
                 let static_vars = LiveVars { inner: static_vars };
 
-                let analysis = LiveAnalysis::new(&cfg, static_vars);
+                let analysis = LiveAnalysis::new(&cfg, static_vars, &self.summaries);
                 let AnalysisResult {
                     block_info,
                     insn_info,
                 } = analysis.analyze();
 
-                let opted_cfg = rewrite_graph(cfg, &block_info, &insn_info);
+                let opted_cfg = rewrite_graph(cfg, &block_info, &insn_info, &self.summaries);
 
                 let opted_body = opted_cfg.emit();
 
@@ -315,6 +368,7 @@ impl CodeGen<Opt> {
                     body: opted_body,
                     linkage,
                     local_vars,
+                    span,
                 }
             },
         }
@@ -325,12 +379,13 @@ fn rewrite_graph(
     cfg: Graph,
     block_infos: &HashMap<usize, LiveVars>,
     insn_infos: &HashMap<InsnId, LiveVars>,
+    summaries: &Summaries,
 ) -> Graph {
     let mut cfg = cfg;
 
     cfg.nodes = cfg.nodes.into_iter()
         .map(|(id, node)| 
-            (id, rewrite_node(node, block_infos, insn_infos)))
+            (id, rewrite_node(node, block_infos, insn_infos, summaries)))
         .collect();
 
     cfg
@@ -340,6 +395,7 @@ fn rewrite_node(
     node: Node,
     block_infos: &HashMap<usize, LiveVars>,
     insn_infos: &HashMap<InsnId, LiveVars>,
+    summaries: &Summaries,
 ) -> Node {
     match node {
         Node::Entry {..} | Node::Exit {..} => node,
@@ -349,6 +405,7 @@ fn rewrite_node(
                 block.insns,
                 block_infos,
                 insn_infos,
+                summaries,
             );
             Node::BasicBlock(block)
         }
@@ -360,6 +417,7 @@ fn rewrite_insns(
     insns: Vec<Insn>,
     block_infos: &HashMap<usize, LiveVars>,
     insn_infos: &HashMap<InsnId, LiveVars>,
+    summaries: &Summaries,
 ) -> Vec<Insn> {
     insns.into_iter()
         .enumerate()
@@ -367,7 +425,7 @@ fn rewrite_insns(
             let insn_id = InsnId::new(block_id, inblocl_id);
             let insn_info = insn_infos.get(&insn_id)
                 .expect("Internal error: InsnId not found in insn_infos");
-            rewrite_insn(insn, insn_info)
+            rewrite_insn(insn, insn_info, summaries)
         })
         .collect()
 }
@@ -375,8 +433,16 @@ fn rewrite_insns(
 fn rewrite_insn(
     insn: Insn,
     insn_info: &LiveVars,
+    summaries: &Summaries,
 ) -> Option<Insn> {
     match insn {
+        // a pure call is only worth its result
+        Insn::FuncCall { target, dst, .. } if summaries.is_pure(target) => {
+            match dst.try_into() {
+                Ok(var) if !insn_info.inner.contains(&var) => None,
+                _ => Some(insn),
+            }
+        },
         f@Insn::FuncCall {..} => Some(f),
         Insn::Unary { dst, .. } |
         Insn::Binary { dst, .. } |
@@ -0,0 +1,103 @@
+//! Graphviz export of the CFG of each function, for `--dump-cfg=dot`.
+//! Every block lists its instructions between the variables live into and out of it,
+//! solid edges are control flow and dashed ones the dominator tree.
+
+use std::collections::HashSet;
+
+use crate::common::*;
+use super::{
+    TopLevel,
+    Function,
+    Operand,
+};
+use super::cfg::{
+    Graph,
+    Node,
+    NodeId,
+};
+use super::interprocedural::Summaries;
+use super::deadstore_elimination::block_liveness;
+
+impl TopLevel {
+    /// The CFG of every defined function as a `digraph`, along with the function's name.
+    pub fn dump_cfg(&self) -> Vec<(StrDescriptor, String)> {
+        let static_vars = self.static_vars.values()
+            .map(|var| (var.name, var.data_type))
+            .collect::<HashSet<_>>();
+        let summaries = Summaries::build(self);
+
+        let mut dumps = self.functions.values()
+            .filter_map(|func| match func {
+                Function::Defined { name, body, .. } => {
+                    let cfg = Graph::build(body.clone());
+                    Some((*name, self.cfg_dot(*name, &cfg, &static_vars, &summaries)))
+                },
+                Function::Declared { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        dumps.sort_by_key(|(name, _)| self.strtb.get(*name).cloned());
+        dumps
+    }
+
+    fn cfg_dot(
+        &self,
+        name: StrDescriptor,
+        cfg: &Graph,
+        static_vars: &HashSet<(StrDescriptor, DataType)>,
+        summaries: &Summaries,
+    ) -> String {
+        let liveness = block_liveness(cfg, static_vars, summaries);
+        let vars = |operands: &[_]| self.emit_operands(operands);
+
+        let mut output = format!("digraph \"{}.tac\" {{\n", self.strtb.get(name).unwrap());
+        output.push_str("\tnode [shape=box, fontname=\"monospace\"];\n");
+        for (&id, node) in cfg.nodes.iter() {
+            let label = match node {
+                Node::Entry { .. } => "entry\\l".to_string(),
+                Node::Exit { .. } => "exit\\l".to_string(),
+                Node::BasicBlock(block) => {
+                    let (live_in, live_out) = &liveness[&block.id];
+                    let insns = block.insns.iter()
+                        .map(|insn| format!("  {}", self.emit_insn(insn)));
+                    dot_label(
+                        std::iter::once(format!("live-in: {}", vars(live_in)))
+                            .chain(insns)
+                            .chain(std::iter::once(format!("live-out: {}", vars(live_out))))
+                    )
+                },
+            };
+            output.push_str(&format!("\t{} [label=\"{}\"];\n", node_name(id), label));
+        }
+        for &id in cfg.nodes.keys() {
+            for &succ in cfg.successors(id) {
+                output.push_str(&format!("\t{} -> {};\n", node_name(id), node_name(succ)));
+            }
+        }
+        for (id, children) in cfg.dominator_tree() {
+            for child in children {
+                output.push_str(&format!(
+                    "\t{} -> {} [style=dashed, color=gray, constraint=false];\n",
+                    node_name(id),
+                    node_name(child),
+                ));
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    fn emit_operands(&self, operands: &[Operand]) -> String {
+        operands.iter()
+            .map(|operand| self.emit_operand(operand))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn node_name(id: NodeId) -> String {
+    match id {
+        NodeId::Entry => "entry".to_string(),
+        NodeId::BasicBlock(id) => format!("b{}", id),
+        NodeId::Exit => "exit".to_string(),
+    }
+}
@@ -0,0 +1,321 @@
+//! Function inlining.
+//!
+//! Calls to small `Internal` functions, and to `Internal` functions called
+//! from a single place, are replaced by a copy of the callee's body.
+//! Functions are visited callees first, so a callee has already been
+//! flattened when it is inlined. Recursive functions are never inlined.
+//!
+//! The copied body gets fresh temporaries, local ids and labels, so that it
+//! cannot collide with the caller. Its parameters become local variables of
+//! the caller, initialized with the arguments, and each `Return` becomes a
+//! move to the call's destination followed by a jump past the inlined body.
+
+use std::collections::HashMap;
+
+use crate::common::*;
+use super::{
+    Operand,
+    Insn,
+    Function,
+    TopLevel,
+    LabelOperand,
+    AutoGenLabel,
+    LocalVar,
+    Opt,
+    CodeGen,
+};
+use super::call_graph::CallGraph;
+
+/// Callees with at most this many instructions are inlined at every call site.
+const INLINE_THRESHOLD: usize = 32;
+
+/// We stop inlining into a caller once it grows beyond this many instructions.
+const CALLER_LIMIT: usize = 4096;
+
+/// Offsets added to the temporaries, local ids and labels of an inlined body.
+#[derive(Debug, Clone, Copy)]
+struct Renumbering {
+    temp: usize,
+    local: usize,
+    label: usize,
+}
+
+impl Renumbering {
+    fn operand(&self, operand: Operand) -> Operand {
+        match operand {
+            Operand::Temp(id, data_type) => Operand::Temp(id + self.temp, data_type),
+            Operand::Var { name, local_id: Some(id), data_type } => Operand::Var {
+                name,
+                local_id: Some(id + self.local),
+                data_type,
+            },
+            // static variables and immediates are shared
+            _ => operand,
+        }
+    }
+
+    fn label(&self, label: LabelOperand) -> LabelOperand {
+        match label {
+            LabelOperand::AutoGen(AutoGenLabel::Branch(id)) =>
+                LabelOperand::AutoGen(AutoGenLabel::Branch(id + self.label)),
+            LabelOperand::AutoGen(AutoGenLabel::Continue(id)) =>
+                LabelOperand::AutoGen(AutoGenLabel::Continue(id + self.label)),
+            LabelOperand::AutoGen(AutoGenLabel::Break(id)) =>
+                LabelOperand::AutoGen(AutoGenLabel::Break(id + self.label)),
+            LabelOperand::Named { name, id } => LabelOperand::Named { name, id: id + self.label },
+        }
+    }
+
+    fn insn(&self, insn: Insn) -> Insn {
+        match insn {
+            Insn::Return(src) => Insn::Return(self.operand(src)),
+            Insn::Unary { op, src, dst } => Insn::Unary {
+                op,
+                src: self.operand(src),
+                dst: self.operand(dst),
+            },
+            Insn::Binary { op, left, right, dst } => Insn::Binary {
+                op,
+                left: self.operand(left),
+                right: self.operand(right),
+                dst: self.operand(dst),
+            },
+            Insn::Label(label) => Insn::Label(self.label(label)),
+            Insn::Jump(label) => Insn::Jump(self.label(label)),
+            Insn::BranchIfZero { src, label } => Insn::BranchIfZero {
+                src: self.operand(src),
+                label: self.label(label),
+            },
+            Insn::BranchNotZero { src, label } => Insn::BranchNotZero {
+                src: self.operand(src),
+                label: self.label(label),
+            },
+            Insn::FuncCall { target, args, dst } => Insn::FuncCall {
+                target,
+                args: args.into_iter().map(|arg| self.operand(arg)).collect(),
+                dst: self.operand(dst),
+            },
+            Insn::Move { src, dst } => Insn::Move {
+                src: self.operand(src),
+                dst: self.operand(dst),
+            },
+            Insn::Truncate { src, dst } => Insn::Truncate {
+                src: self.operand(src),
+                dst: self.operand(dst),
+            },
+            Insn::SignExt { src, dst } => Insn::SignExt {
+                src: self.operand(src),
+                dst: self.operand(dst),
+            },
+            Insn::Loc(..) => insn,
+        }
+    }
+}
+
+/// The first temporary, local id and label that are free in `func`.
+fn first_free(func: &Function) -> Renumbering {
+    let Function::Defined { params, local_vars, body, .. } = func else {
+        return Renumbering { temp: 0, local: 0, label: 0 };
+    };
+
+    let mut free = Renumbering { temp: 0, local: 0, label: 0 };
+    for param in params {
+        free.local = free.local.max(param.local_id + 1);
+    }
+    for &local_id in local_vars.keys() {
+        free.local = free.local.max(local_id + 1);
+    }
+    for insn in body {
+        for operand in insn.operands() {
+            match *operand {
+                Operand::Temp(id, _) => free.temp = free.temp.max(id + 1),
+                Operand::Var { local_id: Some(id), .. } => free.local = free.local.max(id + 1),
+                _ => {},
+            }
+        }
+        if let Insn::Label(label) = insn {
+            free.label = free.label.max(label.id() + 1);
+        }
+    }
+
+    free
+}
+
+/// Moves `src` into `dst`, converting between `int` and `long` if needed.
+fn convert_move(src: Operand, dst: Operand) -> Insn {
+    match (src, dst.data_type()) {
+        (Operand::Imm(constant), DataType::Int) =>
+            Insn::Move { src: Operand::Imm(Constant::Int(constant.value() as i32)), dst },
+        (Operand::Imm(constant), DataType::Long) =>
+            Insn::Move { src: Operand::Imm(Constant::Long(constant.value())), dst },
+        (src, data_type) if src.data_type() == data_type => Insn::Move { src, dst },
+        (src, DataType::Long) => Insn::SignExt { src, dst },
+        (src, _) => Insn::Truncate { src, dst },
+    }
+}
+
+fn should_inline(
+    callee: &Function,
+    args: &[Operand],
+    graph: &CallGraph,
+) -> bool {
+    let Function::Defined { linkage, name, params, body, .. } = callee else {
+        return false;
+    };
+
+    *linkage == Linkage::Internal
+        && !graph.is_recursive(*name)
+        && params.len() == args.len()
+        && params.iter().zip(args).all(|(param, arg)| param.data_type == arg.data_type())
+        && (code_size(body) <= INLINE_THRESHOLD || graph.call_sites(*name) == 1)
+}
+
+/// Instructions in `body` but `Loc`s, so `-g` doesn't change what's inlined.
+fn code_size(body: &[Insn]) -> usize {
+    body.iter().filter(|insn| !matches!(insn, Insn::Loc(..))).count()
+}
+
+/// Copies `callee`'s body in place of a call, renumbered with `renumbering`.
+/// The callee's parameters and locals are added to `local_vars`.
+fn inline_call(
+    callee: &Function,
+    args: Vec<Operand>,
+    dst: Operand,
+    renumbering: Renumbering,
+    local_vars: &mut HashMap<usize, LocalVar>,
+) -> Vec<Insn> {
+    let Function::Defined {
+        params,
+        local_vars: callee_vars,
+        body: callee_body,
+        ..
+    } = callee else { unreachable!() };
+
+    // the label right after the last one of the callee
+    let end_label = LabelOperand::AutoGen(AutoGenLabel::Branch(
+        renumbering.label + first_free(callee).label
+    ));
+
+    let mut insns = vec![];
+    for (param, arg) in params.iter().zip(args) {
+        let local_id = param.local_id + renumbering.local;
+        local_vars.insert(local_id, LocalVar {
+            name: param.name,
+            local_id,
+            data_type: param.data_type,
+            span: None,
+        });
+        insns.push(Insn::Move {
+            src: arg,
+            dst: Operand::Var {
+                name: param.name,
+                local_id: Some(local_id),
+                data_type: param.data_type,
+            },
+        });
+    }
+    for var in callee_vars.values() {
+        let local_id = var.local_id + renumbering.local;
+        local_vars.insert(local_id, LocalVar { local_id, span: None, ..*var });
+    }
+
+    for insn in callee_body.iter().cloned() {
+        match renumbering.insn(insn) {
+            Insn::Return(src) => {
+                if matches!(dst.data_type(), DataType::Int | DataType::Long) {
+                    insns.push(convert_move(src, dst));
+                }
+                insns.push(Insn::Jump(end_label));
+            },
+            insn => insns.push(insn),
+        }
+    }
+    insns.push(Insn::Label(end_label));
+
+    insns
+}
+
+fn inline_calls(
+    caller: Function,
+    functions: &HashMap<StrDescriptor, Function>,
+    graph: &CallGraph,
+) -> Function {
+    let mut free = first_free(&caller);
+    let Function::Defined {
+        return_type,
+        linkage,
+        name,
+        params,
+        mut local_vars,
+        body,
+        span,
+    } = caller else {
+        return caller;
+    };
+
+    let mut new_body = Vec::with_capacity(body.len());
+    for insn in body {
+        match insn {
+            Insn::FuncCall { target, args, dst }
+                if code_size(&new_body) < CALLER_LIMIT
+                    && functions.get(&target).is_some_and(|callee| should_inline(callee, &args, graph)) => {
+                let callee = &functions[&target];
+                new_body.extend(inline_call(callee, args, dst, free, &mut local_vars));
+
+                // the next inlined body goes after this one, including its end label
+                let used = first_free(callee);
+                free.temp += used.temp;
+                free.local += used.local;
+                free.label += used.label + 1;
+            },
+            insn => new_body.push(insn),
+        }
+    }
+
+    Function::Defined {
+        name,
+        params,
+        return_type,
+        body: new_body,
+        linkage,
+        local_vars,
+        span,
+    }
+}
+
+impl CodeGen<Opt> {
+    pub fn inline_functions(&mut self, tac: TopLevel) -> TopLevel {
+        let TopLevel { mut functions, static_vars, strtb } = tac;
+        let graph = CallGraph::build(&functions);
+
+        for name in graph.postorder() {
+            let Some(func) = functions.remove(&name) else { continue };
+            let func = inline_calls(func, &functions, &graph);
+            functions.insert(name, func);
+        }
+
+        self.remove_unused_functions(TopLevel { functions, static_vars, strtb })
+    }
+
+    /// Removes `Internal` functions that are not called anymore.
+    pub fn remove_unused_functions(&mut self, tac: TopLevel) -> TopLevel {
+        let TopLevel { mut functions, static_vars, strtb } = tac;
+
+        loop {
+            let graph = CallGraph::build(&functions);
+            let unused = functions.values()
+                .filter(|func| matches!(func, Function::Defined { linkage: Linkage::Internal, .. }))
+                .map(|func| func.name())
+                .filter(|&name| graph.call_sites(name) == 0)
+                .collect::<Vec<_>>();
+            if unused.is_empty() {
+                break;
+            }
+            for name in unused {
+                functions.remove(&name);
+            }
+        }
+
+        TopLevel { functions, static_vars, strtb }
+    }
+}
@@ -0,0 +1,156 @@
+//! Whole translation unit analyses.
+//!
+//! `static` functions and variables that are never referenced are removed.
+//! Every defined function gets a summary of the static variables it may read
+//! (its ref set) and write (its mod set), directly or through its callees, and
+//! whether it is pure, i.e. it has no effect besides its return value.
+//! The intra-function passes consult these summaries at each `FuncCall`
+//! instead of assuming that the callee reads and writes everything.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::common::*;
+use super::{
+    Operand,
+    Function,
+    TopLevel,
+    Opt,
+    CodeGen,
+};
+use super::call_graph::CallGraph;
+use super::cfg::{def_of, uses_of};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuncSummary {
+    /// static variables the function or its callees may write
+    pub mods: HashSet<StrDescriptor>,
+    /// static variables the function or its callees may read
+    pub refs: HashSet<StrDescriptor>,
+    pub pure: bool,
+}
+
+/// Summaries of the functions defined in the translation unit.
+/// A function without a summary, e.g. a declared one, may read and write
+/// every static variable and is not pure.
+#[derive(Debug, Default)]
+pub struct Summaries {
+    funcs: HashMap<StrDescriptor, FuncSummary>,
+}
+
+impl Summaries {
+    pub(super) fn build(tac: &TopLevel) -> Self {
+        let graph = CallGraph::build(&tac.functions);
+        let all_vars = tac.static_vars.keys().cloned().collect::<HashSet<_>>();
+
+        let mut funcs = HashMap::new();
+        for (&name, func) in tac.functions.iter() {
+            let Function::Defined { body, .. } = func else { continue };
+            let mut summary = FuncSummary { pure: true, ..Default::default() };
+            for insn in body {
+                for operand in uses_of(insn) {
+                    if let Operand::Var { name, local_id: None, .. } = operand {
+                        summary.refs.insert(name);
+                    }
+                }
+                if let Some(Operand::Var { name, local_id: None, .. }) = def_of(insn) {
+                    summary.mods.insert(name);
+                    summary.pure = false;
+                }
+            }
+            funcs.insert(name, summary);
+        }
+
+        // propagate the effects of callees to their callers until nothing changes
+        loop {
+            let mut changed = false;
+            for caller in graph.functions() {
+                let Some(old) = funcs.get(&caller) else { continue };
+                let mut summary = old.clone();
+                for callee in graph.callees_of(caller) {
+                    match funcs.get(callee) {
+                        Some(callee) => {
+                            summary.mods.extend(callee.mods.iter().cloned());
+                            summary.refs.extend(callee.refs.iter().cloned());
+                            summary.pure &= callee.pure;
+                        },
+                        None => {
+                            summary.mods.extend(all_vars.iter().cloned());
+                            summary.refs.extend(all_vars.iter().cloned());
+                            summary.pure = false;
+                        },
+                    }
+                }
+                if summary != *old {
+                    funcs.insert(caller, summary);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        Summaries { funcs }
+    }
+
+    /// Whether a call to `callee` may write `operand`, apart from its destination.
+    pub fn may_modify(&self, callee: StrDescriptor, operand: Operand) -> bool {
+        match operand {
+            Operand::Var { name, local_id: None, .. } => self.funcs.get(&callee)
+                .is_none_or(|summary| summary.mods.contains(&name)),
+            // locals and temporaries are not visible to the callee
+            _ => false,
+        }
+    }
+
+    /// Whether a call to `callee` may read `operand`, apart from its arguments.
+    pub fn may_read(&self, callee: StrDescriptor, operand: Operand) -> bool {
+        match operand {
+            Operand::Var { name, local_id: None, .. } => self.funcs.get(&callee)
+                .is_none_or(|summary| summary.refs.contains(&name)),
+            _ => false,
+        }
+    }
+
+    pub fn is_pure(&self, callee: StrDescriptor) -> bool {
+        self.funcs.get(&callee).is_some_and(|summary| summary.pure)
+    }
+}
+
+impl CodeGen<Opt> {
+    /// Removes unreferenced `static` functions and variables, then summarizes
+    /// the remaining functions for the intra-function passes.
+    pub fn interprocedural_analysis(&mut self, tac: TopLevel) -> TopLevel {
+        let tac = self.remove_unused_functions(tac);
+        let tac = self.remove_unused_static_vars(tac);
+        self.summaries = Summaries::build(&tac);
+        tac
+    }
+
+    /// Removes `Internal` static variables that no function refers to.
+    pub fn remove_unused_static_vars(&mut self, tac: TopLevel) -> TopLevel {
+        let TopLevel { functions, mut static_vars, strtb } = tac;
+
+        let referenced = functions.values()
+            .filter_map(|func| match func {
+                Function::Defined { body, .. } => Some(body),
+                Function::Declared { .. } => None,
+            })
+            .flatten()
+            .flat_map(|insn| uses_of(insn).into_iter().chain(def_of(insn)))
+            .filter_map(|operand| match operand {
+                Operand::Var { name, local_id: None, .. } => Some(name),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let unused = static_vars.values()
+            .filter(|var| var.linkage == Linkage::Internal && !referenced.contains(&var.name))
+            .map(|var| var.name)
+            .collect::<HashSet<_>>();
+        static_vars.retain(|name, _| !unused.contains(name));
+        self.static_vars.retain(|(name, _)| !unused.contains(name));
+
+        TopLevel { functions, static_vars, strtb }
+    }
+}
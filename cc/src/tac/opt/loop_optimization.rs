@@ -0,0 +1,461 @@
+//! Loop optimizations.
+//!
+//! Natural loops are found from the back edges of the CFG, i.e. edges whose
+//! target dominates their source. For each loop we may insert a preheader,
+//! a block placed right before the header which is only entered from outside
+//! the loop, and move into it
+//! - loop-invariant `Unary`/`Binary` instructions that cannot trap, and
+//! - the initial values of strength-reduced induction variable multiplies.
+//!
+//! A multiply `j = i * k`, where `i` is only updated by `i = i + c` inside
+//! the loop, is replaced by a copy from a new temporary `s`, which is
+//! initialized to `i * k` in the preheader and increased by `c * k` right
+//! after every update of `i`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::common::*;
+use crate::tac::opt::cfg::InsnId;
+use super::{
+    Operand,
+    Insn,
+    Function,
+    BinaryOp,
+    LabelOperand,
+    AutoGenLabel,
+    Opt,
+    CodeGen,
+};
+use super::cfg::{
+    Node,
+    NodeId,
+    Graph,
+    def_of,
+    uses_of,
+};
+
+#[derive(Debug)]
+struct Loop {
+    header: usize,
+    body: BTreeSet<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InductionVar {
+    step: Constant,
+    // the instruction after which the variable holds its new value
+    update: InsnId,
+}
+
+/// All the edits we are going to make, so that every loop is analyzed on the
+/// original graph.
+#[derive(Debug, Default)]
+struct LoopEdits {
+    // loop header -> instructions of its preheader
+    preheaders: BTreeMap<usize, Vec<Insn>>,
+    // loop header -> blocks of the loop
+    bodies: HashMap<usize, BTreeSet<usize>>,
+    removed: HashSet<InsnId>,
+    replaced: HashMap<InsnId, Insn>,
+    inserted_after: HashMap<InsnId, Vec<Insn>>,
+}
+
+impl LoopEdits {
+    fn claimed(&self, id: InsnId) -> bool {
+        self.removed.contains(&id) || self.replaced.contains_key(&id)
+    }
+}
+
+fn block_insns(cfg: &Graph, id: usize) -> &[Insn] {
+    match cfg.nodes.get(&NodeId::BasicBlock(id)) {
+        Some(Node::BasicBlock(block)) => &block.insns,
+        _ => panic!("Internal error: Basic block not found in cfg"),
+    }
+}
+
+fn find_loops(cfg: &Graph, idoms: &HashMap<NodeId, NodeId>) -> Vec<Loop> {
+    let mut loops: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+
+    for &id in idoms.keys() {
+        let NodeId::BasicBlock(latch) = id else { continue };
+        for &succ in cfg.successors(id) {
+            let NodeId::BasicBlock(header) = succ else { continue };
+            if !Graph::dominates(idoms, succ, id) {
+                continue;
+            }
+            // the body consists of the header and everything that reaches the latch
+            // without passing through the header.
+            let body = loops.entry(header).or_default();
+            body.insert(header);
+            let mut stack = vec![];
+            if body.insert(latch) {
+                stack.push(latch);
+            }
+            while let Some(block) = stack.pop() {
+                for pred in cfg.predecessors(NodeId::BasicBlock(block)) {
+                    if let NodeId::BasicBlock(pred) = pred
+                        && body.insert(*pred) {
+                        stack.push(*pred);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut loops = loops.into_iter()
+        .map(|(header, body)| Loop { header, body })
+        .collect::<Vec<_>>();
+    // outer loops first, so invariants are hoisted as far as possible
+    loops.sort_by_key(|l| std::cmp::Reverse(l.body.len()));
+    loops
+}
+
+fn can_trap(insn: &Insn) -> bool {
+    match insn {
+        Insn::Binary { op: BinaryOp::Div | BinaryOp::Rem, right, .. } => match right {
+            Operand::Imm(divisor) => divisor.is_zero() || divisor.value() == -1,
+            _ => true,
+        },
+        _ => false,
+    }
+}
+
+struct LoopInfo<'a> {
+    cfg: &'a Graph,
+    idoms: &'a HashMap<NodeId, NodeId>,
+    lp: &'a Loop,
+    // operand -> instructions in the loop defining it
+    defs: HashMap<Operand, Vec<InsnId>>,
+    has_call: bool,
+    // blocks reachable from the header
+    reachable: HashSet<usize>,
+}
+
+impl<'a> LoopInfo<'a> {
+    fn new(cfg: &'a Graph, idoms: &'a HashMap<NodeId, NodeId>, lp: &'a Loop) -> Self {
+        let mut defs: HashMap<Operand, Vec<InsnId>> = HashMap::new();
+        let mut has_call = false;
+        for &block in lp.body.iter() {
+            for (idx, insn) in block_insns(cfg, block).iter().enumerate() {
+                if let Some(dst) = def_of(insn) {
+                    defs.entry(dst).or_default().push(InsnId::new(block, idx));
+                }
+                has_call |= matches!(insn, Insn::FuncCall { .. });
+            }
+        }
+
+        let mut reachable = HashSet::new();
+        let mut stack = vec![lp.header];
+        while let Some(block) = stack.pop() {
+            if !reachable.insert(block) {
+                continue;
+            }
+            for succ in cfg.successors(NodeId::BasicBlock(block)) {
+                if let NodeId::BasicBlock(succ) = succ {
+                    stack.push(*succ);
+                }
+            }
+        }
+
+        LoopInfo { cfg, idoms, lp, defs, has_call, reachable }
+    }
+
+    fn insn(&self, id: InsnId) -> &'a Insn {
+        &block_insns(self.cfg, id.block_id)[id.inblock_idx]
+    }
+
+    fn defs_of(&self, operand: Operand) -> &[InsnId] {
+        self.defs.get(&operand).map(|defs| defs.as_slice()).unwrap_or(&[])
+    }
+
+    fn is_invariant(&self, operand: Operand, invariants: &HashSet<InsnId>) -> bool {
+        match operand {
+            Operand::Imm(_) => true,
+            _ if operand.is_static() && self.has_call => false,
+            _ => match self.defs_of(operand) {
+                [] => true,
+                [def] => invariants.contains(def),
+                _ => false,
+            },
+        }
+    }
+
+    /// Whether every use of `def`'s destination that may execute after
+    /// entering the loop is dominated by `def`, so computing it earlier
+    /// cannot be observed.
+    fn dominates_uses(&self, def: InsnId, dst: Operand) -> bool {
+        let def_block = NodeId::BasicBlock(def.block_id);
+        self.reachable.iter().all(|&block| {
+            block_insns(self.cfg, block).iter()
+                .enumerate()
+                .filter(|(_, insn)| uses_of(insn).contains(&dst))
+                .all(|(idx, _)| if block == def.block_id {
+                    idx > def.inblock_idx
+                } else {
+                    Graph::dominates(self.idoms, def_block, NodeId::BasicBlock(block))
+                })
+        })
+    }
+
+    fn hoist_invariants(&self, edits: &mut LoopEdits) -> Vec<Insn> {
+        let mut invariants = HashSet::new();
+        let mut hoisted = vec![];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in self.lp.body.iter() {
+                for (idx, insn) in block_insns(self.cfg, block).iter().enumerate() {
+                    let id = InsnId::new(block, idx);
+                    if invariants.contains(&id) || edits.claimed(id) || can_trap(insn) {
+                        continue;
+                    }
+                    let (dst, operands) = match insn {
+                        Insn::Unary { src, dst, .. } => (*dst, vec![*src]),
+                        Insn::Binary { left, right, dst, .. } => (*dst, vec![*left, *right]),
+                        _ => continue,
+                    };
+                    if dst.is_static()
+                        || self.defs_of(dst).len() != 1
+                        || !operands.iter().all(|op| self.is_invariant(*op, &invariants))
+                        || !self.dominates_uses(id, dst) {
+                        continue;
+                    }
+                    invariants.insert(id);
+                    hoisted.push(insn.clone());
+                    edits.removed.insert(id);
+                    changed = true;
+                }
+            }
+        }
+
+        hoisted
+    }
+
+    fn induction_vars(&self) -> HashMap<Operand, InductionVar> {
+        let mut ivs = HashMap::new();
+
+        for (&var, defs) in self.defs.iter() {
+            if var.is_static() || !matches!(var.data_type(), DataType::Int | DataType::Long) {
+                continue;
+            }
+            let [update] = defs.as_slice() else { continue };
+            let step = match self.insn(*update) {
+                // i = i + c
+                Insn::Binary { .. } => self.step_of(*update, var),
+                // t = i + c; i = t
+                Insn::Move { src, .. } => match self.defs_of(*src) {
+                    [def] if def.block_id == update.block_id && def.inblock_idx < update.inblock_idx
+                        => self.step_of(*def, var),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(step) = step {
+                ivs.insert(var, InductionVar { step, update: *update });
+            }
+        }
+
+        ivs
+    }
+
+    /// The constant added to `var` by `id`, if it is `var + c`, `c + var` or `var - c`.
+    fn step_of(&self, id: InsnId, var: Operand) -> Option<Constant> {
+        match self.insn(id) {
+            Insn::Binary { op: BinaryOp::Add, left, right: Operand::Imm(c), .. } |
+            Insn::Binary { op: BinaryOp::Add, left: Operand::Imm(c), right: left, .. }
+                if *left == var => Some(*c),
+            Insn::Binary { op: BinaryOp::Sub, left, right: Operand::Imm(c), .. }
+                if *left == var => Some(match c {
+                    Constant::Int(c) => Constant::Int(c.wrapping_neg()),
+                    Constant::Long(c) => Constant::Long(c.wrapping_neg()),
+                }),
+            _ => None,
+        }
+    }
+
+    fn reduce_strength(&self, edits: &mut LoopEdits, next_temp: &mut usize) -> Vec<Insn> {
+        let ivs = self.induction_vars();
+        let mut reduced: HashMap<(Operand, Constant), Operand> = HashMap::new();
+        let mut inits = vec![];
+
+        for &block in self.lp.body.iter() {
+            for (idx, insn) in block_insns(self.cfg, block).iter().enumerate() {
+                let id = InsnId::new(block, idx);
+                if edits.claimed(id) {
+                    continue;
+                }
+                let (var, factor, dst) = match insn {
+                    Insn::Binary { op: BinaryOp::Mul, left, right: Operand::Imm(k), dst } |
+                    Insn::Binary { op: BinaryOp::Mul, left: Operand::Imm(k), right: left, dst }
+                        if ivs.contains_key(left) => (*left, *k, *dst),
+                    _ => continue,
+                };
+                let data_type = dst.data_type();
+                if var.data_type() != data_type {
+                    continue;
+                }
+
+                let reduced_var = *reduced.entry((var, factor)).or_insert_with(|| {
+                    let iv = ivs[&var];
+                    let temp = Operand::Temp(*next_temp, data_type);
+                    *next_temp += 1;
+                    inits.push(Insn::Binary {
+                        op: BinaryOp::Mul,
+                        left: var,
+                        right: Operand::Imm(factor),
+                        dst: temp,
+                    });
+                    let increment = iv.step.value().wrapping_mul(factor.value());
+                    let increment = match data_type {
+                        DataType::Int => Constant::Int(increment as i32),
+                        _ => Constant::Long(increment),
+                    };
+                    edits.inserted_after.entry(iv.update).or_default().push(Insn::Binary {
+                        op: BinaryOp::Add,
+                        left: temp,
+                        right: Operand::Imm(increment),
+                        dst: temp,
+                    });
+                    temp
+                });
+
+                edits.replaced.insert(id, Insn::Move { src: reduced_var, dst });
+            }
+        }
+
+        inits
+    }
+}
+
+fn max_branch_label(body: &[Insn]) -> Option<usize> {
+    body.iter()
+        .filter_map(|insn| match insn {
+            Insn::Label(LabelOperand::AutoGen(AutoGenLabel::Branch(id))) => Some(*id),
+            _ => None,
+        })
+        .max()
+}
+
+fn max_temp(body: &[Insn]) -> Option<usize> {
+    body.iter()
+        .flat_map(|insn| def_of(insn).into_iter().chain(uses_of(insn)))
+        .filter_map(|operand| match operand {
+            Operand::Temp(id, _) => Some(id),
+            _ => None,
+        })
+        .max()
+}
+
+fn retarget(insn: Insn, from: LabelOperand, to: LabelOperand) -> Insn {
+    match insn {
+        Insn::Jump(label) if label == from => Insn::Jump(to),
+        Insn::BranchIfZero { src, label } if label == from => Insn::BranchIfZero { src, label: to },
+        Insn::BranchNotZero { src, label } if label == from => Insn::BranchNotZero { src, label: to },
+        _ => insn,
+    }
+}
+
+/// Lays out the blocks again, applying `edits` and placing each preheader right
+/// before its loop header.
+fn emit_with_preheaders(cfg: Graph, edits: LoopEdits, mut next_label: usize) -> Vec<Insn> {
+    // loop header -> (header label, preheader label)
+    let mut preheader_labels = HashMap::new();
+    for &header in edits.preheaders.keys() {
+        if let Some(Insn::Label(label)) = block_insns(&cfg, header).first() {
+            let preheader = LabelOperand::AutoGen(AutoGenLabel::Branch(next_label));
+            next_label += 1;
+            preheader_labels.insert(header, (*label, preheader));
+        }
+    }
+
+    let mut insns = vec![];
+    let mut prev: Option<usize> = None;
+    let mut edits = edits;
+
+    for (_, node) in cfg.nodes {
+        let Node::BasicBlock(block) = node else { continue };
+
+        if let Some(&(header_label, preheader_label)) = preheader_labels.get(&block.id) {
+            let body = &edits.bodies[&block.id];
+            // a latch laid out right before the header must not fall into the preheader
+            if let Some(prev) = prev
+                && body.contains(&prev)
+                && !matches!(insns.last(), Some(Insn::Jump(..) | Insn::Return(..))) {
+                insns.push(Insn::Jump(header_label));
+            }
+            insns.push(Insn::Label(preheader_label));
+            insns.extend(edits.preheaders.remove(&block.id).unwrap_or_default());
+        }
+
+        for (idx, insn) in block.insns.into_iter().enumerate() {
+            let id = InsnId::new(block.id, idx);
+            if edits.removed.contains(&id) {
+                continue;
+            }
+            let mut insn = edits.replaced.remove(&id).unwrap_or(insn);
+            // jumps from outside a loop enter it through the preheader
+            for (header, (header_label, preheader_label)) in preheader_labels.iter() {
+                if !edits.bodies[header].contains(&block.id) {
+                    insn = retarget(insn, *header_label, *preheader_label);
+                }
+            }
+            insns.push(insn);
+            insns.extend(edits.inserted_after.remove(&id).unwrap_or_default());
+        }
+        prev = Some(block.id);
+    }
+
+    insns
+}
+
+impl CodeGen<Opt> {
+    pub fn loop_optimization(&mut self, func: Function) -> Function {
+        match func {
+            Function::Declared {..} => func,
+            Function::Defined {
+                return_type,
+                linkage,
+                name,
+                params,
+                local_vars,
+                body,
+                span,
+            } => {
+                let next_label = max_branch_label(&body).map_or(0, |id| id + 1);
+                let mut next_temp = max_temp(&body).map_or(0, |id| id + 1);
+
+                let cfg = Graph::build(body);
+                let idoms = cfg.immediate_dominators();
+                let loops = find_loops(&cfg, &idoms);
+
+                let mut edits = LoopEdits::default();
+                for lp in loops.iter() {
+                    // a header without label cannot be the target of a back edge
+                    if !matches!(block_insns(&cfg, lp.header).first(), Some(Insn::Label(..))) {
+                        continue;
+                    }
+                    let info = LoopInfo::new(&cfg, &idoms, lp);
+                    let mut preheader = info.hoist_invariants(&mut edits);
+                    preheader.extend(info.reduce_strength(&mut edits, &mut next_temp));
+                    if !preheader.is_empty() {
+                        edits.preheaders.insert(lp.header, preheader);
+                        edits.bodies.insert(lp.header, lp.body.clone());
+                    }
+                }
+
+                let opted_body = emit_with_preheaders(cfg, edits, next_label);
+
+                Function::Defined {
+                    name,
+                    params,
+                    return_type,
+                    body: opted_body,
+                    linkage,
+                    local_vars,
+                    span,
+                }
+            }
+        }
+    }
+}
@@ -5,6 +5,13 @@ mod constant_folding;
 mod deadcode_elimination;
 mod copy_propagation;
 mod deadstore_elimination;
+mod sccp;
+mod value_numbering;
+mod loop_optimization;
+mod call_graph;
+mod inline;
+mod interprocedural;
+mod dot;
 
 use std::collections::HashMap;
 
@@ -13,17 +20,24 @@ use super::{
     CodeGen,
     Opt,
     Function,
+    LocalVar,
     FuncContext,
     LabelOperand,
+    AutoGenLabel,
     Insn,
     UnaryOp,
     BinaryOp,
     Operand,   
 };
+pub use interprocedural::Summaries;
+pub use cfg::{Graph, Node, NodeId, temp_reads};
 
-/// We only do intra-function optimizations.
+/// Inlining and the interprocedural analysis are done on the whole translation
+/// unit first, the other optimizations are intra-function.
 impl CodeGen<Opt> {
     pub fn optimize_all(mut self, tac: TopLevel) -> TopLevel {
+        let tac = self.inline_functions(tac);
+        let tac = self.interprocedural_analysis(tac);
         let mut opted_funcs = HashMap::new();
         
         for (name, func) in tac.functions {
@@ -31,21 +45,26 @@ impl CodeGen<Opt> {
             opted_funcs.insert(name, opted_func);
         }
 
-        TopLevel {
+        // calls to pure functions may have been removed
+        let tac = self.remove_unused_functions(TopLevel {
             functions: opted_funcs,
             static_vars: tac.static_vars,
             strtb: tac.strtb,
-        }
-
+        });
+        self.remove_unused_static_vars(tac)
     }
 
     // currently a one-pass optimizer
     fn opt_func(&mut self, func: Function) -> Function {
         let post_constant_folding = self.constant_folding(func);
-        let post_deadcode_elimination = self.deadcode_elimination(post_constant_folding);
-        let post_copy_propagation = self.copy_propagation(post_deadcode_elimination);
-
-        post_copy_propagation
+        let post_sccp = self.sccp(post_constant_folding);
+        let post_deadcode_elimination = self.deadcode_elimination(post_sccp);
+        let post_value_numbering = self.global_value_numbering(post_deadcode_elimination);
+        let post_loop_optimization = self.loop_optimization(post_value_numbering);
+        let post_copy_propagation = self.copy_propagation(post_loop_optimization);
+        let post_deadstore_elimination = self.deadstore_elimination(post_copy_propagation);
+
+        post_deadstore_elimination
     }
 }
 
@@ -54,6 +73,7 @@ mod tests {
     use std::collections::HashMap;
     use std::fs::read_to_string;
     use std::io::Write;
+    use std::ops::Range;
 
     use crate::asm::CodeGen;
     use crate::lex::Lexer;
@@ -61,7 +81,7 @@ mod tests {
     use crate::sem::HirParser;
     use crate::tac::opt::cfg::Graph;
     use crate::tac::opt::deadstore_elimination;
-    use crate::tac::{Opt, TacCodeGen, TacFunction, TacTopLevel};
+    use crate::tac::{Opt, TacBinaryOp, TacCodeGen, TacFunction, TacInsn, TacOperand, TacTopLevel};
     use crate::lir::LirCodeGen;
 
     fn gen_tac(path: &str) -> (TacTopLevel, TacCodeGen<Opt>) {
@@ -166,6 +186,204 @@ mod tests {
         tac
     }
 
+    /// Runs `passes` in order over every function and dumps the result to `<path>.<suffix>.tac`.
+    fn test_passes(
+        path: &str,
+        suffix: &str,
+        passes: &[fn(&mut TacCodeGen<Opt>, TacFunction) -> TacFunction],
+    ) -> TacTopLevel {
+        let (mut tac, mut opt) = gen_tac(path);
+
+        let mut refactored_funcs = HashMap::new();
+        for (_, mut func) in tac.functions {
+            for pass in passes {
+                func = pass(&mut opt, func);
+            }
+            refactored_funcs.insert(func.name(), func);
+        }
+        tac.functions = refactored_funcs;
+
+        std::fs::write(format!("{}.{}.tac", path, suffix), tac.emit_code()).unwrap();
+
+        tac
+    }
+
+    /// The body of the function `name`.
+    fn body<'a>(tac: &'a TacTopLevel, name: &str) -> &'a [TacInsn] {
+        let func = tac.functions.values()
+            .find(|func| tac.strtb.get(func.name()).unwrap() == name)
+            .unwrap();
+        let TacFunction::Defined { body, .. } = func else { unreachable!() };
+        body
+    }
+
+    /// Whether `operand` is the variable `name`.
+    fn is_var(tac: &TacTopLevel, operand: &TacOperand, name: &str) -> bool {
+        matches!(operand, TacOperand::Var { name: var, .. } if tac.strtb.get(*var).unwrap() == name)
+    }
+
+    /// Whether `insn` multiplies the variables `left` and `right`, in either order.
+    fn is_mul(tac: &TacTopLevel, insn: &TacInsn, left: &str, right: &str) -> bool {
+        match insn {
+            TacInsn::Binary { op: TacBinaryOp::Mul, left: a, right: b, .. } =>
+                is_var(tac, a, left) && is_var(tac, b, right) || is_var(tac, a, right) && is_var(tac, b, left),
+            _ => false,
+        }
+    }
+
+    /// The instructions of each loop in `body`, from its header to the jump back to it.
+    fn loops(body: &[TacInsn]) -> Vec<Range<usize>> {
+        body.iter()
+            .enumerate()
+            .filter_map(|(end, insn)| match insn {
+                TacInsn::Jump(target) => body[..end].iter()
+                    .position(|insn| matches!(insn, TacInsn::Label(label) if label == target))
+                    .map(|start| start..end),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sccp() {
+        let tac = test_passes(
+            "../testprogs/sccp.c",
+            "sccp.dce",
+            &[TacCodeGen::sccp, TacCodeGen::deadcode_elimination],
+        );
+        let main = body(&tac, "main");
+        // `b > 10` and `k != 7` are known, and the calls to `side` behind them are never made;
+        // only the loop test and `flag == 2`, after `side` may have written `flag`, are left
+        let branches = main.iter()
+            .filter(|insn| matches!(insn, TacInsn::BranchIfZero { .. } | TacInsn::BranchNotZero { .. }))
+            .count();
+        assert_eq!(branches, 2);
+        assert_eq!(main.iter().filter(|insn| matches!(insn, TacInsn::FuncCall { .. })).count(), 1);
+
+        test_passes(
+            "../testprogs/control_flow.c",
+            "sccp.dce",
+            &[TacCodeGen::sccp, TacCodeGen::deadcode_elimination],
+        );
+    }
+
+    #[test]
+    fn test_value_numbering() {
+        // the second `a * b` of `x` is the first one, the others are in other blocks
+        let tac = test_passes(
+            "../testprogs/gvn.c",
+            "lvn",
+            &[TacCodeGen::local_value_numbering],
+        );
+        assert_eq!(body(&tac, "main").iter().filter(|insn| is_mul(&tac, insn, "a", "b")).count(), 4);
+
+        // every `a * b` and `b * a` is the first one, but `s * 2` is computed again after `touch` writes `s`
+        let tac = test_passes(
+            "../testprogs/gvn.c",
+            "gvn",
+            &[TacCodeGen::global_value_numbering],
+        );
+        let main = body(&tac, "main");
+        assert_eq!(main.iter().filter(|insn| is_mul(&tac, insn, "a", "b")).count(), 1);
+        let s_times_2 = main.iter()
+            .enumerate()
+            .filter(|(_, insn)| matches!(insn, TacInsn::Binary { op: TacBinaryOp::Mul, left, .. } if is_var(&tac, left, "s")))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let call = main.iter().position(|insn| matches!(insn, TacInsn::FuncCall { .. })).unwrap();
+        assert!(matches!(s_times_2[..], [before, after] if before < call && call < after), "{:?}", s_times_2);
+    }
+
+    #[test]
+    fn test_loop_optimization() {
+        let tac = test_passes(
+            "../testprogs/loop_opt.c",
+            "licm",
+            &[TacCodeGen::loop_optimization],
+        );
+        let main = body(&tac, "main");
+        let loops = loops(main);
+        assert_eq!(loops.len(), 2);
+        let in_loop = |i: usize| loops.iter().any(|range| range.contains(&i));
+        let position = |f: &dyn Fn(&TacInsn) -> bool| main.iter().position(f).unwrap();
+
+        // `a * b` and `b / 2` are invariant, `a / (j + 1)` isn't
+        assert!(!in_loop(position(&|insn| is_mul(&tac, insn, "a", "b"))));
+        assert!(!in_loop(position(&|insn| matches!(insn,
+            TacInsn::Binary { op: TacBinaryOp::Div, left, .. } if is_var(&tac, left, "b")))));
+        assert!(in_loop(position(&|insn| matches!(insn,
+            TacInsn::Binary { op: TacBinaryOp::Div, left, .. } if is_var(&tac, left, "a")))));
+
+        // `i * 4` is computed once before the loop, and stepped by 4 with `i`
+        let scaled = position(&|insn| matches!(insn,
+            TacInsn::Binary { op: TacBinaryOp::Mul, left, .. } if is_var(&tac, left, "i")));
+        assert!(!in_loop(scaled));
+        let TacInsn::Binary { dst: scaled, .. } = main[scaled] else { unreachable!() };
+        assert!(loops[0].clone().any(|i| matches!(main[i],
+            TacInsn::Binary { op: TacBinaryOp::Add, left, right: TacOperand::Imm(step), dst }
+                if left == scaled && dst == scaled && step.value() == 4)));
+
+        test_passes(
+            "../testprogs/control_flow.c",
+            "licm",
+            &[TacCodeGen::loop_optimization],
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        let path = "../testprogs/inline.c";
+        let (tac, mut opt) = gen_tac(path);
+        let tac = opt.inline_functions(tac);
+
+        let names = tac.functions.keys()
+            .map(|name| tac.strtb.get(*name).unwrap().as_str())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"main"));
+        // recursive
+        assert!(names.contains(&"fact"));
+        // inlined everywhere, or never called
+        assert!(!names.contains(&"square"));
+        assert!(!names.contains(&"once"));
+        assert!(!names.contains(&"unused"));
+
+        std::fs::write(format!("{}.inline.tac", path), tac.emit_code()).unwrap();
+    }
+
+    #[test]
+    fn test_interprocedural() {
+        let path = "../testprogs/interproc.c";
+        let (tac, mut opt) = gen_tac(path);
+        let mut tac = opt.interprocedural_analysis(tac);
+
+        let name_of = |tac: &TacTopLevel, name| tac.strtb.get(name).unwrap().clone();
+        assert!(tac.functions.keys().all(|&name| name_of(&tac, name) != "unused_func"));
+        assert!(tac.static_vars.keys().all(|&name| name_of(&tac, name) != "unused_var"));
+
+        let mut refactored_funcs = HashMap::new();
+        for (_, func) in tac.functions {
+            let func = opt.copy_propagation(func);
+            let func = opt.deadstore_elimination(func);
+            refactored_funcs.insert(func.name(), func);
+        }
+        tac.functions = refactored_funcs;
+
+        // both calls with unused results are gone, the one to `square` with a used result stays
+        let main = tac.functions.values()
+            .find(|func| name_of(&tac, func.name()) == "main")
+            .unwrap();
+        let TacFunction::Defined { body, .. } = main else { unreachable!() };
+        let calls = body.iter()
+            .filter_map(|insn| match insn {
+                TacInsn::FuncCall { target, .. } => Some(name_of(&tac, *target)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(calls, ["square", "putchar", "bump"]);
+
+        std::fs::write(format!("{}.ipa.tac", path), tac.emit_code()).unwrap();
+    }
+
     #[test]
     fn test_basic_opt() {
         test_opt(
@@ -0,0 +1,482 @@
+//! Sparse conditional constant propagation.
+//!
+//! Unlike `constant_folding`, which only looks at a single instruction,
+//! this pass tracks the constant value of every variable and temporary across
+//! the whole CFG, and only follows the edges that can actually be taken.
+//! Branches on known values are resolved into jumps (or removed), so the
+//! blocks that are never executed become unreachable and are left for
+//! `deadcode_elimination` to prune.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::common::*;
+use crate::tac::opt::cfg::{BasicBlock, InsnId};
+use super::{
+    Operand,
+    Insn,
+    Function,
+    UnaryOp,
+    BinaryOp,
+    Opt,
+    CodeGen,
+    Summaries,
+};
+use super::cfg::{
+    Node,
+    NodeId,
+    Graph,
+};
+
+/// Variables (and temporaries) known to hold a constant at some program point.
+/// An operand that is absent is overdefined, i.e. we know nothing about it.
+#[derive(Debug, Clone, PartialEq)]
+struct KnownConstants {
+    consts: HashMap<Operand, Constant>,
+}
+
+impl KnownConstants {
+    fn new() -> Self {
+        KnownConstants { consts: HashMap::new() }
+    }
+
+    fn get(&self, operand: Operand) -> Option<Constant> {
+        match operand {
+            Operand::Imm(constant) => Some(constant),
+            _ => self.consts.get(&operand).copied(),
+        }
+    }
+
+    fn set(&mut self, dst: Operand, value: Option<Constant>) {
+        match (dst, value) {
+            (Operand::Imm(_), _) => panic!("Internal error: Cannot assign to an immediate"),
+            (dst, Some(value)) if is_tracked(dst.data_type()) => {
+                self.consts.insert(dst, convert(value, dst.data_type()));
+            },
+            (dst, _) => {
+                self.consts.remove(&dst);
+            },
+        }
+    }
+
+    fn intersect_with(&mut self, other: &Self) {
+        self.consts.retain(|operand, value| other.consts.get(operand) == Some(value));
+    }
+
+    fn kill_modified_by(&mut self, callee: StrDescriptor, summaries: &Summaries) {
+        self.consts.retain(|operand, _| !summaries.may_modify(callee, *operand));
+    }
+}
+
+struct Sccp<'a> {
+    cfg: &'a Graph,
+    summaries: &'a Summaries,
+    block_outs: HashMap<usize, KnownConstants>,
+    insn_consts: HashMap<InsnId, KnownConstants>,
+    executable: HashSet<(NodeId, NodeId)>,
+}
+
+struct AnalysisResult {
+    insn_consts: HashMap<InsnId, KnownConstants>,
+}
+
+impl<'a> Sccp<'a> {
+    fn new(cfg: &'a Graph, summaries: &'a Summaries) -> Self {
+        Sccp {
+            cfg,
+            summaries,
+            block_outs: HashMap::new(),
+            insn_consts: HashMap::new(),
+            executable: HashSet::new(),
+        }
+    }
+
+    fn analyze(self) -> AnalysisResult {
+        let mut analysis = self;
+
+        analysis.iterate();
+
+        AnalysisResult {
+            insn_consts: analysis.insn_consts,
+        }
+    }
+
+    fn meet(
+        &self,
+        block: &BasicBlock,
+    ) -> KnownConstants {
+        let mut initial: Option<KnownConstants> = None;
+
+        for pred in block.predecessors.iter() {
+            if !self.executable.contains(&(*pred, NodeId::BasicBlock(block.id))) {
+                continue;
+            }
+            match pred {
+                // nothing is known about parameters and static variables on entry
+                NodeId::Entry => return KnownConstants::new(),
+                NodeId::Exit => panic!("Internal error: Exit node should not be a predecessor"),
+                NodeId::BasicBlock(id) => {
+                    let pred = self.block_outs.get(id)
+                        .expect("Internal error: Executable predecessor has not been visited");
+                    match initial.as_mut() {
+                        Some(initial) => initial.intersect_with(pred),
+                        None => initial = Some(pred.clone()),
+                    }
+                }
+            }
+        }
+
+        initial.unwrap_or_else(KnownConstants::new)
+    }
+
+    fn transfer(
+        &mut self,
+        initial: &KnownConstants,
+        block: &BasicBlock,
+    ) -> KnownConstants {
+        let mut current = initial.clone();
+
+        for (inblock_id, insn) in block.insns.iter().enumerate() {
+            self.annotate_insn(
+                InsnId::new(block.id, inblock_id),
+                current.clone(),
+            );
+            transfer_insn(&mut current, insn, self.summaries);
+        }
+
+        current
+    }
+
+    /// Returns the successors that can be reached from `block` given the
+    /// constants known at its end.
+    fn feasible_successors(
+        &self,
+        block: &BasicBlock,
+        out: &KnownConstants,
+    ) -> Vec<NodeId> {
+        let (src, label, jump_if_zero) = match block.insns.last() {
+            Some(Insn::BranchIfZero { src, label }) => (*src, label, true),
+            Some(Insn::BranchNotZero { src, label }) => (*src, label, false),
+            _ => return block.successors.iter().cloned().collect(),
+        };
+
+        // the condition is evaluated before the branch itself, which defines nothing
+        let Some(value) = out.get(src) else {
+            return block.successors.iter().cloned().collect();
+        };
+
+        let target = *self.cfg.label_map.get(label)
+            .expect("Internal error: Branch label not found in label map");
+        let fallthrough = block.successors.iter()
+            .find(|&&succ| succ != target)
+            .cloned()
+            .unwrap_or(target);
+
+        if value.is_zero() == jump_if_zero {
+            vec![target]
+        } else {
+            vec![fallthrough]
+        }
+    }
+
+    fn iterate(&mut self) {
+        let mut to_process = VecDeque::new();
+
+        self.executable.insert((NodeId::Entry, NodeId::BasicBlock(0)));
+        to_process.push_back(0);
+
+        while let Some(id) = to_process.pop_front() {
+            let block = match self.cfg.nodes.get(&NodeId::BasicBlock(id)) {
+                Some(Node::BasicBlock(block)) => block,
+                _ => panic!("Internal error: Basic block not found in cfg"),
+            };
+
+            let incoming = self.meet(block);
+            let out = self.transfer(&incoming, block);
+
+            let changed = self.block_outs.get(&id) != Some(&out);
+            let successors = self.feasible_successors(block, &out);
+            self.block_outs.insert(id, out);
+
+            for succ in successors {
+                let newly_executable = self.executable.insert((NodeId::BasicBlock(id), succ));
+                match succ {
+                    NodeId::Entry => panic!("Internal error: Entry node should not be a successor"),
+                    NodeId::Exit => continue,
+                    NodeId::BasicBlock(succ_id) => {
+                        if (newly_executable || changed) && !to_process.contains(&succ_id) {
+                            to_process.push_back(succ_id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn annotate_insn(
+        &mut self,
+        insn_id: InsnId,
+        known: KnownConstants,
+    ) {
+        self.insn_consts.insert(insn_id, known);
+    }
+}
+
+fn is_tracked(data_type: DataType) -> bool {
+    matches!(data_type, DataType::Int | DataType::Long)
+}
+
+fn convert(value: Constant, data_type: DataType) -> Constant {
+    match data_type {
+        DataType::Int => Constant::Int(value.value() as i32),
+        DataType::Long => Constant::Long(value.value()),
+        _ => unreachable!(),
+    }
+}
+
+fn bool_constant(value: bool) -> Constant {
+    Constant::Int(if value { 1 } else { 0 })
+}
+
+fn eval_unary(op: UnaryOp, src: Constant) -> Constant {
+    match op {
+        UnaryOp::Pos => src,
+        UnaryOp::Negate => match src {
+            Constant::Int(value) => Constant::Int(value.wrapping_neg()),
+            Constant::Long(value) => Constant::Long(value.wrapping_neg()),
+        },
+        UnaryOp::Complement => src.complement(),
+        UnaryOp::Not => bool_constant(src.is_zero()),
+    }
+}
+
+fn eval_binary(op: BinaryOp, left: Constant, right: Constant) -> Option<Constant> {
+    // both sides share the same type after semantic analysis, but we compute
+    // on the wider representation to stay on the safe side.
+    let data_type = left.data_type();
+    let (l, r) = (left.value(), right.value());
+    let value = match op {
+        BinaryOp::Add => l.wrapping_add(r),
+        BinaryOp::Sub => l.wrapping_sub(r),
+        BinaryOp::Mul => l.wrapping_mul(r),
+        BinaryOp::Div | BinaryOp::Rem if r == 0 => return None,
+        BinaryOp::Div => match data_type {
+            DataType::Int => (l as i32).wrapping_div(r as i32) as i64,
+            _ => l.wrapping_div(r),
+        },
+        BinaryOp::Rem => match data_type {
+            DataType::Int => (l as i32).wrapping_rem(r as i32) as i64,
+            _ => l.wrapping_rem(r),
+        },
+        BinaryOp::Eq => return Some(bool_constant(l == r)),
+        BinaryOp::NotEq => return Some(bool_constant(l != r)),
+        BinaryOp::Ls => return Some(bool_constant(l < r)),
+        BinaryOp::LsEq => return Some(bool_constant(l <= r)),
+        BinaryOp::Gt => return Some(bool_constant(l > r)),
+        BinaryOp::GtEq => return Some(bool_constant(l >= r)),
+        BinaryOp::And => return Some(bool_constant(l != 0 && r != 0)),
+        BinaryOp::Or => return Some(bool_constant(l != 0 || r != 0)),
+    };
+
+    Some(convert(Constant::Long(value), data_type))
+}
+
+/// The value `insn` assigns to its destination, if it is a known constant.
+fn eval_insn(known: &KnownConstants, insn: &Insn) -> Option<Constant> {
+    match insn {
+        Insn::Move { src, .. } |
+        Insn::SignExt { src, .. } |
+        Insn::Truncate { src, .. } => known.get(*src),
+        Insn::Unary { op, src, .. } => known.get(*src).map(|src| eval_unary(*op, src)),
+        Insn::Binary { op, left, right, .. } => {
+            let left = known.get(*left)?;
+            let right = known.get(*right)?;
+            eval_binary(*op, left, right)
+        },
+        _ => None,
+    }
+}
+
+fn transfer_insn(current: &mut KnownConstants, insn: &Insn, summaries: &Summaries) {
+    match insn {
+        Insn::Move { dst, .. } |
+        Insn::SignExt { dst, .. } |
+        Insn::Truncate { dst, .. } |
+        Insn::Unary { dst, .. } |
+        Insn::Binary { dst, .. } => {
+            let value = eval_insn(current, insn);
+            current.set(*dst, value);
+        },
+        Insn::FuncCall { target, dst, .. } => {
+            current.kill_modified_by(*target, summaries);
+            current.set(*dst, None);
+        },
+        // other instructions do not involve assignments.
+        _ => {}
+    }
+}
+
+impl CodeGen<Opt> {
+    pub fn sccp(&mut self, func: Function) -> Function {
+        match func {
+            Function::Declared {..} => func,
+            Function::Defined {
+                return_type,
+                linkage,
+                name,
+                params,
+                local_vars,
+                body,
+                span,
+            } => {
+                let cfg = Graph::build(body);
+
+                let analysis = Sccp::new(&cfg, &self.summaries);
+                let result = analysis.analyze();
+
+                let opted_cfg = rewrite_graph(cfg, &result.insn_consts);
+
+                let opted_body = opted_cfg.emit();
+
+                Function::Defined {
+                    name,
+                    params,
+                    return_type,
+                    body: opted_body,
+                    linkage,
+                    local_vars,
+                    span,
+                }
+            }
+        }
+    }
+}
+
+fn rewrite_graph(
+    cfg: Graph,
+    insn_consts: &HashMap<InsnId, KnownConstants>,
+) -> Graph {
+    let mut cfg = cfg;
+
+    cfg.nodes = cfg.nodes.into_iter()
+        .map(|(id, node)| (id, rewrite_node(node, insn_consts)))
+        .collect();
+
+    cfg
+}
+
+fn rewrite_node(
+    node: Node,
+    insn_consts: &HashMap<InsnId, KnownConstants>,
+) -> Node {
+    match node {
+        Node::Entry {..} | Node::Exit {..} => node,
+        Node::BasicBlock(mut block) => {
+            block.insns = rewrite_insns(block.id, block.insns, insn_consts);
+            Node::BasicBlock(block)
+        }
+    }
+}
+
+fn rewrite_insns(
+    block_id: usize,
+    insns: Vec<Insn>,
+    insn_consts: &HashMap<InsnId, KnownConstants>,
+) -> Vec<Insn> {
+    insns.into_iter()
+        .enumerate()
+        .filter_map(|(inblock_id, insn)| {
+            let insn_id = InsnId::new(block_id, inblock_id);
+            // blocks that are never executed are not annotated, we leave them
+            // as they are and let `deadcode_elimination` remove them.
+            match insn_consts.get(&insn_id) {
+                Some(known) => rewrite_insn(insn, known),
+                None => Some(insn),
+            }
+        })
+        .collect()
+}
+
+fn rewrite_insn(
+    insn: Insn,
+    known: &KnownConstants,
+) -> Option<Insn> {
+    match insn {
+        Insn::Move { dst, .. } |
+        Insn::SignExt { dst, .. } |
+        Insn::Truncate { dst, .. } |
+        Insn::Unary { dst, .. } |
+        Insn::Binary { dst, .. } if is_tracked(dst.data_type()) => {
+            match eval_insn(known, &insn) {
+                Some(value) => Some(Insn::Move {
+                    src: Operand::Imm(convert(value, dst.data_type())),
+                    dst,
+                }),
+                None => Some(rewrite_operands(insn, known)),
+            }
+        },
+        Insn::BranchIfZero { src, label } => match known.get(src) {
+            Some(value) if value.is_zero() => Some(Insn::Jump(label)),
+            Some(_) => None,
+            None => Some(insn),
+        },
+        Insn::BranchNotZero { src, label } => match known.get(src) {
+            Some(value) if value.is_zero() => None,
+            Some(_) => Some(Insn::Jump(label)),
+            None => Some(insn),
+        },
+        _ => Some(rewrite_operands(insn, known)),
+    }
+}
+
+fn rewrite_operands(
+    insn: Insn,
+    known: &KnownConstants,
+) -> Insn {
+    match insn {
+        Insn::Move { src, dst } => Insn::Move {
+            src: rewrite_operand(src, known),
+            dst,
+        },
+        Insn::SignExt { src, dst } => Insn::SignExt {
+            src: rewrite_operand(src, known),
+            dst,
+        },
+        Insn::Truncate { src, dst } => Insn::Truncate {
+            src: rewrite_operand(src, known),
+            dst,
+        },
+        Insn::Unary { op, src, dst } => Insn::Unary {
+            op,
+            src: rewrite_operand(src, known),
+            dst,
+        },
+        Insn::Binary { op, left, right, dst } => Insn::Binary {
+            op,
+            left: rewrite_operand(left, known),
+            right: rewrite_operand(right, known),
+            dst,
+        },
+        Insn::FuncCall { target, args, dst } => Insn::FuncCall {
+            target,
+            args: args.into_iter()
+                .map(|arg| rewrite_operand(arg, known))
+                .collect(),
+            dst,
+        },
+        Insn::Return(src) => Insn::Return(rewrite_operand(src, known)),
+        _ => insn,
+    }
+}
+
+fn rewrite_operand(
+    operand: Operand,
+    known: &KnownConstants,
+) -> Operand {
+    match operand {
+        Operand::Imm(_) => operand,
+        _ => match known.get(operand) {
+            Some(value) => Operand::Imm(convert(value, operand.data_type())),
+            None => operand,
+        },
+    }
+}
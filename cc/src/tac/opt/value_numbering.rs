@@ -0,0 +1,394 @@
+//! Value numbering, which removes redundant computations.
+//!
+//! Every value gets a number; two computations applying the same operator to
+//! the same value numbers produce the same value, so the second one can be
+//! replaced with a copy from an operand still holding the first result.
+//!
+//! The local variant works on each basic block in isolation. The global variant
+//! walks the dominator tree, so a block also sees the values computed by its
+//! dominators. Since TAC is not in SSA form, an operand known to a dominator
+//! is only inherited if it is not redefined on any path between the two
+//! blocks. `FuncCall` may clobber any static variable.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::common::*;
+use crate::tac::opt::cfg::BasicBlock;
+use super::{
+    Operand,
+    Insn,
+    Function,
+    UnaryOp,
+    BinaryOp,
+    Opt,
+    CodeGen,
+};
+use super::cfg::{
+    Node,
+    NodeId,
+    Graph,
+};
+
+type ValueNumber = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Expr {
+    Const(Constant),
+    Unary(UnaryOp, ValueNumber, DataType),
+    Binary(BinaryOp, ValueNumber, ValueNumber, DataType),
+    SignExt(ValueNumber),
+    Truncate(ValueNumber),
+}
+
+impl Expr {
+    fn binary(op: BinaryOp, left: ValueNumber, right: ValueNumber, data_type: DataType) -> Self {
+        let commutative = matches!(
+            op,
+            BinaryOp::Add | BinaryOp::Mul | BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::And | BinaryOp::Or
+        );
+        if commutative && left > right {
+            Expr::Binary(op, right, left, data_type)
+        } else {
+            Expr::Binary(op, left, right, data_type)
+        }
+    }
+}
+
+/// Value numbers shared by the whole function. Value numbers are never
+/// invalidated, only the operands holding them are.
+#[derive(Debug)]
+struct ValueTable {
+    exprs: HashMap<Expr, ValueNumber>,
+    next_vn: ValueNumber,
+}
+
+impl ValueTable {
+    fn new() -> Self {
+        ValueTable {
+            exprs: HashMap::new(),
+            next_vn: 0,
+        }
+    }
+
+    fn fresh(&mut self) -> ValueNumber {
+        self.next_vn += 1;
+        self.next_vn
+    }
+
+    fn lookup_or_insert(&mut self, expr: Expr) -> ValueNumber {
+        if let Some(&vn) = self.exprs.get(&expr) {
+            return vn;
+        }
+        let vn = self.fresh();
+        self.exprs.insert(expr, vn);
+        vn
+    }
+}
+
+/// Which operands hold which value at some program point.
+#[derive(Debug, Clone)]
+struct Holders {
+    vn_of: HashMap<Operand, ValueNumber>,
+    // in order of assignment, so that we always reuse the oldest holder
+    holders: HashMap<ValueNumber, Vec<Operand>>,
+}
+
+impl Holders {
+    fn new() -> Self {
+        Holders {
+            vn_of: HashMap::new(),
+            holders: HashMap::new(),
+        }
+    }
+
+    fn value_of(&mut self, operand: Operand, table: &mut ValueTable) -> ValueNumber {
+        match operand {
+            Operand::Imm(constant) => table.lookup_or_insert(Expr::Const(constant)),
+            _ => match self.vn_of.get(&operand) {
+                Some(&vn) => vn,
+                None => {
+                    // first use of a value we know nothing about
+                    let vn = table.fresh();
+                    self.assign(operand, vn);
+                    vn
+                }
+            },
+        }
+    }
+
+    fn holder(&self, vn: ValueNumber) -> Option<Operand> {
+        self.holders.get(&vn).and_then(|holders| holders.first().cloned())
+    }
+
+    fn assign(&mut self, dst: Operand, vn: ValueNumber) {
+        self.kill(dst);
+        self.vn_of.insert(dst, vn);
+        self.holders.entry(vn).or_default().push(dst);
+    }
+
+    fn kill(&mut self, operand: Operand) {
+        if let Some(vn) = self.vn_of.remove(&operand)
+            && let Some(holders) = self.holders.get_mut(&vn) {
+            holders.retain(|holder| *holder != operand);
+        }
+    }
+
+    fn kill_static_vars(&mut self) {
+        let statics = self.vn_of.keys()
+            .filter(|operand| operand.is_static())
+            .cloned()
+            .collect::<Vec<_>>();
+        for operand in statics {
+            self.kill(operand);
+        }
+    }
+}
+
+/// Operands assigned in a set of blocks.
+#[derive(Debug, Default)]
+struct Defs {
+    operands: HashSet<Operand>,
+    has_call: bool,
+}
+
+impl Defs {
+    fn of_block(block: &BasicBlock) -> Self {
+        let mut defs = Defs::default();
+        for insn in block.insns.iter() {
+            match insn {
+                Insn::Move { dst, .. } |
+                Insn::SignExt { dst, .. } |
+                Insn::Truncate { dst, .. } |
+                Insn::Unary { dst, .. } |
+                Insn::Binary { dst, .. } => {
+                    defs.operands.insert(*dst);
+                },
+                Insn::FuncCall { dst, .. } => {
+                    defs.operands.insert(*dst);
+                    defs.has_call = true;
+                },
+                _ => {},
+            }
+        }
+        defs
+    }
+
+    fn extend(&mut self, other: &Defs) {
+        self.operands.extend(other.operands.iter().cloned());
+        self.has_call |= other.has_call;
+    }
+
+    fn kill_in(&self, holders: &mut Holders) {
+        for operand in self.operands.iter() {
+            holders.kill(*operand);
+        }
+        if self.has_call {
+            holders.kill_static_vars();
+        }
+    }
+}
+
+fn value_number_insns(
+    insns: Vec<Insn>,
+    holders: &mut Holders,
+    table: &mut ValueTable,
+) -> Vec<Insn> {
+    insns.into_iter()
+        .filter_map(|insn| value_number_insn(insn, holders, table))
+        .collect()
+}
+
+fn value_number_insn(
+    insn: Insn,
+    holders: &mut Holders,
+    table: &mut ValueTable,
+) -> Option<Insn> {
+    let (dst, expr) = match insn {
+        Insn::Move { src, dst } => {
+            let vn = holders.value_of(src, table);
+            if holders.vn_of.get(&dst) == Some(&vn) {
+                // dst already holds this value
+                return None;
+            }
+            holders.assign(dst, vn);
+            return Some(insn);
+        },
+        Insn::FuncCall { dst, .. } => {
+            holders.kill_static_vars();
+            holders.kill(dst);
+            return Some(insn);
+        },
+        Insn::Unary { op, src, dst } => {
+            let src = holders.value_of(src, table);
+            (dst, Expr::Unary(op, src, dst.data_type()))
+        },
+        Insn::Binary { op, left, right, dst } => {
+            let left = holders.value_of(left, table);
+            let right = holders.value_of(right, table);
+            (dst, Expr::binary(op, left, right, dst.data_type()))
+        },
+        Insn::SignExt { src, dst } => (dst, Expr::SignExt(holders.value_of(src, table))),
+        Insn::Truncate { src, dst } => (dst, Expr::Truncate(holders.value_of(src, table))),
+        _ => return Some(insn),
+    };
+
+    let vn = table.lookup_or_insert(expr);
+    let rewritten = match holders.holder(vn) {
+        Some(holder) if holder == dst => return None,
+        Some(holder) => Insn::Move { src: holder, dst },
+        None => insn,
+    };
+    holders.assign(dst, vn);
+
+    Some(rewritten)
+}
+
+/// For every block, the operands that may be redefined between the end of its
+/// immediate dominator and its own beginning.
+fn defs_since_idom(
+    cfg: &Graph,
+    tree: &BTreeMap<NodeId, Vec<NodeId>>,
+) -> HashMap<NodeId, Defs> {
+    let block_defs = cfg.nodes.values()
+        .filter_map(|node| match node {
+            Node::BasicBlock(block) => Some((node.id(), Defs::of_block(block))),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    // nodes reachable from `from` by following `next` without passing through `avoid`
+    let reach = |from: &[NodeId], avoid: NodeId, next: &dyn Fn(NodeId) -> Vec<NodeId>| {
+        let mut visited = HashSet::new();
+        let mut stack = from.iter()
+            .cloned()
+            .filter(|id| *id != avoid)
+            .collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            stack.extend(next(id).into_iter().filter(|id| *id != avoid));
+        }
+        visited
+    };
+    let successors = |id| cfg.successors(id).iter().cloned().collect();
+    let predecessors = |id| cfg.predecessors(id).iter().cloned().collect();
+
+    let mut result = HashMap::new();
+    for (&idom, children) in tree.iter() {
+        for &child in children {
+            let succs = cfg.successors(idom).iter().cloned().collect::<Vec<_>>();
+            let preds = cfg.predecessors(child).iter().cloned().collect::<Vec<_>>();
+            let forward = reach(&succs, idom, &successors);
+            let backward = reach(&preds, idom, &predecessors);
+
+            let mut defs = Defs::default();
+            for id in forward.intersection(&backward) {
+                if let Some(block) = block_defs.get(id) {
+                    defs.extend(block);
+                }
+            }
+            result.insert(child, defs);
+        }
+    }
+
+    result
+}
+
+fn global_value_number(
+    cfg: &mut Graph,
+    id: NodeId,
+    mut holders: Holders,
+    table: &mut ValueTable,
+    tree: &BTreeMap<NodeId, Vec<NodeId>>,
+    kills: &HashMap<NodeId, Defs>,
+) {
+    if let Some(Node::BasicBlock(block)) = cfg.nodes.get_mut(&id) {
+        let insns = std::mem::take(&mut block.insns);
+        block.insns = value_number_insns(insns, &mut holders, table);
+    }
+
+    for child in tree.get(&id).into_iter().flatten() {
+        let mut inherited = holders.clone();
+        if let Some(defs) = kills.get(child) {
+            defs.kill_in(&mut inherited);
+        }
+        global_value_number(cfg, *child, inherited, table, tree, kills);
+    }
+}
+
+impl CodeGen<Opt> {
+    pub fn local_value_numbering(&mut self, func: Function) -> Function {
+        match func {
+            Function::Declared {..} => func,
+            Function::Defined {
+                return_type,
+                linkage,
+                name,
+                params,
+                local_vars,
+                body,
+                span,
+            } => {
+                let mut cfg = Graph::build(body);
+                let mut table = ValueTable::new();
+
+                for node in cfg.nodes.values_mut() {
+                    if let Node::BasicBlock(block) = node {
+                        let insns = std::mem::take(&mut block.insns);
+                        block.insns = value_number_insns(insns, &mut Holders::new(), &mut table);
+                    }
+                }
+
+                Function::Defined {
+                    name,
+                    params,
+                    return_type,
+                    body: cfg.emit(),
+                    linkage,
+                    local_vars,
+                    span,
+                }
+            }
+        }
+    }
+
+    pub fn global_value_numbering(&mut self, func: Function) -> Function {
+        match func {
+            Function::Declared {..} => func,
+            Function::Defined {
+                return_type,
+                linkage,
+                name,
+                params,
+                local_vars,
+                body,
+                span,
+            } => {
+                let mut cfg = Graph::build(body);
+                let tree = cfg.dominator_tree();
+                let kills = defs_since_idom(&cfg, &tree);
+                let mut table = ValueTable::new();
+
+                global_value_number(
+                    &mut cfg,
+                    NodeId::Entry,
+                    Holders::new(),
+                    &mut table,
+                    &tree,
+                    &kills,
+                );
+
+                Function::Defined {
+                    name,
+                    params,
+                    return_type,
+                    body: cfg.emit(),
+                    linkage,
+                    local_vars,
+                    span,
+                }
+            }
+        }
+    }
+}
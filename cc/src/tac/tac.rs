@@ -5,7 +5,7 @@ use crate::sem::{
     HirBinaryOp, HirParam, HirUnaryOp,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
     Pos,
     Negate,
@@ -13,7 +13,7 @@ pub enum UnaryOp {
     Not,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -66,6 +66,7 @@ pub struct Param {
     pub name: StrDescriptor,
     pub data_type: DataType,
     pub local_id: usize,
+    pub span: Span,
 }
 
 impl From<HirParam> for Param {
@@ -74,6 +75,7 @@ impl From<HirParam> for Param {
             name: param.name,
             data_type: param.data_type,
             local_id: param.local_id,
+            span: param.span,
         }
     }
 }
@@ -148,6 +150,8 @@ pub enum Insn {
         src: Operand,
         dst: Operand,
     },
+    /// the source location of what follows, only generated with `-g`
+    Loc(Span),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -155,6 +159,8 @@ pub struct LocalVar {
     pub name: StrDescriptor,
     pub local_id: usize,
     pub data_type: DataType,
+    // None for the variables and parameters of inlined functions
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone)]
@@ -166,6 +172,7 @@ pub enum Function {
         params: Vec<Param>,
         local_vars: HashMap<usize, LocalVar>,
         body: Vec<Insn>,
+        span: Span,
     },
     Declared {
         linkage: Linkage,
@@ -199,6 +206,7 @@ pub struct StaticVar {
     pub data_type: DataType,
     pub initializer: InitVal,
     pub linkage: Linkage,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
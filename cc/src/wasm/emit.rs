@@ -0,0 +1,674 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+use crate::{
+    common::*,
+    tac::{
+        TacTopLevel,
+        TacFunction,
+        TacStaticVar,
+        TacInsn,
+        TacOperand,
+        TacUnaryOp,
+        TacBinaryOp,
+        TacGraph,
+        TacNode,
+        TacNodeId,
+    },
+};
+
+/// Lowers a translation unit into a WebAssembly text module.
+/// Imports come first, as the text format requires, then globals and functions,
+/// each sorted by name, so the output is stable.
+pub fn emit(tac: &TacTopLevel) -> String {
+    let mut output = String::from("(module\n");
+
+    let signatures = tac.functions.iter()
+        .map(|(&name, func)| (name, func.type_()))
+        .collect::<HashMap<_, _>>();
+
+    // only what is used is imported, the host has to provide every import
+    let mut called = HashSet::new();
+    let mut used_statics = HashSet::new();
+    for func in tac.functions.values() {
+        let TacFunction::Defined { body, .. } = func else {
+            continue;
+        };
+        for insn in body {
+            if let TacInsn::FuncCall { target, .. } = insn {
+                called.insert(*target);
+            }
+            for operand in insn.operands() {
+                if let TacOperand::Var { name, local_id: None, .. } = operand {
+                    used_statics.insert(*name);
+                }
+            }
+        }
+    }
+
+    let mut static_vars = tac.static_vars.values()
+        .map(|var| (tac.strtb.get(var.name).unwrap(), var))
+        .collect::<Vec<_>>();
+    static_vars.sort_by_key(|&(name, _)| name);
+    let mut functions = tac.functions.iter()
+        .map(|(&name, func)| (tac.strtb.get(name).unwrap(), func))
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|&(name, _)| name);
+
+    for &(name, func) in &functions {
+        if let TacFunction::Declared { type_, .. } = func && called.contains(&func.name()) {
+            output.push_str(&format!(
+                "  (import \"env\" \"{}\" (func ${}{}))\n",
+                name, name, func_type(type_),
+            ));
+        }
+    }
+    for &(name, var) in &static_vars {
+        if let InitVal::None = var.initializer && used_statics.contains(&var.name) {
+            output.push_str(&format!(
+                "  (import \"env\" \"{}\" (global ${} (mut {})))\n",
+                name, name, wasm_type(var.data_type),
+            ));
+        }
+    }
+
+    for &(name, var) in &static_vars {
+        if let Some(global) = emit_static_var(name, var) {
+            output.push_str(&global);
+        }
+    }
+
+    for &(name, func) in &functions {
+        if let TacFunction::Defined { .. } = func {
+            let emitter = FuncEmitter::new(tac, &signatures);
+            output.push_str(&emitter.emit(name, func));
+        }
+    }
+
+    output.push_str(")\n");
+    output
+}
+
+fn emit_static_var(name: &str, var: &TacStaticVar) -> Option<String> {
+    let type_ = wasm_type(var.data_type);
+    let export = match var.linkage {
+        Linkage::Internal => String::new(),
+        Linkage::External => format!("(export \"{}\") ", name),
+    };
+    let val = match var.initializer {
+        InitVal::Const(val) => val.value(),
+        InitVal::Tentative => 0,
+        InitVal::None => return None,
+    };
+    Some(format!("  (global ${} {}(mut {}) ({}.const {}))\n", name, export, type_, type_, val))
+}
+
+/// The parameter and result types of a function, as in an import.
+fn func_type(type_: &FuncType) -> String {
+    let mut output = String::new();
+    if !type_.param_types.is_empty() {
+        let params = type_.param_types.iter()
+            .map(|&type_| wasm_type(type_))
+            .collect::<Vec<_>>()
+            .join(" ");
+        output.push_str(&format!(" (param {})", params));
+    }
+    if type_.return_type != DataType::Void {
+        output.push_str(&format!(" (result {})", wasm_type(type_.return_type)));
+    }
+    output
+}
+
+fn wasm_type(type_: DataType) -> &'static str {
+    match type_ {
+        DataType::Int => "i32",
+        DataType::Long => "i64",
+        DataType::Void | DataType::Indeterminate => unreachable!(),
+    }
+}
+
+/// The CFG of a function, along with what recovering structured control flow from it takes.
+struct Cfg {
+    graph: TacGraph,
+    /// reverse postorder numbers of the reachable nodes
+    order: HashMap<TacNodeId, usize>,
+    dominator_tree: BTreeMap<TacNodeId, Vec<TacNodeId>>,
+    /// targets of back edges
+    loop_headers: HashSet<TacNodeId>,
+    /// targets of more than one forward edge
+    merge_nodes: HashSet<TacNodeId>,
+    /// whether every retreating edge goes back to a node dominating its source
+    reducible: bool,
+}
+
+/// How control leaves a basic block.
+enum Terminator<'a> {
+    Return(&'a TacOperand),
+    Goto(TacNodeId),
+    Branch {
+        src: &'a TacOperand,
+        /// whether the branch is taken when `src` is zero, or when it isn't
+        if_zero: bool,
+        taken: TacNodeId,
+        fall: TacNodeId,
+    },
+}
+
+impl Cfg {
+    fn new(body: &[TacInsn]) -> Self {
+        let graph = TacGraph::build(body.to_vec());
+        let rpo = graph.reverse_postorder();
+        let order = rpo.iter()
+            .enumerate()
+            .map(|(idx, id)| (*id, idx))
+            .collect::<HashMap<_, _>>();
+        let idoms = graph.immediate_dominators();
+        let dominator_tree = graph.dominator_tree();
+
+        let mut loop_headers = HashSet::new();
+        let mut forward_edges = HashMap::new();
+        let mut reducible = true;
+        for &id in &rpo {
+            // returns don't branch to the exit, they leave the function
+            for &succ in graph.successors(id).iter().filter(|&&succ| succ != TacNodeId::Exit) {
+                if order[&succ] <= order[&id] {
+                    loop_headers.insert(succ);
+                    reducible &= TacGraph::dominates(&idoms, succ, id);
+                } else {
+                    *forward_edges.entry(succ).or_insert(0) += 1;
+                }
+            }
+        }
+        let merge_nodes = forward_edges.into_iter()
+            .filter(|&(_, count)| count > 1)
+            .map(|(id, _)| id)
+            .collect();
+
+        Self {
+            graph,
+            order,
+            dominator_tree,
+            loop_headers,
+            merge_nodes,
+            reducible,
+        }
+    }
+
+    fn insns(&self, id: TacNodeId) -> &[TacInsn] {
+        match &self.graph.nodes[&id] {
+            TacNode::BasicBlock(block) => &block.insns,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reachable basic blocks, in program order.
+    fn blocks(&self) -> Vec<TacNodeId> {
+        self.graph.nodes.keys()
+            .filter(|id| matches!(id, TacNodeId::BasicBlock(..)) && self.order.contains_key(id))
+            .cloned()
+            .collect()
+    }
+
+    fn terminator(&self, id: TacNodeId) -> Terminator<'_> {
+        let TacNodeId::BasicBlock(idx) = id else {
+            unreachable!()
+        };
+        // the entry and the exit are the two nodes which aren't basic blocks
+        let fall = if idx == self.graph.nodes.len() - 3 {
+            TacNodeId::Exit
+        } else {
+            TacNodeId::BasicBlock(idx + 1)
+        };
+        match self.insns(id).last() {
+            Some(TacInsn::Return(val)) => Terminator::Return(val),
+            Some(TacInsn::Jump(label)) => Terminator::Goto(self.graph.label_map[label]),
+            Some(TacInsn::BranchIfZero { src, label }) |
+            Some(TacInsn::BranchNotZero { src, label }) => Terminator::Branch {
+                src,
+                if_zero: matches!(self.insns(id).last(), Some(TacInsn::BranchIfZero { .. })),
+                taken: self.graph.label_map[label],
+                fall,
+            },
+            _ => Terminator::Goto(fall),
+        }
+    }
+
+    /// The label to branch to from `from` to get to `to`, if it isn't placed right there.
+    fn br_label(&self, from: TacNodeId, to: TacNodeId) -> Option<String> {
+        if to == TacNodeId::Exit {
+            None
+        } else if self.order[&to] <= self.order[&from] {
+            Some(loop_label(to))
+        } else if self.merge_nodes.contains(&to) {
+            Some(block_label(to))
+        } else {
+            None
+        }
+    }
+
+    /// Children of `id` in the dominator tree which are merge nodes,
+    /// the one placed last first.
+    fn merge_children(&self, id: TacNodeId) -> Vec<TacNodeId> {
+        let mut children = self.dominator_tree[&id].iter()
+            .filter(|child| self.merge_nodes.contains(child))
+            .cloned()
+            .collect::<Vec<_>>();
+        children.sort_by_key(|child| Reverse(self.order[child]));
+        children
+    }
+}
+
+fn loop_label(id: TacNodeId) -> String {
+    match id {
+        TacNodeId::BasicBlock(idx) => format!("$L{}", idx),
+        _ => unreachable!(),
+    }
+}
+
+fn block_label(id: TacNodeId) -> String {
+    match id {
+        TacNodeId::BasicBlock(idx) => format!("$B{}", idx),
+        _ => unreachable!(),
+    }
+}
+
+/// Lowers one function onto the operand stack: operands are pushed with `local.get`,
+/// `global.get` and `const`, and results popped into their local or global.
+/// Variables are locals named `$name.id`, temporaries `$tN`.
+///
+/// Control flow is recovered from the CFG as in Ramsey's "Beyond Relooper":
+/// loop headers are wrapped in a `loop`, and every node whose idom it is with more than one
+/// forward edge into it is placed right after a `block` wrapping the code of its idom,
+/// so back edges branch to the start of a `loop` and forward edges to the end of a `block`,
+/// the other nodes are placed where they are branched to.
+/// Irreducible control flow falls back to a loop dispatching on a `$label` local.
+struct FuncEmitter<'a> {
+    tac: &'a TacTopLevel,
+    signatures: &'a HashMap<StrDescriptor, FuncType>,
+    return_type: DataType,
+    output: String,
+    /// nesting of the structured instructions
+    depth: usize,
+}
+
+impl<'a> FuncEmitter<'a> {
+    fn new(tac: &'a TacTopLevel, signatures: &'a HashMap<StrDescriptor, FuncType>) -> Self {
+        Self {
+            tac,
+            signatures,
+            return_type: DataType::Void,
+            output: String::new(),
+            depth: 0,
+        }
+    }
+
+    fn emit(mut self, name: &str, func: &TacFunction) -> String {
+        let TacFunction::Defined { return_type, linkage, params, local_vars, body, .. } = func else {
+            unreachable!()
+        };
+        self.return_type = *return_type;
+
+        let mut header = format!("  (func ${}", name);
+        if let Linkage::External = linkage {
+            header.push_str(&format!(" (export \"{}\")", name));
+        }
+        for param in params {
+            header.push_str(&format!(
+                " (param ${}.{} {})",
+                self.tac.strtb.get(param.name).unwrap(), param.local_id, wasm_type(param.data_type),
+            ));
+        }
+        if *return_type != DataType::Void {
+            header.push_str(&format!(" (result {})", wasm_type(*return_type)));
+        }
+        self.output.push_str(&header);
+        self.output.push('\n');
+
+        let vars = local_vars.values()
+            .map(|var| (var.local_id, (var.name, var.data_type)))
+            .collect::<BTreeMap<_, _>>();
+        let mut temps = BTreeMap::new();
+        for operand in body.iter().flat_map(TacInsn::operands) {
+            match *operand {
+                // where the result of a `void` call would go
+                TacOperand::Temp(_, DataType::Void) => (),
+                TacOperand::Temp(id, data_type) => {
+                    temps.entry(id).or_insert(data_type);
+                },
+                _ => (),
+            }
+        }
+        for (&id, &(name, data_type)) in &vars {
+            self.line(format!("(local ${}.{} {})", self.tac.strtb.get(name).unwrap(), id, wasm_type(data_type)));
+        }
+        for (&id, &data_type) in &temps {
+            self.line(format!("(local $t{} {})", id, wasm_type(data_type)));
+        }
+
+        let cfg = Cfg::new(body);
+        if cfg.reducible {
+            self.do_tree(&cfg, TacNodeId::BasicBlock(0));
+        } else {
+            self.line("(local $label i32)".to_string());
+            self.dispatch(&cfg);
+        }
+        // every path has left the function by now, but the validator doesn't know
+        self.line("unreachable".to_string());
+
+        self.output.push_str("  )\n");
+        self.output
+    }
+
+    /// The code of `id` and of what it dominates, wrapped in a `loop` if it is a loop header.
+    fn do_tree(&mut self, cfg: &Cfg, id: TacNodeId) {
+        let merge_children = cfg.merge_children(id);
+        if cfg.loop_headers.contains(&id) {
+            self.open(format!("loop {}", loop_label(id)));
+            self.node_within(cfg, id, &merge_children);
+            self.close();
+        } else {
+            self.node_within(cfg, id, &merge_children);
+        }
+    }
+
+    /// The code of `id`, followed by its merge children, each placed right after
+    /// the `block` the code before it is wrapped in.
+    fn node_within(&mut self, cfg: &Cfg, id: TacNodeId, merge_children: &[TacNodeId]) {
+        if let Some((&last, rest)) = merge_children.split_first() {
+            self.open(format!("block {}", block_label(last)));
+            self.node_within(cfg, id, rest);
+            self.close();
+            self.do_tree(cfg, last);
+            return;
+        }
+
+        for insn in cfg.insns(id) {
+            self.emit_insn(insn);
+        }
+        match cfg.terminator(id) {
+            Terminator::Return(val) => self.emit_return(val),
+            Terminator::Goto(target) => self.do_branch(cfg, id, target),
+            Terminator::Branch { taken, fall, .. } if taken == fall => self.do_branch(cfg, id, taken),
+            Terminator::Branch { src, if_zero, taken, fall } => {
+                if let Some(label) = cfg.br_label(id, taken) {
+                    self.push_cond(src, if_zero);
+                    self.line(format!("br_if {}", label));
+                    self.do_branch(cfg, id, fall);
+                } else if let Some(label) = cfg.br_label(id, fall) {
+                    self.push_cond(src, !if_zero);
+                    self.line(format!("br_if {}", label));
+                    self.do_branch(cfg, id, taken);
+                } else {
+                    self.push_cond(src, if_zero);
+                    self.open("if".to_string());
+                    self.do_branch(cfg, id, taken);
+                    self.depth -= 1;
+                    self.line("else".to_string());
+                    self.depth += 1;
+                    self.do_branch(cfg, id, fall);
+                    self.close();
+                }
+            },
+        }
+    }
+
+    fn do_branch(&mut self, cfg: &Cfg, from: TacNodeId, to: TacNodeId) {
+        if to == TacNodeId::Exit {
+            // falling off the end, which the `return 0` the code generator appends rules out
+            self.line("unreachable".to_string());
+        } else if let Some(label) = cfg.br_label(from, to) {
+            self.line(format!("br {}", label));
+        } else {
+            self.do_tree(cfg, to);
+        }
+    }
+
+    /// Irreducible control flow, as a `loop` around a `block` for each basic block,
+    /// `br_table` branching out of the one the `$label` local says, to the code of its block.
+    /// Going to another block sets `$label` and branches back to the dispatch,
+    /// unless the block is the next one, whose code follows.
+    fn dispatch(&mut self, cfg: &Cfg) {
+        let blocks = cfg.blocks();
+        let position = blocks.iter()
+            .enumerate()
+            .map(|(idx, id)| (*id, idx))
+            .collect::<HashMap<_, _>>();
+
+        self.line("i32.const 0".to_string());
+        self.line("local.set $label".to_string());
+        self.open("loop $dispatch".to_string());
+        for &id in blocks.iter().rev() {
+            self.line(format!("block {}", block_label(id)));
+        }
+        self.line("local.get $label".to_string());
+        let labels = blocks.iter()
+            .map(|&id| block_label(id))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.line(format!("br_table {}", labels));
+
+        for (idx, &id) in blocks.iter().enumerate() {
+            self.line("end".to_string());
+            for insn in cfg.insns(id) {
+                self.emit_insn(insn);
+            }
+            match cfg.terminator(id) {
+                Terminator::Return(val) => self.emit_return(val),
+                Terminator::Goto(target) => self.goto(&position, idx, target),
+                Terminator::Branch { taken, fall, .. } if taken == fall => self.goto(&position, idx, taken),
+                Terminator::Branch { src, if_zero, taken, fall } => {
+                    // the target which isn't the next block is gone to from an `if`
+                    let (if_zero, taken, fall) = if position.get(&taken) == Some(&(idx + 1)) {
+                        (!if_zero, fall, taken)
+                    } else {
+                        (if_zero, taken, fall)
+                    };
+                    self.push_cond(src, if_zero);
+                    self.open("if".to_string());
+                    self.goto(&position, idx, taken);
+                    self.close();
+                    self.goto(&position, idx, fall);
+                },
+            }
+        }
+        self.close();
+    }
+
+    fn goto(&mut self, position: &HashMap<TacNodeId, usize>, from: usize, to: TacNodeId) {
+        if to == TacNodeId::Exit {
+            self.line("unreachable".to_string());
+        } else if position[&to] != from + 1 {
+            self.line(format!("i32.const {}", position[&to]));
+            self.line("local.set $label".to_string());
+            self.line("br $dispatch".to_string());
+        }
+    }
+
+    /// Pushes an `i32` which is non-zero if `src` is zero, or if it isn't.
+    fn push_cond(&mut self, src: &TacOperand, if_zero: bool) {
+        let type_ = operand_type(src);
+        self.push(src, type_);
+        match (type_, if_zero) {
+            (_, true) => self.line(format!("{}.eqz", wasm_type(type_))),
+            (DataType::Long, false) => {
+                self.line("i64.eqz".to_string());
+                self.line("i32.eqz".to_string());
+            },
+            _ => (),
+        }
+    }
+
+    fn emit_return(&mut self, val: &TacOperand) {
+        if self.return_type != DataType::Void {
+            self.push(val, self.return_type);
+        }
+        self.line("return".to_string());
+    }
+
+    /// Emits a straight-line instruction, control flow is left to the terminators.
+    fn emit_insn(&mut self, insn: &TacInsn) {
+        match insn {
+            TacInsn::Unary { op, src, dst } => {
+                // `!` produces an `int`, the others keep the type of their operand
+                let type_ = match op {
+                    TacUnaryOp::Not => operand_type(src),
+                    _ => operand_type(dst),
+                };
+                let ty = wasm_type(type_);
+                match op {
+                    TacUnaryOp::Pos => self.push(src, type_),
+                    TacUnaryOp::Negate => {
+                        self.line(format!("{}.const 0", ty));
+                        self.push(src, type_);
+                        self.line(format!("{}.sub", ty));
+                    },
+                    TacUnaryOp::Complement => {
+                        self.push(src, type_);
+                        self.line(format!("{}.const -1", ty));
+                        self.line(format!("{}.xor", ty));
+                    },
+                    TacUnaryOp::Not => {
+                        self.push(src, type_);
+                        self.line(format!("{}.eqz", ty));
+                        self.extend_cond(dst);
+                    },
+                }
+                self.pop(dst);
+            },
+            TacInsn::Binary { op, left, right, dst } => {
+                let cond = match op {
+                    TacBinaryOp::Ls => Some("lt_s"),
+                    TacBinaryOp::Gt => Some("gt_s"),
+                    TacBinaryOp::LsEq => Some("le_s"),
+                    TacBinaryOp::GtEq => Some("ge_s"),
+                    TacBinaryOp::Eq => Some("eq"),
+                    TacBinaryOp::NotEq => Some("ne"),
+                    _ => None,
+                };
+                // comparisons produce an `int`, whatever their operands are
+                let type_ = match (cond, left, right) {
+                    (Some(_), TacOperand::Imm(..), operand) | (Some(_), operand, _) => operand_type(operand),
+                    (None, ..) => operand_type(dst),
+                };
+                self.push(left, type_);
+                self.push(right, type_);
+                match cond {
+                    Some(cond) => {
+                        self.line(format!("{}.{}", wasm_type(type_), cond));
+                        self.extend_cond(dst);
+                    },
+                    None => {
+                        let op = match op {
+                            TacBinaryOp::Add => "add",
+                            TacBinaryOp::Sub => "sub",
+                            TacBinaryOp::Mul => "mul",
+                            TacBinaryOp::Div => "div_s",
+                            TacBinaryOp::Rem => "rem_s",
+                            // short-circuited into branches by the code generator
+                            _ => unreachable!(),
+                        };
+                        self.line(format!("{}.{}", wasm_type(type_), op));
+                    },
+                }
+                self.pop(dst);
+            },
+            TacInsn::FuncCall { target, args, dst } => {
+                let signature = &self.signatures[target];
+                let return_type = signature.return_type;
+                for (arg, type_) in args.iter().zip(signature.param_types.clone()) {
+                    self.push(arg, type_);
+                }
+                self.line(format!("call ${}", self.tac.strtb.get(*target).unwrap()));
+                if return_type != DataType::Void {
+                    self.pop(dst);
+                }
+            },
+            TacInsn::Move { src, dst } => {
+                self.push(src, operand_type(dst));
+                self.pop(dst);
+            },
+            TacInsn::Truncate { src, dst } => {
+                self.push(src, DataType::Long);
+                self.line("i32.wrap_i64".to_string());
+                self.pop(dst);
+            },
+            TacInsn::SignExt { src, dst } => {
+                self.push(src, DataType::Int);
+                self.line("i64.extend_i32_s".to_string());
+                self.pop(dst);
+            },
+            TacInsn::Return(..) |
+            TacInsn::Label(..) |
+            TacInsn::Jump(..) |
+            TacInsn::BranchIfZero { .. } |
+            TacInsn::BranchNotZero { .. } |
+            TacInsn::Loc(..) => (),
+        }
+    }
+
+    /// Widens the `i32` a comparison pushes if it is stored into a `long`.
+    fn extend_cond(&mut self, dst: &TacOperand) {
+        if operand_type(dst) == DataType::Long {
+            self.line("i64.extend_i32_u".to_string());
+        }
+    }
+
+    /// Pushes the value of an operand, as `type_` if it is a constant.
+    fn push(&mut self, operand: &TacOperand, type_: DataType) {
+        let line = match operand {
+            TacOperand::Imm(val) => {
+                let val = match type_ {
+                    DataType::Int => val.value() as i32 as i64,
+                    _ => val.value(),
+                };
+                format!("{}.const {}", wasm_type(type_), val)
+            },
+            TacOperand::Var { local_id: None, .. } => format!("global.get {}", self.name(operand)),
+            _ => format!("local.get {}", self.name(operand)),
+        };
+        self.line(line);
+    }
+
+    fn pop(&mut self, dst: &TacOperand) {
+        let line = match dst {
+            TacOperand::Var { local_id: None, .. } => format!("global.set {}", self.name(dst)),
+            _ => format!("local.set {}", self.name(dst)),
+        };
+        self.line(line);
+    }
+
+    fn name(&self, operand: &TacOperand) -> String {
+        match operand {
+            TacOperand::Var { name, local_id: Some(id), .. } => format!("${}.{}", self.tac.strtb.get(*name).unwrap(), id),
+            TacOperand::Var { name, local_id: None, .. } => format!("${}", self.tac.strtb.get(*name).unwrap()),
+            TacOperand::Temp(id, _) => format!("$t{}", id),
+            TacOperand::Imm(..) => unreachable!(),
+        }
+    }
+
+    /// Starts a structured instruction, indenting its body.
+    fn open(&mut self, line: String) {
+        self.line(line);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("end".to_string());
+    }
+
+    fn line(&mut self, line: String) {
+        self.output.push_str(&format!("    {}{}\n", "  ".repeat(self.depth), line));
+    }
+}
+
+fn operand_type(operand: &TacOperand) -> DataType {
+    match operand {
+        TacOperand::Imm(Constant::Int(..)) => DataType::Int,
+        TacOperand::Imm(Constant::Long(..)) => DataType::Long,
+        TacOperand::Var { data_type, .. } |
+        TacOperand::Temp(_, data_type) => *data_type,
+    }
+}
@@ -0,0 +1,127 @@
+//! WebAssembly backend, lowering TAC into a module in the text format (WAT),
+//! to be assembled with `wat2wasm` and run by any engine.
+//! - `int` and `long` are `i32` (ILP32, as for `wasm32` in clang) and `long long` is `i64`
+//! - variables and temporaries are locals, static variables are mutable globals
+//! - control flow is recovered from the TAC CFG as `block`, `loop` and `br_if`,
+//!   irreducible control flow is dispatched on a local in a loop instead
+//! - functions and variables with external linkage are exported,
+//!   undefined ones are imported from the `env` module
+
+mod emit;
+
+pub use emit::emit;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::write;
+    use std::process::Command;
+
+    use crate::common::*;
+    use crate::driver::{compile, Options, Target};
+    use crate::host;
+    use crate::tac::{
+        TacTopLevel,
+        TacFunction,
+        TacInsn,
+        TacOperand,
+        TacBinaryOp,
+        TacParam,
+        TacLabelOperand,
+        TacAutoGenLabel,
+    };
+
+    fn wasm(source: &str, opt_level: u8) -> String {
+        let options = Options {
+            target: Target::Wasm32,
+            opt_level,
+            ..Options::default()
+        };
+        compile(source.to_string(), &options).unwrap()
+    }
+
+    /// Whether every structured instruction is closed, and nothing more.
+    fn balanced(wat: &str) -> bool {
+        let mut depth = 0;
+        for line in wat.lines().map(str::trim) {
+            if line.starts_with("block") || line.starts_with("loop") || line == "if" {
+                depth += 1;
+            } else if line == "end" {
+                depth -= 1;
+            }
+            if depth < 0 {
+                return false;
+            }
+        }
+        depth == 0
+    }
+
+    #[test]
+    fn test_wasm() {
+        if !host::has("wat2wasm") || !host::has("node") {
+            return;
+        }
+        // instantiated with `putchar` and returning what `main` does
+        const RUN: &str = "const bytes = require('fs').readFileSync(process.argv[2]);
+            const env = { putchar: (c) => { process.stdout.write(String.fromCharCode(c)); return c; } };
+            WebAssembly.instantiate(bytes, { env }).then(({ instance }) => process.exit(instance.exports.main() & 255));";
+
+        // tail_call.c recurses deeper than the engine's stack, as calls in tail position stay calls
+        host::check("wasm", &["tail_call"], |dir, name, source, opt_level| {
+            let wat = dir.join(format!("{}.{}.wat", name, opt_level));
+            let module = dir.join(format!("{}.{}.wasm", name, opt_level));
+            let run = dir.join("run.js");
+            write(&wat, wasm(source, opt_level)).unwrap();
+            write(&run, RUN).unwrap();
+            let (code, _) = host::run(Command::new("wat2wasm").arg(&wat).arg("-o").arg(&module));
+            assert_eq!(code, 0, "wat2wasm {}", wat.display());
+            let mut node = Command::new("node");
+            node.arg(run).arg(module);
+            node
+        });
+    }
+
+    #[test]
+    fn test_wasm_irreducible() {
+        // the loop between the two decrements is entered at either of them
+        let mut strtb = StringPool::new();
+        let name = strtb.intern("f".to_string());
+        let n_name = strtb.intern("n".to_string());
+        let n = TacOperand::Var { name: n_name, local_id: Some(0), data_type: DataType::Int };
+        let label = |id| TacLabelOperand::AutoGen(TacAutoGenLabel::Branch(id));
+        let decrement = TacInsn::Binary { op: TacBinaryOp::Sub, left: n, right: TacOperand::Imm(Constant::Int(1)), dst: n };
+        let body = vec![
+            TacInsn::BranchNotZero { src: n, label: label(1) },
+            TacInsn::Label(label(0)),
+            decrement.clone(),
+            TacInsn::BranchIfZero { src: n, label: label(2) },
+            TacInsn::Label(label(1)),
+            decrement,
+            TacInsn::BranchNotZero { src: n, label: label(0) },
+            TacInsn::Label(label(2)),
+            TacInsn::Return(n),
+        ];
+        let param = TacParam { name: n_name, data_type: DataType::Int, local_id: 0, span: Span::new(1, 1) };
+        let func = TacFunction::Defined {
+            return_type: DataType::Int,
+            linkage: Linkage::External,
+            name,
+            params: vec![param],
+            local_vars: HashMap::new(),
+            body,
+            span: Span::new(1, 1),
+        };
+        let tac = TacTopLevel {
+            functions: HashMap::from([(name, func)]),
+            static_vars: HashMap::new(),
+            strtb,
+        };
+
+        let wat = crate::wasm::emit(&tac);
+        assert!(wat.contains("    (local $label i32)\n"));
+        assert!(wat.contains("loop $dispatch\n"));
+        assert!(wat.contains("br_table $B0 $B1 $B2 $B3\n"));
+        assert!(wat.contains("br $dispatch\n"));
+        assert!(balanced(&wat), "{}", wat);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{common::*, tac::TacStaticVar};
+use super::{
+    Size,
+    Operand,
+    BinaryOp,
+    UnaryOp,
+    Insn,
+    LabelOperand,
+    Function,
+    TopLevel,
+};
+
+impl TopLevel {
+    /// Functions and static variables are emitted sorted by name, so the output is stable.
+    pub fn emit(&self) -> String {
+        let mut output = String::new();
+        output.push_str(&self.emit_code());
+        output.push_str(&self.emit_data());
+        output.push_str(&self.emit_bss());
+        // no executable stack
+        output.push_str("\t.section\t.note.GNU-stack,\"\",@progbits\n");
+        output
+    }
+
+    pub fn emit_code(&self) -> String {
+        let mut output = String::new();
+        output.push_str("\t.text\n");
+
+        let mut functions = self.functions.values().collect::<Vec<_>>();
+        functions.sort_by_key(|func| self.strtb.get(func.name).unwrap());
+        for func in functions {
+            output.push_str(&self.emit_func(func));
+        }
+        output
+    }
+
+    pub fn emit_data(&self) -> String {
+        let vars = self.sorted_static_vars(|var| matches!(var.initializer, InitVal::Const(c) if !c.is_zero()));
+        let mut output = String::new();
+        if vars.is_empty() {
+            return output;
+        }
+
+        output.push_str("\t.data\n");
+        for var in vars {
+            output.push_str(&self.emit_static_header(var));
+            let init_str = match var.initializer {
+                InitVal::Const(Constant::Int(i)) =>
+                    format!("\t.long\t{}\n", i),
+                InitVal::Const(Constant::Long(l)) =>
+                    format!("\t.quad\t{}\n", l),
+                InitVal::Tentative => unreachable!(), // these should be put in .bss segment
+                InitVal::None => unreachable!(),
+            };
+            output.push_str(&init_str);
+        }
+
+        output
+    }
+
+    pub fn emit_bss(&self) -> String {
+        let vars = self.sorted_static_vars(|var| match var.initializer {
+            InitVal::Const(c) => c.is_zero(),
+            InitVal::Tentative => true,
+            InitVal::None => false,
+        });
+        let mut output = String::new();
+        if vars.is_empty() {
+            return output;
+        }
+
+        output.push_str("\t.bss\n");
+        for var in vars {
+            output.push_str(&self.emit_static_header(var));
+            output.push_str(&format!("\t.zero\t{}\n", var.data_type.size()));
+        }
+
+        output
+    }
+
+    fn sorted_static_vars(&self, pred: impl Fn(&TacStaticVar) -> bool) -> Vec<&TacStaticVar> {
+        let mut vars = self.static_vars.values()
+            .filter(|var| pred(var))
+            .collect::<Vec<_>>();
+        vars.sort_by_key(|var| self.strtb.get(var.name).unwrap());
+        vars
+    }
+
+    fn emit_static_header(&self, var: &TacStaticVar) -> String {
+        let mut output = String::new();
+        let name = self.strtb.get(var.name).unwrap();
+        if let Linkage::External = var.linkage {
+            output.push_str(&format!("\t.globl\t{}\n", name));
+        }
+        output.push_str(&format!("\t.align\t{}\n", var.data_type.align()));
+        output.push_str(&format!("\t.type\t{}, @object\n", name));
+        output.push_str(&format!("\t.size\t{}, {}\n", name, var.data_type.size()));
+        output.push_str(&format!("{}:\n", name));
+        output
+    }
+
+    pub fn emit_func(&self, func: &Function) -> String {
+        let mut output = String::new();
+
+        let name = self.strtb.get(func.name).unwrap();
+        if let Linkage::External = func.linkage {
+            output.push_str(&format!("\t.globl\t{}\n", name));
+        }
+        output.push_str(&format!("\t.type\t{}, @function\n", name));
+        output.push_str(&format!("{}:\n", name));
+        for insn in &func.body {
+            let prefix = if let Insn::Label(..) = insn { "" } else { "\t" };
+            output.push_str(&format!("{}{}\n", prefix, self.emit_insn(insn)));
+        }
+        output.push_str(&format!("\t.size\t{}, .-{}\n\n", name, name));
+
+        output
+    }
+
+    pub fn emit_insn(&self, insn: &Insn) -> String {
+        match *insn {
+            // only `movabsq` takes an immediate of 64 bits
+            Insn::Mov(Size::Quad, Operand::Imm(imm), dst) if i32::try_from(imm).is_err() =>
+                format!("movabsq\t${}, {}", imm, self.emit_operand(dst, Size::Quad)),
+            Insn::Mov(size, src, dst) =>
+                format!("mov{}\t{}, {}", size.suffix(), self.emit_operand(src, size), self.emit_operand(dst, size)),
+            Insn::Movslq(src, dst) =>
+                format!("movslq\t{}, {}", self.emit_operand(src, Size::Long), self.emit_operand(dst, Size::Quad)),
+            Insn::Binary(op, size, src, dst) => {
+                let op = match op {
+                    BinaryOp::Add => "add",
+                    BinaryOp::Sub => "sub",
+                    BinaryOp::Imul => "imul",
+                };
+                format!("{}{}\t{}, {}", op, size.suffix(), self.emit_operand(src, size), self.emit_operand(dst, size))
+            },
+            Insn::Unary(op, size, dst) => {
+                let op = match op {
+                    UnaryOp::Neg => "neg",
+                    UnaryOp::Not => "not",
+                };
+                format!("{}{}\t{}", op, size.suffix(), self.emit_operand(dst, size))
+            },
+            Insn::Cmp(size, src, dst) =>
+                format!("cmp{}\t{}, {}", size.suffix(), self.emit_operand(src, size), self.emit_operand(dst, size)),
+            Insn::SetCC(cond, dst) => format!(
+                "set{}\t{}\n\tmovzbl\t{}, {}",
+                cond.suffix(),
+                self.emit_operand(dst, Size::Byte),
+                self.emit_operand(dst, Size::Byte),
+                self.emit_operand(dst, Size::Long),
+            ),
+            Insn::Cdq(Size::Quad) => "cqto".to_string(),
+            Insn::Cdq(..) => "cltd".to_string(),
+            Insn::Idiv(size, src) => format!("idiv{}\t{}", size.suffix(), self.emit_operand(src, size)),
+            Insn::Push(src) => format!("pushq\t{}", self.emit_operand(src, Size::Quad)),
+            Insn::Call(name, _) => {
+                // functions from other translation units may be in a shared library
+                let suffix = if self.functions.contains_key(&name) { "" } else { "@PLT" };
+                format!("call\t{}{}", self.strtb.get(name).unwrap(), suffix)
+            },
+            Insn::Jmp(label) => format!("jmp\t{}", emit_label(label)),
+            Insn::JmpCC(cond, label) => format!("j{}\t{}", cond.suffix(), emit_label(label)),
+            Insn::Label(label) => format!("{}:", emit_label(label)),
+            Insn::Leave => "leave".to_string(),
+            Insn::Ret => "ret".to_string(),
+            Insn::Intermediate(..) => unreachable!(),
+        }
+    }
+
+    fn emit_operand(&self, operand: Operand, size: Size) -> String {
+        match operand {
+            Operand::PhysReg(reg) => reg.name(size).to_string(),
+            Operand::Imm(imm) => format!("${}", imm),
+            Operand::Mem { base, offset } => format!("{}({})", offset, base.name(Size::Quad)),
+            Operand::Static(name) => format!("{}(%rip)", self.strtb.get(name).unwrap()),
+            Operand::VirtReg(..) => unreachable!(),
+        }
+    }
+}
+
+fn emit_label(label: LabelOperand) -> String {
+    format!(".L{}", label.0)
+}
@@ -0,0 +1,88 @@
+//! Lays out the frames and expands the prologues and epilogues.
+//! `rbp` points at the saved `rbp`, with the return address and stack parameters above,
+//! the spill slots below and the callee-saved registers under them.
+
+use crate::common::*;
+use super::{
+    CodeGen,
+    Frame,
+    Register,
+    Size,
+    Operand,
+    BinaryOp,
+    Insn,
+    IntermediateInsn,
+    Function,
+    TopLevel,
+};
+
+impl CodeGen<Frame> {
+    pub fn frame(self, x86: TopLevel) -> TopLevel {
+        let functions = x86.functions.into_iter()
+            .map(|(name, func)| (name, frame_func(func)))
+            .collect();
+
+        TopLevel {
+            functions,
+            static_vars: x86.static_vars,
+            strtb: x86.strtb,
+        }
+    }
+}
+
+fn frame_func(mut func: Function) -> Function {
+    let mut callee_saved = func.body.iter()
+        .flat_map(|insn| match *insn {
+            Insn::Mov(_, src, dst) |
+            Insn::Movslq(src, dst) |
+            Insn::Binary(_, _, src, dst) => vec![src, dst],
+            Insn::Unary(_, _, dst) |
+            Insn::SetCC(_, dst) => vec![dst],
+            _ => vec![],
+        })
+        .filter_map(|operand| match operand {
+            Operand::PhysReg(reg) if reg.is_callee_saved() => Some(reg),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    callee_saved.sort();
+    callee_saved.dedup();
+
+    let saves = callee_saved.iter()
+        .enumerate()
+        .map(|(i, &reg)| (reg, Operand::frame(-((func.spill_size + 8 * (i + 1)) as isize))))
+        .collect::<Vec<_>>();
+    // `rsp` is 16-byte aligned after pushing `rbp`, and stays so
+    let frame_size = (func.spill_size + 8 * saves.len()).next_multiple_of(16);
+
+    let mut body = vec![];
+    for insn in func.body {
+        match insn {
+            Insn::Intermediate(IntermediateInsn::Prologue(..)) => {
+                body.push(Insn::Push(Operand::PhysReg(Register::Rbp)));
+                body.push(Insn::Mov(Size::Quad, Operand::PhysReg(Register::Rsp), Operand::PhysReg(Register::Rbp)));
+                if frame_size > 0 {
+                    body.push(Insn::Binary(BinaryOp::Sub, Size::Quad, Operand::Imm(frame_size as i64), Operand::PhysReg(Register::Rsp)));
+                }
+                for &(reg, slot) in &saves {
+                    body.push(Insn::Mov(Size::Quad, Operand::PhysReg(reg), slot));
+                }
+            },
+            Insn::Intermediate(IntermediateInsn::Epilogue) => {
+                for &(reg, slot) in &saves {
+                    body.push(Insn::Mov(Size::Quad, slot, Operand::PhysReg(reg)));
+                }
+                body.push(Insn::Leave);
+                body.push(Insn::Ret);
+            },
+            // jumps to the next instruction
+            Insn::Label(label) if body.last() == Some(&Insn::Jmp(label)) => {
+                body.pop();
+                body.push(insn);
+            },
+            _ => body.push(insn),
+        }
+    }
+    func.body = body;
+    func
+}
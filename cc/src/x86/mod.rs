@@ -0,0 +1,219 @@
+//! x86-64 backend, for Linux and the System V ABI.
+//! TAC -> x86-64 assembly (AT&T syntax, for the GNU assembler)
+//! This stage includes following passes, the counterparts of the `lir` ones:
+//! 1. Convert the TAC code into instructions over virtual registers (`parse.rs`),
+//!    two-address, with divisions through `rax`/`rdx` and arguments in `rdi`, `rsi`, `rdx`, `rcx`, `r8`, `r9`.
+//! 2. Register allocation by graph coloring (`regalloc.rs`), fixed registers are precolored nodes.
+//! 3. Frame layout (`frame.rs`), spill slots and callee-saved registers below `rbp`.
+
+mod x86;
+mod parse;
+mod regalloc;
+mod frame;
+mod emit;
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use crate::{common::*, tac::TacLabelOperand};
+use x86::{
+    Register,
+    Size,
+    Operand,
+    CondCode,
+    BinaryOp,
+    UnaryOp,
+    Insn,
+    IntermediateInsn,
+    LabelOperand,
+    Function,
+    TopLevel,
+};
+
+pub use x86::TopLevel as X86TopLevel;
+
+#[derive(Debug)]
+pub struct CodeGen<Stage = Parse> {
+    pub func_cxs: HashMap<StrDescriptor, FuncContext>,
+    pub cur_func: Option<StrDescriptor>,
+
+    pub next_label: usize,
+    pub lmap: HashMap<(StrDescriptor, TacLabelOperand), usize>,
+
+    _stage: PhantomData<Stage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FuncContext {
+    pub name: StrDescriptor,
+    pub type_: FuncType,
+
+    pub next_v_reg: usize,
+    // Map temporary variables' id to virtual registers
+    pub tmap: HashMap<usize, usize>,
+    // Map variables' local id to virtual registers
+    pub vmap: HashMap<usize, usize>,
+}
+
+impl FuncContext {
+    pub fn new(name: StrDescriptor, type_: FuncType) -> Self {
+        FuncContext {
+            name,
+            type_,
+            next_v_reg: 0,
+            tmap: HashMap::new(),
+            vmap: HashMap::new(),
+        }
+    }
+
+    pub fn alloc_v_reg(&mut self) -> usize {
+        let v_reg = self.next_v_reg;
+        self.next_v_reg += 1;
+        v_reg
+    }
+
+    /// The virtual register of a temporary, allocated on first use.
+    pub fn temp_vreg(&mut self, temp_id: usize) -> usize {
+        match self.tmap.get(&temp_id) {
+            Some(&v_reg) => v_reg,
+            None => {
+                let v_reg = self.alloc_v_reg();
+                self.tmap.insert(temp_id, v_reg);
+                v_reg
+            },
+        }
+    }
+
+    /// The virtual register of a parameter or local variable, allocated on first use.
+    pub fn var_vreg(&mut self, local_id: usize) -> usize {
+        match self.vmap.get(&local_id) {
+            Some(&v_reg) => v_reg,
+            None => {
+                let v_reg = self.alloc_v_reg();
+                self.vmap.insert(local_id, v_reg);
+                v_reg
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegAlloc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame;
+
+impl CodeGen {
+    pub fn new() -> Self {
+        CodeGen {
+            func_cxs: HashMap::new(),
+            cur_func: None,
+            next_label: 0,
+            lmap: HashMap::new(),
+            _stage: PhantomData,
+        }
+    }
+}
+
+impl<Stage> CodeGen<Stage> {
+    fn cur_cx_mut(&mut self) -> &mut FuncContext {
+        self.cur_func
+            .as_ref()
+            .and_then(|name| self.func_cxs.get_mut(name))
+            .expect("Internal error: Current function context not found")
+    }
+
+    fn next_stage<Next>(self) -> CodeGen<Next> {
+        CodeGen {
+            func_cxs: self.func_cxs,
+            cur_func: self.cur_func,
+            next_label: self.next_label,
+            lmap: self.lmap,
+            _stage: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+    use std::process::Command;
+
+    use crate::driver::{compile, Options, Target};
+    use crate::host;
+
+    fn x86(source: &str, opt_level: u8) -> String {
+        let options = Options {
+            opt_level,
+            target: Target::X86_64,
+            ..Options::default()
+        };
+        compile(source.to_string(), &options).unwrap()
+    }
+
+    /// Instructions of `func`, without the directives.
+    fn body(asm: &str, func: &str) -> Vec<String> {
+        asm.lines()
+            .skip_while(|line| *line != format!("{}:", func))
+            .skip(1)
+            .take_while(|line| !line.starts_with("\t.size"))
+            .filter(|line| !line.starts_with('.'))
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_x86() {
+        // on an x86-64 host the assembly links into a program that behaves like the source
+        if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            return;
+        }
+        host::check("x86", &[], |dir, name, source, opt_level| {
+            let asm = dir.join(format!("{}.{}.s", name, opt_level));
+            let exe = dir.join(format!("{}.{}.s.out", name, opt_level));
+            write(&asm, x86(source, opt_level)).unwrap();
+            host::cc(&asm, &exe);
+            Command::new(exe)
+        });
+    }
+
+    #[test]
+    fn test_x86_division() {
+        let asm = x86("int f(int a, int b) { return a / b + a % b; }", 1);
+        let f = body(&asm, "f");
+        let idiv = f.iter().position(|insn| insn.starts_with("idivl")).unwrap();
+        assert_eq!(f[idiv - 1], "cltd");
+        // the divisor can't be in `rax` or `rdx`
+        assert!(!f[idiv].contains("%eax") && !f[idiv].contains("%edx"));
+    }
+
+    #[test]
+    fn test_x86_args() {
+        let asm = x86("long f(int a, int b, int c, int d, int e, int g, long h, int i);
+            long g(void) { return f(1, 2, 3, 4, 5, 6, 7, 8); }", 1);
+        let g = body(&asm, "g");
+        let call = g.iter().position(|insn| insn.starts_with("call")).unwrap();
+        // two arguments on the stack, pushed last to first, and the registers filled in right before the call
+        let pushes = g.iter().filter(|insn| insn.starts_with("pushq\t$")).cloned().collect::<Vec<_>>();
+        assert_eq!(pushes, ["pushq\t$8", "pushq\t$7"]);
+        assert!(g[..call].iter().any(|insn| insn == "movl\t$1, %edi"));
+        assert!(g[..call].iter().any(|insn| insn == "movl\t$6, %r9d"));
+        assert_eq!(g[call + 1], "addq\t$16, %rsp");
+    }
+
+    #[test]
+    fn test_x86_spill() {
+        // more values live at once than there are registers
+        let vars = (0..20).map(|i| format!("long v{} = x * {};", i, i + 1)).collect::<String>();
+        let sum = (0..20).map(|i| format!("v{}", i)).collect::<Vec<_>>().join(" + ");
+        let asm = x86(&format!("long f(long x) {{ {} return {}; }}", vars, sum), 0);
+        let f = body(&asm, "f");
+        assert!(f.iter().any(|insn| insn.starts_with("subq\t$") && insn.ends_with(", %rsp")));
+        assert!(f.iter().any(|insn| insn.contains("(%rbp)")));
+        // callee-saved registers are saved and restored
+        assert!(f.iter().any(|insn| insn.starts_with("movq\t%rbx, ")));
+        assert!(f.iter().any(|insn| insn.starts_with("movq\t") && insn.ends_with(", %rbx")));
+    }
+}
@@ -0,0 +1,396 @@
+//! First pass of x86-64 code generation.
+//! Parses TAC into instructions over virtual registers.
+
+use std::collections::HashMap;
+
+use crate::common::*;
+use crate::tac::{
+    TacTopLevel,
+    TacFunction,
+    TacInsn,
+    TacBinaryOp,
+    TacUnaryOp,
+    TacOperand,
+    TacLabelOperand,
+    temp_reads,
+};
+use super::{
+    CodeGen,
+    FuncContext,
+    Parse,
+    RegAlloc,
+    Register,
+    Size,
+    Operand,
+    CondCode,
+    BinaryOp,
+    UnaryOp,
+    Insn,
+    IntermediateInsn,
+    LabelOperand,
+    Function,
+    TopLevel,
+};
+
+impl CodeGen<Parse> {
+    pub fn parse(mut self, tac: TacTopLevel) -> (TopLevel, CodeGen<RegAlloc>) {
+        // every callee's return type is needed at its calls
+        for (&name, func) in &tac.functions {
+            self.func_cxs.insert(name, FuncContext::new(name, func.type_()));
+        }
+
+        let mut functions = HashMap::new();
+        for (name, func) in tac.functions {
+            self.cur_func = Some(name);
+            if let Some(func) = self.parse_function(func) {
+                functions.insert(name, func);
+            }
+            self.cur_func = None;
+        }
+
+        (TopLevel {
+            functions,
+            static_vars: tac.static_vars,
+            strtb: tac.strtb,
+        }, self.next_stage())
+    }
+
+    fn parse_function(&mut self, func: TacFunction) -> Option<Function> {
+        let TacFunction::Defined { linkage, name, params, body, .. } = func else {
+            return None;
+        };
+
+        let mut insns = vec![Insn::Intermediate(IntermediateInsn::Prologue(params.len().min(6)))];
+        for (i, param) in params.iter().enumerate() {
+            let v_reg = self.cur_cx_mut().var_vreg(param.local_id);
+            // the seventh parameter on is above the return address and the saved `rbp`
+            let src = match Register::ARGS.get(i) {
+                Some(&reg) => Operand::PhysReg(reg),
+                None => Operand::frame(16 + 8 * (i - 6) as isize),
+            };
+            insns.push(Insn::Mov(param.data_type.into(), src, Operand::VirtReg(v_reg)));
+        }
+
+        let temp_reads = temp_reads(&body);
+        let mut body = body.into_iter()
+            .filter(|insn| !matches!(insn, TacInsn::Loc(..)))
+            .peekable();
+        while let Some(insn) = body.next() {
+            if let Some(next) = body.peek()
+                && let Some(fused) = self.parse_fused_branch(&insn, next, &temp_reads) {
+                insns.extend(fused);
+                body.next();
+                continue;
+            }
+            insns.extend(self.parse_insn(insn));
+        }
+
+        Some(Function {
+            name,
+            linkage,
+            body: insns,
+            spill_size: 0,
+        })
+    }
+
+    fn parse_insn(&mut self, insn: TacInsn) -> Vec<Insn> {
+        let mut insns = vec![];
+        match insn {
+            TacInsn::Move { src, dst } => {
+                let size = dst.data_type().into();
+                let (dst, store) = self.dst(dst, size);
+                let src = if dst.is_mem() {
+                    self.reg_or_imm(src, size, &mut insns)
+                } else {
+                    self.src(src, size, &mut insns)
+                };
+                insns.push(Insn::Mov(size, src, dst));
+                insns.extend(store);
+            },
+            TacInsn::Return(val) => {
+                let size = self.cur_cx_mut().type_.return_type.into();
+                let val = self.src(val, size, &mut insns);
+                insns.push(Insn::Mov(size, val, Operand::PhysReg(Register::Rax)));
+                insns.push(Insn::Intermediate(IntermediateInsn::Epilogue));
+            },
+            TacInsn::Unary { op, src, dst } => {
+                let src_size = src.data_type().into();
+                let size = dst.data_type().into();
+                let (dst, store) = self.dst(dst, size);
+                match op {
+                    TacUnaryOp::Pos => {
+                        let src = self.src(src, size, &mut insns);
+                        insns.push(Insn::Mov(size, src, dst));
+                    },
+                    TacUnaryOp::Negate | TacUnaryOp::Complement => {
+                        let src = self.src(src, size, &mut insns);
+                        insns.push(Insn::Mov(size, src, dst));
+                        let op = if let TacUnaryOp::Negate = op { UnaryOp::Neg } else { UnaryOp::Not };
+                        insns.push(Insn::Unary(op, size, dst));
+                    },
+                    TacUnaryOp::Not => {
+                        let src = self.reg(src, src_size, &mut insns);
+                        insns.push(Insn::Cmp(src_size, Operand::Imm(0), src));
+                        insns.push(Insn::SetCC(CondCode::E, dst));
+                    },
+                }
+                insns.extend(store);
+            },
+            TacInsn::Binary { op, left, right, dst } => {
+                let size = Size::from(left.data_type());
+                if let Some(cond) = comparison(op) {
+                    let cond = self.parse_cmp(left, right, cond, size, &mut insns);
+                    let (dst, store) = self.dst(dst, Size::Long);
+                    insns.push(Insn::SetCC(cond, dst));
+                    insns.extend(store);
+                    return insns;
+                }
+
+                let (dst, store) = self.dst(dst, size);
+                match op {
+                    TacBinaryOp::Add | TacBinaryOp::Sub | TacBinaryOp::Mul => {
+                        let op = match op {
+                            TacBinaryOp::Add => BinaryOp::Add,
+                            TacBinaryOp::Sub => BinaryOp::Sub,
+                            _ => BinaryOp::Imul,
+                        };
+                        let left = self.src(left, size, &mut insns);
+                        let right = self.src(right, size, &mut insns);
+                        // `dst = left - dst` can't overwrite `dst` before reading it
+                        let acc = if right == dst && left != dst {
+                            Operand::VirtReg(self.cur_cx_mut().alloc_v_reg())
+                        } else {
+                            dst
+                        };
+                        insns.push(Insn::Mov(size, left, acc));
+                        insns.push(Insn::Binary(op, size, right, acc));
+                        if acc != dst {
+                            insns.push(Insn::Mov(size, acc, dst));
+                        }
+                    },
+                    TacBinaryOp::Div | TacBinaryOp::Rem => {
+                        let right = match self.src(right, size, &mut insns) {
+                            Operand::Imm(imm) => self.materialize(imm, size, &mut insns),
+                            right => right,
+                        };
+                        let left = self.src(left, size, &mut insns);
+                        insns.push(Insn::Mov(size, left, Operand::PhysReg(Register::Rax)));
+                        insns.push(Insn::Cdq(size));
+                        insns.push(Insn::Idiv(size, right));
+                        let result = if let TacBinaryOp::Div = op { Register::Rax } else { Register::Rdx };
+                        insns.push(Insn::Mov(size, Operand::PhysReg(result), dst));
+                    },
+                    // short-circuited into branches by the code generator
+                    _ => unreachable!(),
+                }
+                insns.extend(store);
+            },
+            TacInsn::Jump(label) => insns.push(Insn::Jmp(self.label(label))),
+            TacInsn::Label(label) => insns.push(Insn::Label(self.label(label))),
+            TacInsn::BranchIfZero { src, label } |
+            TacInsn::BranchNotZero { src, label } => {
+                let cond = if let TacInsn::BranchIfZero { .. } = insn { CondCode::E } else { CondCode::Ne };
+                let size = src.data_type().into();
+                let src = self.reg(src, size, &mut insns);
+                insns.push(Insn::Cmp(size, Operand::Imm(0), src));
+                insns.push(Insn::JmpCC(cond, self.label(label)));
+            },
+            TacInsn::SignExt { src, dst } => {
+                let (dst, store) = self.dst(dst, Size::Quad);
+                match self.src(src, Size::Long, &mut insns) {
+                    Operand::Imm(imm) => insns.push(Insn::Mov(Size::Quad, Operand::Imm(imm), dst)),
+                    src => insns.push(Insn::Movslq(src, dst)),
+                }
+                insns.extend(store);
+            },
+            TacInsn::Truncate { src, dst } => {
+                let (dst, store) = self.dst(dst, Size::Long);
+                let src = match src {
+                    TacOperand::Imm(val) => Operand::Imm(val.value() as i32 as i64),
+                    _ => self.src(src, Size::Long, &mut insns),
+                };
+                insns.push(Insn::Mov(Size::Long, src, dst));
+                insns.extend(store);
+            },
+            TacInsn::FuncCall { target, args, dst } => {
+                let stack_args = args.len().saturating_sub(6);
+                // `rsp` is 16-byte aligned at the call
+                let padding = if stack_args % 2 == 1 { 8 } else { 0 };
+                if padding != 0 {
+                    insns.push(Insn::Binary(BinaryOp::Sub, Size::Quad, Operand::Imm(padding), Operand::PhysReg(Register::Rsp)));
+                }
+                for &arg in args.iter().skip(6).rev() {
+                    let arg = self.reg_or_imm(arg, Size::Quad, &mut insns);
+                    insns.push(Insn::Push(arg));
+                }
+                // evaluated before any argument register is set, so none of them is clobbered
+                let reg_args = args.iter()
+                    .take(6)
+                    .map(|&arg| {
+                        let size = arg.data_type().into();
+                        (size, self.src(arg, size, &mut insns))
+                    })
+                    .collect::<Vec<_>>();
+                for (&(size, arg), &reg) in reg_args.iter().zip(Register::ARGS.iter()) {
+                    insns.push(Insn::Mov(size, arg, Operand::PhysReg(reg)));
+                }
+                insns.push(Insn::Call(target, reg_args.len()));
+                if stack_args > 0 {
+                    let size = 8 * stack_args as i64 + padding;
+                    insns.push(Insn::Binary(BinaryOp::Add, Size::Quad, Operand::Imm(size), Operand::PhysReg(Register::Rsp)));
+                }
+
+                let return_type = self.func_cxs[&target].type_.return_type;
+                if return_type != DataType::Void {
+                    let size = return_type.into();
+                    let (dst, store) = self.dst(dst, size);
+                    insns.push(Insn::Mov(size, Operand::PhysReg(Register::Rax), dst));
+                    insns.extend(store);
+                }
+            },
+            TacInsn::Loc(..) => (),
+        }
+        insns
+    }
+
+    /// Compares `left` with `right`, returning the condition `cond` holds on.
+    fn parse_cmp(
+        &mut self,
+        left: TacOperand,
+        right: TacOperand,
+        cond: CondCode,
+        size: Size,
+        insns: &mut Vec<Insn>,
+    ) -> CondCode {
+        let left_op = self.src(left, size, insns);
+        let right_op = self.src(right, size, insns);
+        // `cmp` takes an immediate or a memory operand as its source only
+        match (left_op, right_op) {
+            (Operand::Imm(..), right) if !matches!(right, Operand::Imm(..)) => {
+                insns.push(Insn::Cmp(size, left_op, right));
+                cond.swap()
+            },
+            (left, right) => {
+                let left = if matches!(left, Operand::Imm(..)) || left.is_mem() && right.is_mem() {
+                    self.in_reg(left, size, insns)
+                } else {
+                    left
+                };
+                insns.push(Insn::Cmp(size, right, left));
+                cond
+            },
+        }
+    }
+
+    /// `t = a < b; branch_if_zero t, L` -> `cmp b, a; jge L`,
+    /// as long as `t` isn't read anywhere else.
+    fn parse_fused_branch(
+        &mut self,
+        insn: &TacInsn,
+        next: &TacInsn,
+        temp_reads: &HashMap<usize, usize>,
+    ) -> Option<Vec<Insn>> {
+        let TacInsn::Binary { op, left, right, dst } = *insn else { return None };
+        let (src, label, if_zero) = match *next {
+            TacInsn::BranchIfZero { src, label } => (src, label, true),
+            TacInsn::BranchNotZero { src, label } => (src, label, false),
+            _ => return None,
+        };
+        let TacOperand::Temp(temp_id, _) = dst else { return None };
+        let cond = comparison(op)?;
+        if src != dst || temp_reads.get(&temp_id) != Some(&1) {
+            return None;
+        }
+
+        let mut insns = vec![];
+        let cond = self.parse_cmp(left, right, cond, left.data_type().into(), &mut insns);
+        let cond = if if_zero { cond.negate() } else { cond };
+        insns.push(Insn::JmpCC(cond, self.label(label)));
+        Some(insns)
+    }
+
+    /// A source operand, a register, an immediate of 32 bits, or a static variable.
+    fn src(&mut self, operand: TacOperand, size: Size, insns: &mut Vec<Insn>) -> Operand {
+        let cx = self.cur_cx_mut();
+        match operand {
+            // only `mov` takes 64-bit immediates, into a register
+            TacOperand::Imm(val) => match val.value() {
+                imm if i32::try_from(imm).is_ok() => Operand::Imm(imm),
+                imm => self.materialize(imm, size, insns),
+            },
+            TacOperand::Temp(id, _) => Operand::VirtReg(cx.temp_vreg(id)),
+            TacOperand::Var { local_id: Some(id), .. } => Operand::VirtReg(cx.var_vreg(id)),
+            TacOperand::Var { name, local_id: None, .. } => Operand::Static(name),
+        }
+    }
+
+    /// A source operand, with static variables loaded into a register first.
+    fn reg_or_imm(&mut self, operand: TacOperand, size: Size, insns: &mut Vec<Insn>) -> Operand {
+        match self.src(operand, size, insns) {
+            Operand::Static(name) => {
+                let v_reg = Operand::VirtReg(self.cur_cx_mut().alloc_v_reg());
+                insns.push(Insn::Mov(operand.data_type().into(), Operand::Static(name), v_reg));
+                v_reg
+            },
+            src => src,
+        }
+    }
+
+    /// A source operand in a register.
+    fn reg(&mut self, operand: TacOperand, size: Size, insns: &mut Vec<Insn>) -> Operand {
+        let operand = self.src(operand, size, insns);
+        self.in_reg(operand, size, insns)
+    }
+
+    fn in_reg(&mut self, operand: Operand, size: Size, insns: &mut Vec<Insn>) -> Operand {
+        match operand {
+            Operand::VirtReg(..) | Operand::PhysReg(..) => operand,
+            _ => {
+                let v_reg = Operand::VirtReg(self.cur_cx_mut().alloc_v_reg());
+                insns.push(Insn::Mov(size, operand, v_reg));
+                v_reg
+            },
+        }
+    }
+
+    fn materialize(&mut self, imm: i64, size: Size, insns: &mut Vec<Insn>) -> Operand {
+        let v_reg = Operand::VirtReg(self.cur_cx_mut().alloc_v_reg());
+        insns.push(Insn::Mov(size, Operand::Imm(imm), v_reg));
+        v_reg
+    }
+
+    /// The destination to compute into, and the store to the static variable it stands for if any.
+    fn dst(&mut self, operand: TacOperand, size: Size) -> (Operand, Option<Insn>) {
+        let mut insns = vec![];
+        match self.src(operand, size, &mut insns) {
+            Operand::Static(name) => {
+                let v_reg = Operand::VirtReg(self.cur_cx_mut().alloc_v_reg());
+                (v_reg, Some(Insn::Mov(size, v_reg, Operand::Static(name))))
+            },
+            dst => (dst, None),
+        }
+    }
+
+    fn label(&mut self, label: TacLabelOperand) -> LabelOperand {
+        let func = self.cur_func.expect("Internal error: Current function not found");
+        let next_label = &mut self.next_label;
+        let id = *self.lmap.entry((func, label)).or_insert_with(|| {
+            *next_label += 1;
+            *next_label - 1
+        });
+        LabelOperand(id)
+    }
+}
+
+/// The condition a comparison is true on, `None` for other operations.
+fn comparison(op: TacBinaryOp) -> Option<CondCode> {
+    match op {
+        TacBinaryOp::Eq => Some(CondCode::E),
+        TacBinaryOp::NotEq => Some(CondCode::Ne),
+        TacBinaryOp::Ls => Some(CondCode::L),
+        TacBinaryOp::LsEq => Some(CondCode::Le),
+        TacBinaryOp::Gt => Some(CondCode::G),
+        TacBinaryOp::GtEq => Some(CondCode::Ge),
+        _ => None,
+    }
+}
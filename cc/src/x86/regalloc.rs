@@ -0,0 +1,385 @@
+//! Register allocation by graph coloring, over the 14 registers besides `rsp` and `rbp`.
+//! Registers the instructions name themselves, arguments, `rax`/`rdx` around `idiv` and
+//! the caller-saved ones a call clobbers, are nodes precolored with themselves,
+//! so whatever is live across them interferes and gets another register.
+//! Two-address instructions need nothing special: `mov a, d; add b, d` defines `d` while `b` is live.
+//! Nodes are removed while they have fewer neighbors than registers, otherwise the cheapest to spill
+//! is removed optimistically. The ones left without a color when popping are spilled to the frame,
+//! reloaded into short-lived registers around each access, and the graph is colored again.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::common::*;
+use super::{
+    CodeGen,
+    RegAlloc,
+    Frame,
+    Register,
+    Size,
+    Operand,
+    Insn,
+    IntermediateInsn,
+    Function,
+    TopLevel,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Reg {
+    Phys(Register),
+    Virt(usize),
+}
+
+impl Reg {
+    /// registers that take part in the allocation, `rsp` and `rbp` don't
+    fn of(operand: Operand) -> Option<Reg> {
+        match operand {
+            Operand::VirtReg(v_reg) => Some(Reg::Virt(v_reg)),
+            Operand::PhysReg(reg) if reg.is_allocatable() => Some(Reg::Phys(reg)),
+            _ => None,
+        }
+    }
+}
+
+impl CodeGen<RegAlloc> {
+    pub fn alloc(mut self, x86: TopLevel) -> (TopLevel, CodeGen<Frame>) {
+        let functions = x86.functions.into_iter()
+            .map(|(name, func)| {
+                self.cur_func = Some(name);
+                (name, self.alloc_func(func))
+            })
+            .collect();
+        self.cur_func = None;
+
+        (TopLevel {
+            functions,
+            static_vars: x86.static_vars,
+            strtb: x86.strtb,
+        }, self.next_stage())
+    }
+
+    fn alloc_func(&mut self, mut func: Function) -> Function {
+        // registers holding spilled values for a single instruction
+        let mut unspillable = BTreeSet::new();
+        let colors = loop {
+            let graph = Graph::build(&func.body);
+            match graph.color(&spill_weights(&func.body), &unspillable) {
+                Ok(colors) => break colors,
+                Err(spilled) => self.spill(&mut func, &spilled, &mut unspillable),
+            }
+        };
+
+        func.body = func.body.into_iter()
+            .map(|insn| map_operands(insn, |operand| match operand {
+                Operand::VirtReg(v_reg) => Operand::PhysReg(colors[&v_reg]),
+                _ => operand,
+            }))
+            .filter(|insn| !matches!(insn, Insn::Mov(_, src, dst) if src == dst))
+            .collect();
+        func
+    }
+
+    /// Gives each register in `spilled` a frame slot, loaded before and stored after each instruction accessing it.
+    fn spill(&mut self, func: &mut Function, spilled: &[usize], unspillable: &mut BTreeSet<usize>) {
+        let mut slots = HashMap::new();
+        for &v_reg in spilled {
+            func.spill_size += 8;
+            slots.insert(v_reg, Operand::frame(-(func.spill_size as isize)));
+        }
+
+        let cx = self.cur_cx_mut();
+        let mut body = vec![];
+        for insn in std::mem::take(&mut func.body) {
+            let (uses, defs) = uses_defs(&insn);
+            let mut temps = HashMap::new();
+            for reg in uses.iter().chain(&defs) {
+                if let Reg::Virt(v_reg) = *reg
+                    && slots.contains_key(&v_reg)
+                    && !temps.contains_key(&v_reg) {
+                    let temp = cx.alloc_v_reg();
+                    unspillable.insert(temp);
+                    temps.insert(v_reg, temp);
+                }
+            }
+            if temps.is_empty() {
+                body.push(insn);
+                continue;
+            }
+
+            for reg in &uses {
+                if let Reg::Virt(v_reg) = *reg && let Some(&temp) = temps.get(&v_reg) {
+                    body.push(Insn::Mov(Size::Quad, slots[&v_reg], Operand::VirtReg(temp)));
+                }
+            }
+            body.push(map_operands(insn, |operand| match operand {
+                Operand::VirtReg(v_reg) => Operand::VirtReg(temps.get(&v_reg).copied().unwrap_or(v_reg)),
+                _ => operand,
+            }));
+            for reg in &defs {
+                if let Reg::Virt(v_reg) = *reg && let Some(&temp) = temps.get(&v_reg) {
+                    body.push(Insn::Mov(Size::Quad, Operand::VirtReg(temp), slots[&v_reg]));
+                }
+            }
+        }
+        func.body = body;
+    }
+}
+
+/// Interference graph of a function.
+#[derive(Debug)]
+struct Graph {
+    nodes: BTreeMap<Reg, BTreeSet<Reg>>,
+    /// `(src, dst)` of every register-to-register move, their ends are preferably given the same color
+    moves: Vec<(Reg, Reg)>,
+}
+
+impl Graph {
+    fn build(body: &[Insn]) -> Self {
+        let mut graph = Graph {
+            nodes: BTreeMap::new(),
+            moves: vec![],
+        };
+        for (insn, live_out) in body.iter().zip(liveness(body)) {
+            let (uses, defs) = uses_defs(insn);
+            for &reg in &uses {
+                graph.nodes.entry(reg).or_default();
+            }
+            // a move doesn't make its ends interfere, they hold the same value
+            let move_src = match *insn {
+                Insn::Mov(_, src, dst) => Reg::of(src).zip(Reg::of(dst)).map(|(src, dst)| {
+                    graph.moves.push((src, dst));
+                    src
+                }),
+                _ => None,
+            };
+            for &def in &defs {
+                graph.nodes.entry(def).or_default();
+                for &live in &live_out {
+                    if live != def && Some(live) != move_src {
+                        graph.add_edge(def, live);
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    fn add_edge(&mut self, reg1: Reg, reg2: Reg) {
+        self.nodes.entry(reg1).or_default().insert(reg2);
+        self.nodes.entry(reg2).or_default().insert(reg1);
+    }
+
+    /// A register for each virtual register, or those that have to be spilled.
+    fn color(
+        &self,
+        weights: &HashMap<usize, usize>,
+        unspillable: &BTreeSet<usize>,
+    ) -> std::result::Result<HashMap<usize, Register>, Vec<usize>> {
+        let k = Register::ALLOCATABLE.len();
+        let mut degrees = self.nodes.iter()
+            .filter_map(|(reg, neighbors)| match reg {
+                Reg::Virt(v_reg) => Some((*v_reg, neighbors.len())),
+                Reg::Phys(..) => None,
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut stack = vec![];
+        while !degrees.is_empty() {
+            let simplified = degrees.iter()
+                .find(|&(_, &degree)| degree < k)
+                .map(|(&v_reg, _)| v_reg);
+            let v_reg = simplified.unwrap_or_else(|| {
+                let cost = |v_reg: &usize| match unspillable.contains(v_reg) {
+                    true => f64::INFINITY,
+                    false => weights.get(v_reg).copied().unwrap_or(0) as f64 / degrees[v_reg] as f64,
+                };
+                *degrees.keys()
+                    .min_by(|a, b| cost(a).total_cmp(&cost(b)))
+                    .unwrap()
+            });
+            degrees.remove(&v_reg);
+            for neighbor in &self.nodes[&Reg::Virt(v_reg)] {
+                if let Reg::Virt(neighbor) = neighbor && let Some(degree) = degrees.get_mut(neighbor) {
+                    *degree -= 1;
+                }
+            }
+            stack.push(v_reg);
+        }
+
+        let mut colors: HashMap<usize, Register> = HashMap::new();
+        let mut spilled = vec![];
+        while let Some(v_reg) = stack.pop() {
+            let color_of = |reg: &Reg| match reg {
+                Reg::Phys(reg) => Some(*reg),
+                Reg::Virt(v_reg) => colors.get(v_reg).copied(),
+            };
+            let taken = self.nodes[&Reg::Virt(v_reg)].iter()
+                .filter_map(color_of)
+                .collect::<BTreeSet<_>>();
+            let mut partners = self.moves.iter()
+                .filter_map(|&(src, dst)| match (src, dst) {
+                    (Reg::Virt(src), other) if src == v_reg => Some(other),
+                    (other, Reg::Virt(dst)) if dst == v_reg => Some(other),
+                    _ => None,
+                })
+                .filter_map(|reg| color_of(&reg));
+            // callee-saved registers cost a save and a restore the first time they're used
+            let used = colors.values().copied().collect::<BTreeSet<_>>();
+            let mut free = Register::ALLOCATABLE.iter().copied().filter(|reg| !taken.contains(reg));
+            let by_cost = free.clone().find(|reg| !reg.is_callee_saved() || used.contains(reg));
+            match partners.find(|reg| !taken.contains(reg)).or(by_cost).or_else(|| free.next()) {
+                Some(color) => {
+                    colors.insert(v_reg, color);
+                },
+                None => {
+                    assert!(!unspillable.contains(&v_reg),
+                        "Internal error: no register left for a spilled value");
+                    spilled.push(v_reg);
+                },
+            }
+        }
+
+        if spilled.is_empty() {
+            Ok(colors)
+        } else {
+            Err(spilled)
+        }
+    }
+}
+
+/// Registers live after each instruction.
+fn liveness(body: &[Insn]) -> Vec<BTreeSet<Reg>> {
+    let labels = body.iter()
+        .enumerate()
+        .filter_map(|(idx, insn)| match insn {
+            Insn::Label(label) => Some((*label, idx)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    let succs = body.iter()
+        .enumerate()
+        .map(|(idx, insn)| match insn {
+            Insn::Jmp(label) => vec![labels[label]],
+            Insn::JmpCC(_, label) => vec![labels[label], idx + 1],
+            Insn::Intermediate(IntermediateInsn::Epilogue) | Insn::Ret => vec![],
+            _ => vec![idx + 1],
+        })
+        .map(|succs| succs.into_iter().filter(|&succ| succ < body.len()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let uses_defs = body.iter().map(uses_defs).collect::<Vec<_>>();
+
+    let mut live_in = vec![BTreeSet::new(); body.len()];
+    let mut live_out = vec![BTreeSet::new(); body.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in (0..body.len()).rev() {
+            let out = succs[idx].iter()
+                .flat_map(|&succ| live_in[succ].iter().copied())
+                .collect::<BTreeSet<_>>();
+            let (uses, defs) = &uses_defs[idx];
+            let mut in_ = out.iter()
+                .filter(|reg| !defs.contains(reg))
+                .copied()
+                .collect::<BTreeSet<_>>();
+            in_.extend(uses.iter().copied());
+            if in_ != live_in[idx] {
+                live_in[idx] = in_;
+                changed = true;
+            }
+            live_out[idx] = out;
+        }
+    }
+    live_out
+}
+
+/// Registers read and written by `insn`, including those it names implicitly.
+fn uses_defs(insn: &Insn) -> (Vec<Reg>, Vec<Reg>) {
+    let regs = |operands: &[Operand]| operands.iter().copied().filter_map(Reg::of).collect::<Vec<_>>();
+    let phys = |regs: &[Register]| regs.iter().map(|&reg| Reg::Phys(reg)).collect::<Vec<_>>();
+
+    match *insn {
+        Insn::Mov(_, src, dst) |
+        Insn::Movslq(src, dst) => (regs(&[src]), regs(&[dst])),
+        Insn::Binary(_, _, src, dst) => (regs(&[src, dst]), regs(&[dst])),
+        Insn::Unary(_, _, dst) => (regs(&[dst]), regs(&[dst])),
+        Insn::Cmp(_, src, dst) => (regs(&[src, dst]), vec![]),
+        Insn::SetCC(_, dst) => (vec![], regs(&[dst])),
+        Insn::Cdq(..) => (phys(&[Register::Rax]), phys(&[Register::Rdx])),
+        Insn::Idiv(_, src) => {
+            let mut uses = regs(&[src]);
+            uses.extend(phys(&[Register::Rax, Register::Rdx]));
+            (uses, phys(&[Register::Rax, Register::Rdx]))
+        },
+        Insn::Push(src) => (regs(&[src]), vec![]),
+        Insn::Call(_, reg_args) => (phys(&Register::ARGS[..reg_args]), phys(&Register::CALLER_SAVED)),
+        Insn::Intermediate(IntermediateInsn::Prologue(reg_params)) => (vec![], phys(&Register::ARGS[..reg_params])),
+        Insn::Intermediate(IntermediateInsn::Epilogue) => (phys(&[Register::Rax]), vec![]),
+        Insn::Jmp(..) |
+        Insn::JmpCC(..) |
+        Insn::Label(..) |
+        Insn::Leave |
+        Insn::Ret => (vec![], vec![]),
+    }
+}
+
+/// Rewrites the operands of `insn` with `f`.
+pub fn map_operands(insn: Insn, mut f: impl FnMut(Operand) -> Operand) -> Insn {
+    match insn {
+        Insn::Mov(size, src, dst) => Insn::Mov(size, f(src), f(dst)),
+        Insn::Movslq(src, dst) => Insn::Movslq(f(src), f(dst)),
+        Insn::Binary(op, size, src, dst) => Insn::Binary(op, size, f(src), f(dst)),
+        Insn::Unary(op, size, dst) => Insn::Unary(op, size, f(dst)),
+        Insn::Cmp(size, src, dst) => Insn::Cmp(size, f(src), f(dst)),
+        Insn::SetCC(cond, dst) => Insn::SetCC(cond, f(dst)),
+        Insn::Idiv(size, src) => Insn::Idiv(size, f(src)),
+        Insn::Push(src) => Insn::Push(f(src)),
+        Insn::Cdq(..) |
+        Insn::Call(..) |
+        Insn::Jmp(..) |
+        Insn::JmpCC(..) |
+        Insn::Label(..) |
+        Insn::Leave |
+        Insn::Ret |
+        Insn::Intermediate(..) => insn,
+    }
+}
+
+/// Accesses to each virtual register, every access weighs 10 to the power of its loop depth.
+/// Loops are recognized by their back edges, a jump to a label before it encloses everything in between.
+fn spill_weights(body: &[Insn]) -> HashMap<usize, usize> {
+    let labels = body.iter()
+        .enumerate()
+        .filter_map(|(idx, insn)| match insn {
+            Insn::Label(label) => Some((*label, idx)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    // the furthest back edge to each header, `continue`s jumping back don't nest another loop
+    let mut loops: HashMap<usize, usize> = HashMap::new();
+    for (idx, insn) in body.iter().enumerate() {
+        if let Insn::Jmp(label) | Insn::JmpCC(_, label) = insn && labels[label] <= idx {
+            let end = loops.entry(labels[label]).or_insert(idx);
+            *end = (*end).max(idx);
+        }
+    }
+    let mut deltas = vec![0isize; body.len() + 1];
+    for (header, end) in loops {
+        deltas[header] += 1;
+        deltas[end + 1] -= 1;
+    }
+
+    let mut weights = HashMap::new();
+    let mut depth = 0isize;
+    for (insn, delta) in body.iter().zip(deltas) {
+        depth += delta;
+        let weight = 10usize.saturating_pow(depth as u32);
+        let (uses, defs) = uses_defs(insn);
+        for reg in uses.into_iter().chain(defs) {
+            if let Reg::Virt(v_reg) = reg {
+                let total = weights.entry(v_reg).or_insert(0usize);
+                *total = total.saturating_add(weight);
+            }
+        }
+    }
+    weights
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use crate::{common::*, tac::TacStaticVar};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    /// registers the first six integer arguments are passed in
+    pub const ARGS: [Register; 6] = [
+        Register::Rdi, Register::Rsi, Register::Rdx, Register::Rcx, Register::R8, Register::R9,
+    ];
+
+    pub const CALLER_SAVED: [Register; 9] = [
+        Register::Rax, Register::Rcx, Register::Rdx, Register::Rsi, Register::Rdi,
+        Register::R8, Register::R9, Register::R10, Register::R11,
+    ];
+
+    /// everything but `rsp` and `rbp`, caller-saved first as they cost nothing outside calls
+    pub const ALLOCATABLE: [Register; 14] = [
+        Register::Rax, Register::Rcx, Register::Rdx, Register::Rsi, Register::Rdi,
+        Register::R8, Register::R9, Register::R10, Register::R11,
+        Register::Rbx, Register::R12, Register::R13, Register::R14, Register::R15,
+    ];
+
+    pub fn is_callee_saved(&self) -> bool {
+        matches!(self, Register::Rbx | Register::R12 | Register::R13 | Register::R14 | Register::R15)
+    }
+
+    pub fn is_allocatable(&self) -> bool {
+        !matches!(self, Register::Rsp | Register::Rbp)
+    }
+
+    /// The name of the register's lower `size` bytes.
+    pub fn name(&self, size: Size) -> &'static str {
+        let names = match self {
+            Register::Rax => ["%al", "%eax", "%rax"],
+            Register::Rcx => ["%cl", "%ecx", "%rcx"],
+            Register::Rdx => ["%dl", "%edx", "%rdx"],
+            Register::Rbx => ["%bl", "%ebx", "%rbx"],
+            Register::Rsp => ["%spl", "%esp", "%rsp"],
+            Register::Rbp => ["%bpl", "%ebp", "%rbp"],
+            Register::Rsi => ["%sil", "%esi", "%rsi"],
+            Register::Rdi => ["%dil", "%edi", "%rdi"],
+            Register::R8 => ["%r8b", "%r8d", "%r8"],
+            Register::R9 => ["%r9b", "%r9d", "%r9"],
+            Register::R10 => ["%r10b", "%r10d", "%r10"],
+            Register::R11 => ["%r11b", "%r11d", "%r11"],
+            Register::R12 => ["%r12b", "%r12d", "%r12"],
+            Register::R13 => ["%r13b", "%r13d", "%r13"],
+            Register::R14 => ["%r14b", "%r14d", "%r14"],
+            Register::R15 => ["%r15b", "%r15d", "%r15"],
+        };
+        match size {
+            Size::Byte => names[0],
+            Size::Long => names[1],
+            Size::Quad => names[2],
+        }
+    }
+}
+
+/// Operand sizes, as the suffixes of AT&T mnemonics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Long,
+    Quad,
+}
+
+impl Size {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Size::Byte => "b",
+            Size::Long => "l",
+            Size::Quad => "q",
+        }
+    }
+}
+
+impl From<DataType> for Size {
+    fn from(data_type: DataType) -> Self {
+        match data_type {
+            DataType::Int => Size::Long,
+            DataType::Long => Size::Quad,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    VirtReg(usize),
+    PhysReg(Register),
+    Imm(i64),
+    Mem {
+        base: Register,
+        offset: isize,
+    },
+    /// a static variable, addressed relative to `rip`
+    Static(StrDescriptor),
+}
+
+impl Operand {
+    pub fn frame(offset: isize) -> Self {
+        Operand::Mem {
+            base: Register::Rbp,
+            offset,
+        }
+    }
+
+    pub fn is_mem(&self) -> bool {
+        matches!(self, Operand::Mem { .. } | Operand::Static(..))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CondCode {
+    E,
+    Ne,
+    L,
+    Le,
+    G,
+    Ge,
+}
+
+impl CondCode {
+    pub fn negate(&self) -> Self {
+        match self {
+            CondCode::E => CondCode::Ne,
+            CondCode::Ne => CondCode::E,
+            CondCode::L => CondCode::Ge,
+            CondCode::Ge => CondCode::L,
+            CondCode::G => CondCode::Le,
+            CondCode::Le => CondCode::G,
+        }
+    }
+
+    /// The condition with the operands of the comparison swapped.
+    pub fn swap(&self) -> Self {
+        match self {
+            CondCode::E => CondCode::E,
+            CondCode::Ne => CondCode::Ne,
+            CondCode::L => CondCode::G,
+            CondCode::G => CondCode::L,
+            CondCode::Le => CondCode::Ge,
+            CondCode::Ge => CondCode::Le,
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            CondCode::E => "e",
+            CondCode::Ne => "ne",
+            CondCode::L => "l",
+            CondCode::Le => "le",
+            CondCode::G => "g",
+            CondCode::Ge => "ge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Imul,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+/// Operands are in AT&T order, sources first and the destination last.
+/// Two-address instructions read their destination as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Insn {
+    Mov(Size, Operand, Operand),
+    /// sign-extends an int into a long
+    Movslq(Operand, Operand),
+    Binary(BinaryOp, Size, Operand, Operand),
+    Unary(UnaryOp, Size, Operand),
+    /// sets the flags as `dst - src` does
+    Cmp(Size, Operand, Operand),
+    /// the condition as an int, `setcc` on the lowest byte then `movzbl`
+    SetCC(CondCode, Operand),
+    /// sign-extends `rax` into `rdx` (`cltd`/`cqto`)
+    Cdq(Size),
+    /// divides `rdx:rax` by the operand, the quotient in `rax` and the remainder in `rdx`
+    Idiv(Size, Operand),
+    Push(Operand),
+    /// (callee, number of arguments in registers)
+    Call(StrDescriptor, usize),
+    Jmp(LabelOperand),
+    JmpCC(CondCode, LabelOperand),
+    Label(LabelOperand),
+    Leave,
+    Ret,
+
+    Intermediate(IntermediateInsn),
+}
+
+/// Replaced by the frame setup and teardown once the frame is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntermediateInsn {
+    /// (number of parameters in registers)
+    Prologue(usize),
+    /// returns the value in `rax`
+    Epilogue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LabelOperand(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: StrDescriptor,
+    pub linkage: Linkage,
+    pub body: Vec<Insn>,
+    /// bytes below `rbp` taken by spill slots, callee-saved registers are saved under them
+    pub spill_size: usize,
+}
+
+#[derive(Debug)]
+pub struct TopLevel {
+    pub functions: HashMap<StrDescriptor, Function>,
+    pub static_vars: HashMap<StrDescriptor, TacStaticVar>,
+    pub strtb: StringPool,
+}
@@ -0,0 +1,29 @@
+static int s = 3;
+
+int
+touch(void) {
+    s = s + 1;
+    return s;
+}
+
+int
+main(void) {
+    int a = 5;
+    int b = 7;
+    int x = a * b + a * b;
+    int y = 0;
+
+    if (x > 10) {
+        y = a * b;
+    } else {
+        y = b * a + 1;
+    }
+    int z = a * b;
+
+    int p = s * 2;
+    int q = s * 2;
+    touch();
+    int r = s * 2;
+
+    return x + y + z + p + q + r;
+}
@@ -0,0 +1,46 @@
+static int square(int x) {
+    return x * x;
+}
+
+static long widen(int x, long y) {
+    if (x < 0) {
+        return y;
+    }
+    return x + y;
+}
+
+static int counter = 0;
+
+static int tick(void) {
+    counter = counter + 1;
+    return counter;
+}
+
+static int fact(int n) {
+    if (n <= 1) {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+static int unused(int a) {
+    return a + 1;
+}
+
+static int once(int a, int b) {
+    int sum = 0;
+    for (int i = 0; i < a; i = i + 1) {
+        sum = sum + square(i) + b;
+    }
+    tick();
+    return sum;
+}
+
+int
+main(void) {
+    int a = square(3) + square(4);
+    long b = widen(-1, 10l) + widen(a, 5l);
+    tick();
+    tick();
+    return a + (int)b + once(3, 2) + fact(4) + counter;
+}
@@ -0,0 +1,33 @@
+int putchar(int c);
+
+static int unused_var = 3;
+static int counter = 0;
+static int limit = 10;
+
+static int unused_func(int a) {
+    return a + unused_var;
+}
+
+int square(int x) {
+    return x * x;
+}
+
+int below_limit(int x) {
+    return x < limit;
+}
+
+int bump(void) {
+    counter = counter + 1;
+    return counter;
+}
+
+int
+main(void) {
+    int c = counter;
+    square(7);
+    below_limit(3);
+    int s = square(c + 2);
+    putchar(65 + s);
+    bump();
+    return c + counter + limit;
+}
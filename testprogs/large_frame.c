@@ -0,0 +1,612 @@
+int id(int x) {
+    return x;
+}
+
+int big(int x) {
+    int v0 = id(x + 0);
+    int v1 = id(x + 1);
+    int v2 = id(x + 2);
+    int v3 = id(x + 3);
+    int v4 = id(x + 4);
+    int v5 = id(x + 5);
+    int v6 = id(x + 6);
+    int v7 = id(x + 7);
+    int v8 = id(x + 8);
+    int v9 = id(x + 9);
+    int v10 = id(x + 10);
+    int v11 = id(x + 11);
+    int v12 = id(x + 12);
+    int v13 = id(x + 13);
+    int v14 = id(x + 14);
+    int v15 = id(x + 15);
+    int v16 = id(x + 16);
+    int v17 = id(x + 17);
+    int v18 = id(x + 18);
+    int v19 = id(x + 19);
+    int v20 = id(x + 20);
+    int v21 = id(x + 21);
+    int v22 = id(x + 22);
+    int v23 = id(x + 23);
+    int v24 = id(x + 24);
+    int v25 = id(x + 25);
+    int v26 = id(x + 26);
+    int v27 = id(x + 27);
+    int v28 = id(x + 28);
+    int v29 = id(x + 29);
+    int v30 = id(x + 30);
+    int v31 = id(x + 31);
+    int v32 = id(x + 32);
+    int v33 = id(x + 33);
+    int v34 = id(x + 34);
+    int v35 = id(x + 35);
+    int v36 = id(x + 36);
+    int v37 = id(x + 37);
+    int v38 = id(x + 38);
+    int v39 = id(x + 39);
+    int v40 = id(x + 40);
+    int v41 = id(x + 41);
+    int v42 = id(x + 42);
+    int v43 = id(x + 43);
+    int v44 = id(x + 44);
+    int v45 = id(x + 45);
+    int v46 = id(x + 46);
+    int v47 = id(x + 47);
+    int v48 = id(x + 48);
+    int v49 = id(x + 49);
+    int v50 = id(x + 50);
+    int v51 = id(x + 51);
+    int v52 = id(x + 52);
+    int v53 = id(x + 53);
+    int v54 = id(x + 54);
+    int v55 = id(x + 55);
+    int v56 = id(x + 56);
+    int v57 = id(x + 57);
+    int v58 = id(x + 58);
+    int v59 = id(x + 59);
+    int v60 = id(x + 60);
+    int v61 = id(x + 61);
+    int v62 = id(x + 62);
+    int v63 = id(x + 63);
+    int v64 = id(x + 64);
+    int v65 = id(x + 65);
+    int v66 = id(x + 66);
+    int v67 = id(x + 67);
+    int v68 = id(x + 68);
+    int v69 = id(x + 69);
+    int v70 = id(x + 70);
+    int v71 = id(x + 71);
+    int v72 = id(x + 72);
+    int v73 = id(x + 73);
+    int v74 = id(x + 74);
+    int v75 = id(x + 75);
+    int v76 = id(x + 76);
+    int v77 = id(x + 77);
+    int v78 = id(x + 78);
+    int v79 = id(x + 79);
+    int v80 = id(x + 80);
+    int v81 = id(x + 81);
+    int v82 = id(x + 82);
+    int v83 = id(x + 83);
+    int v84 = id(x + 84);
+    int v85 = id(x + 85);
+    int v86 = id(x + 86);
+    int v87 = id(x + 87);
+    int v88 = id(x + 88);
+    int v89 = id(x + 89);
+    int v90 = id(x + 90);
+    int v91 = id(x + 91);
+    int v92 = id(x + 92);
+    int v93 = id(x + 93);
+    int v94 = id(x + 94);
+    int v95 = id(x + 95);
+    int v96 = id(x + 96);
+    int v97 = id(x + 97);
+    int v98 = id(x + 98);
+    int v99 = id(x + 99);
+    int v100 = id(x + 100);
+    int v101 = id(x + 101);
+    int v102 = id(x + 102);
+    int v103 = id(x + 103);
+    int v104 = id(x + 104);
+    int v105 = id(x + 105);
+    int v106 = id(x + 106);
+    int v107 = id(x + 107);
+    int v108 = id(x + 108);
+    int v109 = id(x + 109);
+    int v110 = id(x + 110);
+    int v111 = id(x + 111);
+    int v112 = id(x + 112);
+    int v113 = id(x + 113);
+    int v114 = id(x + 114);
+    int v115 = id(x + 115);
+    int v116 = id(x + 116);
+    int v117 = id(x + 117);
+    int v118 = id(x + 118);
+    int v119 = id(x + 119);
+    int v120 = id(x + 120);
+    int v121 = id(x + 121);
+    int v122 = id(x + 122);
+    int v123 = id(x + 123);
+    int v124 = id(x + 124);
+    int v125 = id(x + 125);
+    int v126 = id(x + 126);
+    int v127 = id(x + 127);
+    int v128 = id(x + 128);
+    int v129 = id(x + 129);
+    int v130 = id(x + 130);
+    int v131 = id(x + 131);
+    int v132 = id(x + 132);
+    int v133 = id(x + 133);
+    int v134 = id(x + 134);
+    int v135 = id(x + 135);
+    int v136 = id(x + 136);
+    int v137 = id(x + 137);
+    int v138 = id(x + 138);
+    int v139 = id(x + 139);
+    int v140 = id(x + 140);
+    int v141 = id(x + 141);
+    int v142 = id(x + 142);
+    int v143 = id(x + 143);
+    int v144 = id(x + 144);
+    int v145 = id(x + 145);
+    int v146 = id(x + 146);
+    int v147 = id(x + 147);
+    int v148 = id(x + 148);
+    int v149 = id(x + 149);
+    int v150 = id(x + 150);
+    int v151 = id(x + 151);
+    int v152 = id(x + 152);
+    int v153 = id(x + 153);
+    int v154 = id(x + 154);
+    int v155 = id(x + 155);
+    int v156 = id(x + 156);
+    int v157 = id(x + 157);
+    int v158 = id(x + 158);
+    int v159 = id(x + 159);
+    int v160 = id(x + 160);
+    int v161 = id(x + 161);
+    int v162 = id(x + 162);
+    int v163 = id(x + 163);
+    int v164 = id(x + 164);
+    int v165 = id(x + 165);
+    int v166 = id(x + 166);
+    int v167 = id(x + 167);
+    int v168 = id(x + 168);
+    int v169 = id(x + 169);
+    int v170 = id(x + 170);
+    int v171 = id(x + 171);
+    int v172 = id(x + 172);
+    int v173 = id(x + 173);
+    int v174 = id(x + 174);
+    int v175 = id(x + 175);
+    int v176 = id(x + 176);
+    int v177 = id(x + 177);
+    int v178 = id(x + 178);
+    int v179 = id(x + 179);
+    int v180 = id(x + 180);
+    int v181 = id(x + 181);
+    int v182 = id(x + 182);
+    int v183 = id(x + 183);
+    int v184 = id(x + 184);
+    int v185 = id(x + 185);
+    int v186 = id(x + 186);
+    int v187 = id(x + 187);
+    int v188 = id(x + 188);
+    int v189 = id(x + 189);
+    int v190 = id(x + 190);
+    int v191 = id(x + 191);
+    int v192 = id(x + 192);
+    int v193 = id(x + 193);
+    int v194 = id(x + 194);
+    int v195 = id(x + 195);
+    int v196 = id(x + 196);
+    int v197 = id(x + 197);
+    int v198 = id(x + 198);
+    int v199 = id(x + 199);
+    int v200 = id(x + 200);
+    int v201 = id(x + 201);
+    int v202 = id(x + 202);
+    int v203 = id(x + 203);
+    int v204 = id(x + 204);
+    int v205 = id(x + 205);
+    int v206 = id(x + 206);
+    int v207 = id(x + 207);
+    int v208 = id(x + 208);
+    int v209 = id(x + 209);
+    int v210 = id(x + 210);
+    int v211 = id(x + 211);
+    int v212 = id(x + 212);
+    int v213 = id(x + 213);
+    int v214 = id(x + 214);
+    int v215 = id(x + 215);
+    int v216 = id(x + 216);
+    int v217 = id(x + 217);
+    int v218 = id(x + 218);
+    int v219 = id(x + 219);
+    int v220 = id(x + 220);
+    int v221 = id(x + 221);
+    int v222 = id(x + 222);
+    int v223 = id(x + 223);
+    int v224 = id(x + 224);
+    int v225 = id(x + 225);
+    int v226 = id(x + 226);
+    int v227 = id(x + 227);
+    int v228 = id(x + 228);
+    int v229 = id(x + 229);
+    int v230 = id(x + 230);
+    int v231 = id(x + 231);
+    int v232 = id(x + 232);
+    int v233 = id(x + 233);
+    int v234 = id(x + 234);
+    int v235 = id(x + 235);
+    int v236 = id(x + 236);
+    int v237 = id(x + 237);
+    int v238 = id(x + 238);
+    int v239 = id(x + 239);
+    int v240 = id(x + 240);
+    int v241 = id(x + 241);
+    int v242 = id(x + 242);
+    int v243 = id(x + 243);
+    int v244 = id(x + 244);
+    int v245 = id(x + 245);
+    int v246 = id(x + 246);
+    int v247 = id(x + 247);
+    int v248 = id(x + 248);
+    int v249 = id(x + 249);
+    int v250 = id(x + 250);
+    int v251 = id(x + 251);
+    int v252 = id(x + 252);
+    int v253 = id(x + 253);
+    int v254 = id(x + 254);
+    int v255 = id(x + 255);
+    int v256 = id(x + 256);
+    int v257 = id(x + 257);
+    int v258 = id(x + 258);
+    int v259 = id(x + 259);
+    int v260 = id(x + 260);
+    int v261 = id(x + 261);
+    int v262 = id(x + 262);
+    int v263 = id(x + 263);
+    int v264 = id(x + 264);
+    int v265 = id(x + 265);
+    int v266 = id(x + 266);
+    int v267 = id(x + 267);
+    int v268 = id(x + 268);
+    int v269 = id(x + 269);
+    int v270 = id(x + 270);
+    int v271 = id(x + 271);
+    int v272 = id(x + 272);
+    int v273 = id(x + 273);
+    int v274 = id(x + 274);
+    int v275 = id(x + 275);
+    int v276 = id(x + 276);
+    int v277 = id(x + 277);
+    int v278 = id(x + 278);
+    int v279 = id(x + 279);
+    int v280 = id(x + 280);
+    int v281 = id(x + 281);
+    int v282 = id(x + 282);
+    int v283 = id(x + 283);
+    int v284 = id(x + 284);
+    int v285 = id(x + 285);
+    int v286 = id(x + 286);
+    int v287 = id(x + 287);
+    int v288 = id(x + 288);
+    int v289 = id(x + 289);
+    int v290 = id(x + 290);
+    int v291 = id(x + 291);
+    int v292 = id(x + 292);
+    int v293 = id(x + 293);
+    int v294 = id(x + 294);
+    int v295 = id(x + 295);
+    int v296 = id(x + 296);
+    int v297 = id(x + 297);
+    int v298 = id(x + 298);
+    int v299 = id(x + 299);
+    int s = 0;
+    s = s + v0 * 0;
+    s = s + v1 * 1;
+    s = s + v2 * 2;
+    s = s + v3 * 3;
+    s = s + v4 * 4;
+    s = s + v5 * 5;
+    s = s + v6 * 6;
+    s = s + v7 * 0;
+    s = s + v8 * 1;
+    s = s + v9 * 2;
+    s = s + v10 * 3;
+    s = s + v11 * 4;
+    s = s + v12 * 5;
+    s = s + v13 * 6;
+    s = s + v14 * 0;
+    s = s + v15 * 1;
+    s = s + v16 * 2;
+    s = s + v17 * 3;
+    s = s + v18 * 4;
+    s = s + v19 * 5;
+    s = s + v20 * 6;
+    s = s + v21 * 0;
+    s = s + v22 * 1;
+    s = s + v23 * 2;
+    s = s + v24 * 3;
+    s = s + v25 * 4;
+    s = s + v26 * 5;
+    s = s + v27 * 6;
+    s = s + v28 * 0;
+    s = s + v29 * 1;
+    s = s + v30 * 2;
+    s = s + v31 * 3;
+    s = s + v32 * 4;
+    s = s + v33 * 5;
+    s = s + v34 * 6;
+    s = s + v35 * 0;
+    s = s + v36 * 1;
+    s = s + v37 * 2;
+    s = s + v38 * 3;
+    s = s + v39 * 4;
+    s = s + v40 * 5;
+    s = s + v41 * 6;
+    s = s + v42 * 0;
+    s = s + v43 * 1;
+    s = s + v44 * 2;
+    s = s + v45 * 3;
+    s = s + v46 * 4;
+    s = s + v47 * 5;
+    s = s + v48 * 6;
+    s = s + v49 * 0;
+    s = s + v50 * 1;
+    s = s + v51 * 2;
+    s = s + v52 * 3;
+    s = s + v53 * 4;
+    s = s + v54 * 5;
+    s = s + v55 * 6;
+    s = s + v56 * 0;
+    s = s + v57 * 1;
+    s = s + v58 * 2;
+    s = s + v59 * 3;
+    s = s + v60 * 4;
+    s = s + v61 * 5;
+    s = s + v62 * 6;
+    s = s + v63 * 0;
+    s = s + v64 * 1;
+    s = s + v65 * 2;
+    s = s + v66 * 3;
+    s = s + v67 * 4;
+    s = s + v68 * 5;
+    s = s + v69 * 6;
+    s = s + v70 * 0;
+    s = s + v71 * 1;
+    s = s + v72 * 2;
+    s = s + v73 * 3;
+    s = s + v74 * 4;
+    s = s + v75 * 5;
+    s = s + v76 * 6;
+    s = s + v77 * 0;
+    s = s + v78 * 1;
+    s = s + v79 * 2;
+    s = s + v80 * 3;
+    s = s + v81 * 4;
+    s = s + v82 * 5;
+    s = s + v83 * 6;
+    s = s + v84 * 0;
+    s = s + v85 * 1;
+    s = s + v86 * 2;
+    s = s + v87 * 3;
+    s = s + v88 * 4;
+    s = s + v89 * 5;
+    s = s + v90 * 6;
+    s = s + v91 * 0;
+    s = s + v92 * 1;
+    s = s + v93 * 2;
+    s = s + v94 * 3;
+    s = s + v95 * 4;
+    s = s + v96 * 5;
+    s = s + v97 * 6;
+    s = s + v98 * 0;
+    s = s + v99 * 1;
+    s = s + v100 * 2;
+    s = s + v101 * 3;
+    s = s + v102 * 4;
+    s = s + v103 * 5;
+    s = s + v104 * 6;
+    s = s + v105 * 0;
+    s = s + v106 * 1;
+    s = s + v107 * 2;
+    s = s + v108 * 3;
+    s = s + v109 * 4;
+    s = s + v110 * 5;
+    s = s + v111 * 6;
+    s = s + v112 * 0;
+    s = s + v113 * 1;
+    s = s + v114 * 2;
+    s = s + v115 * 3;
+    s = s + v116 * 4;
+    s = s + v117 * 5;
+    s = s + v118 * 6;
+    s = s + v119 * 0;
+    s = s + v120 * 1;
+    s = s + v121 * 2;
+    s = s + v122 * 3;
+    s = s + v123 * 4;
+    s = s + v124 * 5;
+    s = s + v125 * 6;
+    s = s + v126 * 0;
+    s = s + v127 * 1;
+    s = s + v128 * 2;
+    s = s + v129 * 3;
+    s = s + v130 * 4;
+    s = s + v131 * 5;
+    s = s + v132 * 6;
+    s = s + v133 * 0;
+    s = s + v134 * 1;
+    s = s + v135 * 2;
+    s = s + v136 * 3;
+    s = s + v137 * 4;
+    s = s + v138 * 5;
+    s = s + v139 * 6;
+    s = s + v140 * 0;
+    s = s + v141 * 1;
+    s = s + v142 * 2;
+    s = s + v143 * 3;
+    s = s + v144 * 4;
+    s = s + v145 * 5;
+    s = s + v146 * 6;
+    s = s + v147 * 0;
+    s = s + v148 * 1;
+    s = s + v149 * 2;
+    s = s + v150 * 3;
+    s = s + v151 * 4;
+    s = s + v152 * 5;
+    s = s + v153 * 6;
+    s = s + v154 * 0;
+    s = s + v155 * 1;
+    s = s + v156 * 2;
+    s = s + v157 * 3;
+    s = s + v158 * 4;
+    s = s + v159 * 5;
+    s = s + v160 * 6;
+    s = s + v161 * 0;
+    s = s + v162 * 1;
+    s = s + v163 * 2;
+    s = s + v164 * 3;
+    s = s + v165 * 4;
+    s = s + v166 * 5;
+    s = s + v167 * 6;
+    s = s + v168 * 0;
+    s = s + v169 * 1;
+    s = s + v170 * 2;
+    s = s + v171 * 3;
+    s = s + v172 * 4;
+    s = s + v173 * 5;
+    s = s + v174 * 6;
+    s = s + v175 * 0;
+    s = s + v176 * 1;
+    s = s + v177 * 2;
+    s = s + v178 * 3;
+    s = s + v179 * 4;
+    s = s + v180 * 5;
+    s = s + v181 * 6;
+    s = s + v182 * 0;
+    s = s + v183 * 1;
+    s = s + v184 * 2;
+    s = s + v185 * 3;
+    s = s + v186 * 4;
+    s = s + v187 * 5;
+    s = s + v188 * 6;
+    s = s + v189 * 0;
+    s = s + v190 * 1;
+    s = s + v191 * 2;
+    s = s + v192 * 3;
+    s = s + v193 * 4;
+    s = s + v194 * 5;
+    s = s + v195 * 6;
+    s = s + v196 * 0;
+    s = s + v197 * 1;
+    s = s + v198 * 2;
+    s = s + v199 * 3;
+    s = s + v200 * 4;
+    s = s + v201 * 5;
+    s = s + v202 * 6;
+    s = s + v203 * 0;
+    s = s + v204 * 1;
+    s = s + v205 * 2;
+    s = s + v206 * 3;
+    s = s + v207 * 4;
+    s = s + v208 * 5;
+    s = s + v209 * 6;
+    s = s + v210 * 0;
+    s = s + v211 * 1;
+    s = s + v212 * 2;
+    s = s + v213 * 3;
+    s = s + v214 * 4;
+    s = s + v215 * 5;
+    s = s + v216 * 6;
+    s = s + v217 * 0;
+    s = s + v218 * 1;
+    s = s + v219 * 2;
+    s = s + v220 * 3;
+    s = s + v221 * 4;
+    s = s + v222 * 5;
+    s = s + v223 * 6;
+    s = s + v224 * 0;
+    s = s + v225 * 1;
+    s = s + v226 * 2;
+    s = s + v227 * 3;
+    s = s + v228 * 4;
+    s = s + v229 * 5;
+    s = s + v230 * 6;
+    s = s + v231 * 0;
+    s = s + v232 * 1;
+    s = s + v233 * 2;
+    s = s + v234 * 3;
+    s = s + v235 * 4;
+    s = s + v236 * 5;
+    s = s + v237 * 6;
+    s = s + v238 * 0;
+    s = s + v239 * 1;
+    s = s + v240 * 2;
+    s = s + v241 * 3;
+    s = s + v242 * 4;
+    s = s + v243 * 5;
+    s = s + v244 * 6;
+    s = s + v245 * 0;
+    s = s + v246 * 1;
+    s = s + v247 * 2;
+    s = s + v248 * 3;
+    s = s + v249 * 4;
+    s = s + v250 * 5;
+    s = s + v251 * 6;
+    s = s + v252 * 0;
+    s = s + v253 * 1;
+    s = s + v254 * 2;
+    s = s + v255 * 3;
+    s = s + v256 * 4;
+    s = s + v257 * 5;
+    s = s + v258 * 6;
+    s = s + v259 * 0;
+    s = s + v260 * 1;
+    s = s + v261 * 2;
+    s = s + v262 * 3;
+    s = s + v263 * 4;
+    s = s + v264 * 5;
+    s = s + v265 * 6;
+    s = s + v266 * 0;
+    s = s + v267 * 1;
+    s = s + v268 * 2;
+    s = s + v269 * 3;
+    s = s + v270 * 4;
+    s = s + v271 * 5;
+    s = s + v272 * 6;
+    s = s + v273 * 0;
+    s = s + v274 * 1;
+    s = s + v275 * 2;
+    s = s + v276 * 3;
+    s = s + v277 * 4;
+    s = s + v278 * 5;
+    s = s + v279 * 6;
+    s = s + v280 * 0;
+    s = s + v281 * 1;
+    s = s + v282 * 2;
+    s = s + v283 * 3;
+    s = s + v284 * 4;
+    s = s + v285 * 5;
+    s = s + v286 * 6;
+    s = s + v287 * 0;
+    s = s + v288 * 1;
+    s = s + v289 * 2;
+    s = s + v290 * 3;
+    s = s + v291 * 4;
+    s = s + v292 * 5;
+    s = s + v293 * 6;
+    s = s + v294 * 0;
+    s = s + v295 * 1;
+    s = s + v296 * 2;
+    s = s + v297 * 3;
+    s = s + v298 * 4;
+    s = s + v299 * 5;
+    return s;
+}
+
+int main(void) {
+    return big(3) % 256;
+}
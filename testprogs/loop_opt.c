@@ -0,0 +1,25 @@
+int
+main(void) {
+    int a = 6;
+    int b = 7;
+    int sum = 0;
+
+    for (int i = 0; i < 10; i = i + 1) {
+        int inv = a * b;
+        int scaled = i * 4;
+        sum = sum + inv + scaled;
+    }
+
+    int j = 0;
+    while (j < 5) {
+        int q = b / 2;
+        int r = a / (j + 1);
+        sum = sum + q + j * 3;
+        j = j + 1;
+        if (j == 3) {
+            sum = sum + r;
+        }
+    }
+
+    return sum;
+}
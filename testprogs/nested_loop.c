@@ -0,0 +1,11 @@
+int
+main(void) {
+    int sum = 0;
+    for (int i = 0; i < 10; i = i + 1) {
+        for (int j = 0; j < i; j = j + 1) {
+            sum = sum + j;
+        }
+        sum = sum + i;
+    }
+    return sum;
+}
@@ -0,0 +1,35 @@
+static int flag = 1;
+
+int
+side(int x) {
+    flag = x;
+    return x;
+}
+
+int
+main(void) {
+    int a = 3;
+    int b = a * 4;
+    int c;
+    if (b > 10) {
+        c = b - 2;
+    } else {
+        c = side(b);
+    }
+
+    int i = 0;
+    int k = 7;
+    while (i < 10) {
+        if (k != 7) {
+            k = side(i);
+        }
+        i = i + 1;
+    }
+
+    flag = 2;
+    side(5);
+    if (flag == 2) {
+        return 0;
+    }
+    return c + k + i;
+}
@@ -0,0 +1,147 @@
+int wide(int x) {
+    int v0 = x * 2 + 0;
+    int v1 = x * 3 + 1;
+    int v2 = x * 4 + 2;
+    int v3 = x * 5 + 3;
+    int v4 = x * 6 + 4;
+    int v5 = x * 7 + 5;
+    int v6 = x * 8 + 6;
+    int v7 = x * 9 + 7;
+    int v8 = x * 10 + 8;
+    int v9 = x * 11 + 9;
+    int v10 = x * 12 + 10;
+    int v11 = x * 13 + 11;
+    int v12 = x * 14 + 12;
+    int v13 = x * 15 + 13;
+    int v14 = x * 16 + 14;
+    int v15 = x * 17 + 15;
+    int v16 = x * 18 + 16;
+    int v17 = x * 19 + 17;
+    int v18 = x * 20 + 18;
+    int v19 = x * 21 + 19;
+    int s = 0;
+    s = s + v0 * v7;
+    s = s + v1 * v8;
+    s = s + v2 * v9;
+    s = s + v3 * v10;
+    s = s + v4 * v11;
+    s = s + v5 * v12;
+    s = s + v6 * v13;
+    s = s + v7 * v14;
+    s = s + v8 * v15;
+    s = s + v9 * v16;
+    s = s + v10 * v17;
+    s = s + v11 * v18;
+    s = s + v12 * v19;
+    s = s + v13 * v0;
+    s = s + v14 * v1;
+    s = s + v15 * v2;
+    s = s + v16 * v3;
+    s = s + v17 * v4;
+    s = s + v18 * v5;
+    s = s + v19 * v6;
+    return s;
+}
+
+int id(int x) {
+    return x;
+}
+
+int across(int x) {
+    int u0 = id(x + 0);
+    int u1 = id(x + 1);
+    int u2 = id(x + 2);
+    int u3 = id(x + 3);
+    int u4 = id(x + 4);
+    int u5 = id(x + 5);
+    int u6 = id(x + 6);
+    int u7 = id(x + 7);
+    int u8 = id(x + 8);
+    int u9 = id(x + 9);
+    int u10 = id(x + 10);
+    int u11 = id(x + 11);
+    int u12 = id(x + 12);
+    int u13 = id(x + 13);
+    int u14 = id(x + 14);
+    int u15 = id(x + 15);
+    int u16 = id(x + 16);
+    int u17 = id(x + 17);
+    int u18 = id(x + 18);
+    int u19 = id(x + 19);
+    int u20 = id(x + 20);
+    int u21 = id(x + 21);
+    int u22 = id(x + 22);
+    int u23 = id(x + 23);
+    int u24 = id(x + 24);
+    int u25 = id(x + 25);
+    int u26 = id(x + 26);
+    int u27 = id(x + 27);
+    int u28 = id(x + 28);
+    int u29 = id(x + 29);
+    u0 = u0 + id(u1);
+    u1 = u1 + id(u2);
+    u2 = u2 + id(u3);
+    u3 = u3 + id(u4);
+    u4 = u4 + id(u5);
+    u5 = u5 + id(u6);
+    u6 = u6 + id(u7);
+    u7 = u7 + id(u8);
+    u8 = u8 + id(u9);
+    u9 = u9 + id(u10);
+    u10 = u10 + id(u11);
+    u11 = u11 + id(u12);
+    u12 = u12 + id(u13);
+    u13 = u13 + id(u14);
+    u14 = u14 + id(u15);
+    u15 = u15 + id(u16);
+    u16 = u16 + id(u17);
+    u17 = u17 + id(u18);
+    u18 = u18 + id(u19);
+    u19 = u19 + id(u20);
+    u20 = u20 + id(u21);
+    u21 = u21 + id(u22);
+    u22 = u22 + id(u23);
+    u23 = u23 + id(u24);
+    u24 = u24 + id(u25);
+    u25 = u25 + id(u26);
+    u26 = u26 + id(u27);
+    u27 = u27 + id(u28);
+    u28 = u28 + id(u29);
+    u29 = u29 + id(u0);
+    int s = 0;
+    s = s + u0 * 1;
+    s = s + u1 * 2;
+    s = s + u2 * 3;
+    s = s + u3 * 4;
+    s = s + u4 * 5;
+    s = s + u5 * 6;
+    s = s + u6 * 7;
+    s = s + u7 * 8;
+    s = s + u8 * 9;
+    s = s + u9 * 10;
+    s = s + u10 * 11;
+    s = s + u11 * 12;
+    s = s + u12 * 13;
+    s = s + u13 * 14;
+    s = s + u14 * 15;
+    s = s + u15 * 16;
+    s = s + u16 * 17;
+    s = s + u17 * 18;
+    s = s + u18 * 19;
+    s = s + u19 * 20;
+    s = s + u20 * 21;
+    s = s + u21 * 22;
+    s = s + u22 * 23;
+    s = s + u23 * 24;
+    s = s + u24 * 25;
+    s = s + u25 * 26;
+    s = s + u26 * 27;
+    s = s + u27 * 28;
+    s = s + u28 * 29;
+    s = s + u29 * 30;
+    return s;
+}
+
+int main() {
+    return (wide(3) + across(5)) % 256;
+}
@@ -0,0 +1,45 @@
+int
+count(int n, int acc) {
+    if (n == 0) {
+        return acc;
+    }
+    return count(n - 1, acc + 1);
+}
+
+int even(int n);
+
+int
+odd(int n) {
+    if (n == 0) {
+        return 0;
+    }
+    return even(n - 1);
+}
+
+int
+even(int n) {
+    if (n == 0) {
+        return 1;
+    }
+    return odd(n - 1);
+}
+
+int
+sum9(int a, int b, int c, int d, int e, int f, int g, int h, int i) {
+    return a + i;
+}
+
+int
+forward(int a, int b, int c, int d, int e, int f, int g, int h, int i, int j) {
+    return sum9(a, b, c, d, e, f, g, h, i + j);
+}
+
+int
+widen(int a) {
+    return forward(a, 1, 2, 3, 4, 5, 6, 7, 8, 9);
+}
+
+int
+main(void) {
+    return count(100000, 0) % 256 + even(1001) + widen(3);
+}